@@ -1,4 +1,6 @@
 use anchor_lang::prelude::*;
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token::{self, Mint, Token, TokenAccount};
 
 declare_id!("F4Py3YTF1JGhbY9ACztXaseFF89ZfLS69ke5Z7EBGQGr");
 
@@ -6,7 +8,7 @@ declare_id!("F4Py3YTF1JGhbY9ACztXaseFF89ZfLS69ke5Z7EBGQGr");
 mod chess_validation {
     use super::*;
     
-    #[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+    #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
     pub enum Piece {
         WhitePawn,
         WhiteRook,
@@ -22,6 +24,78 @@ mod chess_validation {
         BlackKing,
         Empty,
     }
+
+    impl Piece {
+        const BY_CODE: [Piece; 13] = [
+            Piece::Empty,
+            Piece::WhitePawn,
+            Piece::WhiteRook,
+            Piece::WhiteKnight,
+            Piece::WhiteBishop,
+            Piece::WhiteQueen,
+            Piece::WhiteKing,
+            Piece::BlackPawn,
+            Piece::BlackRook,
+            Piece::BlackKnight,
+            Piece::BlackBishop,
+            Piece::BlackQueen,
+            Piece::BlackKing,
+        ];
+
+        /// Compact code used for the board stored on `GameEscrow` (0 = empty square)
+        pub fn code(self) -> u8 {
+            Self::BY_CODE.iter().position(|p| *p == self).unwrap_or(0) as u8
+        }
+
+        pub fn from_code(code: u8) -> Piece {
+            Self::BY_CODE.get(code as usize).copied().unwrap_or(Piece::Empty)
+        }
+
+        pub fn is_white(self) -> bool {
+            (1..=6).contains(&self.code())
+        }
+
+        /// FEN letter, upper case for White; a space for an empty square
+        pub fn symbol(self) -> char {
+            " PRNBQKprnbqk".as_bytes()[self.code() as usize] as char
+        }
+
+        /// Conventional material value in pawns; kings and empty squares count zero
+        pub fn value(self) -> i16 {
+            match self {
+                Piece::WhitePawn | Piece::BlackPawn => 1,
+                Piece::WhiteKnight | Piece::BlackKnight => 3,
+                Piece::WhiteBishop | Piece::BlackBishop => 3,
+                Piece::WhiteRook | Piece::BlackRook => 5,
+                Piece::WhiteQueen | Piece::BlackQueen => 9,
+                _ => 0,
+            }
+        }
+    }
+
+    /// Castling rights packed one bit each: White kingside, White queenside, Black
+    /// kingside, Black queenside
+    pub const ALL_CASTLING_RIGHTS: u8 = 0b1111;
+
+    // Longest FEN `verify_checkmate` reads; real records stay under 90 characters
+    pub const MAX_FEN_LEN: usize = 100;
+
+    /// Standard starting setup, indexed a1 = 0 .. h8 = 63
+    pub fn starting_board() -> [u8; 64] {
+        let back_rank = [
+            Piece::WhiteRook, Piece::WhiteKnight, Piece::WhiteBishop, Piece::WhiteQueen,
+            Piece::WhiteKing, Piece::WhiteBishop, Piece::WhiteKnight, Piece::WhiteRook,
+        ];
+        let mut board = [0u8; 64];
+        for file in 0..8 {
+            let black_piece = Piece::from_code(back_rank[file].code() + 6);
+            board[file] = back_rank[file].code();
+            board[8 + file] = Piece::WhitePawn.code();
+            board[48 + file] = Piece::BlackPawn.code();
+            board[56 + file] = black_piece.code();
+        }
+        board
+    }
     
     #[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
     pub struct Position {
@@ -32,7 +106,7 @@ mod chess_validation {
         pub black_castle_kingside: bool,
         pub black_castle_queenside: bool,
         pub en_passant_square: Option<u8>,
-        pub halfmove_clock: u8,
+        pub halfmove_clock: u16,
         pub fullmove_number: u16,
     }
     
@@ -47,46 +121,725 @@ mod chess_validation {
         pub is_promotion: bool,
         pub promotion_piece: Option<Piece>,
     }
-    
-    pub fn validate_move(
-        from: String,
-        to: String,
-        piece: String,
-        current_position: &Position,
-        _game_state: &GameState
-    ) -> Result<bool> {
-        // Basic move validation
-        if from.len() != 2 || to.len() != 2 {
-            return Err(ChessError::InvalidMoveFormat.into());
+
+    impl Position {
+        pub fn from_board(board: &[u8; 64], white_to_move: bool) -> Position {
+            Position {
+                squares: board.map(Piece::from_code),
+                white_to_move,
+                white_castle_kingside: true,
+                white_castle_queenside: true,
+                black_castle_kingside: true,
+                black_castle_queenside: true,
+                en_passant_square: None,
+                halfmove_clock: 0,
+                fullmove_number: 1,
+            }
         }
-        
-        // Validate square coordinates
-        let from_square = parse_square(&from)?;
-        let to_square = parse_square(&to)?;
-        
-        // Validate piece movement
-        let piece_type = parse_piece(&piece)?;
-        
-        // Check if move is legal for the piece type
-        if !is_legal_move(from_square, to_square, piece_type, current_position) {
+
+        /// Parse a FEN record; the halfmove clock and fullmove number may be left off
+        pub fn from_fen(fen: &str) -> Result<Position> {
+            let mut fields = fen.split_whitespace();
+            let (Some(placement), Some(side), Some(castling), Some(en_passant)) =
+                (fields.next(), fields.next(), fields.next(), fields.next())
+            else {
+                return Err(ChessError::InvalidFen.into());
+            };
+
+            let mut squares = [Piece::Empty; 64];
+            let ranks: Vec<&str> = placement.split('/').collect();
+            require!(ranks.len() == 8, ChessError::InvalidFen);
+            // FEN lists rank 8 first
+            for (rank, row) in (0..8usize).rev().zip(ranks) {
+                let mut file = 0usize;
+                for symbol in row.chars() {
+                    if let Some(empty) = symbol.to_digit(10).filter(|n| (1..=8).contains(n)) {
+                        file += empty as usize;
+                        continue;
+                    }
+                    require!(file < 8, ChessError::InvalidFen);
+                    squares[rank * 8 + file] = parse_piece(symbol.encode_utf8(&mut [0; 4]))
+                        .map_err(|_| ChessError::InvalidFen)?;
+                    file += 1;
+                }
+                require!(file == 8, ChessError::InvalidFen);
+            }
+
+            let white_to_move = match side {
+                "w" => true,
+                "b" => false,
+                _ => return Err(ChessError::InvalidFen.into()),
+            };
+            require!(
+                castling == "-" || (!castling.is_empty() && castling.chars().all(|c| "KQkq".contains(c))),
+                ChessError::InvalidFen
+            );
+            let en_passant_square = match en_passant {
+                "-" => None,
+                square => Some(parse_square(square).map_err(|_| ChessError::InvalidFen)?),
+            };
+            let mut counter = |default: u16| -> Result<u16> {
+                fields
+                    .next()
+                    .map_or(Ok(default), |field| field.parse().map_err(|_| ChessError::InvalidFen.into()))
+            };
+            let halfmove_clock = counter(0)?;
+            let fullmove_number = counter(1)?;
+
+            Ok(Position {
+                squares,
+                white_to_move,
+                white_castle_kingside: castling.contains('K'),
+                white_castle_queenside: castling.contains('Q'),
+                black_castle_kingside: castling.contains('k'),
+                black_castle_queenside: castling.contains('q'),
+                en_passant_square,
+                halfmove_clock,
+                fullmove_number,
+            })
+        }
+
+        /// FEN record of the position; `from_fen` reads it back
+        pub fn to_fen(&self) -> String {
+            let mut fen = String::new();
+            for rank in (0..8).rev() {
+                let mut empty = 0;
+                for file in 0..8 {
+                    let piece = self.squares[rank * 8 + file];
+                    if piece == Piece::Empty {
+                        empty += 1;
+                        continue;
+                    }
+                    if empty > 0 {
+                        fen.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    fen.push(piece.symbol());
+                }
+                if empty > 0 {
+                    fen.push_str(&empty.to_string());
+                }
+                if rank > 0 {
+                    fen.push('/');
+                }
+            }
+
+            let rights = [
+                (self.white_castle_kingside, 'K'),
+                (self.white_castle_queenside, 'Q'),
+                (self.black_castle_kingside, 'k'),
+                (self.black_castle_queenside, 'q'),
+            ];
+            let castling: String = rights.iter().filter(|(held, _)| *held).map(|(_, c)| *c).collect();
+            let en_passant = self.en_passant_square.map_or("-".to_string(), square_name);
+            format!(
+                "{} {} {} {} {} {}",
+                fen,
+                if self.white_to_move { "w" } else { "b" },
+                if castling.is_empty() { "-" } else { &castling },
+                en_passant,
+                self.halfmove_clock,
+                self.fullmove_number
+            )
+        }
+
+        pub fn board(&self) -> [u8; 64] {
+            self.squares.map(Piece::code)
+        }
+
+        /// Castling rights in the `ALL_CASTLING_RIGHTS` bit layout
+        pub fn castling_rights(&self) -> u8 {
+            self.white_castle_kingside as u8
+                | (self.white_castle_queenside as u8) << 1
+                | (self.black_castle_kingside as u8) << 2
+                | (self.black_castle_queenside as u8) << 3
+        }
+
+        pub fn set_castling_rights(&mut self, rights: u8) {
+            self.white_castle_kingside = rights & 1 != 0;
+            self.white_castle_queenside = rights & 2 != 0;
+            self.black_castle_kingside = rights & 4 != 0;
+            self.black_castle_queenside = rights & 8 != 0;
+        }
+
+        /// `hash(board codes || side to move || en-passant square || castling rights)`, with
+        /// 64 standing in for no en-passant target. The rights are hashed because the board
+        /// can't show them: a king that moved and came back stands home but can't castle.
+        pub fn digest(&self) -> [u8; 32] {
+            anchor_lang::solana_program::hash::hashv(&[
+                &self.board(),
+                &[
+                    self.white_to_move as u8,
+                    self.en_passant_square.unwrap_or(64),
+                    self.castling_rights(),
+                ],
+            ])
+            .to_bytes()
+        }
+
+        /// White's material minus Black's, in pawns
+        pub fn material_balance(&self) -> i16 {
+            self.squares
+                .iter()
+                .map(|piece| if piece.is_white() { piece.value() } else { -piece.value() })
+                .sum()
+        }
+
+        /// Whether neither side has mating material left: bare kings, a lone knight or
+        /// bishop against a bare king, or one bishop each on squares of the same colour
+        pub fn has_insufficient_material(&self) -> bool {
+            let mut minors = Vec::new();
+            for (square, piece) in self.squares.iter().enumerate() {
+                match piece {
+                    Piece::Empty | Piece::WhiteKing | Piece::BlackKing => {}
+                    Piece::WhiteKnight | Piece::BlackKnight | Piece::WhiteBishop | Piece::BlackBishop => {
+                        minors.push((*piece, square))
+                    }
+                    // A pawn, rook or queen can always still mate
+                    _ => return false,
+                }
+            }
+            let square_colour = |square: usize| (square % 8 + square / 8) % 2;
+            match minors[..] {
+                [] | [_] => true,
+                [(Piece::WhiteBishop, white), (Piece::BlackBishop, black)]
+                | [(Piece::BlackBishop, black), (Piece::WhiteBishop, white)] => {
+                    square_colour(white) == square_colour(black)
+                }
+                _ => false,
+            }
+        }
+
+        /// Compact identity of the position for repetition counting: the board, side to
+        /// move, castling rights and en-passant target, hashed and cut to 8 bytes
+        pub fn repetition_key(&self) -> u64 {
+            let board = self.board();
+            let state = [
+                self.white_to_move as u8,
+                self.white_castle_kingside as u8,
+                self.white_castle_queenside as u8,
+                self.black_castle_kingside as u8,
+                self.black_castle_queenside as u8,
+                self.en_passant_square.map_or(u8::MAX, |square| square),
+            ];
+            let hash = anchor_lang::solana_program::hash::hashv(&[&board, &state]).to_bytes();
+            u64::from_le_bytes(hash[..8].try_into().unwrap())
+        }
+
+        pub fn piece_count(&self) -> usize {
+            self.squares.iter().filter(|piece| **piece != Piece::Empty).count()
+        }
+
+        pub fn king_square(&self, white: bool) -> Option<u8> {
+            let king = if white { Piece::WhiteKing } else { Piece::BlackKing };
+            self.squares.iter().position(|piece| *piece == king).map(|square| square as u8)
+        }
+
+        /// Whether the side to move has its king attacked
+        pub fn in_check(&self) -> bool {
+            self.king_square(self.white_to_move)
+                .is_some_and(|king| is_square_attacked(self, king, !self.white_to_move))
+        }
+    }
+
+    /// Atomic chess capture: the piece on `square` is removed together with every
+    /// piece but a pawn on the eight squares around it
+    pub fn explode(position: &mut Position, square: u8) {
+        let file = (square % 8) as i8;
+        let rank = (square / 8) as i8;
+        position.squares[square as usize] = Piece::Empty;
+        for df in -1..=1 {
+            for dr in -1..=1 {
+                let (f, r) = (file + df, rank + dr);
+                if (df, dr) == (0, 0) || !(0..8).contains(&f) || !(0..8).contains(&r) {
+                    continue;
+                }
+                let neighbour = &mut position.squares[(r * 8 + f) as usize];
+                if !matches!(*neighbour, Piece::WhitePawn | Piece::BlackPawn) {
+                    *neighbour = Piece::Empty;
+                }
+            }
+        }
+    }
+
+    /// Whether any piece of the given colour attacks `square`
+    pub fn is_square_attacked(position: &Position, square: u8, by_white: bool) -> bool {
+        const KNIGHT_STEPS: [(i8, i8); 8] =
+            [(1, 2), (2, 1), (2, -1), (1, -2), (-1, -2), (-2, -1), (-2, 1), (-1, 2)];
+        const KING_STEPS: [(i8, i8); 8] =
+            [(1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1), (0, -1), (1, -1)];
+        const ROOK_LINES: [(i8, i8); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+        const BISHOP_LINES: [(i8, i8); 4] = [(1, 1), (-1, 1), (-1, -1), (1, -1)];
+
+        let (pawn, knight, bishop, rook, queen, king) = if by_white {
+            (Piece::WhitePawn, Piece::WhiteKnight, Piece::WhiteBishop,
+             Piece::WhiteRook, Piece::WhiteQueen, Piece::WhiteKing)
+        } else {
+            (Piece::BlackPawn, Piece::BlackKnight, Piece::BlackBishop,
+             Piece::BlackRook, Piece::BlackQueen, Piece::BlackKing)
+        };
+        let file = (square % 8) as i8;
+        let rank = (square / 8) as i8;
+        let piece_at = |df: i8, dr: i8| {
+            let (f, r) = (file + df, rank + dr);
+            ((0..8).contains(&f) && (0..8).contains(&r))
+                .then(|| position.squares[(r * 8 + f) as usize])
+        };
+
+        // Pawns attack diagonally forward, so look one rank behind the square
+        let pawn_rank = if by_white { -1 } else { 1 };
+        if [-1, 1].iter().any(|&df| piece_at(df, pawn_rank) == Some(pawn)) {
+            return true;
+        }
+        if KNIGHT_STEPS.iter().any(|&(df, dr)| piece_at(df, dr) == Some(knight)) {
+            return true;
+        }
+        if KING_STEPS.iter().any(|&(df, dr)| piece_at(df, dr) == Some(king)) {
+            return true;
+        }
+
+        // Sliders: walk each line until the first piece
+        let slider_on_line = |(df, dr): (i8, i8), straight: Piece| {
+            (1..8)
+                .map_while(|step| piece_at(df * step, dr * step))
+                .find(|piece| *piece != Piece::Empty)
+                .is_some_and(|piece| piece == straight || piece == queen)
+        };
+        ROOK_LINES.iter().any(|&line| slider_on_line(line, rook))
+            || BISHOP_LINES.iter().any(|&line| slider_on_line(line, bishop))
+    }
+
+    /// Every legal move for the side to move: pseudo-legal moves for each piece, keeping
+    /// those that don't leave the mover's king attacked. Promotions appear once per piece.
+    /// A castle is offered while the right is held, the path is clear and the king
+    /// doesn't pass through check.
+    pub fn generate_legal_moves(position: &Position) -> Vec<Move> {
+        let mut moves = Vec::new();
+        for from in 0..64u8 {
+            let piece = position.squares[from as usize];
+            if piece != Piece::Empty && piece.is_white() == position.white_to_move {
+                pseudo_legal_moves(position, from, piece, &mut moves);
+            }
+        }
+        moves.retain(|mv| leaves_king_safe(position, mv));
+        moves
+    }
+
+    /// Whether the side to move is in check with no legal move
+    pub fn is_checkmate(position: &Position) -> bool {
+        position.in_check() && generate_legal_moves(position).is_empty()
+    }
+
+    fn pseudo_legal_moves(position: &Position, from: u8, piece: Piece, moves: &mut Vec<Move>) {
+        const KNIGHT_STEPS: [(i8, i8); 8] =
+            [(1, 2), (2, 1), (2, -1), (1, -2), (-1, -2), (-2, -1), (-2, 1), (-1, 2)];
+        const KING_STEPS: [(i8, i8); 8] =
+            [(1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1), (0, -1), (1, -1)];
+        const ROOK_LINES: [(i8, i8); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+        const BISHOP_LINES: [(i8, i8); 4] = [(1, 1), (-1, 1), (-1, -1), (1, -1)];
+
+        let white = piece.is_white();
+        let file = (from % 8) as i8;
+        let rank = (from / 8) as i8;
+        let target = |df: i8, dr: i8| {
+            let (f, r) = (file + df, rank + dr);
+            ((0..8).contains(&f) && (0..8).contains(&r)).then_some((r * 8 + f) as u8)
+        };
+        let occupant = |square: u8| position.squares[square as usize];
+        let is_enemy = |square: u8| {
+            let other = occupant(square);
+            other != Piece::Empty && other.is_white() != white
+        };
+        let mut push = |to: u8| {
+            let captured = occupant(to);
+            moves.push(Move {
+                from,
+                to,
+                piece,
+                captured_piece: (captured != Piece::Empty).then_some(captured),
+                is_castle: false,
+                is_en_passant: false,
+                is_promotion: false,
+                promotion_piece: None,
+            });
+        };
+
+        match piece {
+            Piece::WhitePawn | Piece::BlackPawn => {
+                let (forward, start_rank, last_rank) = if white { (1, 1, 7) } else { (-1, 6, 0) };
+                let mut pawn_targets = Vec::with_capacity(4);
+                if let Some(one) = target(0, forward).filter(|&sq| occupant(sq) == Piece::Empty) {
+                    pawn_targets.push((one, false));
+                    if rank == start_rank {
+                        let two = target(0, 2 * forward).filter(|&sq| occupant(sq) == Piece::Empty);
+                        if let Some(two) = two {
+                            pawn_targets.push((two, false));
+                        }
+                    }
+                }
+                for df in [-1, 1] {
+                    if let Some(diagonal) = target(df, forward) {
+                        if is_enemy(diagonal) {
+                            pawn_targets.push((diagonal, false));
+                        } else if position.en_passant_square == Some(diagonal) {
+                            pawn_targets.push((diagonal, true));
+                        }
+                    }
+                }
+                for (to, en_passant) in pawn_targets {
+                    let captured = if en_passant {
+                        Some(occupant(en_passant_victim_square(from, to)))
+                    } else {
+                        Some(occupant(to)).filter(|p| *p != Piece::Empty)
+                    };
+                    let promotions: &[Piece] = if (to / 8) as i8 != last_rank {
+                        &[Piece::Empty]
+                    } else if white {
+                        &[Piece::WhiteQueen, Piece::WhiteRook, Piece::WhiteBishop, Piece::WhiteKnight]
+                    } else {
+                        &[Piece::BlackQueen, Piece::BlackRook, Piece::BlackBishop, Piece::BlackKnight]
+                    };
+                    for &promotion in promotions {
+                        moves.push(Move {
+                            from,
+                            to,
+                            piece,
+                            captured_piece: captured,
+                            is_castle: false,
+                            is_en_passant: en_passant,
+                            is_promotion: promotion != Piece::Empty,
+                            promotion_piece: (promotion != Piece::Empty).then_some(promotion),
+                        });
+                    }
+                }
+            },
+            Piece::WhiteKnight | Piece::BlackKnight | Piece::WhiteKing | Piece::BlackKing => {
+                let steps = if matches!(piece, Piece::WhiteKnight | Piece::BlackKnight) {
+                    &KNIGHT_STEPS
+                } else {
+                    &KING_STEPS
+                };
+                for &(df, dr) in steps {
+                    if let Some(to) = target(df, dr) {
+                        if occupant(to) == Piece::Empty || is_enemy(to) {
+                            push(to);
+                        }
+                    }
+                }
+            },
+            _ => {
+                let lines: &[(i8, i8)] = match piece {
+                    Piece::WhiteRook | Piece::BlackRook => &ROOK_LINES,
+                    Piece::WhiteBishop | Piece::BlackBishop => &BISHOP_LINES,
+                    _ => &[ROOK_LINES, BISHOP_LINES].concat(),
+                };
+                for &(df, dr) in lines {
+                    for step in 1..8 {
+                        let Some(to) = target(df * step, dr * step) else { break };
+                        if occupant(to) == Piece::Empty {
+                            push(to);
+                            continue;
+                        }
+                        if is_enemy(to) {
+                            push(to);
+                        }
+                        break;
+                    }
+                }
+            },
+        }
+
+        // Castling, from the king's home square
+        let home = if white { 4u8 } else { 60u8 };
+        if matches!(piece, Piece::WhiteKing | Piece::BlackKing) && from == home {
+            for to in [home + 2, home - 2] {
+                if castle_allowed(position, white, to) {
+                    moves.push(Move {
+                        from,
+                        to,
+                        piece,
+                        captured_piece: None,
+                        is_castle: true,
+                        is_en_passant: false,
+                        is_promotion: false,
+                        promotion_piece: None,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Whether the `white` king may castle from its home square to `to`: the right for
+    /// that side is still held, king and rook stand at home with nothing between them,
+    /// and the king is not in check and neither crosses nor lands on an attacked square
+    fn castle_allowed(position: &Position, white: bool, to: u8) -> bool {
+        let (king, rook, home) = if white {
+            (Piece::WhiteKing, Piece::WhiteRook, 4u8)
+        } else {
+            (Piece::BlackKing, Piece::BlackRook, 60u8)
+        };
+        // (right held, rook square, squares that must be empty, squares the king crosses)
+        let (right, rook_square, empty, crossed): (bool, u8, &[u8], [u8; 2]) = if to == home + 2 {
+            let right = if white { position.white_castle_kingside } else { position.black_castle_kingside };
+            (right, home + 3, &[home + 1, home + 2], [home + 1, home + 2])
+        } else if to + 2 == home {
+            let right = if white { position.white_castle_queenside } else { position.black_castle_queenside };
+            (right, home - 4, &[home - 1, home - 2, home - 3], [home - 1, home - 2])
+        } else {
+            return false;
+        };
+
+        right
+            && position.squares[home as usize] == king
+            && position.squares[rook_square as usize] == rook
+            && empty.iter().all(|&sq| position.squares[sq as usize] == Piece::Empty)
+            && !is_square_attacked(position, home, !white)
+            && crossed.iter().all(|&sq| !is_square_attacked(position, sq, !white))
+    }
+
+    /// A castle is the king's two-square step from its home square and nothing else, made
+    /// only when `castle_allowed`. Checked even where full validation is off, since
+    /// applying it also moves the rook.
+    pub fn check_castle(position: &Position, mv: &Move) -> Result<()> {
+        let home = match mv.piece {
+            Piece::WhiteKing => 4,
+            Piece::BlackKing => 60,
+            _ => {
+                require!(!mv.is_castle, ChessError::IllegalMove);
+                return Ok(());
+            }
+        };
+        let castle_step = mv.from == home && mv.from.abs_diff(mv.to) == 2;
+        require!(mv.is_castle == castle_step, ChessError::IllegalMove);
+        if mv.is_castle {
+            require!(
+                castle_allowed(position, mv.piece.is_white(), mv.to),
+                ChessError::IllegalMove
+            );
+        }
+        Ok(())
+    }
+
+    /// Whether the mover's king is safe once `mv` is played
+    fn leaves_king_safe(position: &Position, mv: &Move) -> bool {
+        let mover_is_white = position.white_to_move;
+        let mut after = position.clone();
+        if apply_move(&mut after, mv).is_err() {
+            return false;
+        }
+        match after.king_square(mover_is_white) {
+            Some(king) => !is_square_attacked(&after, king, !mover_is_white),
+            None => true,
+        }
+    }
+
+    /// Square holding the pawn taken by an en-passant capture: the destination file
+    /// on the capturing pawn's own rank
+    pub fn en_passant_victim_square(from: u8, to: u8) -> u8 {
+        (from / 8) * 8 + to % 8
+    }
+
+    /// Check the client's claimed capture against what the board actually holds
+    pub fn validate_capture(position: &Position, mv: &Move) -> Result<()> {
+        let target = if mv.is_en_passant {
+            // The destination itself must be empty for an en-passant capture
+            require!(
+                position.squares[mv.to as usize] == Piece::Empty,
+                ChessError::CaptureMismatch
+            );
+            position.squares[en_passant_victim_square(mv.from, mv.to) as usize]
+        } else {
+            position.squares[mv.to as usize]
+        };
+
+        match mv.captured_piece {
+            Some(captured) => require!(captured == target, ChessError::CaptureMismatch),
+            None => require!(target == Piece::Empty, ChessError::CaptureMismatch),
+        }
+
+        Ok(())
+    }
+
+    /// Everything `record_move` checks about a move against the current position, when
+    /// the game requires validation: the mover's own piece is on the source square, the
+    /// capture claim matches the board, and the piece may legally make the move. It runs
+    /// before anything is written, so a rejected move leaves the game untouched.
+    pub fn check_move(position: &Position, mv: &Move) -> Result<()> {
+        require!(
+            mv.piece.is_white() == position.white_to_move,
+            ChessError::NotPlayerTurn
+        );
+        check_promotion(mv)?;
+        require!(
+            position.squares[mv.from as usize] == mv.piece,
+            ChessError::PieceNotOnSquare
+        );
+        validate_capture(position, mv)?;
+        check_en_passant(position, mv)?;
+        check_castle(position, mv)?;
+
+        if !is_legal_move(mv.from, mv.to, mv.piece, position) {
             return Err(ChessError::IllegalMove.into());
         }
-        
-        // Check if move doesn't put own king in check
-        if would_move_expose_king(from_square, to_square, current_position) {
+        if would_move_expose_king(mv, position) {
             return Err(ChessError::MoveExposesKing.into());
         }
-        
-        Ok(true)
+
+        Ok(())
+    }
+
+    /// An en-passant capture is a pawn on its fifth rank stepping diagonally onto
+    /// `en_passant_square` beside the enemy pawn that skipped it. Only a double push on the
+    /// ply just played sets that square, so a capture a move later finds it cleared. A
+    /// pawn stepping onto the square must claim the capture, and only pawns may. Checked
+    /// even where full validation is off, since applying the move empties a square it
+    /// never touches.
+    pub fn check_en_passant(position: &Position, mv: &Move) -> Result<()> {
+        let (capture_rank, forward, victim) = match mv.piece {
+            Piece::WhitePawn => (4, 1, Piece::BlackPawn),
+            Piece::BlackPawn => (3, -1, Piece::WhitePawn),
+            _ => {
+                require!(!mv.is_en_passant, ChessError::IllegalMove);
+                return Ok(());
+            }
+        };
+        let diagonal = (mv.from % 8).abs_diff(mv.to % 8) == 1
+            && (mv.to / 8) as i8 == (mv.from / 8) as i8 + forward;
+        let onto_target = diagonal && position.en_passant_square == Some(mv.to);
+
+        if !mv.is_en_passant {
+            require!(!onto_target, ChessError::IllegalMove);
+            return Ok(());
+        }
+        require!(
+            onto_target
+                && mv.from / 8 == capture_rank
+                && position.squares[en_passant_victim_square(mv.from, mv.to) as usize] == victim,
+            ChessError::IllegalMove
+        );
+        Ok(())
+    }
+
+    /// A move promotes exactly when a pawn reaches the far rank, and then to a queen,
+    /// rook, bishop or knight of the pawn's own colour. Needs no board, so it holds even
+    /// where full validation is off.
+    pub fn check_promotion(mv: &Move) -> Result<()> {
+        let far_rank = match mv.piece {
+            Piece::WhitePawn => Some(mv.to / 8 == 7),
+            Piece::BlackPawn => Some(mv.to / 8 == 0),
+            _ => None,
+        };
+        match (far_rank, mv.is_promotion) {
+            (Some(true), true) => {
+                let promoted = mv.promotion_piece.ok_or(ChessError::InvalidPromotion)?;
+                let allowed = if mv.piece.is_white() {
+                    [Piece::WhiteQueen, Piece::WhiteRook, Piece::WhiteBishop, Piece::WhiteKnight]
+                } else {
+                    [Piece::BlackQueen, Piece::BlackRook, Piece::BlackBishop, Piece::BlackKnight]
+                };
+                require!(allowed.contains(&promoted), ChessError::InvalidPromotion);
+            }
+            (Some(true), false) | (_, true) => return Err(ChessError::InvalidPromotion.into()),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Apply `mv` to every part of `position`: the pieces (including the rook of a castle,
+    /// the pawn taken en passant and the promoted piece), castling rights, en-passant
+    /// target, halfmove clock, fullmove number and side to move. Every caller that plays
+    /// a move goes through here so the fields can't drift apart.
+    pub fn apply_move(position: &mut Position, mv: &Move) -> Result<()> {
+        let from = mv.from as usize;
+        let to = mv.to as usize;
+        let mover_is_white = position.white_to_move;
+
+        if mv.is_en_passant {
+            position.squares[en_passant_victim_square(mv.from, mv.to) as usize] = Piece::Empty;
+        }
+
+        if mv.is_castle {
+            let (rook_from, rook_to) = match to {
+                6 => (7, 5),
+                2 => (0, 3),
+                62 => (63, 61),
+                58 => (56, 59),
+                _ => return Err(ChessError::IllegalMove.into()),
+            };
+            position.squares[rook_to] = position.squares[rook_from];
+            position.squares[rook_from] = Piece::Empty;
+        }
+
+        let moved = if mv.is_promotion {
+            mv.promotion_piece.ok_or(ChessError::InvalidPiece)?
+        } else {
+            position.squares[from]
+        };
+        position.squares[to] = moved;
+        position.squares[from] = Piece::Empty;
+        position.white_to_move = !mover_is_white;
+
+        // Moving a king or rook off its home square, or capturing a rook on it, loses the right
+        revoke_castling_rights(position, mv.from);
+        revoke_castling_rights(position, mv.to);
+
+        // A double pawn push leaves the skipped square open to en passant for one ply
+        let is_pawn = matches!(mv.piece, Piece::WhitePawn | Piece::BlackPawn);
+        position.en_passant_square = if is_pawn && from.abs_diff(to) == 16 {
+            Some(((from + to) / 2) as u8)
+        } else {
+            None
+        };
+
+        position.halfmove_clock = if is_pawn || mv.captured_piece.is_some() || mv.is_en_passant {
+            0
+        } else {
+            position.halfmove_clock.saturating_add(1)
+        };
+        if !mover_is_white {
+            position.fullmove_number = position.fullmove_number.saturating_add(1);
+        }
+
+        Ok(())
+    }
+
+    fn revoke_castling_rights(position: &mut Position, square: u8) {
+        match square {
+            0 => position.white_castle_queenside = false,
+            7 => position.white_castle_kingside = false,
+            4 => {
+                position.white_castle_kingside = false;
+                position.white_castle_queenside = false;
+            },
+            56 => position.black_castle_queenside = false,
+            63 => position.black_castle_kingside = false,
+            60 => {
+                position.black_castle_kingside = false;
+                position.black_castle_queenside = false;
+            },
+            _ => {},
+        }
     }
     
-    fn parse_square(square: &str) -> Result<u8> {
-        if square.len() != 2 {
+    /// Algebraic name of a 0 (a1) to 63 (h8) square
+    pub fn square_name(square: u8) -> String {
+        let file = (b'a' + square % 8) as char;
+        let rank = (b'1' + square / 8) as char;
+        [file, rank].iter().collect()
+    }
+
+    /// Index of an algebraic square name: exactly two ASCII bytes, file then rank
+    pub fn parse_square(square: &str) -> Result<u8> {
+        // Checked in bytes, so a multi-byte character can't pass as one of the two
+        let &[file, rank] = square.as_bytes() else {
+            return Err(ChessError::InvalidSquareFormat.into());
+        };
+        if !file.is_ascii() || !rank.is_ascii() {
             return Err(ChessError::InvalidSquareFormat.into());
         }
         
-        let file = square.chars().nth(0).unwrap() as u8 - b'a';
-        let rank = square.chars().nth(1).unwrap() as u8 - b'1';
+        let file = file.wrapping_sub(b'a');
+        let rank = rank.wrapping_sub(b'1');
         
         if file > 7 || rank > 7 {
             return Err(ChessError::InvalidSquareCoordinates.into());
@@ -95,7 +848,7 @@ mod chess_validation {
         Ok(rank * 8 + file)
     }
     
-    fn parse_piece(piece: &str) -> Result<Piece> {
+    pub fn parse_piece(piece: &str) -> Result<Piece> {
         match piece {
             "P" => Ok(Piece::WhitePawn),
             "R" => Ok(Piece::WhiteRook),
@@ -113,16 +866,22 @@ mod chess_validation {
         }
     }
     
+    /// Whether `piece` standing on `from` can reach `to` by its own movement: rooks,
+    /// bishops and queens along lines clear of other pieces, knight jumps, single king
+    /// steps or a castle, and pawn pushes (two squares from the starting rank) or diagonal
+    /// captures, including en passant. Never onto a piece of the mover's own colour. Whether
+    /// the move leaves the king in check is left to `would_move_expose_king`.
     fn is_legal_move(from: u8, to: u8, piece: Piece, position: &Position) -> bool {
-        // Basic move validation logic
-        // This is a simplified version - in production, implement full chess rules
-        true // Placeholder - implement full chess validation
+        let mut reachable = Vec::new();
+        pseudo_legal_moves(position, from, piece, &mut reachable);
+        reachable.iter().any(|mv| mv.to == to)
     }
     
-    fn would_move_expose_king(from: u8, to: u8, position: &Position) -> bool {
-        // Check if move would expose king to check
-        // This is a simplified version - implement full check detection
-        false // Placeholder - implement full check detection
+    /// Whether playing `mv` leaves the mover's own king attacked: the king stepping onto an
+    /// attacked square, a pinned piece leaving the line it shields, or a move that fails to
+    /// answer a check already on the board
+    fn would_move_expose_king(mv: &Move, position: &Position) -> bool {
+        !leaves_king_safe(position, mv)
     }
 }
 
@@ -133,49 +892,15 @@ fn is_impossible_move(from: &str, to: &str, piece: &str) -> bool {
         return true; // Can't move to same square
     }
     
-    // Validate square format
-    if from.len() != 2 || to.len() != 2 {
-        return true;
-    }
-    
-    // Check if squares are valid chess coordinates
-    let from_file = from.chars().nth(0).unwrap() as u8;
-    let from_rank = from.chars().nth(1).unwrap() as u8;
-    let to_file = to.chars().nth(0).unwrap() as u8;
-    let to_rank = to.chars().nth(1).unwrap() as u8;
-    
-    if from_file < b'a' || from_file > b'h' || from_rank < b'1' || from_rank > b'8' {
-        return true;
-    }
-    
-    if to_file < b'a' || to_file > b'h' || to_rank < b'1' || to_rank > b'8' {
-        return true;
-    }
-    
-    false
+    // Both squares must name real board coordinates
+    chess_validation::parse_square(from).is_err() || chess_validation::parse_square(to).is_err()
 }
 
-fn is_suspicious_move_pattern(game_escrow: &GameEscrow) -> bool {
-    // Check for suspicious patterns like:
-    // - Too many moves in short time
-    // - Impossible move sequences
-    // - Unusual time patterns
-    
-    if game_escrow.move_history.len() < 3 {
-        return false;
-    }
-    
-    let recent_moves = &game_escrow.move_history[game_escrow.move_history.len().saturating_sub(3)..];
-    
-    // Check for suspicious time patterns (moves too fast)
-    for i in 1..recent_moves.len() {
-        let time_diff = recent_moves[i].timestamp - recent_moves[i-1].timestamp;
-        if time_diff < 1 { // Less than 1 second between moves
-            return true;
-        }
-    }
-    
-    false
+fn is_suspicious_move_pattern(game_escrow: &GameEscrow, time_spent: u64) -> bool {
+    // A single move reported faster than the time control allows. What counts as too
+    // fast depends on the time control: sub-second moves are normal in bullet. Bursts
+    // of such moves are tracked separately, in `GameEscrow::record_think_time`.
+    time_spent < game_escrow.suspicious_move_interval_ms as u64
 }
 
 #[program]
@@ -187,59 +912,314 @@ pub mod chess_escrow {
         ctx: Context<InitializeGame>, 
         room_id: String,
         stake_amount: u64,
-        time_limit_seconds: i64
+        time_limit_seconds: i64,
+        options: GameOptions
     ) -> Result<()> {
         require!(room_id.len() <= 32, ChessError::RoomIdTooLong);
-        require!(stake_amount > 0, ChessError::InvalidStakeAmount);
+        if options.free_game {
+            // Nothing is staked, on either side
+            require!(
+                stake_amount == 0
+                    && options.cross_asset_stake.is_none()
+                    && options.token_stake_mint.is_none(),
+                ChessError::InvalidStakeAmount
+            );
+        } else {
+            require!(stake_amount > 0, ChessError::InvalidStakeAmount);
+            // The SOL floor keeps the vault rent-exempt; token games never fund it
+            require!(
+                options.token_stake_mint.is_some()
+                    || stake_amount >= GameEscrow::min_stake(&Rent::get()?),
+                ChessError::StakeBelowMinimum
+            );
+        }
+        require!(
+            options.token_stake_mint.is_none() || options.cross_asset_stake.is_none(),
+            ChessError::StakeAssetMismatch
+        );
         require!(time_limit_seconds > 0, ChessError::InvalidTimeLimit);
-
-        let game_escrow = &mut ctx.accounts.game_escrow;
-        let clock = Clock::get()?;
-        
-        game_escrow.room_id = room_id;
-        game_escrow.player_white = *ctx.accounts.player.key;
-        game_escrow.player_black = Pubkey::default(); // Will be set when second player joins
-        game_escrow.stake_amount = stake_amount;
-        game_escrow.total_deposited = 0;
-        game_escrow.game_state = GameState::WaitingForPlayers;
-        game_escrow.winner = GameWinner::None;
-        game_escrow.created_at = clock.unix_timestamp;
-        game_escrow.started_at = 0;
-        game_escrow.finished_at = 0;
-        game_escrow.time_limit_seconds = time_limit_seconds;
-        game_escrow.fee_collector = *ctx.accounts.fee_collector.key;
-        game_escrow.white_deposited = false;
-        game_escrow.black_deposited = false;
-        game_escrow.move_count = 0;
-        game_escrow.last_move_time = 0;
-        
-        // Initialize enhanced features
-        game_escrow.time_control = TimeControl {
-            initial_time: time_limit_seconds as u64,
-            increment: 0,
-            delay: 0,
-            time_control_type: TimeControlType::Custom,
-        };
-        game_escrow.position_hash = [0u8; 32];
-        game_escrow.move_history = Vec::new();
+        require!(
+            options.move_history_capacity <= GameEscrow::MAX_MOVE_HISTORY_CAPACITY,
+            ChessError::MoveHistoryCapacityTooLarge
+        );
+        if let Some(cross_asset) = &options.cross_asset_stake {
+            require!(cross_asset.amount > 0, ChessError::InvalidStakeAmount);
+        }
+        require!(
+            options.winnings_lock_seconds == 0 || options.cross_asset_stake.is_some(),
+            ChessError::WinningsLockNeedsTokenStake
+        );
+        require!(
+            options.winnings_lock_seconds <= GameEscrow::MAX_WINNINGS_LOCK_SECONDS,
+            ChessError::WinningsLockTooLong
+        );
+        // The bond prepays part of Black's SOL stake, so it can't exceed it, and the
+        // joiner must be the one playing Black
+        require!(
+            options.griefing_bond <= stake_amount
+                && (options.griefing_bond == 0
+                    || (options.cross_asset_stake.is_none()
+                        && options.token_stake_mint.is_none()
+                        && options.creator_color == CreatorColor::White)),
+            ChessError::InvalidGriefingBond
+        );
+        require!(
+            options.metadata.as_ref().map_or(0, |metadata| metadata.len()) <= GameEscrow::MAX_METADATA_LEN,
+            ChessError::MetadataTooLong
+        );
+        require!(
+            options.draw_split_white_bps as u32 + options.draw_split_black_bps as u32 == 10_000,
+            ChessError::InvalidDrawSplit
+        );
+        require!(options.audit_rate_pct <= 100, ChessError::InvalidAuditRate);
+        // A consolation returns at most half the pot. Cross-asset games pay the token leg
+        // to a single side, so they can only be winner-take-all.
+        if let PayoutPolicy::ConsolationSplit { loser_bps } = options.payout_policy {
+            require!(
+                (1..=GameEscrow::MAX_CONSOLATION_BPS).contains(&loser_bps)
+                    && options.cross_asset_stake.is_none(),
+                ChessError::InvalidPayoutPolicy
+            );
+        }
+        require!(
+            options.fee_bps.unwrap_or(GameEscrow::DEFAULT_FEE_BPS) <= GameEscrow::MAX_FEE_BPS,
+            ChessError::InvalidFeeBps
+        );
+        let config = load_config(&ctx.accounts.config)?;
+        if let (Some(stake), Some(config)) = (&options.cross_asset_stake, &config) {
+            require!(config.is_mint_allowed(&stake.mint), ChessError::MintNotAllowed);
+        }
+        if let (Some(mint), Some(config)) = (&options.token_stake_mint, &config) {
+            require!(config.is_mint_allowed(mint), ChessError::MintNotAllowed);
+        }
+        require_not_paused(config.as_ref())?;
+        if let (Some(fee_collector), Some(config)) = (&ctx.accounts.fee_collector, &config) {
+            require!(
+                !config.enforce_fee_collector || fee_collector.key() == config.fee_collector,
+                ChessError::FeeCollectorNotAllowed
+            );
+        }
+        // The config's stake bounds are in lamports, so token games aren't held to them
+        if let Some(config) = &config {
+            if !options.free_game && options.token_stake_mint.is_none() {
+                require!(stake_amount >= config.min_stake, ChessError::StakeBelowConfigMinimum);
+                require!(
+                    config.max_stake == 0 || stake_amount <= config.max_stake,
+                    ChessError::StakeAboveConfigMaximum
+                );
+            }
+        }
+        if options.is_rated {
+            let min_initial_time = config
+                .as_ref()
+                .map_or(0, |config| config.min_rated_initial_time);
+            require!(
+                time_limit_seconds as u64 >= min_initial_time as u64,
+                ChessError::TimeControlTooFastForRated
+            );
+        }
+        // Phases must start after move 1 at the earliest, in strictly increasing order
+        require!(
+            options.time_control_phases.len() <= GameEscrow::MAX_TIME_CONTROL_PHASES
+                && options.time_control_phases.iter().all(|phase| phase.after_move > 0 && phase.added_seconds > 0)
+                && options.time_control_phases.windows(2).all(|pair| pair[0].after_move < pair[1].after_move),
+            ChessError::InvalidTimeControlPhases
+        );
+
+        // A random seat comes from the latest slot hash, which the creator can't know
+        // when they sign, mixed with the room so games created together can differ
+        let creator_is_white = match options.creator_color {
+            CreatorColor::White => true,
+            CreatorColor::Black => false,
+            CreatorColor::Random => {
+                let slot_hashes = ctx
+                    .accounts
+                    .slot_hashes
+                    .as_ref()
+                    .ok_or(ChessError::SlotHashesRequired)?;
+                let slot_hash = latest_slot_hash(slot_hashes)?;
+                let draw = anchor_lang::solana_program::hash::hashv(&[&slot_hash, room_id.as_bytes()]);
+                draw.to_bytes()[0] & 1 == 0
+            },
+        };
+
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        let clock = Clock::get()?;
+        
+        game_escrow.room_id = room_id;
+        // The other seat is filled when the second player joins
+        let (white, black) = if creator_is_white {
+            (*ctx.accounts.player.key, Pubkey::default())
+        } else {
+            (Pubkey::default(), *ctx.accounts.player.key)
+        };
+        game_escrow.player_white = white;
+        game_escrow.player_black = black;
+        game_escrow.stake_amount = stake_amount;
+        game_escrow.total_deposited = 0;
+        game_escrow.game_state = GameState::WaitingForPlayers;
+        game_escrow.winner = GameWinner::None;
+        game_escrow.created_at = clock.unix_timestamp;
+        game_escrow.started_at = 0;
+        game_escrow.finished_at = 0;
+        game_escrow.time_limit_seconds = time_limit_seconds;
+        // An explicit collector wins; otherwise the game inherits the program-wide default
+        game_escrow.fee_collector = match (&ctx.accounts.fee_collector, &config) {
+            (Some(fee_collector), _) => fee_collector.key(),
+            (None, Some(config)) => config.fee_collector,
+            (None, None) => return Err(ChessError::FeeCollectorMissing.into()),
+        };
+        game_escrow.white_deposited = false;
+        game_escrow.black_deposited = false;
+        game_escrow.move_count = 0;
+        game_escrow.last_move_time = 0;
+        
+        // Initialize enhanced features
+        game_escrow.time_control = TimeControl {
+            initial_time: time_limit_seconds as u64,
+            increment: options.increment_seconds as u64,
+            delay: options.delay_seconds as u64,
+            time_control_type: options.time_control_type.clone(),
+        };
+        game_escrow.position_hash = [0u8; 32];
+        game_escrow.move_history = Vec::new();
         game_escrow.anti_cheat_flags = 0;
         game_escrow.rating_white = 1500;
         game_escrow.rating_black = 1500;
         game_escrow.tournament_id = None;
         game_escrow.game_flags = GameFlags {
             is_tournament_game: false,
-            is_rated: false,
+            is_rated: options.is_rated,
+            auto_draw_on_repetition: options.auto_draw_on_repetition,
             allow_draw_offers: true,
             allow_resignation: true,
-            require_move_validation: true,
+            require_move_validation: !options.optimistic_validation,
             enable_anti_cheat: true,
         };
+        game_escrow.anti_cheat_forfeit_threshold = options.anti_cheat_forfeit_threshold;
+        game_escrow.white_flag_count = 0;
+        game_escrow.black_flag_count = 0;
+        game_escrow.funds_distributed = false;
+        game_escrow.think_time_avg_ms = [0; 2];
+        game_escrow.fast_move_streak = [0; 2];
+        game_escrow.move_history_capacity = options.move_history_capacity;
+        game_escrow.suspicious_move_interval_ms = if options.suspicious_move_interval_ms > 0 {
+            options.suspicious_move_interval_ms
+        } else {
+            options.time_control_type.suspicious_move_interval_ms()
+        };
+        game_escrow.board = chess_validation::starting_board();
+        game_escrow.emit_board_state = options.emit_board_state;
+        game_escrow.token_mint = options
+            .cross_asset_stake
+            .as_ref()
+            .map(|stake| stake.mint)
+            .or(options.token_stake_mint);
+        game_escrow.token_stake_amount = match (&options.cross_asset_stake, options.token_stake_mint) {
+            (Some(stake), _) => stake.amount,
+            (None, Some(_)) => stake_amount,
+            (None, None) => 0,
+        };
+        game_escrow.token_game = options.token_stake_mint.is_some();
+        game_escrow.pull_payouts = options.pull_payouts;
+        game_escrow.claimable = [0; 2];
+        game_escrow.timeout_grace_seconds = options
+            .timeout_grace_seconds
+            .unwrap_or(GameEscrow::DEFAULT_TIMEOUT_GRACE_SECONDS);
+        game_escrow.dispute_window_seconds = options.dispute_window_seconds;
+        game_escrow.disputed_by = None;
+        game_escrow.payout_policy = options.payout_policy;
+        game_escrow.token_total_deposited = 0;
+        game_escrow.token_funds_distributed = false;
+        game_escrow.sealed_move_hash = [0u8; 32];
+        game_escrow.adjourned_at = 0;
+        game_escrow.early_resignation_moves = options.early_resignation_moves;
+        game_escrow.en_passant_square = None;
+        game_escrow.white_time_remaining = time_limit_seconds as u64;
+        game_escrow.black_time_remaining = if options.armageddon {
+            (time_limit_seconds as u64 * GameEscrow::ARMAGEDDON_BLACK_TIME_PCT / 100).max(1)
+        } else {
+            time_limit_seconds as u64
+        };
+        game_escrow.armageddon = options.armageddon;
+        game_escrow.position_keys = Vec::new();
+        game_escrow.white_active_tracked = false;
+        game_escrow.black_active_tracked = false;
+        game_escrow.double_flag_rule = options.double_flag_rule;
+        game_escrow.emit_material_events = options.emit_material_events;
+        game_escrow.min_moves_before_result = options.min_moves_before_result;
+        game_escrow.white_berserk = false;
+        game_escrow.black_berserk = false;
+        game_escrow.draw_claims = options.draw_claims;
+        game_escrow.halfmove_clock = 0;
+        game_escrow.previous_board = game_escrow.board;
+        game_escrow.previous_en_passant_square = None;
+        game_escrow.previous_halfmove_clock = 0;
+        game_escrow.castling_rights = chess_validation::ALL_CASTLING_RIGHTS;
+        game_escrow.previous_castling_rights = chess_validation::ALL_CASTLING_RIGHTS;
+        game_escrow.forfeit_on_illegal_move = options.forfeit_on_illegal_move;
+        game_escrow.variant = options.variant;
+        game_escrow.white_checks = 0;
+        game_escrow.black_checks = 0;
+        game_escrow.rake_mode = options.rake_mode;
+        game_escrow.fee_bps = options.fee_bps.unwrap_or(GameEscrow::DEFAULT_FEE_BPS);
+        game_escrow.anti_cheat_review_threshold = options.anti_cheat_review_threshold;
+        game_escrow.review_required = false;
+        game_escrow.side_bet_totals = [0; 3];
+        game_escrow.open_side_bets = 0;
+        game_escrow.winnings_lock_seconds = options.winnings_lock_seconds;
+        game_escrow.locked_winnings = 0;
+        game_escrow.winnings_unlock_at = 0;
+        game_escrow.max_takebacks = options
+            .max_takebacks_per_player
+            .unwrap_or(GameEscrow::DEFAULT_MAX_TAKEBACKS);
+        game_escrow.white_takebacks = 0;
+        game_escrow.black_takebacks = 0;
+        game_escrow.takeback_requested_by = None;
+        game_escrow.can_undo_last_move = false;
+        game_escrow.increment_start_move = options.increment_start_move;
+        game_escrow.auto_draw_offer_on_disconnect = options.auto_draw_offer_on_disconnect;
+        game_escrow.white_disconnected = false;
+        game_escrow.black_disconnected = false;
+        game_escrow.draw_offered_by = None;
+        game_escrow.draw_offer_automatic = false;
+        game_escrow.draw_offered_at = 0;
+        game_escrow.time_control_phases = options.time_control_phases;
+        game_escrow.rent_beneficiary = *ctx.accounts.player.key;
+        game_escrow.metadata = options.metadata;
+        game_escrow.white_timing = ThinkTimeTotals::default();
+        game_escrow.black_timing = ThinkTimeTotals::default();
+        game_escrow.deposit_match_window_seconds = options.deposit_match_window_seconds;
+        game_escrow.griefing_bond = options.griefing_bond;
+        game_escrow.black_bond_posted = false;
+        game_escrow.first_deposit_at = 0;
+        game_escrow.spectator_chat = options.spectator_chat;
+        game_escrow.chat_hashes = Vec::new();
+        game_escrow.chat_hash_count = 0;
+        game_escrow.rated_pair_cooldown_seconds = config
+            .as_ref()
+            .map_or(0, |config| config.rated_pair_cooldown_seconds);
+        game_escrow.min_move_interval_ms = options.min_move_interval_ms;
+        game_escrow.strict_move_interval = options.strict_move_interval;
+        game_escrow.draw_split_white_bps = options.draw_split_white_bps;
+        game_escrow.draw_split_black_bps = options.draw_split_black_bps;
+        game_escrow.white_auto_resign_below_seconds = 0;
+        game_escrow.black_auto_resign_below_seconds = 0;
+        game_escrow.compact_move_events = options.compact_move_events;
+        game_escrow.audit_rate_pct = options.audit_rate_pct;
+        game_escrow.low_time_warning_seconds = options.low_time_warning_seconds;
+        game_escrow.low_time_warned = [false; 2];
+        game_escrow.fullmove_number = 1;
+        game_escrow.set_turn(true);
+        game_escrow.rematch_proposed_by = None;
         
         emit!(GameCreated {
             room_id: game_escrow.room_id.clone(),
             player_white: game_escrow.player_white,
+            player_black: game_escrow.player_black,
             stake_amount,
             created_at: clock.unix_timestamp,
+            metadata: game_escrow.metadata.clone(),
         });
         
         Ok(())
@@ -250,29 +1230,95 @@ pub mod chess_escrow {
         let game_escrow = &mut ctx.accounts.game_escrow;
         let clock = Clock::get()?;
         
+        require_not_paused(load_config(&ctx.accounts.config)?.as_ref())?;
+        // Checked before the state so a losing racer learns the seat was taken
+        require!(
+            game_escrow.player_white == Pubkey::default()
+                || game_escrow.player_black == Pubkey::default(),
+            ChessError::GameAlreadyHasOpponent
+        );
         require!(
             game_escrow.game_state == GameState::WaitingForPlayers,
             ChessError::GameNotWaitingForPlayers
         );
         require!(
-            game_escrow.player_white != *ctx.accounts.player.key,
+            game_escrow.seated_player() != *ctx.accounts.player.key,
             ChessError::CannotPlayAgainstSelf
         );
+        // Rated games write both ratings at the finish, so the account must exist up front
+        if game_escrow.game_flags.is_rated {
+            require!(
+                ctx.accounts.player_rating.is_some(),
+                ChessError::RatingAccountMissing
+            );
+        }
         
-        game_escrow.player_black = *ctx.accounts.player.key;
+        // Throttle rated rematches between the same two wallets
+        throttle_rated_pair(
+            game_escrow,
+            ctx.accounts.pair_history.as_mut(),
+            *ctx.accounts.player.key,
+            clock.unix_timestamp,
+        )?;
+
+        // Black backs their seat with the bond, which later counts towards their stake
+        if game_escrow.griefing_bond > 0 {
+            let (Some(game_vault), Some(system_program)) =
+                (&ctx.accounts.game_vault, &ctx.accounts.system_program)
+            else {
+                return Err(ChessError::BondAccountsMissing.into());
+            };
+            anchor_lang::system_program::transfer(
+                CpiContext::new(
+                    system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.player.to_account_info(),
+                        to: game_vault.to_account_info(),
+                    },
+                ),
+                game_escrow.griefing_bond,
+            )?;
+            game_escrow.black_bond_posted = true;
+        }
+
+        // The joiner takes whichever seat the creator left open
+        if game_escrow.player_white == Pubkey::default() {
+            game_escrow.player_white = *ctx.accounts.player.key;
+        } else {
+            game_escrow.player_black = *ctx.accounts.player.key;
+        }
         game_escrow.game_state = GameState::WaitingForDeposits;
+        // The creator may have deposited before anyone joined; the joiner's match window
+        // opens now
+        if game_escrow.white_deposited || game_escrow.black_deposited {
+            game_escrow.first_deposit_at = clock.unix_timestamp;
+        }
         
         emit!(PlayerJoined {
             room_id: game_escrow.room_id.clone(),
+            player_white: game_escrow.player_white,
             player_black: game_escrow.player_black,
             joined_at: clock.unix_timestamp,
         });
+
+        // A free game has nothing to deposit, so it starts as soon as both seats are taken
+        if game_escrow.stake_amount == 0 {
+            game_escrow.white_deposited = true;
+            game_escrow.black_deposited = true;
+            let (white_stats, black_stats) = if game_escrow.player_white == *ctx.accounts.player.key {
+                (&ctx.accounts.joiner_stats, &ctx.accounts.black_stats)
+            } else {
+                (&ctx.accounts.white_stats, &ctx.accounts.joiner_stats)
+            };
+            start_game_with_stats(game_escrow, white_stats, black_stats)?;
+        }
         
         Ok(())
     }
 
     /// Player deposits their stake
     pub fn deposit_stake(ctx: Context<DepositStake>) -> Result<()> {
+        require_not_paused(load_config(&ctx.accounts.config)?.as_ref())?;
         let game_escrow = &mut ctx.accounts.game_escrow;
         let player_key = *ctx.accounts.player.key;
         
@@ -289,23 +1335,37 @@ pub mod chess_escrow {
 
         // Check if this player has already deposited
         let is_white = player_key == game_escrow.player_white;
+        // Token games take both stakes through deposit_token_stake
+        require!(!game_escrow.token_game, ChessError::StakeAssetMismatch);
         if is_white {
             require!(!game_escrow.white_deposited, ChessError::AlreadyDeposited);
         } else {
             require!(!game_escrow.black_deposited, ChessError::AlreadyDeposited);
+            // Black stakes through deposit_token_stake in cross-asset games
+            require!(game_escrow.token_mint.is_none(), ChessError::StakeAssetMismatch);
         }
 
-        // Transfer stake to vault
-        anchor_lang::system_program::transfer(
-            CpiContext::new(
-                ctx.accounts.system_program.to_account_info(),
-                anchor_lang::system_program::Transfer {
-                    from: ctx.accounts.player.to_account_info(),
-                    to: ctx.accounts.game_vault.to_account_info(),
-                },
-            ),
-            game_escrow.stake_amount,
-        )?;
+        // Transfer stake to vault; a posted bond already covers part of Black's
+        let amount = if !is_white && game_escrow.black_bond_posted {
+            game_escrow.stake_amount - game_escrow.griefing_bond
+        } else {
+            game_escrow.stake_amount
+        };
+        if amount > 0 {
+            anchor_lang::system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.player.to_account_info(),
+                        to: ctx.accounts.game_vault.to_account_info(),
+                    },
+                ),
+                amount,
+            )?;
+        }
+        if !game_escrow.white_deposited && !game_escrow.black_deposited {
+            game_escrow.first_deposit_at = Clock::get()?.unix_timestamp;
+        }
 
         // Update deposit status
         if is_white {
@@ -317,17 +1377,7 @@ pub mod chess_escrow {
         game_escrow.total_deposited += game_escrow.stake_amount;
 
         // Start game if both players have deposited
-        if game_escrow.white_deposited && game_escrow.black_deposited {
-            let clock = Clock::get()?;
-            game_escrow.game_state = GameState::InProgress;
-            game_escrow.started_at = clock.unix_timestamp;
-            game_escrow.last_move_time = clock.unix_timestamp;
-            
-            emit!(GameStarted {
-                room_id: game_escrow.room_id.clone(),
-                started_at: clock.unix_timestamp,
-            });
-        }
+        start_game_with_stats(game_escrow, &ctx.accounts.white_stats, &ctx.accounts.black_stats)?;
 
         emit!(StakeDeposited {
             room_id: game_escrow.room_id.clone(),
@@ -338,23 +1388,75 @@ pub mod chess_escrow {
         Ok(())
     }
 
-    /// Record a move (for anti-cheat and timing)
+    /// Deposit a token stake: Black's in a cross-asset game, either player's in a token game
+    pub fn deposit_token_stake(ctx: Context<DepositTokenStake>) -> Result<()> {
+        require_not_paused(load_config(&ctx.accounts.config)?.as_ref())?;
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        let player_key = *ctx.accounts.player.key;
+
+        require!(
+            game_escrow.game_state == GameState::WaitingForDeposits,
+            ChessError::InvalidGameStateForDeposit
+        );
+        let is_white = player_key == game_escrow.player_white;
+        require!(
+            player_key == game_escrow.player_black || (is_white && game_escrow.token_game),
+            ChessError::StakeAssetMismatch
+        );
+        let already_deposited = if is_white {
+            game_escrow.white_deposited
+        } else {
+            game_escrow.black_deposited
+        };
+        require!(!already_deposited, ChessError::AlreadyDeposited);
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.player_token_account.to_account_info(),
+                    to: ctx.accounts.vault_token_account.to_account_info(),
+                    authority: ctx.accounts.player.to_account_info(),
+                },
+            ),
+            game_escrow.token_stake_amount,
+        )?;
+
+        if !game_escrow.white_deposited && !game_escrow.black_deposited {
+            game_escrow.first_deposit_at = Clock::get()?.unix_timestamp;
+        }
+        if is_white {
+            game_escrow.white_deposited = true;
+        } else {
+            game_escrow.black_deposited = true;
+        }
+        game_escrow.token_total_deposited += game_escrow.token_stake_amount;
+
+        start_game_with_stats(game_escrow, &ctx.accounts.white_stats, &ctx.accounts.black_stats)?;
+
+        emit!(StakeDeposited {
+            room_id: game_escrow.room_id.clone(),
+            player: player_key,
+            amount: game_escrow.token_stake_amount,
+        });
+
+        Ok(())
+    }
+
+    /// Record a move (for anti-cheat and timing). `expected_move_count` is the number of
+    /// moves the client saw played; a delayed or replayed submission no longer matches it.
     pub fn record_move(
         ctx: Context<RecordMove>,
-        move_notation: String,
-        game_position_hash: [u8; 32],
-        from_square: String,
-        to_square: String,
-        piece: String,
-        captured_piece: Option<String>,
+        submission: MoveSubmission,
         time_spent: u64,
-        is_check: bool,
-        is_checkmate: bool,
-        is_castle: bool,
-        is_en_passant: bool,
-        is_promotion: bool,
-        promotion_piece: Option<String>
+        expected_move_count: u32,
     ) -> Result<()> {
+        grow_account(
+            ctx.accounts.game_escrow.to_account_info(),
+            GameEscrow::space_for_next_move(&ctx.accounts.game_escrow),
+            &ctx.accounts.player,
+            &ctx.accounts.system_program,
+        )?;
         let game_escrow = &mut ctx.accounts.game_escrow;
         let player_key = *ctx.accounts.player.key;
         let clock = Clock::get()?;
@@ -368,944 +1470,9582 @@ pub mod chess_escrow {
             player_key == game_escrow.player_white || player_key == game_escrow.player_black,
             ChessError::UnauthorizedPlayer
         );
+        require!(expected_move_count == game_escrow.move_count, ChessError::StaleMove);
 
-        require!(move_notation.len() <= 10, ChessError::MoveNotationTooLong);
+        play_move(game_escrow, player_key, submission, time_spent, &clock, false)
+    }
 
-        // Check if it's the player's turn
-        let is_white_turn = game_escrow.move_count % 2 == 0;
-        let is_white_player = player_key == game_escrow.player_white;
-        
-        require!(is_white_turn == is_white_player, ChessError::NotPlayerTurn);
+    /// Record several moves at once, such as those a client queued while disconnected. Both
+    /// players sign, and each move is checked in order exactly as `record_move` checks it,
+    /// so one bad move rejects the whole batch. Later moves land in the first one's second:
+    /// the time elapsed since the last recorded move is charged to the first mover only.
+    pub fn record_moves_batch(
+        ctx: Context<RecordMovesBatch>,
+        moves: Vec<BatchedMove>,
+        expected_move_count: u32,
+    ) -> Result<()> {
+        // White fronts the rent for the added history records
+        grow_account(
+            ctx.accounts.game_escrow.to_account_info(),
+            GameEscrow::space_for_moves(
+                &ctx.accounts.game_escrow,
+                moves.len().min(GameEscrow::MAX_BATCH_MOVES),
+            ),
+            &ctx.accounts.player_white,
+            &ctx.accounts.system_program,
+        )?;
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        let clock = Clock::get()?;
+
+        require!(
+            !moves.is_empty() && moves.len() <= GameEscrow::MAX_BATCH_MOVES,
+            ChessError::InvalidBatchSize
+        );
+        require!(expected_move_count == game_escrow.move_count, ChessError::StaleMove);
 
-        // Enhanced time control validation
-        if game_escrow.time_control.initial_time > 0 {
-            let time_elapsed = clock.unix_timestamp - game_escrow.last_move_time;
-            let max_time = (game_escrow.time_control.initial_time + game_escrow.time_control.increment) as i64;
+        for (index, BatchedMove { submission, time_spent }) in moves.into_iter().enumerate() {
+            // A move after a mate or a forfeit ended the game voids the batch
             require!(
-                time_elapsed <= max_time,
-                ChessError::MoveTimeExceeded
+                game_escrow.game_state == GameState::InProgress,
+                ChessError::GameNotInProgress
             );
+            let player_key = game_escrow.side_to_move();
+            play_move(game_escrow, player_key, submission, time_spent, &clock, index > 0)?;
         }
+        Ok(())
+    }
 
-        // Anti-cheat validation if enabled
-        if game_escrow.game_flags.enable_anti_cheat {
-            // Validate move format
-            require!(from_square.len() == 2 && to_square.len() == 2, ChessError::InvalidMoveFormat);
-            
-            // Check for impossible moves (basic validation)
-            if is_impossible_move(&from_square, &to_square, &piece) {
-                return Err(ChessError::ImpossibleMove.into());
-            }
-            
-            // Check for suspicious patterns
-            if is_suspicious_move_pattern(game_escrow) {
-                game_escrow.anti_cheat_flags |= 1; // Flag suspicious activity
-            }
-        }
-
-        // Create move record
-        let move_record = MoveRecord {
-            move_number: game_escrow.move_count + 1,
-            from_square,
-            to_square,
-            piece,
-            captured_piece,
-            move_notation: move_notation.clone(),
-            position_hash: game_position_hash,
-            timestamp: clock.unix_timestamp,
-            time_spent,
-            is_check,
-            is_checkmate,
-            is_castle,
-            is_en_passant,
-            is_promotion,
-            promotion_piece,
-        };
-
-        // Add to move history
-        game_escrow.move_history.push(move_record);
+    /// Adjourn the game: the player to move commits to a hidden move and both clocks stop
+    pub fn seal_move(ctx: Context<SealMove>, sealed_move_hash: [u8; 32]) -> Result<()> {
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        let player_key = *ctx.accounts.player.key;
+        let clock = Clock::get()?;
 
-        game_escrow.move_count += 1;
-        game_escrow.last_move_time = clock.unix_timestamp;
-        game_escrow.position_hash = game_position_hash;
+        require!(
+            game_escrow.game_state == GameState::InProgress,
+            ChessError::GameNotInProgress
+        );
+        require!(
+            player_key == game_escrow.side_to_move(),
+            ChessError::NotPlayerTurn
+        );
+        require!(
+            !game_escrow.mover_flagged(clock.unix_timestamp),
+            ChessError::MoveTimeExceeded
+        );
 
-        // Check for game end conditions
-        if is_checkmate {
-            game_escrow.game_state = GameState::Finished;
-            game_escrow.winner = if is_white_player { GameWinner::White } else { GameWinner::Black };
-            game_escrow.finished_at = clock.unix_timestamp;
-        }
+        game_escrow.sealed_move_hash = sealed_move_hash;
+        game_escrow.adjourned_at = clock.unix_timestamp;
+        game_escrow.game_state = GameState::Paused;
 
-        emit!(MoveRecorded {
+        emit!(MoveSealed {
             room_id: game_escrow.room_id.clone(),
-            player: player_key,
+            sealed_by: player_key,
             move_count: game_escrow.move_count,
-            move_notation,
-            position_hash: game_position_hash,
-            timestamp: clock.unix_timestamp,
+            adjourned_at: clock.unix_timestamp,
         });
 
         Ok(())
     }
 
-    /// Declare game result and distribute funds
-    pub fn declare_result(
-        ctx: Context<DeclareResult>, 
-        winner: GameWinner,
-        reason: GameEndReason
+    /// Resume an adjourned game by revealing the sealed move, which is played immediately
+    pub fn resume_from_seal(
+        ctx: Context<ResumeFromSeal>,
+        submission: MoveSubmission,
+        salt: [u8; 32]
     ) -> Result<()> {
+        grow_account(
+            ctx.accounts.game_escrow.to_account_info(),
+            GameEscrow::space_for_next_move(&ctx.accounts.game_escrow),
+            &ctx.accounts.player,
+            &ctx.accounts.system_program,
+        )?;
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        let player_key = *ctx.accounts.player.key;
         let clock = Clock::get()?;
-        let finished_at = clock.unix_timestamp;
-        let room_id: String;
-        {
-            let game_escrow = &mut ctx.accounts.game_escrow;
-            
-            require!(
-                game_escrow.game_state == GameState::InProgress,
-                ChessError::GameNotInProgress
-            );
 
-            // Only players can declare results
-            let declarer = *ctx.accounts.player.key;
-            require!(
-                declarer == game_escrow.player_white || declarer == game_escrow.player_black,
-                ChessError::UnauthorizedPlayer
-            );
+        require!(game_escrow.game_state == GameState::Paused, ChessError::GameNotPaused);
+        require!(
+            player_key == game_escrow.side_to_move(),
+            ChessError::UnauthorizedPlayer
+        );
+        require!(
+            submission.seal_hash(&salt)? == game_escrow.sealed_move_hash,
+            ChessError::SealedMoveMismatch
+        );
+        submission.validate_bounds()?;
 
-            // Validate winner declaration
-            match winner {
-                GameWinner::White => {
-                    require!(
-                        (reason == GameEndReason::Resignation && declarer == game_escrow.player_black) ||
-                        (reason == GameEndReason::Timeout && declarer == game_escrow.player_white) ||
-                        (reason == GameEndReason::Checkmate && declarer == game_escrow.player_white),
-                        ChessError::InvalidWinnerDeclaration
-                    );
-                },
-                GameWinner::Black => {
-                    require!(
-                        (reason == GameEndReason::Resignation && declarer == game_escrow.player_white) ||
-                        (reason == GameEndReason::Timeout && declarer == game_escrow.player_black) ||
-                        (reason == GameEndReason::Checkmate && declarer == game_escrow.player_black),
-                        ChessError::InvalidWinnerDeclaration
-                    );
-                },
-                GameWinner::Draw => {
-                    // Both players must agree to a draw, or it's a stalemate
-                    require!(
-                        reason == GameEndReason::Agreement || reason == GameEndReason::Stalemate,
-                        ChessError::InvalidDrawDeclaration
-                    );
-                },
-                GameWinner::None => return Err(ChessError::InvalidWinnerDeclaration.into()),
-            }
+        // Think time stops at the seal; the adjournment itself is not charged
+        let think_time = game_escrow.adjourned_at - game_escrow.last_move_time;
+        let time_spent = think_time.max(0) as u64 * 1_000;
+        game_escrow.charge_mover_clock(think_time, time_spent)?;
+        game_escrow.game_state = GameState::InProgress;
+        game_escrow.sealed_move_hash = [0u8; 32];
+        game_escrow.adjourned_at = 0;
 
-            game_escrow.winner = winner.clone();
-            game_escrow.game_state = GameState::Finished;
-            game_escrow.finished_at = finished_at;
-            room_id = game_escrow.room_id.clone();
-        }
+        commit_move(game_escrow, player_key, submission, time_spent, clock.unix_timestamp)
+    }
 
-        // Distribute funds
-        ctx.accounts.distribute_funds(winner.clone(), ctx.bumps.game_vault)?;
+    /// Dry-run a candidate move through the same checks as `record_move` and emit
+    /// the verdict. Never mutates the game.
+    pub fn check_move_legal(ctx: Context<CheckMoveLegal>, candidate: CandidateMove) -> Result<()> {
+        let game_escrow = &ctx.accounts.game_escrow;
 
-        emit!(GameFinished {
-            room_id,
-            winner,
-            reason,
-            finished_at,
-        });
+        require!(
+            game_escrow.game_state == GameState::InProgress,
+            ChessError::GameNotInProgress
+        );
+
+        let verdict = candidate
+            .parse()
+            .and_then(|mv| chess_validation::check_move(&game_escrow.position(), &mv));
+        let reason_code = match verdict {
+            Ok(()) => 0,
+            Err(Error::AnchorError(error)) => error.error_code_number,
+            Err(Error::ProgramError(error)) => u64::from(error.program_error.clone()) as u32,
+        };
+
+        emit!(MoveLegality {
+            room_id: game_escrow.room_id.clone(),
+            move_count: game_escrow.move_count,
+            from_square: candidate.from_square,
+            to_square: candidate.to_square,
+            is_legal: reason_code == 0,
+            reason_code,
+        });
 
         Ok(())
     }
 
-    /// Handle timeout (can be called by anyone after time limit exceeded)
-    pub fn handle_timeout(ctx: Context<HandleTimeout>) -> Result<()> {
+    /// Emit whether the side to move is in check on the stored board. Never mutates the game.
+    pub fn is_in_check(ctx: Context<IsInCheck>) -> Result<()> {
+        let game_escrow = &ctx.accounts.game_escrow;
+        let position = game_escrow.position();
+
+        emit!(CheckStatus {
+            room_id: game_escrow.room_id.clone(),
+            move_count: game_escrow.move_count,
+            white_to_move: position.white_to_move,
+            in_check: position.in_check(),
+        });
+
+        Ok(())
+    }
+
+    /// Emit every legal move in the stored position, `LEGAL_MOVES_PER_EVENT` to an event.
+    /// A position without legal moves (mate or stalemate) emits one empty chunk. Never
+    /// mutates the game.
+    pub fn list_legal_moves(ctx: Context<ListLegalMoves>) -> Result<()> {
+        let game_escrow = &ctx.accounts.game_escrow;
+        let position = game_escrow.position();
+        let moves: Vec<LegalMove> = chess_validation::generate_legal_moves(&position)
+            .iter()
+            .map(|mv| LegalMove {
+                from: mv.from,
+                to: mv.to,
+                promotion: mv.promotion_piece.map(|piece| piece.code()),
+            })
+            .collect();
+
+        let total_moves = moves.len() as u16;
+        let total_chunks = moves.len().div_ceil(LegalMove::PER_EVENT).max(1) as u8;
+        for chunk in 0..total_chunks {
+            let start = chunk as usize * LegalMove::PER_EVENT;
+            let end = (start + LegalMove::PER_EVENT).min(moves.len());
+            emit!(LegalMoves {
+                room_id: game_escrow.room_id.clone(),
+                move_count: game_escrow.move_count,
+                chunk,
+                total_chunks,
+                total_moves,
+                moves: moves[start..end].to_vec(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Challenge the opponent's last move. It is re-validated against the position it was
+    /// played from; if illegal it is taken back (ending any result it produced) and, when
+    /// the game forfeits illegal moves, the offender loses.
+    pub fn challenge_last_move(ctx: Context<ChallengeLastMove>) -> Result<()> {
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        let challenger = *ctx.accounts.player.key;
         let clock = Clock::get()?;
-        let finished_at = clock.unix_timestamp;
-        let room_id: String;
-        let winner: GameWinner;
-        {
-            let game_escrow = &mut ctx.accounts.game_escrow;
-        
-            require!(
-                game_escrow.game_state == GameState::InProgress,
-                ChessError::GameNotInProgress
-            );
 
-            let time_elapsed = clock.unix_timestamp - game_escrow.last_move_time;
-            require!(
-                time_elapsed > game_escrow.time_limit_seconds,
-                ChessError::TimeNotExceeded
-            );
+        let last = game_escrow
+            .move_history
+            .last()
+            .cloned()
+            .ok_or(ChessError::NoMoveToChallenge)?;
+        require!(game_escrow.can_undo_last_move, ChessError::NoMoveToChallenge);
+        // A move that ended the game can be challenged until the pot is paid out
+        let finished_by_last_move = game_escrow.game_state == GameState::Finished
+            && !game_escrow.funds_distributed
+            && game_escrow.finished_at == last.timestamp;
+        require!(
+            game_escrow.game_state == GameState::InProgress || finished_by_last_move,
+            ChessError::GameNotInProgress
+        );
+        require!(
+            challenger == game_escrow.side_to_move(),
+            ChessError::NotPlayerTurn
+        );
+
+        let verdict = last
+            .candidate()
+            .parse()
+            .and_then(|mv| chess_validation::check_move(&game_escrow.previous_position(), &mv));
+        let reason_code = match verdict {
+            Ok(()) => return Err(ChessError::ChallengedMoveLegal.into()),
+            Err(Error::AnchorError(error)) => error.error_code_number,
+            Err(Error::ProgramError(error)) => u64::from(error.program_error.clone()) as u32,
+        };
+
+        // Take the move back; the offender is on move again with their clock restarted
+        game_escrow.undo_last_move(&last, clock.unix_timestamp);
 
-            // Determine winner based on whose turn it is (simplified)
-            // In a real implementation, you'd track whose turn it is
-            winner = if game_escrow.move_count % 2 == 0 {
-                GameWinner::Black // White's turn, so Black wins on timeout
+        let forfeited = game_escrow.forfeit_on_illegal_move;
+        emit!(MoveChallenged {
+            room_id: game_escrow.room_id.clone(),
+            challenger,
+            move_number: last.move_number,
+            reason_code,
+            forfeited,
+        });
+
+        if forfeited {
+            let winner = if challenger == game_escrow.player_white {
+                GameWinner::White
             } else {
-                GameWinner::White // Black's turn, so White wins on timeout
+                GameWinner::Black
             };
-
             game_escrow.winner = winner.clone();
             game_escrow.game_state = GameState::Finished;
-            game_escrow.finished_at = finished_at;
-            room_id = game_escrow.room_id.clone();
-        }
-
-        ctx.accounts.distribute_funds(winner.clone(), ctx.bumps.game_vault)?;
+            game_escrow.finished_at = clock.unix_timestamp;
 
-        emit!(GameFinished {
-            room_id,
-            winner,
-            reason: GameEndReason::Timeout,
-            finished_at,
-        });
+            emit!(GameFinished {
+                room_id: game_escrow.room_id.clone(),
+                winner,
+                reason: GameEndReason::Forfeit,
+                finished_at: clock.unix_timestamp,
+                white_total_time_ms: game_escrow.white_timing.total_ms,
+                black_total_time_ms: game_escrow.black_timing.total_ms,
+            });
+        }
 
         Ok(())
     }
 
-    /// Cancel game (only if not started or both players agree)
-    pub fn cancel_game(ctx: Context<CancelGame>) -> Result<()> {
+    /// Ask the opponent to let you take back the move you just played. Playing on instead
+    /// drops the request.
+    pub fn request_takeback(ctx: Context<Takeback>) -> Result<()> {
         let game_escrow = &mut ctx.accounts.game_escrow;
-        
+        let player_key = *ctx.accounts.player.key;
+
         require!(
-            game_escrow.game_state == GameState::WaitingForPlayers ||
-            game_escrow.game_state == GameState::WaitingForDeposits,
-            ChessError::CannotCancelStartedGame
+            game_escrow.game_state == GameState::InProgress,
+            ChessError::GameNotInProgress
         );
-
-        let player_key = *ctx.accounts.player.key;
         require!(
             player_key == game_escrow.player_white || player_key == game_escrow.player_black,
             ChessError::UnauthorizedPlayer
         );
+        require!(game_escrow.max_takebacks > 0, ChessError::TakebacksDisabled);
+        // Only the player who just moved can ask, and only while the snapshot is fresh
+        require!(player_key != game_escrow.side_to_move(), ChessError::NotPlayerTurn);
+        require!(
+            game_escrow.can_undo_last_move && !game_escrow.move_history.is_empty(),
+            ChessError::NoMoveToTakeBack
+        );
 
-        // Refund any deposited stakes
-        let vault_balance = ctx.accounts.game_vault.lamports();
-        if vault_balance > 0 {
-            // Refund logic here
-            let game_key = game_escrow.key();
-            let vault_bump = ctx.bumps.game_vault;
-            let bump_bytes = [vault_bump];
-            
-            let seeds = &[
-                b"vault".as_ref(),
-                game_key.as_ref(),
-                bump_bytes.as_ref(),
-            ];
-            let signer_seeds = &[&seeds[..]];
+        game_escrow.takeback_requested_by = Some(player_key);
 
-            // Refund white player if they deposited
-            if game_escrow.white_deposited {
-                anchor_lang::system_program::transfer(
-                    CpiContext::new_with_signer(
-                        ctx.accounts.system_program.to_account_info(),
-                        anchor_lang::system_program::Transfer {
-                            from: ctx.accounts.game_vault.to_account_info(),
-                            to: ctx.accounts.player_white.to_account_info(),
-                        },
-                        signer_seeds,
-                    ),
-                    game_escrow.stake_amount,
-                )?;
-            }
+        emit!(TakebackRequested {
+            room_id: game_escrow.room_id.clone(),
+            player: player_key,
+            move_number: game_escrow.move_count,
+        });
 
-            // Refund black player if they deposited
-            if game_escrow.black_deposited {
-                anchor_lang::system_program::transfer(
-                    CpiContext::new_with_signer(
-                        ctx.accounts.system_program.to_account_info(),
-                        anchor_lang::system_program::Transfer {
-                            from: ctx.accounts.game_vault.to_account_info(),
-                            to: ctx.accounts.player_black.to_account_info(),
-                        },
-                        signer_seeds,
-                    ),
-                    game_escrow.stake_amount,
-                )?;
-            }
-        }
+        Ok(())
+    }
 
-        game_escrow.game_state = GameState::Cancelled;
+    /// Flag the caller's opponent as disconnected. With `auto_draw_offer_on_disconnect`,
+    /// a draw is also offered in their name. The caller only vouches for the disconnect,
+    /// so the offer is marked automatic and can't be accepted; it announces the draw the
+    /// absent player is taken to want until they reconnect.
+    pub fn report_disconnect(ctx: Context<PlayerConnection>) -> Result<()> {
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        let player_key = *ctx.accounts.player.key;
+        let clock = Clock::get()?;
 
-        emit!(GameCancelled {
+        require!(
+            game_escrow.game_state == GameState::InProgress,
+            ChessError::GameNotInProgress
+        );
+        let (opponent, opponent_disconnected) = if player_key == game_escrow.player_white {
+            (game_escrow.player_black, &mut game_escrow.black_disconnected)
+        } else if player_key == game_escrow.player_black {
+            (game_escrow.player_white, &mut game_escrow.white_disconnected)
+        } else {
+            return Err(ChessError::UnauthorizedPlayer.into());
+        };
+        require!(!*opponent_disconnected, ChessError::PlayerAlreadyDisconnected);
+        *opponent_disconnected = true;
+
+        emit!(PlayerDisconnected {
             room_id: game_escrow.room_id.clone(),
-            cancelled_by: player_key,
+            player: opponent,
+            reported_by: player_key,
+            reported_at: clock.unix_timestamp,
         });
 
+        if game_escrow.auto_draw_offer_on_disconnect
+            && game_escrow.game_flags.allow_draw_offers
+            && game_escrow.draw_offered_by.is_none()
+        {
+            game_escrow.draw_offered_by = Some(opponent);
+            game_escrow.draw_offer_automatic = true;
+            game_escrow.draw_offered_at = clock.unix_timestamp;
+            emit!(DrawOffered {
+                room_id: game_escrow.room_id.clone(),
+                offered_by: opponent,
+                automatic: true,
+            });
+        }
+
         Ok(())
     }
 
-    /// Create a new tournament
-    pub fn create_tournament(
-        ctx: Context<CreateTournament>,
-        tournament_id: String,
-        name: String,
-        entry_fee: u64,
-        max_participants: u32,
-        time_control: TimeControl
-    ) -> Result<()> {
-        require!(tournament_id.len() <= 32, ChessError::RoomIdTooLong);
-        require!(name.len() <= 64, ChessError::InvalidStakeAmount);
-        require!(entry_fee > 0, ChessError::InvalidStakeAmount);
-        require!(max_participants >= 2, ChessError::InvalidStakeAmount);
-        
-        let tournament = &mut ctx.accounts.tournament;
-        let clock = Clock::get()?;
-        
-        tournament.tournament_id = tournament_id;
-        tournament.name = name;
-        tournament.creator = *ctx.accounts.creator.key;
-        tournament.entry_fee = entry_fee;
-        tournament.max_participants = max_participants;
-        tournament.current_participants = 0;
-        tournament.status = TournamentStatus::Registration;
-        tournament.time_control = time_control;
-        tournament.created_at = clock.unix_timestamp;
-        tournament.started_at = 0;
-        tournament.finished_at = 0;
-        tournament.prize_pool = 0;
-        tournament.participants = Vec::new();
-        tournament.brackets = Vec::new();
-        
-        emit!(TournamentCreated {
-            tournament_id: tournament.tournament_id.clone(),
-            creator: tournament.creator,
-            entry_fee,
-            max_participants,
-            created_at: clock.unix_timestamp,
+    /// Opt in to resigning automatically once the caller's clock drops below
+    /// `below_seconds` on their turn; `handle_timeout` enforces it. 0 opts out.
+    pub fn set_auto_resign(ctx: Context<PlayerConnection>, below_seconds: u32) -> Result<()> {
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        let player_key = *ctx.accounts.player.key;
+
+        require!(
+            game_escrow.game_state == GameState::WaitingForPlayers
+                || game_escrow.game_state == GameState::InProgress,
+            ChessError::GameNotInProgress
+        );
+        if player_key == game_escrow.player_white {
+            game_escrow.white_auto_resign_below_seconds = below_seconds;
+        } else if player_key == game_escrow.player_black {
+            game_escrow.black_auto_resign_below_seconds = below_seconds;
+        } else {
+            return Err(ChessError::UnauthorizedPlayer.into());
+        }
+
+        emit!(AutoResignSet {
+            room_id: game_escrow.room_id.clone(),
+            player: player_key,
+            below_seconds,
         });
-        
+
         Ok(())
     }
 
-    /// Join a tournament
-    pub fn join_tournament(ctx: Context<JoinTournament>) -> Result<()> {
-        let tournament = &mut ctx.accounts.tournament;
+    /// Clear the caller's disconnected flag, withdrawing any draw offered in their name
+    pub fn reconnect(ctx: Context<PlayerConnection>) -> Result<()> {
+        let game_escrow = &mut ctx.accounts.game_escrow;
         let player_key = *ctx.accounts.player.key;
         let clock = Clock::get()?;
-        
-        require!(
-            tournament.status == TournamentStatus::Registration,
-            ChessError::TournamentAlreadyStarted
-        );
-        
+
         require!(
-            tournament.current_participants < tournament.max_participants,
-            ChessError::InvalidStakeAmount
+            game_escrow.game_state == GameState::InProgress,
+            ChessError::GameNotInProgress
         );
-        
-        // Check if player already joined
-        if tournament.participants.contains(&player_key) {
-            return Err(ChessError::AlreadyDeposited.into());
+        let disconnected = if player_key == game_escrow.player_white {
+            &mut game_escrow.white_disconnected
+        } else if player_key == game_escrow.player_black {
+            &mut game_escrow.black_disconnected
+        } else {
+            return Err(ChessError::UnauthorizedPlayer.into());
+        };
+        require!(*disconnected, ChessError::PlayerNotDisconnected);
+        *disconnected = false;
+
+        let draw_offer_withdrawn = game_escrow.draw_offered_by == Some(player_key);
+        if draw_offer_withdrawn {
+            game_escrow.draw_offered_by = None;
         }
-        
-        tournament.participants.push(player_key);
-        tournament.current_participants += 1;
-        
-        emit!(PlayerJoinedTournament {
-            tournament_id: tournament.tournament_id.clone(),
+
+        emit!(PlayerReconnected {
+            room_id: game_escrow.room_id.clone(),
             player: player_key,
-            joined_at: clock.unix_timestamp,
+            draw_offer_withdrawn,
+            reconnected_at: clock.unix_timestamp,
         });
-        
+
         Ok(())
     }
 
-    /// Start a tournament
-    pub fn start_tournament(ctx: Context<StartTournament>) -> Result<()> {
-        let tournament = &mut ctx.accounts.tournament;
+    /// Offer the opponent a draw. The offer stands until the opponent accepts it, the
+    /// offerer revokes it, or the opponent plays a move instead.
+    pub fn offer_draw(ctx: Context<PlayerConnection>) -> Result<()> {
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        let player_key = *ctx.accounts.player.key;
         let clock = Clock::get()?;
-        
+
         require!(
-            tournament.status == TournamentStatus::Registration,
-            ChessError::TournamentAlreadyStarted
+            game_escrow.game_state == GameState::InProgress,
+            ChessError::GameNotInProgress
         );
-        
         require!(
-            tournament.current_participants >= 2,
-            ChessError::InvalidStakeAmount
+            player_key == game_escrow.player_white || player_key == game_escrow.player_black,
+            ChessError::UnauthorizedPlayer
         );
-        
-        tournament.status = TournamentStatus::Active;
-        tournament.started_at = clock.unix_timestamp;
-        tournament.prize_pool = tournament.entry_fee * tournament.current_participants as u64;
-        
-        emit!(TournamentStarted {
-            tournament_id: tournament.tournament_id.clone(),
-            started_at: clock.unix_timestamp,
-            participants: tournament.current_participants,
-            prize_pool: tournament.prize_pool,
+        require!(game_escrow.game_flags.allow_draw_offers, ChessError::DrawOffersDisabled);
+        require!(game_escrow.draw_offered_by.is_none(), ChessError::DrawAlreadyOffered);
+
+        game_escrow.draw_offered_by = Some(player_key);
+        game_escrow.draw_offer_automatic = false;
+        game_escrow.draw_offered_at = clock.unix_timestamp;
+
+        emit!(DrawOffered {
+            room_id: game_escrow.room_id.clone(),
+            offered_by: player_key,
+            automatic: false,
         });
-        
+
         Ok(())
     }
 
+    /// Withdraw the caller's pending draw offer
+    pub fn revoke_draw_offer(ctx: Context<PlayerConnection>) -> Result<()> {
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        let player_key = *ctx.accounts.player.key;
 
-}
-
-// Helper functions moved outside the #[program] module
-impl<'info> DeclareResult<'info> {
-    pub fn distribute_funds(&self, winner: GameWinner, vault_bump: u8) -> Result<()> {
-        let game_escrow = &self.game_escrow;
-        let vault_balance = self.game_vault.lamports();
-        
-        if vault_balance == 0 {
-            return Ok(());
-        }
+        require!(
+            game_escrow.game_state == GameState::InProgress,
+            ChessError::GameNotInProgress
+        );
+        let offered_by = game_escrow.draw_offered_by.ok_or(ChessError::NoDrawOffer)?;
+        require_keys_eq!(offered_by, player_key, ChessError::UnauthorizedPlayer);
 
-        // Calculate 2% fee
-        let fee_amount = vault_balance
-            .checked_mul(2)
-            .and_then(|x| x.checked_div(100))
-            .unwrap_or(0);
+        game_escrow.draw_offered_by = None;
 
-        let remaining_amount = vault_balance.checked_sub(fee_amount).unwrap_or(0);
+        emit!(DrawOfferRevoked {
+            room_id: game_escrow.room_id.clone(),
+            offered_by,
+        });
 
-        let game_key = game_escrow.key();
-        let bump_bytes = [vault_bump];
-        
-        let seeds = &[
-            b"vault".as_ref(),
-            game_key.as_ref(),
-            bump_bytes.as_ref(),
-        ];
-        let signer_seeds = &[&seeds[..]];
+        Ok(())
+    }
 
-        // Transfer fee to fee collector
-        if fee_amount > 0 {
-            anchor_lang::system_program::transfer(
-                CpiContext::new_with_signer(
-                    self.system_program.to_account_info(),
-                    anchor_lang::system_program::Transfer {
-                        from: self.game_vault.to_account_info(),
-                        to: self.fee_collector.to_account_info(),
-                    },
-                    signer_seeds,
-                ),
-                fee_amount,
-            )?;
+    /// Accept the opponent's pending draw offer, finishing the game as a draw by agreement
+    pub fn accept_draw(ctx: Context<DeclareResult>) -> Result<()> {
+        let clock = Clock::get()?;
+        let finished_at = clock.unix_timestamp;
+        let room_id: String;
+        {
+            let game_escrow = &mut ctx.accounts.game_escrow;
+            let player_key = *ctx.accounts.player.key;
+
+            require!(
+                game_escrow.game_state == GameState::InProgress,
+                ChessError::GameNotInProgress
+            );
+            require!(
+                player_key == game_escrow.player_white || player_key == game_escrow.player_black,
+                ChessError::UnauthorizedPlayer
+            );
+            let offered_by = game_escrow.draw_offered_by.ok_or(ChessError::NoDrawOffer)?;
+            require!(offered_by != player_key, ChessError::CannotAcceptOwnDrawOffer);
+            require!(!game_escrow.draw_offer_automatic, ChessError::AutomaticDrawOffer);
+            require!(
+                game_escrow.move_count >= game_escrow.min_moves_before_result as u32,
+                ChessError::TooFewMoves
+            );
+            // A flag that has already fallen beats a draw agreed after it
+            require!(
+                !game_escrow.mover_flagged(finished_at),
+                ChessError::TimeoutTakesPrecedence
+            );
+
+            game_escrow.draw_offered_by = None;
+            game_escrow.winner = game_escrow.scored_result(GameWinner::Draw);
+            game_escrow.game_state = GameState::Finished;
+            game_escrow.finished_at = finished_at;
+            room_id = game_escrow.room_id.clone();
         }
+        let winner = ctx.accounts.game_escrow.winner.clone();
 
-        // Distribute remaining amount based on winner
-        match winner {
-            GameWinner::White => {
-                if remaining_amount > 0 {
-                    anchor_lang::system_program::transfer(
-                        CpiContext::new_with_signer(
-                            self.system_program.to_account_info(),
-                            anchor_lang::system_program::Transfer {
-                                from: self.game_vault.to_account_info(),
-                                to: self.player_white.to_account_info(),
-                            },
-                            signer_seeds,
-                        ),
-                        remaining_amount,
-                    )?;
+        ctx.accounts.distribute_funds(winner.clone(), ctx.bumps.game_vault)?;
+        ctx.accounts.game_escrow.funds_distributed = true;
+        record_player_stats(
+            &mut ctx.accounts.game_escrow,
+            ctx.accounts.white_stats.as_mut(),
+            ctx.accounts.black_stats.as_mut(),
+            &winner,
+            finished_at,
+        )?;
+        record_head_to_head(
+            &ctx.accounts.game_escrow,
+            ctx.accounts.head_to_head.as_mut(),
+            &winner,
+            finished_at,
+        )?;
+        update_ratings(
+            &mut ctx.accounts.game_escrow,
+            ctx.accounts.white_rating.as_mut(),
+            ctx.accounts.black_rating.as_mut(),
+            &winner,
+            false,
+            finished_at,
+        )?;
+
+        emit!(GameFinished {
+            room_id,
+            winner,
+            reason: GameEndReason::Agreement,
+            finished_at,
+            white_total_time_ms: ctx.accounts.game_escrow.white_timing.total_ms,
+            black_total_time_ms: ctx.accounts.game_escrow.black_timing.total_ms,
+        });
+
+        Ok(())
+    }
+
+    /// Settle the game on a result both players sign in the same transaction, with no
+    /// offer to wait on; funds are distributed immediately.
+    pub fn settle_by_agreement(
+        ctx: Context<SettleByAgreement>,
+        winner: GameWinner,
+        reason: GameEndReason,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        let finished_at = clock.unix_timestamp;
+        let room_id: String;
+        {
+            let game_escrow = &mut ctx.accounts.game_escrow;
+
+            require!(
+                game_escrow.game_state == GameState::InProgress,
+                ChessError::GameNotInProgress
+            );
+            match winner {
+                GameWinner::White | GameWinner::Black => {
+                    require!(!reason.is_drawn(), ChessError::InvalidWinnerDeclaration);
                 }
-            },
-            GameWinner::Black => {
-                if remaining_amount > 0 {
-                    anchor_lang::system_program::transfer(
-                        CpiContext::new_with_signer(
-                            self.system_program.to_account_info(),
-                            anchor_lang::system_program::Transfer {
-                                from: self.game_vault.to_account_info(),
-                                to: self.player_black.to_account_info(),
-                            },
-                            signer_seeds,
-                        ),
-                        remaining_amount,
-                    )?;
+                GameWinner::Draw => {
+                    require!(reason.is_drawn(), ChessError::InvalidDrawDeclaration);
                 }
-            },
-            GameWinner::Draw => {
-                // Split the remaining amount equally
-                let half_amount = remaining_amount / 2;
-                
-                if half_amount > 0 {
-                    // Transfer to white player
-                    anchor_lang::system_program::transfer(
-                        CpiContext::new_with_signer(
-                            self.system_program.to_account_info(),
-                            anchor_lang::system_program::Transfer {
-                                from: self.game_vault.to_account_info(),
-                                to: self.player_white.to_account_info(),
-                            },
-                            signer_seeds,
-                        ),
-                        half_amount,
-                    )?;
-
-                    // Transfer to black player
-                    anchor_lang::system_program::transfer(
-                        CpiContext::new_with_signer(
-                            self.system_program.to_account_info(),
-                            anchor_lang::system_program::Transfer {
-                                from: self.game_vault.to_account_info(),
-                                to: self.player_black.to_account_info(),
-                            },
-                            signer_seeds,
-                        ),
-                        half_amount,
-                    )?;
+                GameWinner::None | GameWinner::Aborted => {
+                    return Err(ChessError::InvalidWinnerDeclaration.into())
                 }
-            },
-            GameWinner::None => {
-                return Err(ChessError::InvalidWinnerDeclaration.into());
             }
+            if reason != GameEndReason::Resignation && reason != GameEndReason::Timeout {
+                require!(
+                    game_escrow.move_count >= game_escrow.min_moves_before_result as u32,
+                    ChessError::TooFewMoves
+                );
+            }
+
+            game_escrow.draw_offered_by = None;
+            game_escrow.winner = game_escrow.scored_result(winner);
+            game_escrow.game_state = GameState::Finished;
+            game_escrow.finished_at = finished_at;
+            room_id = game_escrow.room_id.clone();
         }
+        let winner = ctx.accounts.game_escrow.winner.clone();
+
+        ctx.accounts.distribute_funds(winner.clone(), ctx.bumps.game_vault)?;
+        ctx.accounts.game_escrow.funds_distributed = true;
+        record_player_stats(
+            &mut ctx.accounts.game_escrow,
+            ctx.accounts.white_stats.as_mut(),
+            ctx.accounts.black_stats.as_mut(),
+            &winner,
+            finished_at,
+        )?;
+        record_head_to_head(
+            &ctx.accounts.game_escrow,
+            ctx.accounts.head_to_head.as_mut(),
+            &winner,
+            finished_at,
+        )?;
+        let early_resignation = reason == GameEndReason::Resignation
+            && ctx.accounts.game_escrow.move_count
+                < ctx.accounts.game_escrow.early_resignation_moves as u32;
+        update_ratings(
+            &mut ctx.accounts.game_escrow,
+            ctx.accounts.white_rating.as_mut(),
+            ctx.accounts.black_rating.as_mut(),
+            &winner,
+            early_resignation,
+            finished_at,
+        )?;
+
+        emit!(GameFinished {
+            room_id,
+            winner,
+            reason,
+            finished_at,
+            white_total_time_ms: ctx.accounts.game_escrow.white_timing.total_ms,
+            black_total_time_ms: ctx.accounts.game_escrow.black_timing.total_ms,
+        });
 
         Ok(())
     }
-}
 
-impl<'info> HandleTimeout<'info> {
-    pub fn distribute_funds(&self, winner: GameWinner, vault_bump: u8) -> Result<()> {
-        let game_escrow = &self.game_escrow;
-        let vault_balance = self.game_vault.lamports();
-        
-        if vault_balance == 0 {
-            return Ok(());
-        }
+    /// Grant the opponent's pending takeback. Each player may take back at most
+    /// `max_takebacks` moves per game.
+    pub fn accept_takeback(ctx: Context<Takeback>) -> Result<()> {
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        let player_key = *ctx.accounts.player.key;
+        let clock = Clock::get()?;
 
-        // Calculate 2% fee
-        let fee_amount = vault_balance
-            .checked_mul(2)
-            .and_then(|x| x.checked_div(100))
-            .unwrap_or(0);
+        require!(
+            game_escrow.game_state == GameState::InProgress,
+            ChessError::GameNotInProgress
+        );
+        let requester = game_escrow
+            .takeback_requested_by
+            .ok_or(ChessError::NoTakebackRequested)?;
+        require!(player_key == game_escrow.side_to_move(), ChessError::NotPlayerTurn);
+        let last = game_escrow
+            .move_history
+            .last()
+            .cloned()
+            .ok_or(ChessError::NoMoveToTakeBack)?;
 
-        let remaining_amount = vault_balance.checked_sub(fee_amount).unwrap_or(0);
+        let game: &mut GameEscrow = game_escrow;
+        let used = if requester == game.player_white {
+            &mut game.white_takebacks
+        } else {
+            &mut game.black_takebacks
+        };
+        require!(*used < game.max_takebacks, ChessError::TooManyTakebacks);
+        *used += 1;
+        let takebacks_used = *used;
 
-        let game_key = game_escrow.key();
-        let bump_bytes = [vault_bump];
-        
-        let seeds = &[
-            b"vault".as_ref(),
-            game_key.as_ref(),
-            bump_bytes.as_ref(),
-        ];
-        let signer_seeds = &[&seeds[..]];
+        game_escrow.undo_last_move(&last, clock.unix_timestamp);
 
-        // Transfer fee to fee collector
-        if fee_amount > 0 {
-            anchor_lang::system_program::transfer(
-                CpiContext::new_with_signer(
-                    self.system_program.to_account_info(),
-                    anchor_lang::system_program::Transfer {
-                        from: self.game_vault.to_account_info(),
-                        to: self.fee_collector.to_account_info(),
-                    },
-                    signer_seeds,
-                ),
-                fee_amount,
-            )?;
-        }
+        emit!(TakebackAccepted {
+            room_id: game_escrow.room_id.clone(),
+            player: requester,
+            move_number: last.move_number,
+            takebacks_used,
+        });
 
-        // Transfer remaining amount to winner
-        match winner {
-            GameWinner::White => {
-                if remaining_amount > 0 {
-                    anchor_lang::system_program::transfer(
-                        CpiContext::new_with_signer(
-                            self.system_program.to_account_info(),
-                            anchor_lang::system_program::Transfer {
-                                from: self.game_vault.to_account_info(),
-                                to: self.player_white.to_account_info(),
-                            },
-                            signer_seeds,
-                        ),
-                        remaining_amount,
-                    )?;
-                }
-            },
-            GameWinner::Black => {
-                if remaining_amount > 0 {
-                    anchor_lang::system_program::transfer(
-                        CpiContext::new_with_signer(
-                            self.system_program.to_account_info(),
-                            anchor_lang::system_program::Transfer {
-                                from: self.game_vault.to_account_info(),
-                                to: self.player_black.to_account_info(),
-                            },
-                            signer_seeds,
-                        ),
-                        remaining_amount,
-                    )?;
+        Ok(())
+    }
+
+    /// Emit the retained move history in UCI long algebraic notation for engines and bots.
+    /// `first_move_number` says where the list starts when older moves have been evicted.
+    pub fn get_uci_moves(ctx: Context<GetUciMoves>) -> Result<()> {
+        let game_escrow = &ctx.accounts.game_escrow;
+
+        emit!(UciMoves {
+            room_id: game_escrow.room_id.clone(),
+            first_move_number: game_escrow
+                .move_history
+                .first()
+                .map_or(game_escrow.move_count + 1, |record| record.move_number),
+            moves: game_escrow.move_history.iter().map(MoveRecord::uci).collect(),
+        });
+
+        Ok(())
+    }
+
+    /// Re-derive the hash chain over the retained move history and emit the first move
+    /// whose link doesn't hold, if any. Never mutates the game.
+    pub fn verify_move_chain(ctx: Context<VerifyMoveChain>) -> Result<()> {
+        let game_escrow = &ctx.accounts.game_escrow;
+        let history = &game_escrow.move_history;
+
+        // Older records may have been evicted, so the chain starts from the first one kept
+        let mut expected_prev = history.first().map(|record| record.prev_hash);
+        let mut broken_at = None;
+        for record in history {
+            if Some(record.prev_hash) != expected_prev || record.position_hash != record.chain_hash() {
+                broken_at = Some(record.move_number);
+                break;
+            }
+            expected_prev = Some(record.position_hash);
+        }
+        if broken_at.is_none() {
+            if let Some(last) = history.last() {
+                if last.position_hash != game_escrow.position_hash {
+                    broken_at = Some(last.move_number);
                 }
-            },
-            _ => return Err(ChessError::InvalidWinnerDeclaration.into()),
+            }
         }
 
+        emit!(MoveChainVerified {
+            room_id: game_escrow.room_id.clone(),
+            moves_checked: history.len() as u32,
+            head: game_escrow.position_hash,
+            broken_at,
+        });
+
         Ok(())
     }
-}
 
-// Account Structs
-#[derive(Accounts)]
-#[instruction(room_id: String)]
-pub struct InitializeGame<'info> {
-    #[account(
-        init, 
-        payer = player, 
-        space = 8 + GameEscrow::INIT_SPACE,
-        seeds = [b"game", room_id.as_bytes()],
-        bump
-    )]
-    pub game_escrow: Account<'info, GameEscrow>,
-    #[account(mut)]
-    pub player: Signer<'info>,
-    /// CHECK: Fee collector can be any account
-    pub fee_collector: UncheckedAccount<'info>,
-    pub system_program: Program<'info, System>,
-}
+    /// Emit the game vault's balance against what the escrow still owes out of it,
+    /// for auditing. Never mutates the game.
+    pub fn get_escrow_health(ctx: Context<GetEscrowHealth>) -> Result<()> {
+        let game_escrow = &ctx.accounts.game_escrow;
+        let vault_balance = ctx.accounts.game_vault.lamports();
 
-#[derive(Accounts)]
-pub struct JoinGame<'info> {
-    #[account(mut)]
-    pub game_escrow: Account<'info, GameEscrow>,
-    #[account(mut)]
-    pub player: Signer<'info>,
-}
+        // Paid-out, aborted and cancelled games owe nothing; otherwise every deposit is held
+        let settled = game_escrow.funds_distributed || game_escrow.game_state == GameState::Cancelled;
+        let obligations = if settled { 0 } else { game_escrow.total_deposited };
+        // What the players receive between them once the fee for the recorded result is taken
+        let expected_payout =
+            obligations.saturating_sub(game_escrow.platform_fee(obligations, &game_escrow.winner));
 
-#[derive(Accounts)]
-pub struct DepositStake<'info> {
-    #[account(mut)]
-    pub game_escrow: Account<'info, GameEscrow>,
-    #[account(mut)]
-    pub player: Signer<'info>,
-    #[account(
-        mut,
-        seeds = [b"vault", game_escrow.key().as_ref()],
-        bump
-    )]
-    pub game_vault: SystemAccount<'info>,
-    pub system_program: Program<'info, System>,
-}
+        emit!(EscrowHealth {
+            room_id: game_escrow.room_id.clone(),
+            vault_balance,
+            total_deposited: game_escrow.total_deposited,
+            obligations,
+            expected_payout,
+            solvent: vault_balance >= obligations,
+            discrepancy: vault_balance as i64 - obligations as i64,
+        });
 
-#[derive(Accounts)]
-pub struct RecordMove<'info> {
-    #[account(mut)]
-    pub game_escrow: Account<'info, GameEscrow>,
-    #[account(mut)]
-    pub player: Signer<'info>,
-}
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct DeclareResult<'info> {
-    #[account(mut)]
-    pub game_escrow: Account<'info, GameEscrow>,
+    /// Emit each player's think-time totals for a finished game: time used, average per
+    /// move and longest think. Totals are kept as moves are played, so they cover the
+    /// whole game even when the history only retains its last moves.
+    pub fn get_timing_stats(ctx: Context<GetTimingStats>) -> Result<()> {
+        let game_escrow = &ctx.accounts.game_escrow;
+
+        require!(
+            game_escrow.game_state == GameState::Finished,
+            ChessError::GameNotFinished
+        );
+
+        emit!(TimingStats {
+            room_id: game_escrow.room_id.clone(),
+            white: game_escrow.white_timing.summary(),
+            black: game_escrow.black_timing.summary(),
+        });
+
+        Ok(())
+    }
+
+    /// Emit the game's PGN-style metadata (players, stake, time control, result and
+    /// dates) for listings that don't need the moves. Never mutates the game.
+    pub fn get_game_header(ctx: Context<GetGameHeader>) -> Result<()> {
+        let game_escrow = &ctx.accounts.game_escrow;
+
+        emit!(GameHeader {
+            room_id: game_escrow.room_id.clone(),
+            player_white: game_escrow.player_white,
+            player_black: game_escrow.player_black,
+            rating_white: game_escrow.rating_white,
+            rating_black: game_escrow.rating_black,
+            stake_amount: game_escrow.stake_amount,
+            initial_time: game_escrow.time_control.initial_time,
+            increment: game_escrow.time_control.increment,
+            time_control_type: game_escrow.time_control.time_control_type.clone(),
+            variant: game_escrow.variant.clone(),
+            game_state: game_escrow.game_state.clone(),
+            winner: game_escrow.winner.clone(),
+            move_count: game_escrow.move_count,
+            tournament_id: game_escrow.tournament_id.clone(),
+            created_at: game_escrow.created_at,
+            started_at: game_escrow.started_at,
+            finished_at: game_escrow.finished_at,
+        });
+
+        Ok(())
+    }
+
+    /// Emit what a lobby card shows (players, stake, state, result, move count and
+    /// clocks) without the move history. Never mutates the game.
+    pub fn get_game_summary(ctx: Context<GetGameSummary>) -> Result<()> {
+        let game_escrow = &ctx.accounts.game_escrow;
+
+        emit!(GameSummary {
+            room_id: game_escrow.room_id.clone(),
+            player_white: game_escrow.player_white,
+            player_black: game_escrow.player_black,
+            stake_amount: game_escrow.stake_amount,
+            game_state: game_escrow.game_state.clone(),
+            winner: game_escrow.winner.clone(),
+            move_count: game_escrow.move_count,
+            white_time_remaining: game_escrow.white_time_remaining,
+            black_time_remaining: game_escrow.black_time_remaining,
+            white_to_move: game_escrow.is_white_turn(),
+            last_move_time: game_escrow.last_move_time,
+        });
+
+        Ok(())
+    }
+
+    /// Declare game result and distribute funds
+    pub fn declare_result(
+        ctx: Context<DeclareResult>, 
+        winner: GameWinner,
+        reason: GameEndReason
+    ) -> Result<()> {
+        // A stalemate claim may be rescored as the checkmate the board shows
+        let (mut winner, mut reason) = (winner, reason);
+        let clock = Clock::get()?;
+        let finished_at = clock.unix_timestamp;
+        let room_id: String;
+        {
+            let game_escrow = &mut ctx.accounts.game_escrow;
+            
+            // Only the transition out of InProgress pays out, so declaring a finished
+            // game again fails here, before the vault is touched
+            require!(
+                game_escrow.game_state == GameState::InProgress,
+                ChessError::GameNotInProgress
+            );
+
+            // Only players can declare results
+            let declarer = *ctx.accounts.player.key;
+            require!(
+                declarer == game_escrow.player_white || declarer == game_escrow.player_black,
+                ChessError::UnauthorizedPlayer
+            );
+
+            // Validate winner declaration
+            match winner {
+                GameWinner::White => {
+                    require!(
+                        (reason == GameEndReason::Resignation && declarer == game_escrow.player_black) ||
+                        (reason == GameEndReason::Timeout && declarer == game_escrow.player_white) ||
+                        (reason == GameEndReason::Checkmate && declarer == game_escrow.player_white),
+                        ChessError::InvalidWinnerDeclaration
+                    );
+                },
+                GameWinner::Black => {
+                    require!(
+                        (reason == GameEndReason::Resignation && declarer == game_escrow.player_white) ||
+                        (reason == GameEndReason::Timeout && declarer == game_escrow.player_black) ||
+                        (reason == GameEndReason::Checkmate && declarer == game_escrow.player_black),
+                        ChessError::InvalidWinnerDeclaration
+                    );
+                },
+                GameWinner::Draw => {
+                    // Both players must agree to a draw, or it's a stalemate. Agreement
+                    // means taking up the opponent's standing offer, so one player can't
+                    // turn a lost game into a draw alone
+                    match reason {
+                        GameEndReason::Stalemate => {}
+                        GameEndReason::Agreement => {
+                            let opponent = if declarer == game_escrow.player_white {
+                                game_escrow.player_black
+                            } else {
+                                game_escrow.player_white
+                            };
+                            require!(
+                                game_escrow.draw_offered_by == Some(opponent),
+                                ChessError::NoDrawOffer
+                            );
+                            require!(
+                                !game_escrow.draw_offer_automatic,
+                                ChessError::AutomaticDrawOffer
+                            );
+                            game_escrow.draw_offered_by = None;
+                        }
+                        _ => return Err(ChessError::InvalidDrawDeclaration.into()),
+                    }
+                },
+                GameWinner::None | GameWinner::Aborted => {
+                    return Err(ChessError::InvalidWinnerDeclaration.into())
+                }
+            }
+
+            // Resigning or flagging is always possible; other results need a real game first
+            if reason != GameEndReason::Resignation && reason != GameEndReason::Timeout {
+                require!(
+                    game_escrow.move_count >= game_escrow.min_moves_before_result as u32,
+                    ChessError::TooFewMoves
+                );
+            }
+
+            // A stalemate claim has to stand on the board; if the side to move is in check
+            // with no legal moves it is checkmate instead. Atomic boards aren't modelled by
+            // the move generator, so a stalemate there can't be confirmed and isn't accepted.
+            if reason == GameEndReason::Stalemate {
+                require!(game_escrow.variant != Variant::Atomic, ChessError::NotStalemate);
+                (winner, reason) = game_escrow.board_result().ok_or(ChessError::NotStalemate)?;
+            }
+
+            // Once the player to move has flagged, `handle_timeout` owns the result: a
+            // declaration racing it only stands if the board confirms it, or if it is the
+            // flag itself or the flagged player resigning, which score the same
+            if game_escrow.mover_flagged(finished_at) {
+                let flagged_player = game_escrow.side_to_move();
+                let confirmed = game_escrow.board_result() == Some((winner.clone(), reason.clone()));
+                let same_as_flag = match reason {
+                    GameEndReason::Timeout => declarer != flagged_player,
+                    GameEndReason::Resignation => declarer == flagged_player,
+                    _ => false,
+                };
+                require!(confirmed || same_as_flag, ChessError::TimeoutTakesPrecedence);
+            }
+
+            // The declarer gains from a mate or a flag, so the game must bear it out: the
+            // board shows the mate (atomic boards aren't judged, so those games end by
+            // resignation or the clock), or the opponent has flagged past the grace
+            // `handle_timeout` allows. A double flag scored as a draw is left to it.
+            match reason {
+                GameEndReason::Checkmate => require!(
+                    game_escrow.board_result() == Some((winner.clone(), GameEndReason::Checkmate)),
+                    ChessError::NotCheckmate
+                ),
+                GameEndReason::Timeout => {
+                    let (_, declarer_remaining) = game_escrow.clocks_by_turn();
+                    require!(
+                        declarer != game_escrow.side_to_move()
+                            && game_escrow.mover_overtime(finished_at)
+                                > game_escrow.timeout_grace_seconds as i64
+                            && !(declarer_remaining == 0
+                                && game_escrow.double_flag_rule == DoubleFlagRule::Draw),
+                        ChessError::TimeNotExceeded
+                    );
+                }
+                _ => {}
+            }
+
+            game_escrow.winner = game_escrow.scored_result(winner);
+            game_escrow.game_state = GameState::Finished;
+            game_escrow.finished_at = finished_at;
+            room_id = game_escrow.room_id.clone();
+        }
+        let winner = ctx.accounts.game_escrow.winner.clone();
+
+        // With a dispute window the result waits, unpaid, for it to close; `settle_game`
+        // then pays it out
+        if ctx.accounts.game_escrow.dispute_window_seconds == 0 {
+            ctx.accounts.distribute_funds(winner.clone(), ctx.bumps.game_vault)?;
+            ctx.accounts.game_escrow.funds_distributed = true;
+            record_player_stats(
+                &mut ctx.accounts.game_escrow,
+                ctx.accounts.white_stats.as_mut(),
+                ctx.accounts.black_stats.as_mut(),
+                &winner,
+                finished_at,
+            )?;
+            record_head_to_head(
+                &ctx.accounts.game_escrow,
+                ctx.accounts.head_to_head.as_mut(),
+                &winner,
+                finished_at,
+            )?;
+            let early_resignation = reason == GameEndReason::Resignation
+                && ctx.accounts.game_escrow.move_count
+                    < ctx.accounts.game_escrow.early_resignation_moves as u32;
+            update_ratings(
+                &mut ctx.accounts.game_escrow,
+                ctx.accounts.white_rating.as_mut(),
+                ctx.accounts.black_rating.as_mut(),
+                &winner,
+                early_resignation,
+                finished_at,
+            )?;
+        }
+
+        emit!(GameFinished {
+            room_id,
+            winner,
+            reason,
+            finished_at,
+            white_total_time_ms: ctx.accounts.game_escrow.white_timing.total_ms,
+            black_total_time_ms: ctx.accounts.game_escrow.black_timing.total_ms,
+        });
+
+        Ok(())
+    }
+
+    /// Settle a game on the word of the registered off-chain referee or one of the config's
+    /// moderators. The transaction must carry an Ed25519 program instruction, immediately
+    /// before this one, in which that authority signs `referee_message(game_escrow, winner,
+    /// reason)`; no player needs to sign.
+    pub fn declare_result_signed(
+        ctx: Context<DeclareResultSigned>,
+        winner: GameWinner,
+        reason: GameEndReason,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        let finished_at = clock.unix_timestamp;
+        let room_id: String;
+        {
+            let game_escrow_key = ctx.accounts.game_escrow.key();
+            let game_escrow = &mut ctx.accounts.game_escrow;
+
+            require!(
+                game_escrow.game_state == GameState::InProgress,
+                ChessError::GameNotInProgress
+            );
+            require!(
+                winner != GameWinner::None && winner != GameWinner::Aborted,
+                ChessError::InvalidWinnerDeclaration
+            );
+
+            let config = &ctx.accounts.config;
+            require!(
+                config.referee != Pubkey::default() || !config.moderators.is_empty(),
+                ChessError::RefereeNotRegistered
+            );
+            verify_referee_signature(
+                &ctx.accounts.instructions,
+                config,
+                &referee_message(&game_escrow_key, &winner, &reason),
+            )?;
+
+            game_escrow.winner = game_escrow.scored_result(winner);
+            game_escrow.game_state = GameState::Finished;
+            game_escrow.finished_at = finished_at;
+            room_id = game_escrow.room_id.clone();
+        }
+        let winner = ctx.accounts.game_escrow.winner.clone();
+
+        ctx.accounts.distribute_funds(winner.clone(), ctx.bumps.game_vault)?;
+        ctx.accounts.game_escrow.funds_distributed = true;
+        record_player_stats(
+            &mut ctx.accounts.game_escrow,
+            ctx.accounts.white_stats.as_mut(),
+            ctx.accounts.black_stats.as_mut(),
+            &winner,
+            finished_at,
+        )?;
+        record_head_to_head(
+            &ctx.accounts.game_escrow,
+            ctx.accounts.head_to_head.as_mut(),
+            &winner,
+            finished_at,
+        )?;
+        update_ratings(
+            &mut ctx.accounts.game_escrow,
+            ctx.accounts.white_rating.as_mut(),
+            ctx.accounts.black_rating.as_mut(),
+            &winner,
+            false,
+            finished_at,
+        )?;
+
+        emit!(GameFinished {
+            room_id,
+            winner,
+            reason,
+            finished_at,
+            white_total_time_ms: ctx.accounts.game_escrow.white_timing.total_ms,
+            black_total_time_ms: ctx.accounts.game_escrow.black_timing.total_ms,
+        });
+
+        Ok(())
+    }
+
+    /// Resign the game for the caller; their opponent wins and the pot is paid out
+    pub fn resign(ctx: Context<DeclareResult>) -> Result<()> {
+        let clock = Clock::get()?;
+        let finished_at = clock.unix_timestamp;
+        let room_id: String;
+        {
+            let game_escrow = &mut ctx.accounts.game_escrow;
+            let player_key = *ctx.accounts.player.key;
+
+            require!(
+                game_escrow.game_state == GameState::InProgress,
+                ChessError::GameNotInProgress
+            );
+            require!(
+                game_escrow.game_flags.allow_resignation,
+                ChessError::ResignationDisabled
+            );
+            let winner = if player_key == game_escrow.player_white {
+                GameWinner::Black
+            } else if player_key == game_escrow.player_black {
+                GameWinner::White
+            } else {
+                return Err(ChessError::UnauthorizedPlayer.into());
+            };
+            // Once the player to move has flagged, only their own resignation scores the same
+            if game_escrow.mover_flagged(finished_at) {
+                require_keys_eq!(
+                    player_key,
+                    game_escrow.side_to_move(),
+                    ChessError::TimeoutTakesPrecedence
+                );
+            }
+
+            game_escrow.winner = game_escrow.scored_result(winner);
+            game_escrow.game_state = GameState::Finished;
+            game_escrow.finished_at = finished_at;
+            room_id = game_escrow.room_id.clone();
+        }
+        let winner = ctx.accounts.game_escrow.winner.clone();
+
+        ctx.accounts.distribute_funds(winner.clone(), ctx.bumps.game_vault)?;
+        ctx.accounts.game_escrow.funds_distributed = true;
+        record_player_stats(
+            &mut ctx.accounts.game_escrow,
+            ctx.accounts.white_stats.as_mut(),
+            ctx.accounts.black_stats.as_mut(),
+            &winner,
+            finished_at,
+        )?;
+        record_head_to_head(
+            &ctx.accounts.game_escrow,
+            ctx.accounts.head_to_head.as_mut(),
+            &winner,
+            finished_at,
+        )?;
+        let early_resignation = ctx.accounts.game_escrow.move_count
+            < ctx.accounts.game_escrow.early_resignation_moves as u32;
+        update_ratings(
+            &mut ctx.accounts.game_escrow,
+            ctx.accounts.white_rating.as_mut(),
+            ctx.accounts.black_rating.as_mut(),
+            &winner,
+            early_resignation,
+            finished_at,
+        )?;
+
+        emit!(GameFinished {
+            room_id,
+            winner,
+            reason: GameEndReason::Resignation,
+            finished_at,
+            white_total_time_ms: ctx.accounts.game_escrow.white_timing.total_ms,
+            black_total_time_ms: ctx.accounts.game_escrow.black_timing.total_ms,
+        });
+
+        Ok(())
+    }
+
+    /// Resign a player on the strength of their earlier consent, so a server can end the
+    /// game for a player who has disconnected. The transaction must carry an Ed25519
+    /// program instruction, immediately before this one, in which one of the players signs
+    /// `resignation_message(game_escrow)`; whoever submits it needs no other authority.
+    pub fn resign_with_authorization(ctx: Context<ResignWithAuthorization>) -> Result<()> {
+        let clock = Clock::get()?;
+        let finished_at = clock.unix_timestamp;
+        let room_id: String;
+        let resigning_player: Pubkey;
+        {
+            let game_escrow_key = ctx.accounts.game_escrow.key();
+            let game_escrow = &mut ctx.accounts.game_escrow;
+
+            require!(
+                game_escrow.game_state == GameState::InProgress,
+                ChessError::GameNotInProgress
+            );
+            require!(
+                game_escrow.game_flags.allow_resignation,
+                ChessError::ResignationDisabled
+            );
+
+            let (signed_key, signed_message) = load_ed25519_signature(
+                &ctx.accounts.instructions,
+                ChessError::InvalidResignationAuthorization,
+            )?;
+            require!(
+                signed_message == resignation_message(&game_escrow_key),
+                ChessError::InvalidResignationAuthorization
+            );
+            let winner = if signed_key == game_escrow.player_white {
+                GameWinner::Black
+            } else if signed_key == game_escrow.player_black {
+                GameWinner::White
+            } else {
+                return Err(ChessError::InvalidResignationAuthorization.into());
+            };
+
+            resigning_player = signed_key;
+            game_escrow.winner = game_escrow.scored_result(winner);
+            game_escrow.game_state = GameState::Finished;
+            game_escrow.finished_at = finished_at;
+            room_id = game_escrow.room_id.clone();
+        }
+        let winner = ctx.accounts.game_escrow.winner.clone();
+
+        ctx.accounts.distribute_funds(winner.clone(), ctx.bumps.game_vault)?;
+        ctx.accounts.game_escrow.funds_distributed = true;
+        record_player_stats(
+            &mut ctx.accounts.game_escrow,
+            ctx.accounts.white_stats.as_mut(),
+            ctx.accounts.black_stats.as_mut(),
+            &winner,
+            finished_at,
+        )?;
+        record_head_to_head(
+            &ctx.accounts.game_escrow,
+            ctx.accounts.head_to_head.as_mut(),
+            &winner,
+            finished_at,
+        )?;
+        let early_resignation = ctx.accounts.game_escrow.move_count
+            < ctx.accounts.game_escrow.early_resignation_moves as u32;
+        update_ratings(
+            &mut ctx.accounts.game_escrow,
+            ctx.accounts.white_rating.as_mut(),
+            ctx.accounts.black_rating.as_mut(),
+            &winner,
+            early_resignation,
+            finished_at,
+        )?;
+
+        emit!(ResignationAuthorized {
+            room_id: room_id.clone(),
+            player: resigning_player,
+            relayer: ctx.accounts.relayer.key(),
+        });
+        emit!(GameFinished {
+            room_id,
+            winner,
+            reason: GameEndReason::Resignation,
+            finished_at,
+            white_total_time_ms: ctx.accounts.game_escrow.white_timing.total_ms,
+            black_total_time_ms: ctx.accounts.game_escrow.black_timing.total_ms,
+        });
+
+        Ok(())
+    }
+
+    /// Settle a game both players have walked away from as a draw, refunding each stake
+    /// minus the abort fee. Permissionless once nobody has moved for twice the time left on
+    /// both clocks combined, well past the point where the last mover could have claimed
+    /// `handle_timeout`.
+    pub fn finalize_double_abandonment(ctx: Context<FinalizeDoubleAbandonment>) -> Result<()> {
+        let clock = Clock::get()?;
+        let game_escrow = &ctx.accounts.game_escrow;
+
+        require!(
+            game_escrow.game_state == GameState::InProgress,
+            ChessError::GameNotInProgress
+        );
+        require!(
+            clock.unix_timestamp > game_escrow.abandonment_deadline(),
+            ChessError::GameNotAbandoned
+        );
+
+        let fee_per_stake = game_escrow
+            .stake_amount
+            .checked_mul(GameEscrow::ABORT_FEE_BPS)
+            .map(|x| x / 10_000)
+            .unwrap_or(0);
+        let refund_per_player = game_escrow.stake_amount.saturating_sub(fee_per_stake);
+
+        ctx.accounts.refund_stakes(fee_per_stake, ctx.bumps.game_vault)?;
+
+        release_active_games(
+            &mut ctx.accounts.game_escrow,
+            ctx.accounts.white_stats.as_mut(),
+            ctx.accounts.black_stats.as_mut(),
+        )?;
+
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        game_escrow.game_state = GameState::Finished;
+        game_escrow.winner = GameWinner::Draw;
+        game_escrow.finished_at = clock.unix_timestamp;
+        game_escrow.funds_distributed = true;
+
+        emit!(GameDoublyAbandoned {
+            room_id: game_escrow.room_id.clone(),
+            last_move_time: game_escrow.last_move_time,
+            refund_per_player,
+            fee_per_player: fee_per_stake,
+        });
+        emit!(GameFinished {
+            room_id: game_escrow.room_id.clone(),
+            winner: GameWinner::Draw,
+            reason: GameEndReason::Abandonment,
+            finished_at: clock.unix_timestamp,
+            white_total_time_ms: game_escrow.white_timing.total_ms,
+            black_total_time_ms: game_escrow.black_timing.total_ms,
+        });
+
+        Ok(())
+    }
+
+    /// Handle timeout once the player to move is past their clock and the game's
+    /// `timeout_grace_seconds`. The opponent claims it; anyone may once the game has sat
+    /// `OPEN_TIMEOUT_CLAIM_SECONDS` beyond that.
+    pub fn handle_timeout(ctx: Context<HandleTimeout>) -> Result<()> {
+        let clock = Clock::get()?;
+        let finished_at = clock.unix_timestamp;
+        let claimant = ctx.accounts.claimant.key();
+        let room_id: String;
+        let winner: GameWinner;
+        let reason: GameEndReason;
+        {
+            let game_escrow = &mut ctx.accounts.game_escrow;
+        
+            // As in `declare_result`, only the game's first finish reaches the payout
+            require!(
+                game_escrow.game_state == GameState::InProgress,
+                ChessError::GameNotInProgress
+            );
+
+            let last_mover = if game_escrow.is_white_turn() {
+                GameWinner::Black
+            } else {
+                GameWinner::White
+            };
+            let auto_resign_below = if last_mover == GameWinner::Black {
+                game_escrow.white_auto_resign_below_seconds
+            } else {
+                game_escrow.black_auto_resign_below_seconds
+            };
+
+            let time_elapsed = game_escrow.mover_clock_elapsed(clock.unix_timestamp);
+            let (mover_remaining, opponent_remaining) = game_escrow.clocks_by_turn();
+            let overtime = game_escrow.mover_overtime(clock.unix_timestamp);
+            let grace = game_escrow.timeout_grace_seconds as i64;
+            let flagged = overtime > grace;
+            // Short of flagging, a mover who opted in resigns once their clock drops
+            // below their threshold
+            let auto_resigned = !flagged
+                && auto_resign_below > 0
+                && (mover_remaining as i64 - time_elapsed) < auto_resign_below as i64;
+            require!(flagged || auto_resigned, ChessError::TimeNotExceeded);
+            let opponent = if last_mover == GameWinner::White {
+                game_escrow.player_white
+            } else {
+                game_escrow.player_black
+            };
+            require!(
+                claimant == opponent || overtime > grace + GameEscrow::OPEN_TIMEOUT_CLAIM_SECONDS,
+                ChessError::TimeoutClaimNotAllowed
+            );
+
+            if let Some((board_winner, board_reason)) = game_escrow.board_result() {
+                // The game already ended on the board, before anyone's clock mattered; this
+                // is the same result a racing `declare_result` would be allowed to record
+                winner = game_escrow.scored_result(board_winner);
+                reason = board_reason;
+            } else {
+                // The player to move has flagged (or resigned), so the last mover wins unless
+                // their own clock is also empty and the game scores that as a draw
+                winner = game_escrow.scored_result(
+                    if flagged && opponent_remaining == 0 && game_escrow.double_flag_rule == DoubleFlagRule::Draw {
+                        GameWinner::Draw
+                    } else {
+                        last_mover
+                    },
+                );
+                reason = if auto_resigned { GameEndReason::Resignation } else { GameEndReason::Timeout };
+            }
+
+            game_escrow.winner = winner.clone();
+            game_escrow.game_state = GameState::Finished;
+            game_escrow.finished_at = finished_at;
+            room_id = game_escrow.room_id.clone();
+        }
+
+        ctx.accounts.distribute_funds(winner.clone(), ctx.bumps.game_vault)?;
+        ctx.accounts.game_escrow.funds_distributed = true;
+        record_player_stats(
+            &mut ctx.accounts.game_escrow,
+            ctx.accounts.white_stats.as_mut(),
+            ctx.accounts.black_stats.as_mut(),
+            &winner,
+            finished_at,
+        )?;
+        record_head_to_head(
+            &ctx.accounts.game_escrow,
+            ctx.accounts.head_to_head.as_mut(),
+            &winner,
+            finished_at,
+        )?;
+        update_ratings(
+            &mut ctx.accounts.game_escrow,
+            ctx.accounts.white_rating.as_mut(),
+            ctx.accounts.black_rating.as_mut(),
+            &winner,
+            false,
+            finished_at,
+        )?;
+
+        emit!(GameFinished {
+            room_id,
+            winner,
+            reason,
+            finished_at,
+            white_total_time_ms: ctx.accounts.game_escrow.white_timing.total_ms,
+            black_total_time_ms: ctx.accounts.game_escrow.black_timing.total_ms,
+        });
+
+        Ok(())
+    }
+
+    /// Pay out a game that was finished inside `record_move` (checkmate, forfeit).
+    /// Permissionless, since the result is already fixed on the escrow.
+    pub fn settle_game(ctx: Context<SettleGame>) -> Result<()> {
+        let game_escrow = &ctx.accounts.game_escrow;
+
+        require!(
+            game_escrow.game_state == GameState::Finished,
+            ChessError::GameNotFinished
+        );
+        require!(!game_escrow.funds_distributed, ChessError::FundsAlreadyDistributed);
+        require!(
+            !game_escrow.dispute_window_open(Clock::get()?.unix_timestamp),
+            ChessError::DisputeWindowOpen
+        );
+
+        let winner = game_escrow.winner.clone();
+        let finished_at = game_escrow.finished_at;
+        ctx.accounts.distribute_funds(winner.clone(), ctx.bumps.game_vault)?;
+        ctx.accounts.game_escrow.funds_distributed = true;
+        record_player_stats(
+            &mut ctx.accounts.game_escrow,
+            ctx.accounts.white_stats.as_mut(),
+            ctx.accounts.black_stats.as_mut(),
+            &winner,
+            finished_at,
+        )?;
+        record_head_to_head(
+            &ctx.accounts.game_escrow,
+            ctx.accounts.head_to_head.as_mut(),
+            &winner,
+            finished_at,
+        )?;
+        update_ratings(
+            &mut ctx.accounts.game_escrow,
+            ctx.accounts.white_rating.as_mut(),
+            ctx.accounts.black_rating.as_mut(),
+            &winner,
+            false,
+            finished_at,
+        )?;
+
+        Ok(())
+    }
+
+    /// Contest a declared result while the game's dispute window is open (either player).
+    /// The pot stays in the vault until a referee or moderator rules with `resolve_dispute`.
+    pub fn dispute_result(ctx: Context<PlayerConnection>) -> Result<()> {
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        let player_key = ctx.accounts.player.key();
+        let clock = Clock::get()?;
+
+        require!(
+            player_key == game_escrow.player_white || player_key == game_escrow.player_black,
+            ChessError::UnauthorizedPlayer
+        );
+        require!(
+            game_escrow.dispute_window_open(clock.unix_timestamp),
+            ChessError::DisputeWindowClosed
+        );
+
+        game_escrow.game_state = GameState::Disputed;
+        game_escrow.disputed_by = Some(player_key);
+
+        emit!(ResultDisputed {
+            room_id: game_escrow.room_id.clone(),
+            disputed_by: player_key,
+            declared_winner: game_escrow.winner.clone(),
+            disputed_at: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Rule on a disputed result (referee or moderator only): `winner` becomes the game's
+    /// result and the pot is paid out for it.
+    pub fn resolve_dispute(ctx: Context<ResolveDispute>, winner: GameWinner) -> Result<()> {
+        let clock = Clock::get()?;
+        let arbiter = ctx.accounts.arbiter.key();
+        let finished_at;
+        {
+            let game_escrow = &mut ctx.accounts.game_escrow;
+
+            require!(
+                ctx.accounts.config.is_result_authority(&arbiter),
+                ChessError::ModeratorNotListed
+            );
+            require!(game_escrow.game_state == GameState::Disputed, ChessError::NoDispute);
+            require!(
+                matches!(winner, GameWinner::White | GameWinner::Black | GameWinner::Draw),
+                ChessError::InvalidWinnerDeclaration
+            );
+
+            game_escrow.winner = game_escrow.scored_result(winner);
+            game_escrow.game_state = GameState::Finished;
+            game_escrow.disputed_by = None;
+            finished_at = game_escrow.finished_at;
+        }
+        let winner = ctx.accounts.game_escrow.winner.clone();
+
+        ctx.accounts.distribute_funds(winner.clone(), ctx.bumps.game_vault)?;
+        ctx.accounts.game_escrow.funds_distributed = true;
+        record_player_stats(
+            &mut ctx.accounts.game_escrow,
+            ctx.accounts.white_stats.as_mut(),
+            ctx.accounts.black_stats.as_mut(),
+            &winner,
+            finished_at,
+        )?;
+        record_head_to_head(
+            &ctx.accounts.game_escrow,
+            ctx.accounts.head_to_head.as_mut(),
+            &winner,
+            finished_at,
+        )?;
+        update_ratings(
+            &mut ctx.accounts.game_escrow,
+            ctx.accounts.white_rating.as_mut(),
+            ctx.accounts.black_rating.as_mut(),
+            &winner,
+            false,
+            finished_at,
+        )?;
+
+        emit!(DisputeResolved {
+            room_id: ctx.accounts.game_escrow.room_id.clone(),
+            arbiter,
+            winner,
+            resolved_at: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Pay out the token side of a cross-asset game, or the whole pot of a token game, once
+    /// it is finished or cancelled. Permissionless, like `settle_game`; the SOL side settles
+    /// through the usual paths.
+    pub fn settle_token_stake(ctx: Context<SettleTokenStake>) -> Result<()> {
+        let clock = Clock::get()?;
+        let game_escrow = &ctx.accounts.game_escrow;
+
+        require!(
+            game_escrow.game_state == GameState::Finished
+                || game_escrow.game_state == GameState::Cancelled,
+            ChessError::GameNotFinished
+        );
+        require!(
+            !game_escrow.token_funds_distributed,
+            ChessError::FundsAlreadyDistributed
+        );
+        require!(
+            !game_escrow.dispute_window_open(clock.unix_timestamp),
+            ChessError::DisputeWindowOpen
+        );
+
+        let pot = game_escrow.token_total_deposited;
+        let fee_exempt = is_fee_exempt(ctx.accounts.config.as_deref(), game_escrow);
+        if game_escrow.token_game {
+            require!(!game_escrow.review_required, ChessError::AntiCheatReviewPending);
+            let (fee_amount, white_amount, black_amount) = game_escrow.token_game_shares(pot, fee_exempt)?;
+            let vault_bump = ctx.bumps.game_vault;
+            for (recipient, amount) in [
+                (ctx.accounts.fee_token_account.to_account_info(), fee_amount),
+                (ctx.accounts.white_token_account.to_account_info(), white_amount),
+                (ctx.accounts.black_token_account.to_account_info(), black_amount),
+            ] {
+                ctx.accounts.transfer_from_vault(recipient, amount, vault_bump)?;
+            }
+
+            let game_escrow = &mut ctx.accounts.game_escrow;
+            game_escrow.token_funds_distributed = true;
+
+            emit!(TokenPotSettled {
+                room_id: game_escrow.room_id.clone(),
+                white_amount,
+                black_amount,
+                fee_amount,
+                settled_at: clock.unix_timestamp,
+            });
+            return Ok(());
+        }
+
+        let (fee_amount, winner_takes_all) = if game_escrow.game_state == GameState::Cancelled {
+            // Aborted and cancelled games are returned without rake
+            (0, false)
+        } else {
+            let fee = if fee_exempt { 0 } else { game_escrow.token_stake_fee(pot) };
+            match game_escrow.winner {
+                GameWinner::White => (fee, true),
+                GameWinner::Black | GameWinner::Draw => (fee, false),
+                GameWinner::None | GameWinner::Aborted => {
+                    return Err(ChessError::InvalidWinnerDeclaration.into())
+                }
+            }
+        };
+        let payout = pot.saturating_sub(fee_amount);
+        let (recipient, recipient_account) = if winner_takes_all {
+            (game_escrow.player_white, ctx.accounts.white_token_account.to_account_info())
+        } else {
+            (game_escrow.player_black, ctx.accounts.black_token_account.to_account_info())
+        };
+        // Won tokens can be held back for `winnings_lock_seconds`; a returned stake never is
+        let lock_winnings = winner_takes_all && game_escrow.winnings_lock_seconds > 0;
+
+        let vault_bump = ctx.bumps.game_vault;
+        ctx.accounts.transfer_from_vault(
+            ctx.accounts.fee_token_account.to_account_info(),
+            fee_amount,
+            vault_bump,
+        )?;
+        if !lock_winnings {
+            ctx.accounts.transfer_from_vault(recipient_account, payout, vault_bump)?;
+        }
+
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        game_escrow.token_funds_distributed = true;
+
+        if lock_winnings {
+            game_escrow.locked_winnings = payout;
+            game_escrow.winnings_unlock_at =
+                clock.unix_timestamp + game_escrow.winnings_lock_seconds as i64;
+
+            emit!(WinningsLocked {
+                room_id: game_escrow.room_id.clone(),
+                recipient,
+                amount: payout,
+                unlock_at: game_escrow.winnings_unlock_at,
+            });
+            return Ok(());
+        }
+
+        emit!(TokenStakeSettled {
+            room_id: game_escrow.room_id.clone(),
+            recipient,
+            amount: payout,
+            fee_amount,
+            settled_at: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Release time-locked token winnings to the winner once `winnings_unlock_at` has passed.
+    /// Until then they stay in the vault's token account, which only the program can move.
+    pub fn claim_winnings(ctx: Context<ClaimWinnings>) -> Result<()> {
+        let clock = Clock::get()?;
+        let game_escrow = &ctx.accounts.game_escrow;
+
+        let amount = game_escrow.locked_winnings;
+        require!(amount > 0, ChessError::NoLockedWinnings);
+        require!(
+            clock.unix_timestamp >= game_escrow.winnings_unlock_at,
+            ChessError::WinningsStillLocked
+        );
+
+        ctx.accounts.transfer_from_vault(amount, ctx.bumps.game_vault)?;
+
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        game_escrow.locked_winnings = 0;
+
+        emit!(TokenStakeSettled {
+            room_id: game_escrow.room_id.clone(),
+            recipient: game_escrow.player_white,
+            amount,
+            fee_amount: 0,
+            settled_at: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Withdraw the caller's share of a `pull_payouts` game, as recorded in `claimable`
+    /// when it was settled. Each share can be claimed once.
+    pub fn claim_payout(ctx: Context<ClaimPayout>) -> Result<()> {
+        let game_escrow = &ctx.accounts.game_escrow;
+        let player_key = ctx.accounts.player.key();
+
+        require!(
+            game_escrow.game_state == GameState::Finished && game_escrow.funds_distributed,
+            ChessError::GameNotSettled
+        );
+        let index = if player_key == game_escrow.player_white {
+            0
+        } else if player_key == game_escrow.player_black {
+            1
+        } else {
+            return Err(ChessError::UnauthorizedPlayer.into());
+        };
+        require!(game_escrow.claimable[index] > 0, ChessError::NothingToClaim);
+        // The last claim empties the vault, taking anything sent to it since settlement,
+        // since a remainder under the rent-exempt minimum couldn't stay behind
+        let amount = if game_escrow.claimable[1 - index] == 0 {
+            ctx.accounts.game_vault.lamports()
+        } else {
+            game_escrow.claimable[index]
+        };
+
+        let game_key = game_escrow.key();
+        let bump_bytes = [ctx.bumps.game_vault];
+        let seeds = &[
+            b"vault".as_ref(),
+            game_key.as_ref(),
+            bump_bytes.as_ref(),
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        anchor_lang::system_program::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.game_vault.to_account_info(),
+                    to: ctx.accounts.player.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            amount,
+        )?;
+
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        game_escrow.claimable[index] = 0;
+
+        emit!(PayoutClaimed {
+            room_id: game_escrow.room_id.clone(),
+            player: player_key,
+            amount,
+            claimed_at: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Both players agree to call off a game started by mistake. Only allowed in
+    /// the opening plies; each stake comes back in full and the game is `Aborted`.
+    pub fn mutual_abort(ctx: Context<MutualAbort>) -> Result<()> {
+        let clock = Clock::get()?;
+        let game_escrow = &ctx.accounts.game_escrow;
+
+        require!(
+            game_escrow.game_state == GameState::InProgress,
+            ChessError::GameNotInProgress
+        );
+        require!(
+            game_escrow.move_count <= GameEscrow::MUTUAL_ABORT_MOVE_LIMIT,
+            ChessError::AbortWindowClosed
+        );
+
+        // Aborts are never raked
+        let refund_per_player = game_escrow.stake_amount;
+        ctx.accounts.refund_stakes(0, ctx.bumps.game_vault)?;
+
+        release_active_games(
+            &mut ctx.accounts.game_escrow,
+            ctx.accounts.white_stats.as_mut(),
+            ctx.accounts.black_stats.as_mut(),
+        )?;
+
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        game_escrow.game_state = GameState::Cancelled;
+        game_escrow.winner = GameWinner::Aborted;
+        game_escrow.finished_at = clock.unix_timestamp;
+        game_escrow.funds_distributed = true;
+
+        emit!(GameMutuallyAborted {
+            room_id: game_escrow.room_id.clone(),
+            move_count: game_escrow.move_count,
+            refund_per_player,
+            aborted_at: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Turn anti-cheat off for the rest of an unrated game; needs both players' signatures
+    pub fn disable_anti_cheat(ctx: Context<DisableAntiCheat>) -> Result<()> {
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        let clock = Clock::get()?;
+
+        require!(
+            game_escrow.game_state == GameState::InProgress,
+            ChessError::GameNotInProgress
+        );
+        require!(!game_escrow.game_flags.is_rated, ChessError::RatedGameAntiCheat);
+
+        game_escrow.game_flags.enable_anti_cheat = false;
+        game_escrow.anti_cheat_flags = 0;
+
+        emit!(AntiCheatDisabled {
+            room_id: game_escrow.room_id.clone(),
+            move_count: game_escrow.move_count,
+            disabled_at: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Release a pot held for anti-cheat review (referee or moderator only). The game's
+    /// flags stay on record; only the hold on payouts is lifted.
+    pub fn clear_anti_cheat_review(ctx: Context<ClearAntiCheatReview>) -> Result<()> {
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        let moderator = ctx.accounts.moderator.key();
+        let clock = Clock::get()?;
+
+        require!(
+            ctx.accounts.config.is_result_authority(&moderator),
+            ChessError::ModeratorNotListed
+        );
+        require!(game_escrow.review_required, ChessError::NoAntiCheatReview);
+
+        game_escrow.review_required = false;
+
+        emit!(AntiCheatReviewCleared {
+            room_id: game_escrow.room_id.clone(),
+            moderator,
+            anti_cheat_flags: game_escrow.anti_cheat_flags,
+            cleared_at: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Claim a draw by threefold repetition or the fifty-move rule. The game is finished
+    /// here and paid out by `settle_game`.
+    pub fn claim_draw(ctx: Context<ClaimDraw>, claim: DrawClaim) -> Result<()> {
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        let player_key = *ctx.accounts.player.key;
+        let clock = Clock::get()?;
+
+        require!(
+            game_escrow.game_state == GameState::InProgress,
+            ChessError::GameNotInProgress
+        );
+        require!(
+            player_key == game_escrow.player_white || player_key == game_escrow.player_black,
+            ChessError::UnauthorizedPlayer
+        );
+        match game_escrow.draw_claims {
+            DrawClaims::Disabled => return Err(ChessError::DrawClaimsDisabled.into()),
+            DrawClaims::AnyPlayer => {}
+            DrawClaims::SideToMove => require!(
+                player_key == game_escrow.side_to_move(),
+                ChessError::NotYourClaim
+            ),
+        }
+
+        let reason = match claim {
+            DrawClaim::Repetition => {
+                let key = game_escrow.position().repetition_key();
+                let occurrences = game_escrow.position_keys.iter().filter(|k| **k == key).count();
+                require!(occurrences >= 3, ChessError::DrawClaimInvalid);
+                GameEndReason::Repetition
+            }
+            DrawClaim::FiftyMoves => {
+                require!(
+                    game_escrow.halfmove_clock >= GameEscrow::FIFTY_MOVE_PLIES,
+                    ChessError::DrawClaimInvalid
+                );
+                GameEndReason::FiftyMoveRule
+            }
+        };
+
+        let winner = game_escrow.scored_result(GameWinner::Draw);
+        game_escrow.winner = winner.clone();
+        game_escrow.game_state = GameState::Finished;
+        game_escrow.finished_at = clock.unix_timestamp;
+
+        emit!(GameFinished {
+            room_id: game_escrow.room_id.clone(),
+            winner,
+            reason,
+            finished_at: clock.unix_timestamp,
+            white_total_time_ms: game_escrow.white_timing.total_ms,
+            black_total_time_ms: game_escrow.black_timing.total_ms,
+        });
+
+        Ok(())
+    }
+
+    /// Draw a dead position in which neither side can mate any more (either player, in
+    /// standard chess). Like `claim_draw`, the game is paid out by `settle_game`.
+    pub fn claim_insufficient_material(ctx: Context<ClaimDraw>) -> Result<()> {
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        let player_key = *ctx.accounts.player.key;
+        let clock = Clock::get()?;
+
+        require!(
+            game_escrow.game_state == GameState::InProgress,
+            ChessError::GameNotInProgress
+        );
+        require!(
+            player_key == game_escrow.player_white || player_key == game_escrow.player_black,
+            ChessError::UnauthorizedPlayer
+        );
+        // Variants win by other means than mate, so bare kings aren't dead there
+        require!(game_escrow.variant == Variant::Standard, ChessError::DrawClaimInvalid);
+        require!(
+            game_escrow.position().has_insufficient_material(),
+            ChessError::MatingMaterialRemains
+        );
+
+        let winner = game_escrow.scored_result(GameWinner::Draw);
+        game_escrow.winner = winner.clone();
+        game_escrow.game_state = GameState::Finished;
+        game_escrow.finished_at = clock.unix_timestamp;
+
+        emit!(GameFinished {
+            room_id: game_escrow.room_id.clone(),
+            winner,
+            reason: GameEndReason::InsufficientMaterial,
+            finished_at: clock.unix_timestamp,
+            white_total_time_ms: game_escrow.white_timing.total_ms,
+            black_total_time_ms: game_escrow.black_timing.total_ms,
+        });
+
+        Ok(())
+    }
+
+    /// Berserk: before their first move a player halves their own clock in exchange
+    /// for a bigger rating gain if they go on to win
+    pub fn berserk(ctx: Context<Berserk>) -> Result<()> {
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        let player_key = *ctx.accounts.player.key;
+        let clock = Clock::get()?;
+
+        require!(
+            game_escrow.game_state == GameState::WaitingForDeposits
+                || game_escrow.game_state == GameState::InProgress,
+            ChessError::GameNotInProgress
+        );
+        let is_white = player_key == game_escrow.player_white;
+        require!(
+            is_white || player_key == game_escrow.player_black,
+            ChessError::UnauthorizedPlayer
+        );
+        // White's first move is ply 0, Black's is ply 1
+        let first_move_played = if is_white {
+            game_escrow.move_count > 0
+        } else {
+            game_escrow.move_count > 1
+        };
+        require!(!first_move_played, ChessError::BerserkTooLate);
+
+        let game: &mut GameEscrow = game_escrow;
+        let (berserked, time_remaining) = if is_white {
+            (&mut game.white_berserk, &mut game.white_time_remaining)
+        } else {
+            (&mut game.black_berserk, &mut game.black_time_remaining)
+        };
+        require!(!*berserked, ChessError::AlreadyBerserked);
+        *berserked = true;
+        *time_remaining /= 2;
+        let time_remaining = *time_remaining;
+
+        emit!(PlayerBerserked {
+            room_id: game_escrow.room_id.clone(),
+            player: player_key,
+            time_remaining,
+            berserked_at: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Add `seconds` to one or both clocks by mutual agreement; both players must sign.
+    /// Meant for friendly and correspondence games, so no clock may grow past
+    /// `MAX_EXTENDED_CLOCK_SECONDS`.
+    pub fn extend_time(ctx: Context<ExtendTime>, seconds: u64, clocks: ExtendedClocks) -> Result<()> {
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        let clock = Clock::get()?;
+
+        require!(
+            game_escrow.game_state == GameState::InProgress,
+            ChessError::GameNotInProgress
+        );
+        require!(seconds > 0, ChessError::InvalidTimeExtension);
+
+        let extend = |remaining: u64| -> Result<u64> {
+            let extended = remaining.saturating_add(seconds);
+            require!(
+                extended <= GameEscrow::MAX_EXTENDED_CLOCK_SECONDS,
+                ChessError::InvalidTimeExtension
+            );
+            Ok(extended)
+        };
+        if clocks != ExtendedClocks::Black {
+            game_escrow.white_time_remaining = extend(game_escrow.white_time_remaining)?;
+        }
+        if clocks != ExtendedClocks::White {
+            game_escrow.black_time_remaining = extend(game_escrow.black_time_remaining)?;
+        }
+
+        emit!(TimeExtended {
+            room_id: game_escrow.room_id.clone(),
+            seconds,
+            clocks,
+            white_time_remaining: game_escrow.white_time_remaining,
+            black_time_remaining: game_escrow.black_time_remaining,
+            extended_at: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Switch to a new time control by mutual agreement; both players must sign. Both clocks
+    /// restart from the new initial time, and the type must fit it (no 30-minute `Bullet`).
+    pub fn adjust_time_control(ctx: Context<AdjustTimeControl>, time_control: TimeControl) -> Result<()> {
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        let clock = Clock::get()?;
+
+        require!(
+            game_escrow.game_state == GameState::InProgress,
+            ChessError::GameNotInProgress
+        );
+        require!(
+            time_control
+                .time_control_type
+                .admits_initial_time(time_control.initial_time),
+            ChessError::InconsistentTimeControl
+        );
+        if game_escrow.game_flags.is_rated {
+            let min_initial_time = ctx
+                .accounts
+                .config
+                .as_ref()
+                .map_or(0, |config| config.min_rated_initial_time);
+            require!(
+                time_control.initial_time >= min_initial_time as u64,
+                ChessError::TimeControlTooFastForRated
+            );
+        }
+
+        let initial_time = time_control.initial_time;
+        game_escrow.time_limit_seconds = initial_time as i64;
+        game_escrow.white_time_remaining = initial_time;
+        game_escrow.black_time_remaining = if game_escrow.armageddon {
+            (initial_time * GameEscrow::ARMAGEDDON_BLACK_TIME_PCT / 100).max(1)
+        } else {
+            initial_time
+        };
+        game_escrow.time_control = time_control.clone();
+
+        emit!(TimeControlAdjusted {
+            room_id: game_escrow.room_id.clone(),
+            time_control,
+            white_time_remaining: game_escrow.white_time_remaining,
+            black_time_remaining: game_escrow.black_time_remaining,
+            adjusted_at: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Cancel game (only if not started or both players agree)
+    pub fn cancel_game(ctx: Context<CancelGame>) -> Result<()> {
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        
+        require!(
+            game_escrow.game_state == GameState::WaitingForPlayers ||
+            game_escrow.game_state == GameState::WaitingForDeposits,
+            ChessError::CannotCancelStartedGame
+        );
+
+        let player_key = *ctx.accounts.player.key;
+        require!(
+            player_key == game_escrow.player_white || player_key == game_escrow.player_black,
+            ChessError::UnauthorizedPlayer
+        );
+
+        // Refund any deposited stakes
+        let vault_balance = ctx.accounts.game_vault.lamports();
+        if vault_balance > 0 {
+            // Refund logic here
+            let game_key = game_escrow.key();
+            let vault_bump = ctx.bumps.game_vault;
+            let bump_bytes = [vault_bump];
+            
+            let seeds = &[
+                b"vault".as_ref(),
+                game_key.as_ref(),
+                bump_bytes.as_ref(),
+            ];
+            let signer_seeds = &[&seeds[..]];
+
+            // Refund white player if they deposited SOL
+            if game_escrow.white_deposited && !game_escrow.token_game {
+                anchor_lang::system_program::transfer(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.system_program.to_account_info(),
+                        anchor_lang::system_program::Transfer {
+                            from: ctx.accounts.game_vault.to_account_info(),
+                            to: ctx.accounts.player_white.to_account_info(),
+                        },
+                        signer_seeds,
+                    ),
+                    game_escrow.stake_amount,
+                )?;
+                emit!(DepositRefunded {
+                    room_id: game_escrow.room_id.clone(),
+                    player: game_escrow.player_white,
+                    amount: game_escrow.stake_amount,
+                });
+            }
+
+            // Refund black player if they deposited SOL (token stakes go through settle_token_stake),
+            // or just their bond if they hadn't
+            let black_refund = if game_escrow.black_deposited && game_escrow.token_mint.is_none() {
+                game_escrow.stake_amount
+            } else if !game_escrow.black_deposited && game_escrow.black_bond_posted {
+                game_escrow.griefing_bond
+            } else {
+                0
+            };
+            if black_refund > 0 {
+                anchor_lang::system_program::transfer(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.system_program.to_account_info(),
+                        anchor_lang::system_program::Transfer {
+                            from: ctx.accounts.game_vault.to_account_info(),
+                            to: ctx.accounts.player_black.to_account_info(),
+                        },
+                        signer_seeds,
+                    ),
+                    black_refund,
+                )?;
+                emit!(DepositRefunded {
+                    room_id: game_escrow.room_id.clone(),
+                    player: game_escrow.player_black,
+                    amount: black_refund,
+                });
+            }
+
+            // Whatever is left was sent to the vault directly; leaving it would strand a
+            // rent-paying balance the refunds above would have been refused over
+            let surplus = ctx.accounts.game_vault.lamports();
+            if surplus > 0 {
+                anchor_lang::system_program::transfer(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.system_program.to_account_info(),
+                        anchor_lang::system_program::Transfer {
+                            from: ctx.accounts.game_vault.to_account_info(),
+                            to: ctx.accounts.fee_collector.to_account_info(),
+                        },
+                        signer_seeds,
+                    ),
+                    surplus,
+                )?;
+                emit!(VaultSurplusSwept {
+                    room_id: game_escrow.room_id.clone(),
+                    amount: surplus,
+                });
+            }
+        }
+
+        game_escrow.game_state = GameState::Cancelled;
+        game_escrow.winner = GameWinner::Aborted;
+
+        emit!(GameCancelled {
+            room_id: game_escrow.room_id.clone(),
+            cancelled_by: player_key,
+        });
+
+        Ok(())
+    }
+
+    /// Reclaim a deposit the opponent never matched and cancel the game. Once the
+    /// `deposit_deadline` has passed, the player who deposited gets their stake back,
+    /// plus Black's griefing bond if Black is the one who didn't deposit.
+    pub fn reclaim_unmatched_deposit(ctx: Context<CancelGame>) -> Result<()> {
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        let player_key = *ctx.accounts.player.key;
+        let clock = Clock::get()?;
+
+        require!(
+            game_escrow.game_state == GameState::WaitingForDeposits,
+            ChessError::InvalidGameStateForDeposit
+        );
+        let white_waiting = game_escrow.white_deposited && !game_escrow.black_deposited;
+        let black_waiting = game_escrow.black_deposited && !game_escrow.white_deposited;
+        require!(
+            (white_waiting && player_key == game_escrow.player_white)
+                || (black_waiting && player_key == game_escrow.player_black),
+            ChessError::UnauthorizedPlayer
+        );
+        // Token stakes are returned through settle_token_stake
+        require!(
+            if white_waiting { !game_escrow.token_game } else { game_escrow.token_mint.is_none() },
+            ChessError::StakeAssetMismatch
+        );
+        require!(
+            clock.unix_timestamp >= game_escrow.deposit_deadline(),
+            ChessError::DepositWindowOpen
+        );
+
+        let bond_forfeited = if white_waiting && game_escrow.black_bond_posted {
+            game_escrow.griefing_bond
+        } else {
+            0
+        };
+        let refund = game_escrow.stake_amount + bond_forfeited;
+
+        let game_key = game_escrow.key();
+        let bump_bytes = [ctx.bumps.game_vault];
+        let seeds = &[
+            b"vault".as_ref(),
+            game_key.as_ref(),
+            bump_bytes.as_ref(),
+        ];
+        let signer_seeds = &[&seeds[..]];
+        anchor_lang::system_program::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.game_vault.to_account_info(),
+                    to: ctx.accounts.player.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            refund,
+        )?;
+
+        // Nothing else is owed from the vault, so anything left was sent to it directly
+        let surplus = ctx.accounts.game_vault.lamports();
+        if surplus > 0 {
+            anchor_lang::system_program::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.game_vault.to_account_info(),
+                        to: ctx.accounts.fee_collector.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                surplus,
+            )?;
+            emit!(VaultSurplusSwept {
+                room_id: game_escrow.room_id.clone(),
+                amount: surplus,
+            });
+        }
+
+        game_escrow.game_state = GameState::Cancelled;
+        game_escrow.winner = GameWinner::Aborted;
+
+        emit!(DepositRefunded {
+            room_id: game_escrow.room_id.clone(),
+            player: player_key,
+            amount: refund,
+        });
+        emit!(UnmatchedDepositReclaimed {
+            room_id: game_escrow.room_id.clone(),
+            player: player_key,
+            refund,
+            bond_forfeited,
+            reclaimed_at: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Take the creator's stake back while no opponent has joined. The room stays open,
+    /// so the creator can deposit again once someone joins.
+    pub fn withdraw_stake(ctx: Context<WithdrawStake>) -> Result<()> {
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        let player_key = ctx.accounts.player.key();
+
+        require!(
+            game_escrow.player_white == Pubkey::default()
+                || game_escrow.player_black == Pubkey::default(),
+            ChessError::GameAlreadyHasOpponent
+        );
+        require_keys_eq!(
+            player_key,
+            game_escrow.seated_player(),
+            ChessError::UnauthorizedPlayer
+        );
+        require!(
+            game_escrow.game_state == GameState::WaitingForPlayers,
+            ChessError::InvalidGameStateForDeposit
+        );
+        let is_white = player_key == game_escrow.player_white;
+        require!(
+            if is_white { game_escrow.white_deposited } else { game_escrow.black_deposited },
+            ChessError::NoStakeToWithdraw
+        );
+        // Token stakes are returned through settle_token_stake
+        require!(!game_escrow.token_game, ChessError::StakeAssetMismatch);
+
+        let refund = game_escrow.stake_amount;
+        let game_key = game_escrow.key();
+        let bump_bytes = [ctx.bumps.game_vault];
+        let seeds = &[
+            b"vault".as_ref(),
+            game_key.as_ref(),
+            bump_bytes.as_ref(),
+        ];
+        let signer_seeds = &[&seeds[..]];
+        if refund > 0 {
+            anchor_lang::system_program::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.game_vault.to_account_info(),
+                        to: ctx.accounts.player.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                refund,
+            )?;
+        }
+
+        // With no opponent seated this was the only deposit, so anything left in the vault
+        // was sent to it directly
+        let surplus = ctx
+            .accounts
+            .game_vault
+            .lamports()
+            .saturating_sub(game_escrow.total_deposited.saturating_sub(refund));
+        if surplus > 0 {
+            anchor_lang::system_program::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.game_vault.to_account_info(),
+                        to: ctx.accounts.fee_collector.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                surplus,
+            )?;
+            emit!(VaultSurplusSwept {
+                room_id: game_escrow.room_id.clone(),
+                amount: surplus,
+            });
+        }
+
+        if is_white {
+            game_escrow.white_deposited = false;
+        } else {
+            game_escrow.black_deposited = false;
+        }
+        game_escrow.total_deposited = game_escrow.total_deposited.saturating_sub(refund);
+        game_escrow.first_deposit_at = 0;
+
+        emit!(DepositRefunded {
+            room_id: game_escrow.room_id.clone(),
+            player: player_key,
+            amount: refund,
+        });
+
+        Ok(())
+    }
+
+    /// Record the hash of a spectator chat message so a moderated off-chain log can be
+    /// checked against it. Only the last `MAX_CHAT_HASHES` stay on the game; every hash
+    /// is emitted with its sequence number.
+    pub fn post_chat_hash(ctx: Context<PostChatHash>, message_hash: [u8; 32]) -> Result<()> {
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        let sender = *ctx.accounts.sender.key;
+        let clock = Clock::get()?;
+
+        require!(game_escrow.spectator_chat, ChessError::ChatDisabled);
+
+        if game_escrow.chat_hashes.len() >= GameEscrow::MAX_CHAT_HASHES {
+            game_escrow.chat_hashes.remove(0);
+        }
+        game_escrow.chat_hashes.push(ChatHash {
+            sender,
+            message_hash,
+            posted_at: clock.unix_timestamp,
+        });
+        game_escrow.chat_hash_count += 1;
+
+        emit!(ChatHashPosted {
+            room_id: game_escrow.room_id.clone(),
+            sender,
+            message_hash,
+            sequence: game_escrow.chat_hash_count,
+            posted_at: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Redirect the rent reclaimed by `close_game` (current beneficiary only). The
+    /// creator paid the rent and starts as the beneficiary.
+    pub fn set_rent_beneficiary(ctx: Context<SetRentBeneficiary>, new_beneficiary: Pubkey) -> Result<()> {
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        let clock = Clock::get()?;
+
+        require!(
+            new_beneficiary != Pubkey::default() && new_beneficiary != game_escrow.key(),
+            ChessError::InvalidRentBeneficiary
+        );
+        let previous = game_escrow.rent_beneficiary;
+        game_escrow.rent_beneficiary = new_beneficiary;
+
+        emit!(RentBeneficiaryChanged {
+            room_id: game_escrow.room_id.clone(),
+            previous,
+            rent_beneficiary: new_beneficiary,
+            changed_at: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Close a settled game, returning its rent to the rent beneficiary. Anyone may
+    /// close once the pot is paid out (or the game cancelled), any token pot is settled
+    /// and no winnings are locked.
+    pub fn close_game(ctx: Context<CloseGame>) -> Result<()> {
+        let game_escrow = &ctx.accounts.game_escrow;
+
+        let settled = (game_escrow.game_state == GameState::Finished && game_escrow.funds_distributed)
+            || game_escrow.game_state == GameState::Cancelled;
+        require!(settled && game_escrow.locked_winnings == 0, ChessError::GameNotSettled);
+        // Side bets are paid out against this account's result, so it outlives them
+        require!(game_escrow.open_side_bets == 0, ChessError::GameNotSettled);
+        require!(game_escrow.claimable == [0; 2], ChessError::GameNotSettled);
+        // Only this account can release the vault's tokens, so it outlives any token pot
+        require!(
+            game_escrow.token_mint.is_none()
+                || game_escrow.token_funds_distributed
+                || game_escrow.token_total_deposited == 0,
+            ChessError::GameNotSettled
+        );
+
+        emit!(GameClosed {
+            room_id: game_escrow.room_id.clone(),
+            rent_beneficiary: game_escrow.rent_beneficiary,
+            rent_reclaimed: game_escrow.to_account_info().lamports(),
+        });
+
+        Ok(())
+    }
+
+    /// Offer the opponent a rematch of a finished game, colours swapped
+    pub fn propose_rematch(ctx: Context<PlayerConnection>) -> Result<()> {
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        let player_key = *ctx.accounts.player.key;
+
+        require!(
+            game_escrow.game_state == GameState::Finished,
+            ChessError::GameNotFinished
+        );
+        require!(
+            player_key == game_escrow.player_white || player_key == game_escrow.player_black,
+            ChessError::UnauthorizedPlayer
+        );
+        require!(
+            game_escrow.rematch_proposed_by.is_none(),
+            ChessError::RematchAlreadyProposed
+        );
+
+        game_escrow.rematch_proposed_by = Some(player_key);
+
+        emit!(RematchProposed {
+            room_id: game_escrow.room_id.clone(),
+            proposed_by: player_key,
+        });
+
+        Ok(())
+    }
+
+    /// Withdraw the caller's pending rematch proposal
+    pub fn withdraw_rematch(ctx: Context<PlayerConnection>) -> Result<()> {
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        let player_key = *ctx.accounts.player.key;
+
+        let proposed_by = game_escrow
+            .rematch_proposed_by
+            .ok_or(ChessError::NoRematchProposed)?;
+        require_keys_eq!(proposed_by, player_key, ChessError::UnauthorizedPlayer);
+
+        game_escrow.rematch_proposed_by = None;
+
+        emit!(RematchWithdrawn {
+            room_id: game_escrow.room_id.clone(),
+            proposed_by,
+        });
+
+        Ok(())
+    }
+
+    /// Accept the opponent's rematch proposal, creating game `room_id` between the same
+    /// two players with colours swapped, on the same stake and settings. It waits for
+    /// both deposits, like a game both players have joined; the caller pays its rent.
+    pub fn accept_rematch(ctx: Context<AcceptRematch>, room_id: String) -> Result<()> {
+        require!(room_id.len() <= 32, ChessError::RoomIdTooLong);
+        let player_key = *ctx.accounts.player.key;
+        let clock = Clock::get()?;
+
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        require!(
+            game_escrow.game_state == GameState::Finished,
+            ChessError::GameNotFinished
+        );
+        require!(
+            player_key == game_escrow.player_white || player_key == game_escrow.player_black,
+            ChessError::UnauthorizedPlayer
+        );
+        let proposed_by = game_escrow
+            .rematch_proposed_by
+            .ok_or(ChessError::NoRematchProposed)?;
+        require!(proposed_by != player_key, ChessError::CannotAcceptOwnRematch);
+        game_escrow.rematch_proposed_by = None;
+
+        let rematch = game_escrow.rematch(room_id, player_key, clock.unix_timestamp);
+        throttle_rated_pair(
+            &rematch,
+            ctx.accounts.pair_history.as_mut(),
+            rematch.player_black,
+            clock.unix_timestamp,
+        )?;
+        let previous_room_id = game_escrow.room_id.clone();
+        let rematch_escrow = &mut ctx.accounts.rematch_escrow;
+        rematch_escrow.set_inner(rematch);
+
+        emit!(GameCreated {
+            room_id: rematch_escrow.room_id.clone(),
+            player_white: rematch_escrow.player_white,
+            player_black: rematch_escrow.player_black,
+            stake_amount: rematch_escrow.stake_amount,
+            created_at: clock.unix_timestamp,
+            metadata: rematch_escrow.metadata.clone(),
+        });
+        emit!(RematchAccepted {
+            room_id: previous_room_id,
+            rematch_room_id: rematch_escrow.room_id.clone(),
+            player_white: rematch_escrow.player_white,
+            player_black: rematch_escrow.player_black,
+            stake_amount: rematch_escrow.stake_amount,
+        });
+
+        // A free game has nothing to deposit, so its rematch starts straight away
+        if rematch_escrow.stake_amount == 0 {
+            rematch_escrow.white_deposited = true;
+            rematch_escrow.black_deposited = true;
+            start_game_with_stats(rematch_escrow, &ctx.accounts.white_stats, &ctx.accounts.black_stats)?;
+        }
+
+        Ok(())
+    }
+
+    /// Create a new tournament
+    pub fn create_tournament(
+        ctx: Context<CreateTournament>,
+        tournament_id: String,
+        name: String,
+        entry_fee: u64,
+        max_participants: u32,
+        time_control: TimeControl,
+        options: TournamentOptions
+    ) -> Result<()> {
+        require!(tournament_id.len() <= 32, ChessError::RoomIdTooLong);
+        require!(name.len() <= 64, ChessError::InvalidStakeAmount);
+        require!(entry_fee > 0, ChessError::InvalidStakeAmount);
+        require!(max_participants >= 2, ChessError::InvalidStakeAmount);
+        require!(
+            max_participants <= Tournament::MAX_PARTICIPANTS,
+            ChessError::TooManyParticipants
+        );
+        require!(options.form_weight_pct <= 100, ChessError::InvalidSeedingWeight);
+        require!(
+            !options.payout_pcts.is_empty()
+                && options.payout_pcts.len() <= Tournament::MAX_PAYOUT_PLACES
+                && options.payout_pcts.iter().all(|&pct| pct > 0)
+                && options.payout_pcts.iter().map(|&pct| pct as u32).sum::<u32>() == 100,
+            ChessError::InvalidPayoutStructure
+        );
+        
+        let tournament = &mut ctx.accounts.tournament;
+        let clock = Clock::get()?;
+        
+        tournament.tournament_id = tournament_id;
+        tournament.name = name;
+        tournament.creator = *ctx.accounts.creator.key;
+        tournament.entry_fee = entry_fee;
+        tournament.max_participants = max_participants;
+        tournament.current_participants = 0;
+        tournament.status = TournamentStatus::Registration;
+        tournament.time_control = time_control;
+        tournament.created_at = clock.unix_timestamp;
+        tournament.started_at = 0;
+        tournament.finished_at = 0;
+        tournament.prize_pool = 0;
+        tournament.participants = Vec::new();
+        tournament.brackets = Vec::new();
+        tournament.seeding_mode = options.seeding_mode;
+        tournament.form_weight_pct = options.form_weight_pct;
+        tournament.seeds = Vec::new();
+        tournament.seeded_round = 0;
+        tournament.flag_tallies = Vec::new();
+        tournament.shuffle_seed = [0; 32];
+        tournament.tournament_version = Tournament::CURRENT_VERSION;
+        tournament.payout_pcts = options.payout_pcts;
+        tournament.guaranteed_pool = options.guaranteed_pool;
+        tournament.late_registration_seconds = options.late_registration_seconds;
+        tournament.winners = Vec::new();
+        tournament.bracket_round = 0;
+        tournament.bracket_pairings = Vec::new();
+        tournament.bracket_bye = None;
+        tournament.bracket_champion = None;
+
+        // The creator funds the entry-fee vault's rent so every fee is held in full
+        let rent_reserve = Rent::get()?.minimum_balance(0);
+        let vault_balance = ctx.accounts.tournament_vault.lamports();
+        if vault_balance < rent_reserve {
+            anchor_lang::system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.creator.to_account_info(),
+                        to: ctx.accounts.tournament_vault.to_account_info(),
+                    },
+                ),
+                rent_reserve - vault_balance,
+            )?;
+        }
+        
+        emit!(TournamentCreated {
+            tournament_id: tournament.tournament_id.clone(),
+            creator: tournament.creator,
+            entry_fee,
+            max_participants,
+            created_at: clock.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+
+    /// Join a tournament. Once it has started, players may still join for
+    /// `late_registration_seconds`; they enter the standings on zero points and are
+    /// paired from the next round, and the standings account must be passed.
+    pub fn join_tournament(ctx: Context<JoinTournament>) -> Result<()> {
+        let tournament = &mut ctx.accounts.tournament;
+        let player_key = *ctx.accounts.player.key;
+        let clock = Clock::get()?;
+        
+        let late = tournament.status == TournamentStatus::Active
+            && clock.unix_timestamp
+                <= tournament.started_at + tournament.late_registration_seconds as i64;
+        require!(
+            tournament.status == TournamentStatus::Registration || late,
+            ChessError::TournamentAlreadyStarted
+        );
+        
+        require!(
+            tournament.current_participants < tournament.max_participants,
+            ChessError::InvalidStakeAmount
+        );
+        
+        // Check if player already joined
+        if tournament.participants.contains(&player_key) {
+            return Err(ChessError::AlreadyDeposited.into());
+        }
+        
+        // Entry fees are held in the tournament vault until payout
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.player.to_account_info(),
+                    to: ctx.accounts.tournament_vault.to_account_info(),
+                },
+            ),
+            tournament.entry_fee,
+        )?;
+
+        tournament.participants.push(player_key);
+        tournament.current_participants += 1;
+
+        if late {
+            tournament.prize_pool += tournament.entry_fee;
+
+            let standings = ctx
+                .accounts
+                .standings
+                .as_mut()
+                .ok_or(ChessError::StandingsAccountMissing)?;
+            let info = standings.to_account_info();
+            let space = 8
+                + TournamentStandings::space_for(tournament.current_participants)
+                + TournamentStandings::MATCH_SPACE * standings.results.len();
+            if info.data_len() < space {
+                let shortfall = Rent::get()?.minimum_balance(space).saturating_sub(info.lamports());
+                if shortfall > 0 {
+                    anchor_lang::system_program::transfer(
+                        CpiContext::new(
+                            ctx.accounts.system_program.to_account_info(),
+                            anchor_lang::system_program::Transfer {
+                                from: ctx.accounts.player.to_account_info(),
+                                to: info.clone(),
+                            },
+                        ),
+                        shortfall,
+                    )?;
+                }
+                info.resize(space)?;
+            }
+
+            standings.entries.push(StandingEntry {
+                player: player_key,
+                wins: 0,
+                losses: 0,
+                draws: 0,
+                points: 0,
+                sonneborn_berger: 0,
+            });
+            standings.sort();
+        }
+        
+        emit!(PlayerJoinedTournament {
+            tournament_id: tournament.tournament_id.clone(),
+            player: player_key,
+            joined_at: clock.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+
+    /// Start a tournament
+    pub fn start_tournament(ctx: Context<StartTournament>) -> Result<()> {
+        let tournament = &mut ctx.accounts.tournament;
+        let clock = Clock::get()?;
+        
+        require!(
+            tournament.status == TournamentStatus::Registration,
+            ChessError::TournamentAlreadyStarted
+        );
+        
+        require!(
+            tournament.current_participants >= 2,
+            ChessError::InvalidStakeAmount
+        );
+        
+        tournament.status = TournamentStatus::Active;
+        tournament.started_at = clock.unix_timestamp;
+        tournament.prize_pool = tournament.entry_fee * tournament.current_participants as u64;
+
+        // The pool must be exactly what the vault holds above its rent reserve
+        let held = ctx
+            .accounts
+            .tournament_vault
+            .lamports()
+            .saturating_sub(Rent::get()?.minimum_balance(0));
+        require!(held == tournament.prize_pool, ChessError::PrizePoolMismatch);
+
+        // Every registered player starts the event on zero points
+        let standings = &mut ctx.accounts.standings;
+        standings.tournament = tournament.key();
+        standings.entries = tournament
+            .participants
+            .iter()
+            .map(|player| StandingEntry {
+                player: *player,
+                wins: 0,
+                losses: 0,
+                draws: 0,
+                points: 0,
+                sonneborn_berger: 0,
+            })
+            .collect();
+        standings.results = Vec::new();
+        standings.sort();
+        
+        emit!(TournamentStarted {
+            tournament_id: tournament.tournament_id.clone(),
+            started_at: clock.unix_timestamp,
+            participants: tournament.current_participants,
+            prize_pool: tournament.prize_pool,
+        });
+        
+        Ok(())
+    }
+
+    /// Report the result of a tournament match (creator only) and update standings.
+    /// The players and result are read from the finished game, which can only be
+    /// reported once.
+    pub fn report_match_result(ctx: Context<ReportMatchResult>) -> Result<()> {
+        grow_account(
+            ctx.accounts.standings.to_account_info(),
+            TournamentStandings::space_for_next_result(&ctx.accounts.tournament, &ctx.accounts.standings),
+            &ctx.accounts.creator,
+            &ctx.accounts.system_program,
+        )?;
+        let tournament = &ctx.accounts.tournament;
+        let game_escrow = &ctx.accounts.game_escrow;
+        let game = game_escrow.key();
+        let clock = Clock::get()?;
+
+        require!(
+            tournament.status == TournamentStatus::Active,
+            ChessError::TournamentNotActive
+        );
+        require!(
+            game_escrow.game_state == GameState::Finished,
+            ChessError::GameNotFinished
+        );
+        // A game linked for flags must have been linked to this tournament
+        require!(
+            game_escrow
+                .tournament_id
+                .as_ref()
+                .is_none_or(|id| *id == tournament.tournament_id),
+            ChessError::GameAlreadyLinked
+        );
+        let white = game_escrow.player_white;
+        let black = game_escrow.player_black;
+        let result = game_escrow.winner.clone();
+        require!(white != black, ChessError::CannotPlayAgainstSelf);
+        require!(
+            tournament.participants.contains(&white) && tournament.participants.contains(&black),
+            ChessError::PlayerNotInTournament
+        );
+
+        let (white_result, black_result) = result
+            .outcomes()
+            .ok_or(ChessError::InvalidWinnerDeclaration)?;
+
+        let standings = &mut ctx.accounts.standings;
+        require!(
+            standings.results.iter().all(|reported| reported.game != game),
+            ChessError::MatchAlreadyReported
+        );
+        standings.record(&white, white_result)?;
+        standings.record(&black, black_result)?;
+        standings.results.push(ReportedMatch {
+            game,
+            white,
+            black,
+            result: result.clone(),
+        });
+        standings.update_tiebreaks();
+        standings.sort();
+
+        emit!(MatchResultReported {
+            tournament_id: tournament.tournament_id.clone(),
+            game,
+            white,
+            black,
+            result,
+            reported_at: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Link a finished game between two participants to the tournament and add each
+    /// player's anti-cheat flags from it to their tournament tally (creator only).
+    /// A game can only be linked, and so counted, once.
+    pub fn report_tournament_flag(ctx: Context<ReportTournamentFlag>) -> Result<()> {
+        let tournament = &mut ctx.accounts.tournament;
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        let clock = Clock::get()?;
+
+        require!(
+            tournament.status == TournamentStatus::Active,
+            ChessError::TournamentNotActive
+        );
+        require!(
+            game_escrow.game_state == GameState::Finished,
+            ChessError::GameNotFinished
+        );
+        require!(
+            tournament.participants.contains(&game_escrow.player_white)
+                && tournament.participants.contains(&game_escrow.player_black),
+            ChessError::PlayerNotInTournament
+        );
+        require!(game_escrow.tournament_id.is_none(), ChessError::GameAlreadyLinked);
+
+        game_escrow.tournament_id = Some(tournament.tournament_id.clone());
+        game_escrow.game_flags.is_tournament_game = true;
+
+        for (player, flags) in [
+            (game_escrow.player_white, game_escrow.white_flag_count),
+            (game_escrow.player_black, game_escrow.black_flag_count),
+        ] {
+            if flags == 0 {
+                continue;
+            }
+            let index = match tournament.flag_tallies.iter().position(|t| t.player == player) {
+                Some(index) => index,
+                None => {
+                    tournament.flag_tallies.push(FlagTally { player, flags: 0 });
+                    tournament.flag_tallies.len() - 1
+                }
+            };
+            let tally = &mut tournament.flag_tallies[index];
+            tally.flags = tally.flags.saturating_add(flags as u32);
+            let total_flags = tally.flags;
+
+            emit!(TournamentFlagReported {
+                tournament_id: tournament.tournament_id.clone(),
+                room_id: game_escrow.room_id.clone(),
+                player,
+                game_flags: flags,
+                total_flags,
+                reported_at: clock.unix_timestamp,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Rewrite a tournament from an older layout into the current one (creator only). Participants, brackets, seeds and standings carry over
+    /// untouched, so an in-flight tournament resumes where it left off; the creator pays
+    /// for any extra space.
+    pub fn migrate_tournament(ctx: Context<MigrateTournament>) -> Result<()> {
+        let info = ctx.accounts.tournament.to_account_info();
+        let clock = Clock::get()?;
+
+        let (from_version, tournament) = {
+            let data = info.try_borrow_data()?;
+            require!(
+                data.len() >= 8 && data[..8] == *Tournament::DISCRIMINATOR,
+                ChessError::InvalidTournamentAccount
+            );
+            let mut rest: &[u8] = &data[8..];
+            let legacy = LegacyTournament::deserialize(&mut rest)?;
+            // Version 0 ends at `shuffle_seed` (anything after is zeroed slack); later
+            // layouts follow it with their version byte
+            let from_version = rest.first().copied().unwrap_or(0);
+            let mut tournament = legacy.into_current();
+            // Version 2 added the payout structure right after the version byte, version 3
+            // the late-registration window after that and version 4 the winners
+            if from_version >= 2 {
+                let mut payouts: &[u8] = &rest[1..];
+                tournament.payout_pcts = Vec::<u8>::deserialize(&mut payouts)?;
+                tournament.guaranteed_pool = u64::deserialize(&mut payouts)?;
+                if from_version >= 3 {
+                    tournament.late_registration_seconds = u32::deserialize(&mut payouts)?;
+                }
+                if from_version >= 4 {
+                    tournament.winners = Vec::<Pubkey>::deserialize(&mut payouts)?;
+                }
+            }
+            (from_version, tournament)
+        };
+        require!(
+            from_version < Tournament::CURRENT_VERSION,
+            ChessError::TournamentAlreadyMigrated
+        );
+        require_keys_eq!(
+            tournament.creator,
+            ctx.accounts.creator.key(),
+            ChessError::UnauthorizedPlayer
+        );
+
+        let space = 8 + Tournament::space_for(tournament.max_participants);
+        if info.data_len() < space {
+            let shortfall = Rent::get()?.minimum_balance(space).saturating_sub(info.lamports());
+            if shortfall > 0 {
+                anchor_lang::system_program::transfer(
+                    CpiContext::new(
+                        ctx.accounts.system_program.to_account_info(),
+                        anchor_lang::system_program::Transfer {
+                            from: ctx.accounts.creator.to_account_info(),
+                            to: info.clone(),
+                        },
+                    ),
+                    shortfall,
+                )?;
+            }
+            info.resize(space)?;
+        }
+
+        let mut data = info.try_borrow_mut_data()?;
+        let mut writer: &mut [u8] = &mut data[8..];
+        tournament.serialize(&mut writer)?;
+
+        emit!(TournamentMigrated {
+            tournament_id: tournament.tournament_id.clone(),
+            from_version,
+            to_version: Tournament::CURRENT_VERSION,
+            migrated_at: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Pay out the prize pool by final standings and finish the tournament (creator only).
+    /// `remaining_accounts` holds the paid finishers, in standings order. The pool is the
+    /// guaranteed pool or the entry fees, whichever is larger, but never more than the
+    /// vault really holds; an under-subscribed field shares only what was paid in, and
+    /// paid places beyond the field size are folded into the rest (see `Tournament::payouts`).
+    pub fn finalize_tournament<'info>(
+        ctx: Context<'_, '_, 'info, 'info, FinalizeTournament<'info>>,
+    ) -> Result<()> {
+        let tournament_key = ctx.accounts.tournament.key();
+        let tournament = &mut ctx.accounts.tournament;
+        let clock = Clock::get()?;
+
+        require!(
+            tournament.status == TournamentStatus::Active,
+            ChessError::TournamentNotActive
+        );
+
+        let held = ctx
+            .accounts
+            .tournament_vault
+            .lamports()
+            .saturating_sub(Rent::get()?.minimum_balance(0));
+        let advertised = tournament.guaranteed_pool.max(tournament.prize_pool);
+        let pool = advertised.min(held);
+        let payouts = tournament.payouts(pool);
+        require!(
+            ctx.remaining_accounts.len() == payouts.len(),
+            ChessError::PayoutAccountsMismatch
+        );
+
+        let bump_bytes = [ctx.bumps.tournament_vault];
+        let seeds = &[
+            b"tournament_vault".as_ref(),
+            tournament_key.as_ref(),
+            bump_bytes.as_ref(),
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        let mut winners = Vec::with_capacity(payouts.len());
+        for ((entry, recipient), amount) in ctx
+            .accounts
+            .standings
+            .entries
+            .iter()
+            .zip(ctx.remaining_accounts)
+            .zip(&payouts)
+        {
+            require_keys_eq!(recipient.key(), entry.player, ChessError::PayoutAccountsMismatch);
+            if *amount > 0 {
+                anchor_lang::system_program::transfer(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.system_program.to_account_info(),
+                        anchor_lang::system_program::Transfer {
+                            from: ctx.accounts.tournament_vault.to_account_info(),
+                            to: recipient.clone(),
+                        },
+                        signer_seeds,
+                    ),
+                    *amount,
+                )?;
+            }
+            winners.push(entry.player);
+        }
+
+        tournament.status = TournamentStatus::Finished;
+        tournament.finished_at = clock.unix_timestamp;
+        tournament.winners = winners.clone();
+
+        emit!(TournamentFinalized {
+            tournament_id: tournament.tournament_id.clone(),
+            prize_pool: pool,
+            shortfall: advertised - pool,
+            winners,
+            payouts,
+            finalized_at: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Call off a tournament that hasn't started (creator only), refunding every
+    /// participant's entry fee from the vault. `remaining_accounts` holds the
+    /// participants, in joining order.
+    pub fn cancel_tournament<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CancelTournament<'info>>,
+    ) -> Result<()> {
+        let tournament_key = ctx.accounts.tournament.key();
+        let tournament = &mut ctx.accounts.tournament;
+        let clock = Clock::get()?;
+
+        require!(
+            tournament.status == TournamentStatus::Registration,
+            ChessError::TournamentAlreadyStarted
+        );
+        require!(
+            ctx.remaining_accounts.len() == tournament.participants.len(),
+            ChessError::PayoutAccountsMismatch
+        );
+
+        let bump_bytes = [ctx.bumps.tournament_vault];
+        let seeds = &[
+            b"tournament_vault".as_ref(),
+            tournament_key.as_ref(),
+            bump_bytes.as_ref(),
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        for (participant, recipient) in tournament.participants.iter().zip(ctx.remaining_accounts) {
+            require_keys_eq!(recipient.key(), *participant, ChessError::PayoutAccountsMismatch);
+            if tournament.entry_fee > 0 {
+                anchor_lang::system_program::transfer(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.system_program.to_account_info(),
+                        anchor_lang::system_program::Transfer {
+                            from: ctx.accounts.tournament_vault.to_account_info(),
+                            to: recipient.clone(),
+                        },
+                        signer_seeds,
+                    ),
+                    tournament.entry_fee,
+                )?;
+            }
+        }
+
+        tournament.status = TournamentStatus::Cancelled;
+        tournament.finished_at = clock.unix_timestamp;
+
+        emit!(TournamentCancelled {
+            tournament_id: tournament.tournament_id.clone(),
+            refunded: tournament.participants.clone(),
+            entry_fee: tournament.entry_fee,
+            cancelled_at: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Close a finished or cancelled tournament, returning the rent of the tournament and
+    /// its standings to the creator along with whatever the vault still holds. Prizes and
+    /// refunds are all paid by the time a tournament is finished or cancelled, so that is
+    /// only the rent reserve and anything sent to the vault beyond the entries.
+    /// The standings account must be passed if the tournament was ever started.
+    pub fn close_tournament(ctx: Context<CloseTournament>) -> Result<()> {
+        let tournament_key = ctx.accounts.tournament.key();
+        let tournament = &ctx.accounts.tournament;
+
+        require!(
+            tournament.status == TournamentStatus::Finished
+                || tournament.status == TournamentStatus::Cancelled,
+            ChessError::TournamentNotClosable
+        );
+        require!(
+            tournament.started_at == 0 || ctx.accounts.standings.is_some(),
+            ChessError::TournamentNotClosable
+        );
+        let vault_balance = ctx.accounts.tournament_vault.lamports();
+
+        let bump_bytes = [ctx.bumps.tournament_vault];
+        let seeds = &[
+            b"tournament_vault".as_ref(),
+            tournament_key.as_ref(),
+            bump_bytes.as_ref(),
+        ];
+        if vault_balance > 0 {
+            anchor_lang::system_program::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.tournament_vault.to_account_info(),
+                        to: ctx.accounts.creator.to_account_info(),
+                    },
+                    &[&seeds[..]],
+                ),
+                vault_balance,
+            )?;
+        }
+
+        let rent_reclaimed = vault_balance
+            + tournament.to_account_info().lamports()
+            + ctx
+                .accounts
+                .standings
+                .as_ref()
+                .map_or(0, |standings| standings.to_account_info().lamports());
+        emit!(TournamentClosed {
+            tournament_id: tournament.tournament_id.clone(),
+            creator: tournament.creator,
+            rent_reclaimed,
+        });
+
+        Ok(())
+    }
+
+    /// Emit the current standings, already in leaderboard order
+    pub fn get_standings(ctx: Context<GetStandings>) -> Result<()> {
+        emit!(TournamentStandingsSnapshot {
+            tournament_id: ctx.accounts.tournament.tournament_id.clone(),
+            entries: ctx.accounts.standings.entries.clone(),
+        });
+
+        Ok(())
+    }
+
+    /// Seed an active tournament's participants (creator only).
+    /// `remaining_accounts` holds each participant's rating and stats PDAs, in
+    /// participant order; players without them seed as a fresh 1500 with no form.
+    pub fn generate_brackets(ctx: Context<GenerateBrackets>) -> Result<()> {
+        let tournament_key = ctx.accounts.tournament.key();
+        let tournament = &mut ctx.accounts.tournament;
+        let clock = Clock::get()?;
+
+        require!(
+            tournament.status == TournamentStatus::Active,
+            ChessError::TournamentNotActive
+        );
+        require!(tournament.seeds.is_empty(), ChessError::BracketsAlreadyGenerated);
+
+        // Random brackets: the seed comes from the latest slot hash, which the creator
+        // can't know in advance, and is kept on the tournament so anyone can replay it
+        if tournament.seeding_mode == SeedingMode::Random {
+            let slot_hashes = ctx
+                .accounts
+                .slot_hashes
+                .as_ref()
+                .ok_or(ChessError::SlotHashesRequired)?;
+            let slot_hash = latest_slot_hash(slot_hashes)?;
+            let seed = anchor_lang::solana_program::hash::hashv(&[&slot_hash, tournament_key.as_ref()])
+                .to_bytes();
+            tournament.shuffle_seed = seed;
+            tournament.seeds = shuffled_participants(&tournament.participants, &seed);
+
+            emit!(BracketsGenerated {
+                tournament_id: tournament.tournament_id.clone(),
+                seeds: tournament.seeds.clone(),
+                shuffle_seed: seed,
+                generated_at: clock.unix_timestamp,
+            });
+            let field = tournament.seeds.clone();
+            open_bracket_round(tournament, &tournament_key, &field, clock.unix_timestamp);
+            return Ok(());
+        }
+
+        require!(
+            ctx.remaining_accounts.len() == tournament.participants.len() * 2,
+            ChessError::SeedingAccountsMismatch
+        );
+
+        let mut scored = Vec::with_capacity(tournament.participants.len());
+        for (player, accounts) in tournament
+            .participants
+            .iter()
+            .zip(ctx.remaining_accounts.chunks(2))
+        {
+            let rating = load_player_account::<PlayerRating>(&accounts[0], b"rating", player)?
+                .map(|rating| rating.rating)
+                .unwrap_or(PlayerRating::DEFAULT_RATING);
+            let stats = load_player_account::<PlayerStats>(&accounts[1], b"stats", player)?;
+            scored.push((*player, tournament.seed_score(rating, stats.as_ref())));
+        }
+
+        // Highest score first; pubkey bytes keep ties deterministic
+        scored.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.to_bytes().cmp(&b.0.to_bytes())));
+        tournament.seeds = scored.into_iter().map(|(player, _)| player).collect();
+
+        emit!(BracketsGenerated {
+            tournament_id: tournament.tournament_id.clone(),
+            seeds: tournament.seeds.clone(),
+            shuffle_seed: [0; 32],
+            generated_at: clock.unix_timestamp,
+        });
+        let field = tournament.seeds.clone();
+        open_bracket_round(tournament, &tournament_key, &field, clock.unix_timestamp);
+
+        Ok(())
+    }
+
+    /// Record the winners of the current single-elimination round, in pairing order, and
+    /// pair the next one (creator only). The player who had a bye joins the winners; once
+    /// only one player is left they are the bracket's champion.
+    pub fn advance_bracket(ctx: Context<AdvanceBracket>, winners: Vec<Pubkey>) -> Result<()> {
+        let tournament_key = ctx.accounts.tournament.key();
+        let tournament = &mut ctx.accounts.tournament;
+        let clock = Clock::get()?;
+
+        require!(
+            tournament.status == TournamentStatus::Active,
+            ChessError::TournamentNotActive
+        );
+        require!(tournament.bracket_round > 0, ChessError::SeedsNotGenerated);
+        require!(
+            tournament.bracket_champion.is_none(),
+            ChessError::BracketAlreadyDecided
+        );
+        require!(
+            winners.len() == tournament.bracket_pairings.len()
+                && winners
+                    .iter()
+                    .zip(&tournament.bracket_pairings)
+                    .all(|(winner, pairing)| *winner == pairing.white || *winner == pairing.black),
+            ChessError::BracketWinnersMismatch
+        );
+
+        // The bye goes to the back, so the same player doesn't sit out twice running
+        let mut field = winners;
+        field.extend(tournament.bracket_bye);
+
+        if let [champion] = field[..] {
+            tournament.bracket_champion = Some(champion);
+            tournament.bracket_pairings.clear();
+            tournament.brackets.clear();
+            tournament.bracket_bye = None;
+
+            emit!(BracketDecided {
+                tournament_id: tournament.tournament_id.clone(),
+                champion,
+                rounds: tournament.bracket_round,
+                decided_at: clock.unix_timestamp,
+            });
+            return Ok(());
+        }
+
+        open_bracket_round(tournament, &tournament_key, &field, clock.unix_timestamp);
+
+        Ok(())
+    }
+
+    /// Emit the seed order a random shuffle of the field would give for `seed`, so a
+    /// stored `shuffle_seed` can be checked against the brackets it produced
+    pub fn preview_bracket_shuffle(ctx: Context<PreviewBracketShuffle>, seed: [u8; 32]) -> Result<()> {
+        let tournament = &ctx.accounts.tournament;
+
+        emit!(BracketShufflePreview {
+            tournament_id: tournament.tournament_id.clone(),
+            seed,
+            seeds: shuffled_participants(&tournament.participants, &seed),
+        });
+
+        Ok(())
+    }
+
+    /// Re-seed the field from the current standings before pairing `round` (creator only),
+    /// for accelerated Swiss. Players are ordered by points, ties keeping their previous
+    /// seed order. A round is only re-seeded once; repeating the call changes nothing.
+    pub fn reseed_round(ctx: Context<ReseedRound>, round: u16) -> Result<()> {
+        let tournament = &mut ctx.accounts.tournament;
+        let clock = Clock::get()?;
+
+        require!(
+            tournament.status == TournamentStatus::Active,
+            ChessError::TournamentNotActive
+        );
+        require!(!tournament.seeds.is_empty(), ChessError::SeedsNotGenerated);
+        if round <= tournament.seeded_round {
+            return Ok(());
+        }
+        require!(round == tournament.seeded_round + 1, ChessError::RoundOutOfOrder);
+
+        let entries = &ctx.accounts.standings.entries;
+        let points = |player: &Pubkey| {
+            entries
+                .iter()
+                .find(|entry| entry.player == *player)
+                .map_or(0, |entry| entry.points)
+        };
+        // Stable sort, so equal scores stay in their previous seed order
+        tournament.seeds.sort_by_key(|player| std::cmp::Reverse(points(player)));
+        tournament.seeded_round = round;
+
+        emit!(RoundReseeded {
+            tournament_id: tournament.tournament_id.clone(),
+            round,
+            seeds: tournament.seeds.clone(),
+            pairings: tournament.pairings(),
+            reseeded_at: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Create the caller's rating account at the default rating
+    pub fn initialize_player_rating(ctx: Context<InitializePlayerRating>) -> Result<()> {
+        let player_rating = &mut ctx.accounts.player_rating;
+        let clock = Clock::get()?;
+
+        player_rating.player = *ctx.accounts.player.key;
+        let config = ctx.accounts.config.as_ref();
+        player_rating.rating = config.map_or(PlayerRating::DEFAULT_RATING, |config| config.default_rating);
+        player_rating.peak_rating = player_rating.rating;
+        player_rating.rating_floor_gap = config.map_or(0, |config| config.rating_floor_gap);
+        player_rating.games_played = 0;
+        player_rating.last_updated = clock.unix_timestamp;
+        player_rating.last_game = String::new();
+
+        Ok(())
+    }
+
+    /// Rebuild a player's rating by replaying Elo updates over `games` from `baseline`
+    /// (admin only), e.g. after an arbiter overturns a result. The replay overwrites
+    /// rather than adjusts the rating, so submitting the same list twice changes nothing.
+    pub fn recalc_rating_from_games(
+        ctx: Context<RecalcRating>,
+        baseline: u32,
+        games: Vec<RatedGameResult>,
+    ) -> Result<()> {
+        require!(
+            games.len() <= PlayerRating::MAX_RECALC_GAMES,
+            ChessError::TooManyRecalcGames
+        );
+        for (i, game) in games.iter().enumerate() {
+            require!(
+                games[..i].iter().all(|earlier| earlier.game != game.game),
+                ChessError::DuplicateRecalcGame
+            );
+        }
+
+        let player_rating = &mut ctx.accounts.player_rating;
+        let clock = Clock::get()?;
+        let previous_rating = player_rating.rating;
+
+        let mut rating = baseline;
+        for (games_played, game) in games.iter().enumerate() {
+            let scale = if game.early_resignation { GameEscrow::EARLY_RESIGNATION_RATING_PCT } else { 100 };
+            let k_factor = PlayerRating::k_factor(games_played as u32);
+            let delta = elo_delta(rating, game.opponent_rating, game.outcome, k_factor) * scale / 100;
+            rating = rating.saturating_add_signed(delta);
+        }
+        player_rating.rating = rating;
+        player_rating.last_updated = clock.unix_timestamp;
+
+        emit!(RatingRecalculated {
+            player: player_rating.player,
+            previous_rating,
+            new_rating: rating,
+            games_replayed: games.len() as u32,
+            recalculated_at: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Create the account tracking rated games between two players, passed in
+    /// ascending key order. Anyone may pay for it.
+    pub fn initialize_pair_history(
+        ctx: Context<InitializePairHistory>,
+        player_a: Pubkey,
+        player_b: Pubkey,
+    ) -> Result<()> {
+        require!(player_a < player_b, ChessError::InvalidPlayerPair);
+
+        let pair_history = &mut ctx.accounts.pair_history;
+        pair_history.players = [player_a, player_b];
+        pair_history.last_rated_game_at = 0;
+        pair_history.rated_games = 0;
+
+        Ok(())
+    }
+
+    /// Open a best-of-N series against `opponent`, escrowing the creator's stake. The first
+    /// player to `wins_required` game wins takes both stakes. With a non-zero
+    /// `flag_forfeit_threshold`, a player whose anti-cheat flags across the series' games
+    /// reach it forfeits the series to the opponent.
+    pub fn create_series(
+        ctx: Context<CreateSeries>,
+        series_id: String,
+        opponent: Pubkey,
+        stake_amount: u64,
+        wins_required: u8,
+        flag_forfeit_threshold: u8,
+    ) -> Result<()> {
+        require!(series_id.len() <= 32, ChessError::RoomIdTooLong);
+        require!(
+            stake_amount >= GameEscrow::min_stake(&Rent::get()?),
+            ChessError::StakeBelowMinimum
+        );
+        require!(
+            stake_amount.checked_mul(2).is_some(),
+            ChessError::SeriesStakeTooLarge
+        );
+        require!(
+            (1..=MatchSeries::MAX_WINS_REQUIRED).contains(&wins_required),
+            ChessError::InvalidSeriesLength
+        );
+        require!(
+            opponent != Pubkey::default() && opponent != *ctx.accounts.creator.key,
+            ChessError::InvalidPlayerPair
+        );
+
+        let series = &mut ctx.accounts.series;
+        let clock = Clock::get()?;
+
+        series.series_id = series_id;
+        series.players = [*ctx.accounts.creator.key, opponent];
+        series.stake_amount = stake_amount;
+        series.wins_required = wins_required;
+        series.flag_forfeit_threshold = flag_forfeit_threshold;
+        series.status = SeriesStatus::WaitingForOpponent;
+        series.wins = [0, 0];
+        series.draws = 0;
+        series.flags = [0, 0];
+        series.games = Vec::new();
+        series.winner = None;
+        series.created_at = clock.unix_timestamp;
+        series.started_at = 0;
+        series.finished_at = 0;
+        series.last_activity_at = clock.unix_timestamp;
+
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.creator.to_account_info(),
+                    to: ctx.accounts.series_vault.to_account_info(),
+                },
+            ),
+            stake_amount,
+        )?;
+
+        emit!(SeriesCreated {
+            series_id: series.series_id.clone(),
+            players: series.players,
+            stake_amount,
+            wins_required,
+            flag_forfeit_threshold,
+            created_at: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Match the creator's stake and start the series (invited opponent only)
+    pub fn join_series(ctx: Context<JoinSeries>) -> Result<()> {
+        let series = &mut ctx.accounts.series;
+        let clock = Clock::get()?;
+
+        require!(
+            series.status == SeriesStatus::WaitingForOpponent,
+            ChessError::SeriesNotInProgress
+        );
+        require_keys_eq!(
+            *ctx.accounts.player.key,
+            series.players[1],
+            ChessError::UnauthorizedPlayer
+        );
+
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.player.to_account_info(),
+                    to: ctx.accounts.series_vault.to_account_info(),
+                },
+            ),
+            series.stake_amount,
+        )?;
+
+        series.status = SeriesStatus::InProgress;
+        series.started_at = clock.unix_timestamp;
+        series.last_activity_at = clock.unix_timestamp;
+
+        emit!(SeriesStarted {
+            series_id: series.series_id.clone(),
+            started_at: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Count a finished game between the series players, created after the series started.
+    /// Anyone may report. Once a player reaches the required wins, or the other player's
+    /// flags reach the forfeit threshold, both stakes go to the series winner.
+    pub fn report_series_game(ctx: Context<ReportSeriesGame>) -> Result<()> {
+        let series_key = ctx.accounts.series.key();
+        let game_key = ctx.accounts.game_escrow.key();
+        let game_escrow = &ctx.accounts.game_escrow;
+        let series = &mut ctx.accounts.series;
+        let clock = Clock::get()?;
+
+        require!(
+            series.status == SeriesStatus::InProgress,
+            ChessError::SeriesNotInProgress
+        );
+        require!(
+            game_escrow.game_state == GameState::Finished && game_escrow.winner != GameWinner::None,
+            ChessError::GameNotFinished
+        );
+        let white = series
+            .index_of(&game_escrow.player_white)
+            .ok_or(ChessError::SeriesGameMismatch)?;
+        let black = series
+            .index_of(&game_escrow.player_black)
+            .ok_or(ChessError::SeriesGameMismatch)?;
+        require!(
+            white != black && game_escrow.created_at >= series.started_at,
+            ChessError::SeriesGameMismatch
+        );
+        require!(!series.games.contains(&game_key), ChessError::SeriesGameAlreadyCounted);
+        require!(
+            series.games.len() < MatchSeries::MAX_GAMES,
+            ChessError::SeriesGameLimitReached
+        );
+
+        series.games.push(game_key);
+        series.last_activity_at = clock.unix_timestamp;
+        match game_escrow.winner {
+            GameWinner::White => series.wins[white] += 1,
+            GameWinner::Black => series.wins[black] += 1,
+            _ => series.draws += 1,
+        }
+        series.flags[white] = series.flags[white].saturating_add(game_escrow.white_flag_count as u16);
+        series.flags[black] = series.flags[black].saturating_add(game_escrow.black_flag_count as u16);
+
+        emit!(SeriesGameReported {
+            series_id: series.series_id.clone(),
+            game: game_key,
+            result: game_escrow.winner.clone(),
+            wins: series.wins,
+            flags: series.flags,
+            reported_at: clock.unix_timestamp,
+        });
+
+        let Some((winner, forfeit)) = series.decided() else {
+            return Ok(());
+        };
+
+        let winner_key = series.players[winner];
+        let recipient = if winner == 0 {
+            &ctx.accounts.player_one
+        } else {
+            &ctx.accounts.player_two
+        };
+        // The whole vault goes, so a stray transfer into it can't leave it rent-paying
+        let payout = ctx.accounts.series_vault.lamports();
+        pay_from_series_vault(
+            &series_key,
+            ctx.bumps.series_vault,
+            &ctx.accounts.series_vault,
+            recipient,
+            &ctx.accounts.system_program,
+            payout,
+        )?;
+
+        series.status = SeriesStatus::Finished;
+        series.winner = Some(winner_key);
+        series.finished_at = clock.unix_timestamp;
+
+        emit!(SeriesFinished {
+            series_id: series.series_id.clone(),
+            winner: winner_key,
+            wins: series.wins,
+            forfeit,
+            payout,
+            finished_at: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Call off a series nobody has joined yet, returning the vault to its creator
+    pub fn cancel_series(ctx: Context<CancelSeries>) -> Result<()> {
+        let series_key = ctx.accounts.series.key();
+        let series = &mut ctx.accounts.series;
+        let clock = Clock::get()?;
+
+        require!(
+            series.status == SeriesStatus::WaitingForOpponent,
+            ChessError::SeriesNotInProgress
+        );
+
+        let refund = ctx.accounts.series_vault.lamports();
+        pay_from_series_vault(
+            &series_key,
+            ctx.bumps.series_vault,
+            &ctx.accounts.series_vault,
+            &ctx.accounts.creator,
+            &ctx.accounts.system_program,
+            refund,
+        )?;
+
+        series.status = SeriesStatus::Cancelled;
+        series.finished_at = clock.unix_timestamp;
+
+        emit!(SeriesCancelled {
+            series_id: series.series_id.clone(),
+            refunds: [refund, 0],
+            cancelled_at: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Refund a series that has stalled: once no game has been reported for
+    /// `MatchSeries::IDLE_TIMEOUT_SECONDS`, anyone may return each player's stake. The
+    /// creator, who opened the vault, also gets anything else it holds.
+    pub fn expire_series(ctx: Context<ExpireSeries>) -> Result<()> {
+        let series_key = ctx.accounts.series.key();
+        let series = &mut ctx.accounts.series;
+        let clock = Clock::get()?;
+
+        require!(
+            series.status == SeriesStatus::InProgress,
+            ChessError::SeriesNotInProgress
+        );
+        require!(
+            clock.unix_timestamp >= series.last_activity_at + MatchSeries::IDLE_TIMEOUT_SECONDS,
+            ChessError::SeriesNotExpired
+        );
+
+        let opponent_refund = series.stake_amount;
+        pay_from_series_vault(
+            &series_key,
+            ctx.bumps.series_vault,
+            &ctx.accounts.series_vault,
+            &ctx.accounts.player_two,
+            &ctx.accounts.system_program,
+            opponent_refund,
+        )?;
+        let creator_refund = ctx.accounts.series_vault.lamports();
+        pay_from_series_vault(
+            &series_key,
+            ctx.bumps.series_vault,
+            &ctx.accounts.series_vault,
+            &ctx.accounts.player_one,
+            &ctx.accounts.system_program,
+            creator_refund,
+        )?;
+
+        series.status = SeriesStatus::Cancelled;
+        series.finished_at = clock.unix_timestamp;
+
+        emit!(SeriesCancelled {
+            series_id: series.series_id.clone(),
+            refunds: [creator_refund, opponent_refund],
+            cancelled_at: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Create the head-to-head record of two players, passed in ascending key order.
+    /// Anyone may pay for it; settlement updates it when passed in.
+    pub fn initialize_head_to_head(
+        ctx: Context<InitializeHeadToHead>,
+        player_a: Pubkey,
+        player_b: Pubkey,
+    ) -> Result<()> {
+        require!(player_a < player_b, ChessError::InvalidPlayerPair);
+
+        let head_to_head = &mut ctx.accounts.head_to_head;
+        head_to_head.players = [player_a, player_b];
+        head_to_head.wins = [0, 0];
+        head_to_head.draws = 0;
+        head_to_head.last_game_at = 0;
+
+        Ok(())
+    }
+
+    /// Emit two players' head-to-head record
+    pub fn get_head_to_head(ctx: Context<GetHeadToHead>) -> Result<()> {
+        let head_to_head = &ctx.accounts.head_to_head;
+
+        emit!(HeadToHeadRecord {
+            players: head_to_head.players,
+            wins: head_to_head.wins,
+            draws: head_to_head.draws,
+            games: head_to_head.wins[0] + head_to_head.wins[1] + head_to_head.draws,
+            last_game_at: head_to_head.last_game_at,
+        });
+
+        Ok(())
+    }
+
+    /// Emit whether `fen` is checkmate for the side to move, for reviewing disputed games
+    /// independently of any escrow
+    pub fn verify_checkmate(_ctx: Context<VerifyCheckmate>, fen: String) -> Result<()> {
+        require!(fen.len() <= chess_validation::MAX_FEN_LEN, ChessError::InvalidFen);
+        let position = chess_validation::Position::from_fen(&fen)?;
+
+        emit!(CheckmateVerified {
+            is_checkmate: chess_validation::is_checkmate(&position),
+            fen,
+        });
+
+        Ok(())
+    }
+
+    /// Emit the game's current position as a FEN record
+    pub fn get_fen(ctx: Context<GetFen>) -> Result<()> {
+        let game_escrow = &ctx.accounts.game_escrow;
+        let position = game_escrow.position();
+
+        emit!(PositionFen {
+            room_id: game_escrow.room_id.clone(),
+            move_count: game_escrow.move_count,
+            fen: position.to_fen(),
+        });
+
+        Ok(())
+    }
+
+    /// Replay the game's move history from the starting setup and emit the resulting
+    /// board, flagging whether it agrees with the stored position. Only possible while
+    /// the history window still holds every move played.
+    pub fn replay_position(ctx: Context<GetFen>) -> Result<()> {
+        let game_escrow = &ctx.accounts.game_escrow;
+        let replayed = game_escrow.replayed_position()?;
+        let stored = game_escrow.position();
+
+        emit!(PositionReplayed {
+            room_id: game_escrow.room_id.clone(),
+            move_count: game_escrow.move_count,
+            board: replayed.squares.map(|piece| piece.code()),
+            matches_stored: replayed.squares == stored.squares
+                && replayed.white_to_move == stored.white_to_move
+                && replayed.en_passant_square == stored.en_passant_square
+                && replayed.castling_rights() == stored.castling_rights()
+                && replayed.halfmove_clock == stored.halfmove_clock,
+        });
+
+        Ok(())
+    }
+
+    /// Create the caller's results account; settlement updates it when passed in
+    pub fn initialize_player_stats(ctx: Context<InitializePlayerStats>) -> Result<()> {
+        let player_stats = &mut ctx.accounts.player_stats;
+
+        player_stats.player = *ctx.accounts.player.key;
+        player_stats.games_played = 0;
+        player_stats.wins = 0;
+        player_stats.losses = 0;
+        player_stats.draws = 0;
+        player_stats.recent_wins = 0;
+        player_stats.recent_draws = 0;
+        player_stats.recent_games = 0;
+        player_stats.last_game_at = 0;
+        player_stats.active_games = 0;
+
+        Ok(())
+    }
+
+    /// Create the program-wide config; only the program's upgrade authority may do this
+    pub fn initialize_config(ctx: Context<InitializeConfig>, fee_collector: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        let clock = Clock::get()?;
+
+        config.admin = *ctx.accounts.admin.key;
+        config.fee_collector = fee_collector;
+        config.fee_exempt = Vec::new();
+        config.referee = Pubkey::default();
+        config.moderators = Vec::new();
+        config.default_rating = PlayerRating::DEFAULT_RATING;
+        config.rating_floor_gap = 0;
+        config.rated_pair_cooldown_seconds = 0;
+        config.min_rated_initial_time = 0;
+        config.allowed_mints = Vec::new();
+        config.dead_vault_window_seconds = 0;
+        config.enforce_fee_collector = false;
+        config.paused = false;
+        config.min_stake = 0;
+        config.max_stake = 0;
+
+        emit!(ConfigUpdated {
+            admin: config.admin,
+            fee_collector: config.fee_collector,
+            updated_at: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Change config values for games created from now on (admin only)
+    pub fn update_config(ctx: Context<UpdateConfig>, update: ConfigUpdate) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        let clock = Clock::get()?;
+
+        if let Some(fee_collector) = update.fee_collector {
+            config.fee_collector = fee_collector;
+        }
+        if let Some(fee_exempt) = update.fee_exempt {
+            require!(
+                fee_exempt.len() <= ProgramConfig::MAX_FEE_EXEMPT,
+                ChessError::TooManyFeeExemptPlayers
+            );
+            config.fee_exempt = fee_exempt;
+        }
+        if let Some(referee) = update.referee {
+            config.referee = referee;
+        }
+        if let Some(default_rating) = update.default_rating {
+            require!(
+                (PlayerRating::MIN_DEFAULT_RATING..=PlayerRating::MAX_DEFAULT_RATING)
+                    .contains(&default_rating),
+                ChessError::InvalidDefaultRating
+            );
+            config.default_rating = default_rating;
+        }
+        if let Some(rating_floor_gap) = update.rating_floor_gap {
+            config.rating_floor_gap = rating_floor_gap;
+        }
+        if let Some(rated_pair_cooldown_seconds) = update.rated_pair_cooldown_seconds {
+            config.rated_pair_cooldown_seconds = rated_pair_cooldown_seconds;
+        }
+        if let Some(min_rated_initial_time) = update.min_rated_initial_time {
+            config.min_rated_initial_time = min_rated_initial_time;
+        }
+
+        emit!(ConfigUpdated {
+            admin: config.admin,
+            fee_collector: config.fee_collector,
+            updated_at: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Let `moderator` settle any game through `declare_result_signed` (admin only)
+    pub fn add_moderator(ctx: Context<UpdateConfig>, moderator: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+
+        require_keys_neq!(moderator, Pubkey::default(), ChessError::InvalidModerator);
+        require!(!config.moderators.contains(&moderator), ChessError::ModeratorAlreadyListed);
+        require!(
+            config.moderators.len() < ProgramConfig::MAX_MODERATORS,
+            ChessError::TooManyModerators
+        );
+        config.moderators.push(moderator);
+
+        emit!(ModeratorAdded {
+            moderator,
+            moderators: config.moderators.len() as u8,
+            updated_at: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Revoke a moderator's authority to settle games (admin only)
+    pub fn remove_moderator(ctx: Context<UpdateConfig>, moderator: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+
+        let index = config
+            .moderators
+            .iter()
+            .position(|listed| *listed == moderator)
+            .ok_or(ChessError::ModeratorNotListed)?;
+        config.moderators.remove(index);
+
+        emit!(ModeratorRemoved {
+            moderator,
+            moderators: config.moderators.len() as u8,
+            updated_at: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Accept `mint` for token stakes in new games (admin only). While the list is empty
+    /// any mint is accepted.
+    pub fn add_allowed_mint(ctx: Context<UpdateConfig>, mint: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+
+        require!(!config.allowed_mints.contains(&mint), ChessError::MintAlreadyAllowed);
+        require!(
+            config.allowed_mints.len() < ProgramConfig::MAX_ALLOWED_MINTS,
+            ChessError::TooManyAllowedMints
+        );
+        config.allowed_mints.push(mint);
+
+        emit!(AllowedMintAdded {
+            mint,
+            allowed_mints: config.allowed_mints.len() as u8,
+            updated_at: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Stop accepting `mint` for token stakes in new games (admin only); games already
+    /// staked in it are unaffected
+    pub fn remove_allowed_mint(ctx: Context<UpdateConfig>, mint: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+
+        let index = config
+            .allowed_mints
+            .iter()
+            .position(|listed| *listed == mint)
+            .ok_or(ChessError::MintNotAllowed)?;
+        config.allowed_mints.remove(index);
+
+        emit!(AllowedMintRemoved {
+            mint,
+            allowed_mints: config.allowed_mints.len() as u8,
+            updated_at: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Set how long a settled game must sit before `sweep_dead_vault` may empty its vault
+    /// (admin only). 0 disables sweeping.
+    pub fn set_dead_vault_window(ctx: Context<UpdateConfig>, seconds: u32) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.dead_vault_window_seconds = seconds;
+
+        emit!(DeadVaultWindowSet {
+            seconds,
+            updated_at: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Halt or resume new games, joins and deposits (admin only). Games already under way
+    /// play on and settle as usual, so no stake is trapped by a pause.
+    pub fn set_paused(ctx: Context<UpdateConfig>, paused: bool) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.paused = paused;
+
+        emit!(ProgramPauseSet {
+            paused,
+            updated_at: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Set the lamport range SOL stakes of new games must fall in (admin only); 0 leaves
+    /// that end open. Enforced by `initialize_game`, which always reads the config.
+    pub fn set_stake_bounds(ctx: Context<UpdateConfig>, min_stake: u64, max_stake: u64) -> Result<()> {
+        require!(max_stake == 0 || min_stake <= max_stake, ChessError::InvalidStakeBounds);
+        let config = &mut ctx.accounts.config;
+        config.min_stake = min_stake;
+        config.max_stake = max_stake;
+
+        emit!(StakeBoundsSet {
+            min_stake,
+            max_stake,
+            updated_at: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Require new games to name the config's fee collector, or lift that requirement
+    /// (admin only). Enforced by `initialize_game`, which always reads the config.
+    pub fn set_fee_collector_enforced(ctx: Context<UpdateConfig>, enforced: bool) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.enforce_fee_collector = enforced;
+
+        emit!(FeeCollectorEnforcementSet {
+            fee_collector: config.fee_collector,
+            enforced,
+            updated_at: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Move whatever is left in a long-settled game's vault to the config's fee collector
+    /// (admin only). Only games whose pot is already paid out, or that were cancelled, and
+    /// that finished more than `dead_vault_window_seconds` ago qualify; an undistributed
+    /// pot can always be paid out through `settle_game` instead.
+    pub fn sweep_dead_vault(ctx: Context<SweepDeadVault>) -> Result<()> {
+        let game_escrow = &ctx.accounts.game_escrow;
+        let window = ctx.accounts.config.dead_vault_window_seconds;
+        let clock = Clock::get()?;
+
+        require!(window > 0, ChessError::DeadVaultSweepDisabled);
+        let settled = (game_escrow.game_state == GameState::Finished && game_escrow.funds_distributed)
+            || game_escrow.game_state == GameState::Cancelled;
+        require!(
+            settled
+                && game_escrow.locked_winnings == 0
+                && game_escrow.claimable == [0; 2]
+                && game_escrow.finished_at > 0,
+            ChessError::GameNotSettled
+        );
+        require!(
+            clock.unix_timestamp >= game_escrow.finished_at.saturating_add(window as i64),
+            ChessError::DeadVaultWindowNotElapsed
+        );
+
+        let amount = ctx.accounts.game_vault.lamports();
+        if amount > 0 {
+            let game_key = game_escrow.key();
+            let bump_bytes = [ctx.bumps.game_vault];
+            let seeds = &[
+                b"vault".as_ref(),
+                game_key.as_ref(),
+                bump_bytes.as_ref(),
+            ];
+            let signer_seeds = &[&seeds[..]];
+
+            anchor_lang::system_program::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.game_vault.to_account_info(),
+                        to: ctx.accounts.treasury.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                amount,
+            )?;
+        }
+
+        emit!(DeadVaultSwept {
+            room_id: game_escrow.room_id.clone(),
+            treasury: ctx.accounts.treasury.key(),
+            amount,
+            swept_at: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Bet `amount` on the outcome of someone else's game. Bets are pooled per game in the
+    /// side-bet vault and close once the first move is played; the first bettor also funds
+    /// the vault's rent reserve.
+    pub fn place_side_bet(
+        ctx: Context<PlaceSideBet>,
+        predicted_winner: GameWinner,
+        amount: u64,
+    ) -> Result<()> {
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        let bettor = ctx.accounts.bettor.key();
+        let clock = Clock::get()?;
+
+        require!(game_escrow.side_bets_open(), ChessError::SideBetsClosed);
+        require!(
+            bettor != game_escrow.player_white && bettor != game_escrow.player_black,
+            ChessError::PlayersCannotSideBet
+        );
+        let outcome = predicted_winner
+            .side_bet_index()
+            .ok_or(ChessError::InvalidSideBetPrediction)?;
+        require!(amount > 0, ChessError::InvalidStakeAmount);
+
+        let rent_reserve = Rent::get()?.minimum_balance(0);
+        let reserve_shortfall = rent_reserve.saturating_sub(ctx.accounts.side_bet_vault.lamports());
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.bettor.to_account_info(),
+                    to: ctx.accounts.side_bet_vault.to_account_info(),
+                },
+            ),
+            amount + reserve_shortfall,
+        )?;
+
+        game_escrow.side_bet_totals[outcome] += amount;
+        game_escrow.open_side_bets += 1;
+
+        let side_bet = &mut ctx.accounts.side_bet;
+        side_bet.game = game_escrow.key();
+        side_bet.bettor = bettor;
+        side_bet.predicted_winner = predicted_winner.clone();
+        side_bet.amount = amount;
+        side_bet.placed_at = clock.unix_timestamp;
+
+        emit!(SideBetPlaced {
+            room_id: game_escrow.room_id.clone(),
+            bettor,
+            predicted_winner,
+            amount,
+            pool_total: game_escrow.side_bet_totals.iter().sum(),
+        });
+
+        Ok(())
+    }
+
+    /// Settle a side bet once its game is paid out (permissionless). A winning bet gets its
+    /// stake back plus its share of the losing bets; a losing one forfeits its stake. If
+    /// nobody backed the result, or the game was cancelled or aborted, every bet is
+    /// refunded. The bet account closes to the bettor, and the last bet settled also takes
+    /// the vault's rent reserve and any rounding dust.
+    pub fn settle_side_bet(ctx: Context<SettleSideBet>) -> Result<()> {
+        let game_escrow = &mut ctx.accounts.game_escrow;
+        let side_bet = &ctx.accounts.side_bet;
+
+        let payout = game_escrow
+            .side_bet_payout(&side_bet.predicted_winner, side_bet.amount)
+            .ok_or(ChessError::GameNotFinished)?;
+        // A result is only final once the pot is paid out; until then a dispute or a
+        // challenge to the mating move can still overturn it
+        require!(
+            game_escrow.funds_distributed || game_escrow.game_state == GameState::Cancelled,
+            ChessError::GameNotSettled
+        );
+        game_escrow.open_side_bets -= 1;
+        let amount = if game_escrow.open_side_bets == 0 {
+            ctx.accounts.side_bet_vault.lamports()
+        } else {
+            payout
+        };
+
+        if amount > 0 {
+            let game_key = game_escrow.key();
+            let bump_bytes = [ctx.bumps.side_bet_vault];
+            let seeds = &[
+                b"sidebet_vault".as_ref(),
+                game_key.as_ref(),
+                bump_bytes.as_ref(),
+            ];
+            anchor_lang::system_program::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.side_bet_vault.to_account_info(),
+                        to: ctx.accounts.bettor.to_account_info(),
+                    },
+                    &[&seeds[..]],
+                ),
+                amount,
+            )?;
+        }
+
+        emit!(SideBetSettled {
+            room_id: game_escrow.room_id.clone(),
+            bettor: side_bet.bettor,
+            predicted_winner: side_bet.predicted_winner.clone(),
+            amount: side_bet.amount,
+            payout,
+        });
+
+        Ok(())
+    }
+}
+
+// Helper functions moved outside the #[program] module
+
+/// Fails while the config, once created, has the program paused
+fn require_not_paused(config: Option<&ProgramConfig>) -> Result<()> {
+    if let Some(config) = config {
+        require!(!config.paused, ChessError::ProgramPaused);
+    }
+    Ok(())
+}
+
+/// Read the program config from its PDA, which the caller's accounts pin by seeds.
+/// Returns `None` until `initialize_config` has created it.
+fn load_config(config: &AccountInfo) -> Result<Option<ProgramConfig>> {
+    if config.data_is_empty() {
+        return Ok(None);
+    }
+    require_keys_eq!(*config.owner, crate::ID, ErrorCode::AccountOwnedByWrongProgram);
+
+    let data = config.try_borrow_data()?;
+    Ok(Some(ProgramConfig::try_deserialize(&mut &data[..])?))
+}
+
+/// Read a player's `[seed, player]` PDA passed via `remaining_accounts`.
+/// Returns `None` when the account has not been created yet.
+fn load_player_account<T: AccountDeserialize>(
+    account: &AccountInfo,
+    seed: &[u8],
+    player: &Pubkey,
+) -> Result<Option<T>> {
+    let (expected, _) = Pubkey::find_program_address(&[seed, player.as_ref()], &crate::ID);
+    require_keys_eq!(account.key(), expected, ChessError::SeedingAccountsMismatch);
+
+    if account.data_is_empty() {
+        return Ok(None);
+    }
+    require_keys_eq!(*account.owner, crate::ID, ChessError::SeedingAccountsMismatch);
+
+    let data = account.try_borrow_data()?;
+    Ok(Some(T::try_deserialize(&mut &data[..])?))
+}
+
+/// Grow a program account to `space` bytes before more records are stored in it, with
+/// `payer` topping up its rent. Never shrinks it.
+fn grow_account<'info>(
+    info: AccountInfo<'info>,
+    space: usize,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+) -> Result<()> {
+    if info.data_len() >= space {
+        return Ok(());
+    }
+    let shortfall = Rent::get()?.minimum_balance(space).saturating_sub(info.lamports());
+    if shortfall > 0 {
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                system_program.clone(),
+                anchor_lang::system_program::Transfer {
+                    from: payer.clone(),
+                    to: info.clone(),
+                },
+            ),
+            shortfall,
+        )?;
+    }
+    info.resize(space)?;
+    Ok(())
+}
+
+/// Check and apply one submitted move for `player_key`, already known to be seated in an
+/// in-progress game: turn, timing and anti-cheat rules, then `commit_move`. `batched` marks
+/// a move after the first in a `record_moves_batch`, played in the same on-chain second.
+fn play_move(
+    game_escrow: &mut Account<GameEscrow>,
+    player_key: Pubkey,
+    submission: MoveSubmission,
+    time_spent: u64,
+    clock: &Clock,
+    batched: bool,
+) -> Result<()> {
+    submission.validate_bounds()?;
+    let CandidateMove { from_square, to_square, piece, .. } = &submission.candidate;
+
+    // Check if it's the player's turn
+    let is_white_turn = game_escrow.is_white_turn();
+    let is_white_player = player_key == game_escrow.player_white;
+    
+    require!(is_white_turn == is_white_player, ChessError::NotPlayerTurn);
+
+    // At most one accepted move per game per on-chain second, save for the later moves of
+    // a batch, which share the first one's
+    require!(
+        batched || game_escrow.move_count == 0 || clock.unix_timestamp > game_escrow.last_move_time,
+        ChessError::MoveTooSoon
+    );
+
+    let flags_before = game_escrow.anti_cheat_flags;
+
+    // Think times under the floor are humanly implausible: rejected in strict mode,
+    // otherwise recorded in their own anti-cheat bit
+    if time_spent < game_escrow.min_move_interval_ms as u64 {
+        require!(!game_escrow.strict_move_interval, ChessError::MoveTooSoon);
+        game_escrow.anti_cheat_flags |= GameEscrow::BELOW_MOVE_FLOOR_FLAG;
+    }
+
+    // Charge the think time to the mover's clock; the reported time must fit on it too
+    let time_elapsed = clock.unix_timestamp - game_escrow.last_move_time;
+    game_escrow.charge_mover_clock(time_elapsed, time_spent)?;
+    if let Some(time_remaining) = game_escrow.low_time_crossing(is_white_turn) {
+        emit!(LowTimeWarning {
+            room_id: game_escrow.room_id.clone(),
+            player: player_key,
+            time_remaining,
+            threshold: game_escrow.low_time_warning_seconds,
+        });
+    }
+
+    // Anti-cheat validation if enabled
+    if game_escrow.game_flags.enable_anti_cheat {
+        // Validate move format
+        require!(from_square.len() == 2 && to_square.len() == 2, ChessError::InvalidMoveFormat);
+        
+        // Check for impossible moves (basic validation)
+        if is_impossible_move(from_square, to_square, piece) {
+            return Err(ChessError::ImpossibleMove.into());
+        }
+        
+        // A premove is fast but rarely has many options; a run of instant answers in
+        // positions with plenty of them is what a bot looks like
+        let fast = is_suspicious_move_pattern(game_escrow, time_spent);
+        let fast_and_complex = fast
+            && chess_validation::generate_legal_moves(&game_escrow.position()).len()
+                >= GameEscrow::COMPLEX_POSITION_MOVES;
+        if game_escrow.record_think_time(is_white_player, time_spent, fast_and_complex) {
+            game_escrow.anti_cheat_flags |= GameEscrow::TIMING_ANOMALY_FLAG;
+        }
+
+        // Check for suspicious patterns
+        if fast {
+            game_escrow.anti_cheat_flags |= 1; // Flag suspicious activity
+
+            // Strikes are attributed to the player whose move tripped the check
+            let strikes = if is_white_player {
+                game_escrow.white_flag_count = game_escrow.white_flag_count.saturating_add(1);
+                game_escrow.white_flag_count
+            } else {
+                game_escrow.black_flag_count = game_escrow.black_flag_count.saturating_add(1);
+                game_escrow.black_flag_count
+            };
+
+            // Opt-in auto-forfeit: the flagged player loses and the move is discarded
+            let threshold = game_escrow.anti_cheat_forfeit_threshold;
+            if threshold > 0 && strikes >= threshold {
+                let winner = if is_white_player { GameWinner::Black } else { GameWinner::White };
+                game_escrow.winner = winner.clone();
+                game_escrow.game_state = GameState::Finished;
+                game_escrow.finished_at = clock.unix_timestamp;
+
+                emit!(GameFinished {
+                    room_id: game_escrow.room_id.clone(),
+                    winner,
+                    reason: GameEndReason::Forfeit,
+                    finished_at: clock.unix_timestamp,
+                    white_total_time_ms: game_escrow.white_timing.total_ms,
+                    black_total_time_ms: game_escrow.black_timing.total_ms,
+                });
+
+                let move_number = game_escrow.move_count + 1;
+                raise_anti_cheat_review(game_escrow, player_key, flags_before, move_number);
+                return Ok(());
+            }
+        }
+    }
+
+    commit_move(game_escrow, player_key, submission, time_spent, clock.unix_timestamp)?;
+    let move_number = game_escrow.move_count;
+    raise_anti_cheat_review(game_escrow, player_key, flags_before, move_number);
+    Ok(())
+}
+
+/// Validate a move against the stored board, apply it and append it to the history.
+/// Shared by `play_move` and `resume_from_seal`; callers check turn and timing first.
+fn commit_move(
+    game_escrow: &mut Account<GameEscrow>,
+    player_key: Pubkey,
+    submission: MoveSubmission,
+    time_spent: u64,
+    now: i64,
+) -> Result<()> {
+    // The client's own position hash isn't stored; records carry the move-chain link instead.
+    // It commits the client to the resulting position and is checked below.
+    let MoveSubmission {
+        move_notation,
+        game_position_hash,
+        candidate,
+        is_check,
+        is_checkmate,
+    } = submission;
+
+    // Reconstruct the board and check the move against it
+    let mut position = game_escrow.position();
+    // The stored move number must agree with the plies played, or the record has drifted
+    require!(
+        position.fullmove_number as u32 == game_escrow.move_count / 2 + 1,
+        ChessError::FullmoveNumberMismatch
+    );
+    let parsed_move = candidate.parse()?;
+    chess_validation::check_promotion(&parsed_move)?;
+    chess_validation::check_en_passant(&position, &parsed_move)?;
+    chess_validation::check_castle(&position, &parsed_move)?;
+    if game_escrow.game_flags.require_move_validation {
+        chess_validation::check_move(&position, &parsed_move)?;
+    } else if game_escrow.is_audited_ply() {
+        // Spot check under optimistic validation: an illegal move is still recorded, but flagged
+        let passed = chess_validation::check_move(&position, &parsed_move).is_ok();
+        if !passed {
+            game_escrow.anti_cheat_flags |= GameEscrow::FAILED_AUDIT_FLAG;
+        }
+        emit!(MoveAudited {
+            room_id: game_escrow.room_id.clone(),
+            move_number: game_escrow.move_count + 1,
+            player: player_key,
+            passed,
+        });
+    }
+    // Colour of the piece actually moving on the reconstructed board, independent of the signer
+    let mover_is_white = position.squares[parsed_move.from as usize].is_white();
+    let balance_before = position.material_balance();
+    let previous_key = position.repetition_key();
+    let first_capture = position.piece_count() == 32 && parsed_move.captured_piece.is_some();
+    chess_validation::apply_move(&mut position, &parsed_move)?;
+    if game_escrow.variant == Variant::Atomic && parsed_move.captured_piece.is_some() {
+        chess_validation::explode(&mut position, parsed_move.to);
+        // Blowing up your own king is never legal, even if it takes the enemy king too
+        require!(
+            position.king_square(mover_is_white).is_some(),
+            ChessError::MoveExposesKing
+        );
+    }
+    require!(game_position_hash == position.digest(), ChessError::PositionHashMismatch);
+    // A claimed mate ends the game, so it must hold on the board. Atomic kings fall to
+    // explosions instead, which `variant_win` judges below.
+    if is_checkmate && game_escrow.variant != Variant::Atomic {
+        require!(chess_validation::is_checkmate(&position), ChessError::NotCheckmate);
+    }
+    game_escrow.previous_board = game_escrow.board;
+    game_escrow.previous_en_passant_square = game_escrow.en_passant_square;
+    game_escrow.previous_castling_rights = game_escrow.castling_rights;
+    game_escrow.previous_halfmove_clock = game_escrow.halfmove_clock;
+    game_escrow.can_undo_last_move = true;
+    game_escrow.takeback_requested_by = None;
+    // Playing on instead of accepting declines the opponent's draw offer
+    if game_escrow.draw_offered_by.is_some_and(|offered_by| offered_by != player_key) {
+        game_escrow.draw_offered_by = None;
+    }
+    game_escrow.board = position.board();
+    game_escrow.en_passant_square = position.en_passant_square;
+    game_escrow.castling_rights = position.castling_rights();
+    game_escrow.halfmove_clock = position.halfmove_clock;
+    game_escrow.fullmove_number = position.fullmove_number;
+
+    // Create move record, chained to the previous one
+    let mut move_record = MoveRecord {
+        move_number: game_escrow.move_count + 1,
+        from_square: candidate.from_square,
+        to_square: candidate.to_square,
+        piece: candidate.piece,
+        captured_piece: candidate.captured_piece,
+        move_notation: move_notation.clone(),
+        position_hash: [0u8; 32],
+        timestamp: now,
+        time_spent,
+        is_check,
+        is_checkmate,
+        is_castle: candidate.is_castle,
+        is_en_passant: candidate.is_en_passant,
+        is_promotion: candidate.is_promotion,
+        promotion_piece: candidate.promotion_piece,
+        prev_hash: game_escrow.position_hash,
+    };
+    move_record.position_hash = move_record.chain_hash();
+    let position_hash = move_record.position_hash;
+
+    // Add to move history, evicting the oldest retained record once the window is full.
+    // Evicted records are emitted in full so indexers always hold the complete game.
+    let capacity = game_escrow.move_history_capacity as usize;
+    if capacity == 0 {
+        emit!(MoveArchived {
+            room_id: game_escrow.room_id.clone(),
+            record: move_record,
+        });
+    } else {
+        if game_escrow.move_history.len() >= capacity {
+            let evicted = game_escrow.move_history.remove(0);
+            emit!(MoveArchived {
+                room_id: game_escrow.room_id.clone(),
+                record: evicted,
+            });
+        }
+        game_escrow.move_history.push(move_record);
+    }
+
+    // Kept outside the history so the stats survive the retention window
+    let timing = if mover_is_white {
+        &mut game_escrow.white_timing
+    } else {
+        &mut game_escrow.black_timing
+    };
+    timing.record(time_spent);
+
+    let white_moved = game_escrow.is_white_turn();
+    game_escrow.move_count += 1;
+    game_escrow.set_turn(!white_moved);
+    game_escrow.last_move_time = now;
+    game_escrow.position_hash = position_hash;
+
+    // Check for game end conditions; the mate belongs to the side that just moved
+    if is_checkmate {
+        game_escrow.game_state = GameState::Finished;
+        game_escrow.winner = if mover_is_white {
+            GameWinner::White
+        } else {
+            GameWinner::Black
+        };
+        game_escrow.finished_at = now;
+    }
+
+    if game_escrow.compact_move_events {
+        emit!(MoveRecordedCompact {
+            room_id: game_escrow.room_id.clone(),
+            move_count: game_escrow.move_count,
+            from: parsed_move.from,
+            to: parsed_move.to,
+            bits: MoveRecordedCompact::bits(&parsed_move, is_check, is_checkmate),
+            timestamp: now,
+        });
+    } else {
+        emit!(MoveRecorded {
+            room_id: game_escrow.room_id.clone(),
+            player: player_key,
+            move_count: game_escrow.move_count,
+            move_notation,
+            position_hash,
+            timestamp: now,
+        });
+    }
+
+    if game_escrow.emit_board_state {
+        emit!(BoardState {
+            room_id: game_escrow.room_id.clone(),
+            move_count: game_escrow.move_count,
+            board: game_escrow.packed_board(),
+        });
+    }
+
+    // Variant win conditions, judged on the reconstructed position
+    if game_escrow.game_state == GameState::InProgress {
+        if let Some(reason) = game_escrow.variant_win(&position, mover_is_white) {
+            let winner = if mover_is_white { GameWinner::White } else { GameWinner::Black };
+            game_escrow.winner = winner.clone();
+            game_escrow.game_state = GameState::Finished;
+            game_escrow.finished_at = now;
+
+            emit!(GameFinished {
+                room_id: game_escrow.room_id.clone(),
+                winner,
+                reason,
+                finished_at: now,
+                white_total_time_ms: game_escrow.white_timing.total_ms,
+                black_total_time_ms: game_escrow.black_timing.total_ms,
+            });
+        }
+    }
+
+    let balance = position.material_balance();
+    if game_escrow.emit_material_events
+        && (first_capture || balance.signum() != balance_before.signum())
+    {
+        emit!(MaterialLeadChanged {
+            room_id: game_escrow.room_id.clone(),
+            move_count: game_escrow.move_count,
+            balance,
+            first_capture,
+        });
+    }
+
+    // `apply_move` reset the halfmove clock for a pawn move or capture
+    let irreversible = position.halfmove_clock == 0;
+
+    // Opt-in repetition tracking over the positions since the last pawn move or capture
+    if game_escrow.tracks_repetition() && game_escrow.game_state == GameState::InProgress {
+        if irreversible {
+            game_escrow.position_keys.clear();
+        } else if game_escrow.position_keys.is_empty() {
+            // First reversible move of the game: count the starting position too
+            game_escrow.position_keys.push(previous_key);
+        }
+
+        let key = position.repetition_key();
+        let occurrences = game_escrow.position_keys.iter().filter(|k| **k == key).count() + 1;
+        if game_escrow.position_keys.len() >= GameEscrow::REPETITION_WINDOW {
+            game_escrow.position_keys.remove(0);
+        }
+        game_escrow.position_keys.push(key);
+
+        // Without auto-draw the positions are only kept for draw claims
+        let auto_draw = game_escrow.game_flags.auto_draw_on_repetition;
+        if auto_draw && occurrences == 2 {
+            emit!(RepetitionWarning {
+                room_id: game_escrow.room_id.clone(),
+                move_count: game_escrow.move_count,
+            });
+        } else if auto_draw && occurrences >= 3 {
+            let winner = game_escrow.scored_result(GameWinner::Draw);
+            game_escrow.winner = winner.clone();
+            game_escrow.game_state = GameState::Finished;
+            game_escrow.finished_at = now;
+
+            emit!(GameFinished {
+                room_id: game_escrow.room_id.clone(),
+                winner,
+                reason: GameEndReason::Repetition,
+                finished_at: now,
+                white_total_time_ms: game_escrow.white_timing.total_ms,
+                black_total_time_ms: game_escrow.black_timing.total_ms,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Announce the anti-cheat bits `player`'s move raised since `flags_before`, and hold the
+/// pot for review once the game has collected `anti_cheat_review_threshold` kinds of flag
+fn raise_anti_cheat_review(
+    game_escrow: &mut GameEscrow,
+    player: Pubkey,
+    flags_before: u32,
+    move_number: u32,
+) {
+    let flag_bits = game_escrow.anti_cheat_flags & !flags_before;
+    if flag_bits == 0 {
+        return;
+    }
+    emit!(AntiCheatFlagged {
+        room_id: game_escrow.room_id.clone(),
+        player,
+        flag_bits,
+        move_count: move_number,
+    });
+
+    let threshold = game_escrow.anti_cheat_review_threshold as u32;
+    if threshold > 0
+        && !game_escrow.review_required
+        && game_escrow.anti_cheat_flags.count_ones() >= threshold
+    {
+        game_escrow.review_required = true;
+        emit!(AntiCheatReviewRequired {
+            room_id: game_escrow.room_id.clone(),
+            anti_cheat_flags: game_escrow.anti_cheat_flags,
+            move_count: move_number,
+        });
+    }
+}
+
+/// Count a rated game between White and `black` against their pair's cooldown, failing
+/// if it hasn't passed since their last one. Unrated games and configs without a
+/// cooldown skip the check.
+fn throttle_rated_pair(
+    game_escrow: &GameEscrow,
+    pair_history: Option<&mut Account<PairHistory>>,
+    joiner: Pubkey,
+    now: i64,
+) -> Result<()> {
+    if !game_escrow.game_flags.is_rated || game_escrow.rated_pair_cooldown_seconds == 0 {
+        return Ok(());
+    }
+    let pair_history = pair_history.ok_or(ChessError::PairHistoryMissing)?;
+    require!(
+        pair_history.players == PairHistory::ordered(game_escrow.seated_player(), joiner),
+        ChessError::PairHistoryMissing
+    );
+    require!(
+        pair_history.rated_games == 0
+            || now >= pair_history.last_rated_game_at + game_escrow.rated_pair_cooldown_seconds as i64,
+        ChessError::RatedPairCooldown
+    );
+    pair_history.last_rated_game_at = now;
+    pair_history.rated_games += 1;
+
+    Ok(())
+}
+
+/// Move the game to `InProgress` once both stakes are in the escrow. Players whose
+/// `PlayerStats` are passed have the game counted against their active-game cap.
+fn start_game_if_funded(
+    game_escrow: &mut Account<GameEscrow>,
+    white_stats: Option<&mut PlayerStats>,
+    black_stats: Option<&mut PlayerStats>,
+) -> Result<()> {
+    if !(game_escrow.white_deposited && game_escrow.black_deposited) {
+        return Ok(());
+    }
+
+    // Never start a game against the default (unjoined) opponent slot
+    require!(
+        game_escrow.player_white != Pubkey::default()
+            && game_escrow.player_black != Pubkey::default(),
+        ChessError::OpponentNotJoined
+    );
+
+    if let Some(stats) = white_stats {
+        stats.start_game()?;
+        game_escrow.white_active_tracked = true;
+    }
+    if let Some(stats) = black_stats {
+        stats.start_game()?;
+        game_escrow.black_active_tracked = true;
+    }
+
+    let clock = Clock::get()?;
+    game_escrow.game_state = GameState::InProgress;
+    game_escrow.started_at = clock.unix_timestamp;
+    game_escrow.last_move_time = clock.unix_timestamp;
+
+    emit!(BothDepositsReceived {
+        room_id: game_escrow.room_id.clone(),
+        total_pot: game_escrow.total_deposited,
+        token_pot: game_escrow.token_total_deposited,
+    });
+
+    emit!(GameStarted {
+        room_id: game_escrow.room_id.clone(),
+        started_at: clock.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+/// Apply Elo updates for a finished rated game; unrated games are left alone.
+/// An early resignation scales both players' changes down to
+/// `EARLY_RESIGNATION_RATING_PCT` of the normal amount.
+fn update_ratings<'info>(
+    game_escrow: &mut Account<'info, GameEscrow>,
+    white_rating: Option<&mut Account<'info, PlayerRating>>,
+    black_rating: Option<&mut Account<'info, PlayerRating>>,
+    winner: &GameWinner,
+    early_resignation: bool,
+    finished_at: i64,
+) -> Result<()> {
+    if !game_escrow.game_flags.is_rated {
+        return Ok(());
+    }
+    let (Some(white_rating), Some(black_rating)) = (white_rating, black_rating) else {
+        return Err(ChessError::RatingAccountMissing.into());
+    };
+    let (white_result, black_result) = winner
+        .outcomes()
+        .ok_or(ChessError::InvalidWinnerDeclaration)?;
+
+    // Keep the pre-game ratings on the escrow for auditing
+    game_escrow.rating_white = white_rating.rating;
+    game_escrow.rating_black = black_rating.rating;
+
+    let scale = if early_resignation { GameEscrow::EARLY_RESIGNATION_RATING_PCT } else { 100 };
+    // Only the winner's own gain is boosted by berserking; the loser's drop is unchanged
+    let berserk_scale = |berserked: bool, outcome: MatchOutcome| {
+        if berserked && outcome == MatchOutcome::Win {
+            GameEscrow::BERSERK_WIN_RATING_PCT
+        } else {
+            100
+        }
+    };
+    let white_k = PlayerRating::k_factor(white_rating.games_played);
+    let black_k = PlayerRating::k_factor(black_rating.games_played);
+    let white_delta = elo_delta(white_rating.rating, black_rating.rating, white_result, white_k) * scale / 100
+        * berserk_scale(game_escrow.white_berserk, white_result) / 100;
+    let black_delta = elo_delta(black_rating.rating, white_rating.rating, black_result, black_k) * scale / 100
+        * berserk_scale(game_escrow.black_berserk, black_result) / 100;
+
+    for (rating, delta) in [(white_rating, white_delta), (black_rating, black_delta)] {
+        rating.apply_delta(delta);
+        rating.games_played += 1;
+        rating.last_updated = finished_at;
+        rating.last_game = game_escrow.room_id.clone();
+
+        emit!(RatingUpdated {
+            player: rating.player,
+            new_rating: rating.rating,
+            games_played: rating.games_played,
+            updated_at: finished_at,
+        });
+    }
+
+    Ok(())
+}
+
+/// Standard Elo change for `rating` after `outcome` against `opponent`
+fn elo_delta(rating: u32, opponent: u32, outcome: MatchOutcome, k_factor: f64) -> i32 {
+    let expected = 1.0 / (1.0 + 10f64.powf((opponent as f64 - rating as f64) / 400.0));
+    let score = match outcome {
+        MatchOutcome::Win => 1.0,
+        MatchOutcome::Draw => 0.5,
+        MatchOutcome::Loss => 0.0,
+    };
+    (k_factor * (score - expected)).round() as i32
+}
+
+/// Credit a finished game to whichever players' stats accounts were supplied
+fn record_player_stats(
+    game_escrow: &mut Account<GameEscrow>,
+    mut white_stats: Option<&mut Account<PlayerStats>>,
+    mut black_stats: Option<&mut Account<PlayerStats>>,
+    winner: &GameWinner,
+    finished_at: i64,
+) -> Result<()> {
+    let (white_result, black_result) = winner
+        .outcomes()
+        .ok_or(ChessError::InvalidWinnerDeclaration)?;
+
+    release_active_games(game_escrow, white_stats.as_deref_mut(), black_stats.as_deref_mut())?;
+    if let Some(stats) = white_stats {
+        stats.record(white_result, finished_at);
+    }
+    if let Some(stats) = black_stats {
+        stats.record(black_result, finished_at);
+    }
+
+    Ok(())
+}
+
+/// Add a finished game to the two players' head-to-head record, if it was passed
+fn record_head_to_head(
+    game_escrow: &GameEscrow,
+    head_to_head: Option<&mut Account<HeadToHead>>,
+    winner: &GameWinner,
+    finished_at: i64,
+) -> Result<()> {
+    let Some(record) = head_to_head else {
+        return Ok(());
+    };
+    require!(
+        record.players == PairHistory::ordered(game_escrow.player_white, game_escrow.player_black),
+        ChessError::HeadToHeadMismatch
+    );
+
+    let white_first = game_escrow.player_white == record.players[0];
+    match winner {
+        GameWinner::White => record.wins[if white_first { 0 } else { 1 }] += 1,
+        GameWinner::Black => record.wins[if white_first { 1 } else { 0 }] += 1,
+        GameWinner::Draw => record.draws += 1,
+        GameWinner::None | GameWinner::Aborted => return Err(ChessError::InvalidWinnerDeclaration.into()),
+    }
+    record.last_game_at = finished_at;
+
+    Ok(())
+}
+
+/// `start_game_if_funded` with each player's `PlayerStats` read from their stats PDA
+/// whenever they have created one. A game counted here must be settled with those stats,
+/// so a player can't keep it off their record by leaving the account out.
+fn start_game_with_stats<'info>(
+    game_escrow: &mut Account<GameEscrow>,
+    white_stats: &AccountInfo<'info>,
+    black_stats: &AccountInfo<'info>,
+) -> Result<()> {
+    let mut white = load_player_account::<PlayerStats>(white_stats, b"stats", &game_escrow.player_white)?;
+    let mut black = load_player_account::<PlayerStats>(black_stats, b"stats", &game_escrow.player_black)?;
+    start_game_if_funded(game_escrow, white.as_mut(), black.as_mut())?;
+
+    for (info, stats) in [(white_stats, white), (black_stats, black)] {
+        if let Some(stats) = stats {
+            let mut data = info.try_borrow_mut_data()?;
+            stats.try_serialize(&mut &mut data[..])?;
+        }
+    }
+    Ok(())
+}
+
+/// Take a finished or aborted game off the active-game count of every player it
+/// was counted for; their `PlayerStats` must be passed so the count can drop
+fn release_active_games(
+    game_escrow: &mut Account<GameEscrow>,
+    white_stats: Option<&mut Account<PlayerStats>>,
+    black_stats: Option<&mut Account<PlayerStats>>,
+) -> Result<()> {
+    if game_escrow.white_active_tracked {
+        let stats = white_stats.ok_or(ChessError::PlayerStatsMissing)?;
+        stats.active_games = stats.active_games.saturating_sub(1);
+        game_escrow.white_active_tracked = false;
+    }
+    if game_escrow.black_active_tracked {
+        let stats = black_stats.ok_or(ChessError::PlayerStatsMissing)?;
+        stats.active_games = stats.active_games.saturating_sub(1);
+        game_escrow.black_active_tracked = false;
+    }
+
+    Ok(())
+}
+
+/// Move `amount` out of a series vault, signing for the PDA
+fn pay_from_series_vault<'info>(
+    series_key: &Pubkey,
+    vault_bump: u8,
+    series_vault: &SystemAccount<'info>,
+    to: &AccountInfo<'info>,
+    system_program: &Program<'info, System>,
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+
+    let bump_bytes = [vault_bump];
+    let seeds = &[
+        b"series_vault".as_ref(),
+        series_key.as_ref(),
+        bump_bytes.as_ref(),
+    ];
+    anchor_lang::system_program::transfer(
+        CpiContext::new_with_signer(
+            system_program.to_account_info(),
+            anchor_lang::system_program::Transfer {
+                from: series_vault.to_account_info(),
+                to: to.clone(),
+            },
+            &[&seeds[..]],
+        ),
+        amount,
+    )
+}
+
+/// Pair the next single-elimination round from `field` and announce its games
+fn open_bracket_round(tournament: &mut Tournament, tournament_key: &Pubkey, field: &[Pubkey], now: i64) {
+    tournament.pair_bracket_round(tournament_key, field);
+
+    emit!(BracketRoundPaired {
+        tournament_id: tournament.tournament_id.clone(),
+        round: tournament.bracket_round,
+        room_ids: tournament.brackets.clone(),
+        pairings: tournament.bracket_pairings.clone(),
+        bye: tournament.bracket_bye,
+        paired_at: now,
+    });
+}
+
+/// Hash of the most recent slot in the SlotHashes sysvar: after the u64 entry count,
+/// entries are (slot u64, hash) pairs, newest first
+fn latest_slot_hash(slot_hashes: &AccountInfo) -> Result<[u8; 32]> {
+    let data = slot_hashes.try_borrow_data()?;
+    let hash = data.get(16..48).ok_or(ChessError::SlotHashesRequired)?;
+    let mut latest = [0u8; 32];
+    latest.copy_from_slice(hash);
+    Ok(latest)
+}
+
+/// Fisher-Yates shuffle of the field, drawing each swap from `hashv(seed, position)`
+fn shuffled_participants(participants: &[Pubkey], seed: &[u8; 32]) -> Vec<Pubkey> {
+    let mut shuffled = participants.to_vec();
+    for i in (1..shuffled.len()).rev() {
+        let draw =
+            anchor_lang::solana_program::hash::hashv(&[seed, &(i as u32).to_le_bytes()]).to_bytes();
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&draw[..8]);
+        let j = (u64::from_le_bytes(bytes) % (i as u64 + 1)) as usize;
+        shuffled.swap(i, j);
+    }
+    shuffled
+}
+
+/// Whether the config waives the platform fee for either player of the game
+fn is_fee_exempt(config: Option<&ProgramConfig>, game_escrow: &GameEscrow) -> bool {
+    config.is_some_and(|config| {
+        config.fee_exempt.contains(&game_escrow.player_white)
+            || config.fee_exempt.contains(&game_escrow.player_black)
+    })
+}
+
+/// Bytes the referee signs to settle a game: the escrow address, then the borsh-encoded
+/// winner and end reason.
+fn referee_message(game_escrow: &Pubkey, winner: &GameWinner, reason: &GameEndReason) -> Vec<u8> {
+    let mut message = game_escrow.to_bytes().to_vec();
+    message.push(winner.clone() as u8);
+    message.push(reason.clone() as u8);
+    message
+}
+
+/// Check that the instruction before the current one is an Ed25519 program instruction
+/// holding a single signature by the referee or a moderator over exactly `message`.
+fn verify_referee_signature(
+    instructions: &AccountInfo,
+    config: &ProgramConfig,
+    message: &[u8],
+) -> Result<()> {
+    let (signed_key, signed_message) =
+        load_ed25519_signature(instructions, ChessError::InvalidRefereeSignature)?;
+    require!(
+        config.is_result_authority(&signed_key) && signed_message == message,
+        ChessError::InvalidRefereeSignature
+    );
+
+    Ok(())
+}
+
+/// Bytes a player signs to let anyone resign the game for them: a domain tag, so no
+/// referee message can double as one, then the escrow address.
+fn resignation_message(game_escrow: &Pubkey) -> Vec<u8> {
+    let mut message = b"resign".to_vec();
+    message.extend_from_slice(game_escrow.as_ref());
+    message
+}
+
+/// Key and message of the single signature in the Ed25519 program instruction just before
+/// the current one. The precompile has already checked the signature itself; a missing
+/// instruction or one in any other layout fails with `error`.
+#[allow(deprecated)]
+fn load_ed25519_signature(instructions: &AccountInfo, error: ChessError) -> Result<(Pubkey, Vec<u8>)> {
+    use anchor_lang::solana_program::ed25519_program;
+    use anchor_lang::solana_program::sysvar::instructions::{
+        load_current_index_checked, load_instruction_at_checked,
+    };
+
+    let current = load_current_index_checked(instructions)?;
+    let previous = (current as usize).checked_sub(1).ok_or(error)?;
+    let ed25519_ix = load_instruction_at_checked(previous, instructions)?;
+    require_keys_eq!(ed25519_ix.program_id, ed25519_program::ID, error);
+    Ok(parse_ed25519_signature(&ed25519_ix.data).ok_or(error)?)
+}
+
+/// Key and message of an Ed25519 program instruction holding a single signature, with the
+/// signature, key and message all inline in the instruction's own data
+fn parse_ed25519_signature(data: &[u8]) -> Option<(Pubkey, Vec<u8>)> {
+    // Header: signature count and padding, then seven u16 offsets per signature
+    const HEADER_LEN: usize = 2;
+    const OFFSETS_LEN: usize = 14;
+    const THIS_INSTRUCTION: u16 = u16::MAX;
+
+    if data.len() < HEADER_LEN + OFFSETS_LEN || data[0] != 1 {
+        return None;
+    }
+    let read_u16 = |at: usize| u16::from_le_bytes([data[HEADER_LEN + at], data[HEADER_LEN + at + 1]]);
+    let signature_ix = read_u16(2);
+    let public_key_offset = read_u16(4) as usize;
+    let public_key_ix = read_u16(6);
+    let message_offset = read_u16(8) as usize;
+    let message_size = read_u16(10) as usize;
+    let message_ix = read_u16(12);
+    if signature_ix != THIS_INSTRUCTION
+        || public_key_ix != THIS_INSTRUCTION
+        || message_ix != THIS_INSTRUCTION
+    {
+        return None;
+    }
+
+    let signed_key = data.get(public_key_offset..public_key_offset + 32)?;
+    let signed_message = data.get(message_offset..message_offset + message_size)?;
+    Some((Pubkey::try_from(signed_key).ok()?, signed_message.to_vec()))
+}
+
+/// Accounts every settlement path needs in order to pay out a game vault.
+pub struct VaultPayout<'a, 'info> {
+    pub game_escrow: &'a Account<'info, GameEscrow>,
+    pub game_vault: AccountInfo<'info>,
+    pub player_white: AccountInfo<'info>,
+    pub player_black: AccountInfo<'info>,
+    pub fee_collector: AccountInfo<'info>,
+    pub system_program: AccountInfo<'info>,
+    pub vault_bump: u8,
+}
+
+impl<'info> VaultPayout<'_, 'info> {
+    fn transfer_from_vault(&self, to: &AccountInfo<'info>, amount: u64) -> Result<()> {
+        if amount == 0 {
+            return Ok(());
+        }
+
+        let game_key = self.game_escrow.key();
+        let bump_bytes = [self.vault_bump];
+        let seeds = &[
+            b"vault".as_ref(),
+            game_key.as_ref(),
+            bump_bytes.as_ref(),
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        anchor_lang::system_program::transfer(
+            CpiContext::new_with_signer(
+                self.system_program.clone(),
+                anchor_lang::system_program::Transfer {
+                    from: self.game_vault.clone(),
+                    to: to.clone(),
+                },
+                signer_seeds,
+            ),
+            amount,
+        )
+    }
+
+    /// Return each deposited stake, less `fee_per_stake` which goes to the fee collector
+    /// along with any lamports sent to the vault directly
+    pub fn refund_stakes(&self, fee_per_stake: u64) -> Result<()> {
+        let refund = self.game_escrow.stake_amount.saturating_sub(fee_per_stake);
+
+        // Token games hold no SOL stakes; settle_token_stake returns theirs
+        if self.game_escrow.white_deposited && !self.game_escrow.token_game {
+            self.transfer_from_vault(&self.fee_collector, fee_per_stake)?;
+            self.transfer_from_vault(&self.player_white, refund)?;
+            emit!(DepositRefunded {
+                room_id: self.game_escrow.room_id.clone(),
+                player: self.game_escrow.player_white,
+                amount: refund,
+            });
+        }
+        // In cross-asset games black's stake is refunded by settle_token_stake
+        if self.game_escrow.black_deposited && self.game_escrow.token_mint.is_none() {
+            self.transfer_from_vault(&self.fee_collector, fee_per_stake)?;
+            self.transfer_from_vault(&self.player_black, refund)?;
+            emit!(DepositRefunded {
+                room_id: self.game_escrow.room_id.clone(),
+                player: self.game_escrow.player_black,
+                amount: refund,
+            });
+        }
+
+        // Every stake is back, so anything left was sent to the vault directly
+        let surplus = self.game_vault.lamports();
+        self.transfer_from_vault(&self.fee_collector, surplus)?;
+        if surplus > 0 {
+            emit!(VaultSurplusSwept {
+                room_id: self.game_escrow.room_id.clone(),
+                amount: surplus,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Pay out the vault for `winner`; `fee_exempt` games skip the platform fee. The whole
+    /// balance goes out and no rent reserve is held back: the vault is a data-less system
+    /// account, so emptying it simply closes it, which is what returns its rent.
+    ///
+    /// The pot is what the players deposited, `total_deposited`; anything else in the vault
+    /// was sent to it directly and goes to the fee collector, so it can't skew the split.
+    ///
+    /// Returns the (white, black) shares left in the vault for `claim_payout`, which are
+    /// non-zero only in `pull_payouts` games; those still pay the fee here, and any share
+    /// under the vault's rent-exempt minimum, which couldn't be left there alone.
+    ///
+    /// A game is paid out once: callers set `funds_distributed` afterwards, and it is
+    /// checked here before the vault is read, whichever path tries again.
+    pub fn distribute(&self, winner: GameWinner, fee_exempt: bool) -> Result<[u64; 2]> {
+        require!(!self.game_escrow.funds_distributed, ChessError::FundsAlreadyDistributed);
+        let vault_balance = self.game_vault.lamports();
+        
+        if vault_balance == 0 {
+            return Ok([0; 2]);
+        }
+        require!(!self.game_escrow.review_required, ChessError::AntiCheatReviewPending);
+
+        let pot = vault_balance.min(self.game_escrow.total_deposited);
+        let surplus = vault_balance - pot;
+        let fee_amount = if fee_exempt {
+            0
+        } else {
+            self.game_escrow.platform_fee(pot, &winner)
+        };
+
+        let remaining_amount = pot.saturating_sub(fee_amount);
+
+        // Transfer fee and any surplus to fee collector
+        self.transfer_from_vault(&self.fee_collector, fee_amount + surplus)?;
+        if surplus > 0 {
+            emit!(VaultSurplusSwept {
+                room_id: self.game_escrow.room_id.clone(),
+                amount: surplus,
+            });
+        }
+
+        // Distribute remaining amount based on winner
+        let (white_amount, black_amount) = match winner {
+            GameWinner::White => self.game_escrow.decisive_split(pot, fee_amount),
+            GameWinner::Black => {
+                let (winner_amount, loser_amount) = self.game_escrow.decisive_split(pot, fee_amount);
+                (loser_amount, winner_amount)
+            }
+            // Cross-asset draws hand each asset back to the side that staked it
+            GameWinner::Draw if self.game_escrow.token_mint.is_some() => (remaining_amount, 0),
+            // Split the remaining amount by the shares agreed at creation
+            GameWinner::Draw => self.game_escrow.draw_split(remaining_amount),
+            // Games without a winner never reach here: an abandoned game is settled as a
+            // draw by `finalize_double_abandonment` and an aborted one refunded by
+            // `refund_stakes`, so failing leaves the vault untouched for those paths
+            GameWinner::None | GameWinner::Aborted => {
+                return Err(ChessError::InvalidWinnerDeclaration.into());
+            }
+        };
+
+        if self.game_escrow.pull_payouts {
+            // Once the other share is claimed, a share this small would leave the vault
+            // rent-paying, which the runtime refuses
+            let rent_reserve = Rent::get()?.minimum_balance(0);
+            let mut claimable = [white_amount, black_amount];
+            for (share, player) in claimable.iter_mut().zip([&self.player_white, &self.player_black]) {
+                if *share < rent_reserve {
+                    self.transfer_from_vault(player, *share)?;
+                    *share = 0;
+                }
+            }
+            return Ok(claimable);
+        }
+        self.transfer_from_vault(&self.player_white, white_amount)?;
+        self.transfer_from_vault(&self.player_black, black_amount)?;
+
+        Ok([0; 2])
+    }
+}
+
+impl<'info> DeclareResult<'info> {
+    pub fn distribute_funds(&mut self, winner: GameWinner, vault_bump: u8) -> Result<()> {
+        let fee_exempt = is_fee_exempt(self.config.as_deref(), &self.game_escrow);
+        let claimable = VaultPayout {
+            game_escrow: &self.game_escrow,
+            game_vault: self.game_vault.to_account_info(),
+            player_white: self.player_white.to_account_info(),
+            player_black: self.player_black.to_account_info(),
+            fee_collector: self.fee_collector.to_account_info(),
+            system_program: self.system_program.to_account_info(),
+            vault_bump,
+        }
+        .distribute(winner, fee_exempt)?;
+        self.game_escrow.claimable = claimable;
+        Ok(())
+    }
+}
+
+impl<'info> DeclareResultSigned<'info> {
+    pub fn distribute_funds(&mut self, winner: GameWinner, vault_bump: u8) -> Result<()> {
+        let fee_exempt = is_fee_exempt(Some(&self.config), &self.game_escrow);
+        let claimable = VaultPayout {
+            game_escrow: &self.game_escrow,
+            game_vault: self.game_vault.to_account_info(),
+            player_white: self.player_white.to_account_info(),
+            player_black: self.player_black.to_account_info(),
+            fee_collector: self.fee_collector.to_account_info(),
+            system_program: self.system_program.to_account_info(),
+            vault_bump,
+        }
+        .distribute(winner, fee_exempt)?;
+        self.game_escrow.claimable = claimable;
+        Ok(())
+    }
+}
+
+impl<'info> ResignWithAuthorization<'info> {
+    pub fn distribute_funds(&mut self, winner: GameWinner, vault_bump: u8) -> Result<()> {
+        let fee_exempt = is_fee_exempt(self.config.as_deref(), &self.game_escrow);
+        let claimable = VaultPayout {
+            game_escrow: &self.game_escrow,
+            game_vault: self.game_vault.to_account_info(),
+            player_white: self.player_white.to_account_info(),
+            player_black: self.player_black.to_account_info(),
+            fee_collector: self.fee_collector.to_account_info(),
+            system_program: self.system_program.to_account_info(),
+            vault_bump,
+        }
+        .distribute(winner, fee_exempt)?;
+        self.game_escrow.claimable = claimable;
+        Ok(())
+    }
+}
+
+impl<'info> HandleTimeout<'info> {
+    pub fn distribute_funds(&mut self, winner: GameWinner, vault_bump: u8) -> Result<()> {
+        let fee_exempt = is_fee_exempt(self.config.as_deref(), &self.game_escrow);
+        let claimable = VaultPayout {
+            game_escrow: &self.game_escrow,
+            game_vault: self.game_vault.to_account_info(),
+            player_white: self.player_white.to_account_info(),
+            player_black: self.player_black.to_account_info(),
+            fee_collector: self.fee_collector.to_account_info(),
+            system_program: self.system_program.to_account_info(),
+            vault_bump,
+        }
+        .distribute(winner, fee_exempt)?;
+        self.game_escrow.claimable = claimable;
+        Ok(())
+    }
+}
+
+impl<'info> FinalizeDoubleAbandonment<'info> {
+    pub fn refund_stakes(&self, fee_per_stake: u64, vault_bump: u8) -> Result<()> {
+        VaultPayout {
+            game_escrow: &self.game_escrow,
+            game_vault: self.game_vault.to_account_info(),
+            player_white: self.player_white.to_account_info(),
+            player_black: self.player_black.to_account_info(),
+            fee_collector: self.fee_collector.to_account_info(),
+            system_program: self.system_program.to_account_info(),
+            vault_bump,
+        }
+        .refund_stakes(fee_per_stake)
+    }
+}
+
+impl<'info> MutualAbort<'info> {
+    pub fn refund_stakes(&self, fee_per_stake: u64, vault_bump: u8) -> Result<()> {
+        VaultPayout {
+            game_escrow: &self.game_escrow,
+            game_vault: self.game_vault.to_account_info(),
+            player_white: self.player_white.to_account_info(),
+            player_black: self.player_black.to_account_info(),
+            fee_collector: self.fee_collector.to_account_info(),
+            system_program: self.system_program.to_account_info(),
+            vault_bump,
+        }
+        .refund_stakes(fee_per_stake)
+    }
+}
+
+impl<'info> SettleByAgreement<'info> {
+    pub fn distribute_funds(&mut self, winner: GameWinner, vault_bump: u8) -> Result<()> {
+        let fee_exempt = is_fee_exempt(self.config.as_deref(), &self.game_escrow);
+        let claimable = VaultPayout {
+            game_escrow: &self.game_escrow,
+            game_vault: self.game_vault.to_account_info(),
+            player_white: self.player_white.to_account_info(),
+            player_black: self.player_black.to_account_info(),
+            fee_collector: self.fee_collector.to_account_info(),
+            system_program: self.system_program.to_account_info(),
+            vault_bump,
+        }
+        .distribute(winner, fee_exempt)?;
+        self.game_escrow.claimable = claimable;
+        Ok(())
+    }
+}
+
+impl<'info> SettleGame<'info> {
+    pub fn distribute_funds(&mut self, winner: GameWinner, vault_bump: u8) -> Result<()> {
+        let fee_exempt = is_fee_exempt(self.config.as_deref(), &self.game_escrow);
+        let claimable = VaultPayout {
+            game_escrow: &self.game_escrow,
+            game_vault: self.game_vault.to_account_info(),
+            player_white: self.player_white.to_account_info(),
+            player_black: self.player_black.to_account_info(),
+            fee_collector: self.fee_collector.to_account_info(),
+            system_program: self.system_program.to_account_info(),
+            vault_bump,
+        }
+        .distribute(winner, fee_exempt)?;
+        self.game_escrow.claimable = claimable;
+        Ok(())
+    }
+}
+
+impl<'info> ResolveDispute<'info> {
+    pub fn distribute_funds(&mut self, winner: GameWinner, vault_bump: u8) -> Result<()> {
+        let fee_exempt = is_fee_exempt(Some(&self.config), &self.game_escrow);
+        let claimable = VaultPayout {
+            game_escrow: &self.game_escrow,
+            game_vault: self.game_vault.to_account_info(),
+            player_white: self.player_white.to_account_info(),
+            player_black: self.player_black.to_account_info(),
+            fee_collector: self.fee_collector.to_account_info(),
+            system_program: self.system_program.to_account_info(),
+            vault_bump,
+        }
+        .distribute(winner, fee_exempt)?;
+        self.game_escrow.claimable = claimable;
+        Ok(())
+    }
+}
+
+impl<'info> ClaimWinnings<'info> {
+    fn transfer_from_vault(&self, amount: u64, vault_bump: u8) -> Result<()> {
+        let game_key = self.game_escrow.key();
+        let bump_bytes = [vault_bump];
+        let seeds = &[
+            b"vault".as_ref(),
+            game_key.as_ref(),
+            bump_bytes.as_ref(),
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                token::Transfer {
+                    from: self.vault_token_account.to_account_info(),
+                    to: self.winner_token_account.to_account_info(),
+                    authority: self.game_vault.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            amount,
+        )
+    }
+}
+
+impl<'info> SettleTokenStake<'info> {
+    fn transfer_from_vault(&self, to: AccountInfo<'info>, amount: u64, vault_bump: u8) -> Result<()> {
+        if amount == 0 {
+            return Ok(());
+        }
+
+        let game_key = self.game_escrow.key();
+        let bump_bytes = [vault_bump];
+        let seeds = &[
+            b"vault".as_ref(),
+            game_key.as_ref(),
+            bump_bytes.as_ref(),
+        ];
+        let signer_seeds = &[&seeds[..]];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                token::Transfer {
+                    from: self.vault_token_account.to_account_info(),
+                    to,
+                    authority: self.game_vault.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            amount,
+        )
+    }
+}
+
+// Account Structs
+#[derive(Accounts)]
+#[instruction(room_id: String, stake_amount: u64, time_limit_seconds: i64, options: GameOptions)]
+pub struct InitializeGame<'info> {
+    #[account(
+        init, 
+        payer = player, 
+        space = 8 + GameEscrow::space_for(0),
+        seeds = [b"game", room_id.as_bytes()],
+        bump
+    )]
+    pub game_escrow: Account<'info, GameEscrow>,
+    #[account(mut)]
+    pub player: Signer<'info>,
+    /// CHECK: Fee collector can be any account; falls back to the config's collector when omitted
+    pub fee_collector: Option<UncheckedAccount<'info>>,
+    #[account(seeds = [b"config"], bump)]
+    /// CHECK: Program config PDA, read with `load_config` once it has been created
+    pub config: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    #[account(address = anchor_lang::solana_program::sysvar::slot_hashes::ID)]
+    /// CHECK: SlotHashes sysvar, read to draw the creator's color when it's `Random`
+    pub slot_hashes: Option<UncheckedAccount<'info>>,
+}
+
+#[derive(Accounts)]
+#[instruction(room_id: String)]
+pub struct AcceptRematch<'info> {
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    #[account(
+        init,
+        payer = player,
+        space = 8 + GameEscrow::space_for(0),
+        seeds = [b"game", room_id.as_bytes()],
+        bump
+    )]
+    pub rematch_escrow: Account<'info, GameEscrow>,
+    #[account(mut)]
+    pub player: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    // Required for rated games when the config sets a rematch cooldown
+    #[account(mut)]
+    pub pair_history: Option<Account<'info, PairHistory>>,
+    // Colours swap in the rematch, so its White is the finished game's Black
+    #[account(
+        mut,
+        seeds = [b"stats", game_escrow.player_black.as_ref()],
+        bump
+    )]
+    /// CHECK: Rematch White's stats PDA, updated by `start_game_with_stats` once created
+    pub white_stats: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"stats", game_escrow.player_white.as_ref()],
+        bump
+    )]
+    /// CHECK: Rematch Black's stats PDA, updated by `start_game_with_stats` once created
+    pub black_stats: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct JoinGame<'info> {
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    #[account(mut)]
+    pub player: Signer<'info>,
+    #[account(
+        seeds = [b"rating", player.key().as_ref()],
+        bump
+    )]
+    pub player_rating: Option<Account<'info, PlayerRating>>,
+    // Only needed when the game asks for a griefing bond
+    #[account(
+        mut,
+        seeds = [b"vault", game_escrow.key().as_ref()],
+        bump
+    )]
+    pub game_vault: Option<SystemAccount<'info>>,
+    pub system_program: Option<Program<'info, System>>,
+    // Required for rated games when the config sets a rematch cooldown
+    #[account(mut)]
+    pub pair_history: Option<Account<'info, PairHistory>>,
+    #[account(seeds = [b"config"], bump)]
+    /// CHECK: Program config PDA, read with `load_config` once it has been created
+    pub config: UncheckedAccount<'info>,
+    // A free game starts on join, so the stats of both players are read here: the creator's
+    // from whichever seat they hold, the joiner's from `joiner_stats`
+    #[account(
+        mut,
+        seeds = [b"stats", game_escrow.player_white.as_ref()],
+        bump
+    )]
+    /// CHECK: White's stats PDA, updated by `start_game_with_stats` once created
+    pub white_stats: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"stats", game_escrow.player_black.as_ref()],
+        bump
+    )]
+    /// CHECK: Black's stats PDA, updated by `start_game_with_stats` once created
+    pub black_stats: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"stats", player.key().as_ref()],
+        bump
+    )]
+    /// CHECK: Joining player's stats PDA, updated by `start_game_with_stats` once created
+    pub joiner_stats: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct DepositStake<'info> {
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    #[account(mut)]
+    pub player: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"vault", game_escrow.key().as_ref()],
+        bump
+    )]
+    pub game_vault: SystemAccount<'info>,
+    pub system_program: Program<'info, System>,
+    #[account(
+        mut,
+        seeds = [b"stats", game_escrow.player_white.as_ref()],
+        bump
+    )]
+    /// CHECK: White's stats PDA, updated by `start_game_with_stats` once created
+    pub white_stats: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"stats", game_escrow.player_black.as_ref()],
+        bump
+    )]
+    /// CHECK: Black's stats PDA, updated by `start_game_with_stats` once created
+    pub black_stats: UncheckedAccount<'info>,
+    #[account(seeds = [b"config"], bump)]
+    /// CHECK: Program config PDA, read with `load_config` once it has been created
+    pub config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct DepositTokenStake<'info> {
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    #[account(mut)]
+    pub player: Signer<'info>,
+    #[account(
+        seeds = [b"vault", game_escrow.key().as_ref()],
+        bump
+    )]
+    pub game_vault: SystemAccount<'info>,
+    #[account(constraint = game_escrow.token_mint == Some(mint.key()) @ ChessError::StakeAssetMismatch)]
+    pub mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = player
+    )]
+    pub player_token_account: Account<'info, TokenAccount>,
+    // Created by the first token deposit; in token games both players deposit into it
+    #[account(
+        init_if_needed,
+        payer = player,
+        associated_token::mint = mint,
+        associated_token::authority = game_vault
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    #[account(
+        mut,
+        seeds = [b"stats", game_escrow.player_white.as_ref()],
+        bump
+    )]
+    /// CHECK: White's stats PDA, updated by `start_game_with_stats` once created
+    pub white_stats: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"stats", game_escrow.player_black.as_ref()],
+        bump
+    )]
+    /// CHECK: Black's stats PDA, updated by `start_game_with_stats` once created
+    pub black_stats: UncheckedAccount<'info>,
+    #[account(seeds = [b"config"], bump)]
+    /// CHECK: Program config PDA, read with `load_config` once it has been created
+    pub config: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RecordMove<'info> {
+    // Grown by the handler to hold the move's history record
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    #[account(mut)]
+    pub player: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(moves: Vec<BatchedMove>)]
+pub struct RecordMovesBatch<'info> {
+    // Grown by the handler to hold the batch's history records
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    #[account(mut, address = game_escrow.player_white)]
+    pub player_white: Signer<'info>,
+    #[account(address = game_escrow.player_black)]
+    pub player_black: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SealMove<'info> {
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    pub player: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ResumeFromSeal<'info> {
+    // Grown by the handler to hold the move's history record
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    #[account(mut)]
+    pub player: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CheckMoveLegal<'info> {
+    pub game_escrow: Account<'info, GameEscrow>,
+}
+
+#[derive(Accounts)]
+pub struct PlayerConnection<'info> {
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    pub player: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Takeback<'info> {
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    pub player: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ChallengeLastMove<'info> {
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    pub player: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct GetTimingStats<'info> {
+    pub game_escrow: Account<'info, GameEscrow>,
+}
+
+#[derive(Accounts)]
+pub struct GetGameHeader<'info> {
+    pub game_escrow: Account<'info, GameEscrow>,
+}
+
+#[derive(Accounts)]
+pub struct GetGameSummary<'info> {
+    pub game_escrow: Account<'info, GameEscrow>,
+}
+
+#[derive(Accounts)]
+pub struct GetEscrowHealth<'info> {
+    pub game_escrow: Account<'info, GameEscrow>,
+    #[account(
+        seeds = [b"vault", game_escrow.key().as_ref()],
+        bump
+    )]
+    pub game_vault: SystemAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct GetUciMoves<'info> {
+    pub game_escrow: Account<'info, GameEscrow>,
+}
+
+#[derive(Accounts)]
+pub struct VerifyMoveChain<'info> {
+    pub game_escrow: Account<'info, GameEscrow>,
+}
+
+#[derive(Accounts)]
+pub struct ListLegalMoves<'info> {
+    pub game_escrow: Account<'info, GameEscrow>,
+}
+
+#[derive(Accounts)]
+pub struct IsInCheck<'info> {
+    pub game_escrow: Account<'info, GameEscrow>,
+}
+
+#[derive(Accounts)]
+pub struct DeclareResult<'info> {
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    #[account(mut)]
+    pub player: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"vault", game_escrow.key().as_ref()],
+        bump
+    )]
+    pub game_vault: SystemAccount<'info>,
+    #[account(
+        mut,
+        address = game_escrow.player_white
+    )]
+    /// CHECK: White player address validated against game escrow
+    pub player_white: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        address = game_escrow.player_black
+    )]
+    /// CHECK: Black player address validated against game escrow
+    pub player_black: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        address = game_escrow.fee_collector
+    )]
+    /// CHECK: Fee collector address validated against game escrow
+    pub fee_collector: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    #[account(
+        mut,
+        seeds = [b"stats", game_escrow.player_white.as_ref()],
+        bump
+    )]
+    pub white_stats: Option<Account<'info, PlayerStats>>,
+    #[account(
+        mut,
+        seeds = [b"stats", game_escrow.player_black.as_ref()],
+        bump
+    )]
+    pub black_stats: Option<Account<'info, PlayerStats>>,
+    #[account(
+        mut,
+        seeds = [b"rating", game_escrow.player_white.as_ref()],
+        bump
+    )]
+    pub white_rating: Option<Account<'info, PlayerRating>>,
+    #[account(
+        mut,
+        seeds = [b"rating", game_escrow.player_black.as_ref()],
+        bump
+    )]
+    pub black_rating: Option<Account<'info, PlayerRating>>,
+    #[account(seeds = [b"config"], bump)]
+    pub config: Option<Account<'info, ProgramConfig>>,
+    // Updated when passed; must be the account for this game's two players
+    #[account(mut)]
+    pub head_to_head: Option<Account<'info, HeadToHead>>,
+}
+
+#[derive(Accounts)]
+pub struct DeclareResultSigned<'info> {
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    #[account(
+        mut,
+        seeds = [b"vault", game_escrow.key().as_ref()],
+        bump
+    )]
+    pub game_vault: SystemAccount<'info>,
+    #[account(
+        mut,
+        address = game_escrow.player_white
+    )]
+    /// CHECK: White player address validated against game escrow
+    pub player_white: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        address = game_escrow.player_black
+    )]
+    /// CHECK: Black player address validated against game escrow
+    pub player_black: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        address = game_escrow.fee_collector
+    )]
+    /// CHECK: Fee collector address validated against game escrow
+    pub fee_collector: UncheckedAccount<'info>,
+    #[account(seeds = [b"config"], bump)]
+    pub config: Account<'info, ProgramConfig>,
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    /// CHECK: Instructions sysvar, read to find the referee's Ed25519 signature
+    pub instructions: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    #[account(
+        mut,
+        seeds = [b"stats", game_escrow.player_white.as_ref()],
+        bump
+    )]
+    pub white_stats: Option<Account<'info, PlayerStats>>,
+    #[account(
+        mut,
+        seeds = [b"stats", game_escrow.player_black.as_ref()],
+        bump
+    )]
+    pub black_stats: Option<Account<'info, PlayerStats>>,
+    #[account(
+        mut,
+        seeds = [b"rating", game_escrow.player_white.as_ref()],
+        bump
+    )]
+    pub white_rating: Option<Account<'info, PlayerRating>>,
+    #[account(
+        mut,
+        seeds = [b"rating", game_escrow.player_black.as_ref()],
+        bump
+    )]
+    pub black_rating: Option<Account<'info, PlayerRating>>,
+    // Updated when passed; must be the account for this game's two players
+    #[account(mut)]
+    pub head_to_head: Option<Account<'info, HeadToHead>>,
+}
+
+#[derive(Accounts)]
+pub struct ResignWithAuthorization<'info> {
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    #[account(
+        mut,
+        seeds = [b"vault", game_escrow.key().as_ref()],
+        bump
+    )]
+    pub game_vault: SystemAccount<'info>,
+    #[account(
+        mut,
+        address = game_escrow.player_white
+    )]
+    /// CHECK: White player address validated against game escrow
+    pub player_white: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        address = game_escrow.player_black
+    )]
+    /// CHECK: Black player address validated against game escrow
+    pub player_black: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        address = game_escrow.fee_collector
+    )]
+    /// CHECK: Fee collector address validated against game escrow
+    pub fee_collector: UncheckedAccount<'info>,
+    // Submits the player's authorization; needs no standing in the game
+    pub relayer: Signer<'info>,
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    /// CHECK: Instructions sysvar, read to find the player's Ed25519 signature
+    pub instructions: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    // Only read for fee exemptions
+    #[account(seeds = [b"config"], bump)]
+    pub config: Option<Account<'info, ProgramConfig>>,
+    #[account(
+        mut,
+        seeds = [b"stats", game_escrow.player_white.as_ref()],
+        bump
+    )]
+    pub white_stats: Option<Account<'info, PlayerStats>>,
+    #[account(
+        mut,
+        seeds = [b"stats", game_escrow.player_black.as_ref()],
+        bump
+    )]
+    pub black_stats: Option<Account<'info, PlayerStats>>,
+    #[account(
+        mut,
+        seeds = [b"rating", game_escrow.player_white.as_ref()],
+        bump
+    )]
+    pub white_rating: Option<Account<'info, PlayerRating>>,
+    #[account(
+        mut,
+        seeds = [b"rating", game_escrow.player_black.as_ref()],
+        bump
+    )]
+    pub black_rating: Option<Account<'info, PlayerRating>>,
+    // Updated when passed; must be the account for this game's two players
+    #[account(mut)]
+    pub head_to_head: Option<Account<'info, HeadToHead>>,
+}
+
+#[derive(Accounts)]
+pub struct HandleTimeout<'info> {
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    pub claimant: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"vault", game_escrow.key().as_ref()],
+        bump
+    )]
+    pub game_vault: SystemAccount<'info>,
+    #[account(
+        mut,
+        address = game_escrow.player_white
+    )]
+    /// CHECK: White player address validated against game escrow
+    pub player_white: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        address = game_escrow.player_black
+    )]
+    /// CHECK: Black player address validated against game escrow
+    pub player_black: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        address = game_escrow.fee_collector
+    )]
+    /// CHECK: Fee collector address validated against game escrow
+    pub fee_collector: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    #[account(
+        mut,
+        seeds = [b"stats", game_escrow.player_white.as_ref()],
+        bump
+    )]
+    pub white_stats: Option<Account<'info, PlayerStats>>,
+    #[account(
+        mut,
+        seeds = [b"stats", game_escrow.player_black.as_ref()],
+        bump
+    )]
+    pub black_stats: Option<Account<'info, PlayerStats>>,
+    #[account(
+        mut,
+        seeds = [b"rating", game_escrow.player_white.as_ref()],
+        bump
+    )]
+    pub white_rating: Option<Account<'info, PlayerRating>>,
+    #[account(
+        mut,
+        seeds = [b"rating", game_escrow.player_black.as_ref()],
+        bump
+    )]
+    pub black_rating: Option<Account<'info, PlayerRating>>,
+    #[account(seeds = [b"config"], bump)]
+    pub config: Option<Account<'info, ProgramConfig>>,
+    // Updated when passed; must be the account for this game's two players
+    #[account(mut)]
+    pub head_to_head: Option<Account<'info, HeadToHead>>,
+}
+
+#[derive(Accounts)]
+pub struct SettleGame<'info> {
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    #[account(
+        mut,
+        seeds = [b"vault", game_escrow.key().as_ref()],
+        bump
+    )]
+    pub game_vault: SystemAccount<'info>,
+    #[account(
+        mut,
+        address = game_escrow.player_white
+    )]
+    /// CHECK: White player address validated against game escrow
+    pub player_white: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        address = game_escrow.player_black
+    )]
+    /// CHECK: Black player address validated against game escrow
+    pub player_black: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        address = game_escrow.fee_collector
+    )]
+    /// CHECK: Fee collector address validated against game escrow
+    pub fee_collector: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    #[account(
+        mut,
+        seeds = [b"stats", game_escrow.player_white.as_ref()],
+        bump
+    )]
+    pub white_stats: Option<Account<'info, PlayerStats>>,
+    #[account(
+        mut,
+        seeds = [b"stats", game_escrow.player_black.as_ref()],
+        bump
+    )]
+    pub black_stats: Option<Account<'info, PlayerStats>>,
+    #[account(
+        mut,
+        seeds = [b"rating", game_escrow.player_white.as_ref()],
+        bump
+    )]
+    pub white_rating: Option<Account<'info, PlayerRating>>,
+    #[account(
+        mut,
+        seeds = [b"rating", game_escrow.player_black.as_ref()],
+        bump
+    )]
+    pub black_rating: Option<Account<'info, PlayerRating>>,
+    #[account(seeds = [b"config"], bump)]
+    pub config: Option<Account<'info, ProgramConfig>>,
+    // Updated when passed; must be the account for this game's two players
+    #[account(mut)]
+    pub head_to_head: Option<Account<'info, HeadToHead>>,
+}
+
+#[derive(Accounts)]
+pub struct ResolveDispute<'info> {
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    #[account(
+        mut,
+        seeds = [b"vault", game_escrow.key().as_ref()],
+        bump
+    )]
+    pub game_vault: SystemAccount<'info>,
+    #[account(
+        mut,
+        address = game_escrow.player_white
+    )]
+    /// CHECK: White player address validated against game escrow
+    pub player_white: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        address = game_escrow.player_black
+    )]
+    /// CHECK: Black player address validated against game escrow
+    pub player_black: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        address = game_escrow.fee_collector
+    )]
+    /// CHECK: Fee collector address validated against game escrow
+    pub fee_collector: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    #[account(
+        mut,
+        seeds = [b"stats", game_escrow.player_white.as_ref()],
+        bump
+    )]
+    pub white_stats: Option<Account<'info, PlayerStats>>,
+    #[account(
+        mut,
+        seeds = [b"stats", game_escrow.player_black.as_ref()],
+        bump
+    )]
+    pub black_stats: Option<Account<'info, PlayerStats>>,
+    #[account(
+        mut,
+        seeds = [b"rating", game_escrow.player_white.as_ref()],
+        bump
+    )]
+    pub white_rating: Option<Account<'info, PlayerRating>>,
+    #[account(
+        mut,
+        seeds = [b"rating", game_escrow.player_black.as_ref()],
+        bump
+    )]
+    pub black_rating: Option<Account<'info, PlayerRating>>,
+    #[account(seeds = [b"config"], bump)]
+    pub config: Account<'info, ProgramConfig>,
+    pub arbiter: Signer<'info>,
+    // Updated when passed; must be the account for this game's two players
+    #[account(mut)]
+    pub head_to_head: Option<Account<'info, HeadToHead>>,
+}
+
+#[derive(Accounts)]
+pub struct SettleTokenStake<'info> {
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    #[account(
+        seeds = [b"vault", game_escrow.key().as_ref()],
+        bump
+    )]
+    pub game_vault: SystemAccount<'info>,
+    #[account(constraint = game_escrow.token_mint == Some(mint.key()) @ ChessError::StakeAssetMismatch)]
+    pub mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = game_vault
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = game_escrow.player_white
+    )]
+    pub white_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = game_escrow.player_black
+    )]
+    pub black_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = game_escrow.fee_collector
+    )]
+    pub fee_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+    // Only read for fee exemptions
+    #[account(seeds = [b"config"], bump)]
+    pub config: Option<Account<'info, ProgramConfig>>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimWinnings<'info> {
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    #[account(
+        seeds = [b"vault", game_escrow.key().as_ref()],
+        bump
+    )]
+    pub game_vault: SystemAccount<'info>,
+    #[account(constraint = game_escrow.token_mint == Some(mint.key()) @ ChessError::StakeAssetMismatch)]
+    pub mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = game_vault
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = game_escrow.player_white
+    )]
+    pub winner_token_account: Account<'info, TokenAccount>,
+    #[account(address = game_escrow.player_white)]
+    pub winner: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimPayout<'info> {
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    #[account(
+        mut,
+        seeds = [b"vault", game_escrow.key().as_ref()],
+        bump
+    )]
+    pub game_vault: SystemAccount<'info>,
+    #[account(mut)]
+    pub player: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FinalizeDoubleAbandonment<'info> {
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    #[account(
+        mut,
+        seeds = [b"vault", game_escrow.key().as_ref()],
+        bump
+    )]
+    pub game_vault: SystemAccount<'info>,
+    #[account(
+        mut,
+        address = game_escrow.player_white
+    )]
+    /// CHECK: White player address validated against game escrow
+    pub player_white: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        address = game_escrow.player_black
+    )]
+    /// CHECK: Black player address validated against game escrow
+    pub player_black: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        address = game_escrow.fee_collector
+    )]
+    /// CHECK: Fee collector address validated against game escrow
+    pub fee_collector: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    #[account(
+        mut,
+        seeds = [b"stats", game_escrow.player_white.as_ref()],
+        bump
+    )]
+    pub white_stats: Option<Account<'info, PlayerStats>>,
+    #[account(
+        mut,
+        seeds = [b"stats", game_escrow.player_black.as_ref()],
+        bump
+    )]
+    pub black_stats: Option<Account<'info, PlayerStats>>,
+}
+
+#[derive(Accounts)]
+pub struct SettleByAgreement<'info> {
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    #[account(
+        mut,
+        seeds = [b"vault", game_escrow.key().as_ref()],
+        bump
+    )]
+    pub game_vault: SystemAccount<'info>,
+    #[account(
+        mut,
+        address = game_escrow.player_white
+    )]
+    pub player_white: Signer<'info>,
+    #[account(
+        mut,
+        address = game_escrow.player_black
+    )]
+    pub player_black: Signer<'info>,
+    #[account(
+        mut,
+        address = game_escrow.fee_collector
+    )]
+    /// CHECK: Fee collector address validated against game escrow
+    pub fee_collector: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    #[account(
+        mut,
+        seeds = [b"stats", game_escrow.player_white.as_ref()],
+        bump
+    )]
+    pub white_stats: Option<Account<'info, PlayerStats>>,
+    #[account(
+        mut,
+        seeds = [b"stats", game_escrow.player_black.as_ref()],
+        bump
+    )]
+    pub black_stats: Option<Account<'info, PlayerStats>>,
+    #[account(
+        mut,
+        seeds = [b"rating", game_escrow.player_white.as_ref()],
+        bump
+    )]
+    pub white_rating: Option<Account<'info, PlayerRating>>,
+    #[account(
+        mut,
+        seeds = [b"rating", game_escrow.player_black.as_ref()],
+        bump
+    )]
+    pub black_rating: Option<Account<'info, PlayerRating>>,
+    #[account(seeds = [b"config"], bump)]
+    pub config: Option<Account<'info, ProgramConfig>>,
+    // Updated when passed; must be the account for this game's two players
+    #[account(mut)]
+    pub head_to_head: Option<Account<'info, HeadToHead>>,
+}
+
+#[derive(Accounts)]
+pub struct MutualAbort<'info> {
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    #[account(
+        mut,
+        seeds = [b"vault", game_escrow.key().as_ref()],
+        bump
+    )]
+    pub game_vault: SystemAccount<'info>,
+    #[account(
+        mut,
+        address = game_escrow.player_white
+    )]
+    pub player_white: Signer<'info>,
+    #[account(
+        mut,
+        address = game_escrow.player_black
+    )]
+    pub player_black: Signer<'info>,
+    #[account(
+        mut,
+        address = game_escrow.fee_collector
+    )]
+    /// CHECK: Fee collector address validated against game escrow
+    pub fee_collector: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    #[account(
+        mut,
+        seeds = [b"stats", game_escrow.player_white.as_ref()],
+        bump
+    )]
+    pub white_stats: Option<Account<'info, PlayerStats>>,
+    #[account(
+        mut,
+        seeds = [b"stats", game_escrow.player_black.as_ref()],
+        bump
+    )]
+    pub black_stats: Option<Account<'info, PlayerStats>>,
+}
+
+#[derive(Accounts)]
+pub struct DisableAntiCheat<'info> {
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    #[account(address = game_escrow.player_white)]
+    pub player_white: Signer<'info>,
+    #[account(address = game_escrow.player_black)]
+    pub player_black: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClearAntiCheatReview<'info> {
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    #[account(seeds = [b"config"], bump)]
+    pub config: Account<'info, ProgramConfig>,
+    pub moderator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimDraw<'info> {
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    pub player: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Berserk<'info> {
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    pub player: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExtendTime<'info> {
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    #[account(address = game_escrow.player_white)]
+    pub player_white: Signer<'info>,
+    #[account(address = game_escrow.player_black)]
+    pub player_black: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AdjustTimeControl<'info> {
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    #[account(address = game_escrow.player_white)]
+    pub player_white: Signer<'info>,
+    #[account(address = game_escrow.player_black)]
+    pub player_black: Signer<'info>,
+    #[account(seeds = [b"config"], bump)]
+    pub config: Option<Account<'info, ProgramConfig>>,
+}
+
+#[derive(Accounts)]
+pub struct PostChatHash<'info> {
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    pub sender: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetRentBeneficiary<'info> {
+    #[account(mut, has_one = rent_beneficiary @ ChessError::UnauthorizedPlayer)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    pub rent_beneficiary: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseGame<'info> {
+    #[account(
+        mut,
+        has_one = rent_beneficiary @ ChessError::UnauthorizedPlayer,
+        close = rent_beneficiary
+    )]
+    pub game_escrow: Account<'info, GameEscrow>,
+    #[account(mut)]
+    /// CHECK: Rent recipient validated against game escrow
+    pub rent_beneficiary: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawStake<'info> {
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    #[account(mut)]
+    pub player: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"vault", game_escrow.key().as_ref()],
+        bump
+    )]
+    pub game_vault: SystemAccount<'info>,
+    #[account(
+        mut,
+        address = game_escrow.fee_collector
+    )]
+    /// CHECK: Fee collector address validated against game escrow
+    pub fee_collector: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelGame<'info> {
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    #[account(mut)]
+    pub player: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"vault", game_escrow.key().as_ref()],
+        bump
+    )]
+    pub game_vault: SystemAccount<'info>,
+    #[account(
+        mut,
+        address = game_escrow.player_white
+    )]
+    /// CHECK: White player address validated against game escrow
+    pub player_white: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        address = game_escrow.player_black
+    )]
+    /// CHECK: Black player address validated against game escrow
+    pub player_black: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        address = game_escrow.fee_collector
+    )]
+    /// CHECK: Fee collector address validated against game escrow
+    pub fee_collector: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// Tournament account structures
+#[derive(Accounts)]
+#[instruction(tournament_id: String, name: String, entry_fee: u64, max_participants: u32)]
+pub struct CreateTournament<'info> {
+    #[account(
+        init, 
+        payer = creator, 
+        space = 8 + Tournament::space_for(max_participants),
+        seeds = [b"tournament", tournament_id.as_bytes()],
+        bump
+    )]
+    pub tournament: Account<'info, Tournament>,
+    #[account(
+        mut,
+        seeds = [b"tournament_vault", tournament.key().as_ref()],
+        bump
+    )]
+    pub tournament_vault: SystemAccount<'info>,
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct JoinTournament<'info> {
+    #[account(mut)]
+    pub tournament: Account<'info, Tournament>,
+    #[account(
+        mut,
+        seeds = [b"tournament_vault", tournament.key().as_ref()],
+        bump
+    )]
+    pub tournament_vault: SystemAccount<'info>,
+    #[account(mut)]
+    pub player: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    /// Needed for a late join, which adds the player to the standings
+    #[account(
+        mut,
+        seeds = [b"standings", tournament.key().as_ref()],
+        bump
+    )]
+    pub standings: Option<Account<'info, TournamentStandings>>,
+}
+
+#[derive(Accounts)]
+pub struct StartTournament<'info> {
+    #[account(mut)]
+    pub tournament: Account<'info, Tournament>,
+    #[account(
+        seeds = [b"tournament_vault", tournament.key().as_ref()],
+        bump
+    )]
+    pub tournament_vault: SystemAccount<'info>,
+    #[account(
+        init,
+        payer = creator,
+        space = 8 + TournamentStandings::space_for(tournament.current_participants),
+        seeds = [b"standings", tournament.key().as_ref()],
+        bump
+    )]
+    pub standings: Account<'info, TournamentStandings>,
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ReportMatchResult<'info> {
+    #[account(has_one = creator)]
+    pub tournament: Account<'info, Tournament>,
+    #[account(
+        mut,
+        seeds = [b"standings", tournament.key().as_ref()],
+        bump
+    )]
+    pub standings: Account<'info, TournamentStandings>,
+    pub game_escrow: Account<'info, GameEscrow>,
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ReportTournamentFlag<'info> {
+    #[account(mut, has_one = creator)]
+    pub tournament: Account<'info, Tournament>,
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateTournament<'info> {
+    /// CHECK: read as the legacy layout and rewritten in `migrate_tournament`, since an
+    /// unmigrated account can't deserialize as the current `Tournament`. Not checked
+    /// against seeds: tournaments created before ids were part of the seed all live at
+    /// the bare `b"tournament"` address, so the discriminator check stands in for it
+    #[account(mut, owner = crate::ID)]
+    pub tournament: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FinalizeTournament<'info> {
+    #[account(mut, has_one = creator)]
+    pub tournament: Account<'info, Tournament>,
+    #[account(
+        mut,
+        seeds = [b"tournament_vault", tournament.key().as_ref()],
+        bump
+    )]
+    pub tournament_vault: SystemAccount<'info>,
+    #[account(
+        seeds = [b"standings", tournament.key().as_ref()],
+        bump
+    )]
+    pub standings: Account<'info, TournamentStandings>,
+    pub creator: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelTournament<'info> {
+    #[account(mut, has_one = creator)]
+    pub tournament: Account<'info, Tournament>,
+    #[account(
+        mut,
+        seeds = [b"tournament_vault", tournament.key().as_ref()],
+        bump
+    )]
+    pub tournament_vault: SystemAccount<'info>,
+    pub creator: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseTournament<'info> {
+    #[account(mut, has_one = creator, close = creator)]
+    pub tournament: Account<'info, Tournament>,
+    #[account(
+        mut,
+        seeds = [b"tournament_vault", tournament.key().as_ref()],
+        bump
+    )]
+    pub tournament_vault: SystemAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"standings", tournament.key().as_ref()],
+        bump,
+        close = creator
+    )]
+    pub standings: Option<Account<'info, TournamentStandings>>,
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct GetStandings<'info> {
+    pub tournament: Account<'info, Tournament>,
+    #[account(
+        seeds = [b"standings", tournament.key().as_ref()],
+        bump
+    )]
+    pub standings: Account<'info, TournamentStandings>,
+}
+
+#[derive(Accounts)]
+pub struct GenerateBrackets<'info> {
+    #[account(mut, has_one = creator)]
+    pub tournament: Account<'info, Tournament>,
+    pub creator: Signer<'info>,
+    #[account(address = anchor_lang::solana_program::sysvar::slot_hashes::ID)]
+    /// CHECK: SlotHashes sysvar, read for the shuffle seed in `Random` seeding
+    pub slot_hashes: Option<UncheckedAccount<'info>>,
+}
+
+#[derive(Accounts)]
+pub struct AdvanceBracket<'info> {
+    #[account(mut, has_one = creator)]
+    pub tournament: Account<'info, Tournament>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct PreviewBracketShuffle<'info> {
+    pub tournament: Account<'info, Tournament>,
+}
+
+#[derive(Accounts)]
+pub struct ReseedRound<'info> {
+    #[account(mut, has_one = creator)]
+    pub tournament: Account<'info, Tournament>,
+    #[account(
+        seeds = [b"standings", tournament.key().as_ref()],
+        bump
+    )]
+    pub standings: Account<'info, TournamentStandings>,
+    pub creator: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitializePlayerRating<'info> {
+    #[account(
+        init,
+        payer = player,
+        space = 8 + PlayerRating::SPACE,
+        seeds = [b"rating", player.key().as_ref()],
+        bump
+    )]
+    pub player_rating: Account<'info, PlayerRating>,
+    #[account(mut)]
+    pub player: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    /// Supplies the starting rating; without it new players start at `DEFAULT_RATING`
+    #[account(seeds = [b"config"], bump)]
+    pub config: Option<Account<'info, ProgramConfig>>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + ProgramConfig::SPACE,
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Account<'info, ProgramConfig>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::ChessEscrow>,
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(admin.key())
+            @ ChessError::UnauthorizedAdmin
+    )]
+    pub program_data: Account<'info, ProgramData>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump,
+        has_one = admin @ ChessError::UnauthorizedAdmin
+    )]
+    pub config: Account<'info, ProgramConfig>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct PlaceSideBet<'info> {
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    #[account(
+        init,
+        payer = bettor,
+        space = 8 + SideBet::SPACE,
+        seeds = [b"sidebet", game_escrow.key().as_ref(), bettor.key().as_ref()],
+        bump
+    )]
+    pub side_bet: Account<'info, SideBet>,
+    #[account(
+        mut,
+        seeds = [b"sidebet_vault", game_escrow.key().as_ref()],
+        bump
+    )]
+    pub side_bet_vault: SystemAccount<'info>,
+    #[account(mut)]
+    pub bettor: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SettleSideBet<'info> {
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    #[account(
+        mut,
+        seeds = [b"sidebet", game_escrow.key().as_ref(), bettor.key().as_ref()],
+        bump,
+        has_one = bettor,
+        close = bettor
+    )]
+    pub side_bet: Account<'info, SideBet>,
+    #[account(
+        mut,
+        seeds = [b"sidebet_vault", game_escrow.key().as_ref()],
+        bump
+    )]
+    pub side_bet_vault: SystemAccount<'info>,
+    /// CHECK: the bettor recorded on `side_bet`; receives the payout and the bet's rent
+    #[account(mut)]
+    pub bettor: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SweepDeadVault<'info> {
+    #[account(mut)]
+    pub game_escrow: Account<'info, GameEscrow>,
+    #[account(
+        mut,
+        seeds = [b"vault", game_escrow.key().as_ref()],
+        bump
+    )]
+    pub game_vault: SystemAccount<'info>,
+    #[account(
+        seeds = [b"config"],
+        bump,
+        has_one = admin @ ChessError::UnauthorizedAdmin
+    )]
+    pub config: Account<'info, ProgramConfig>,
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        address = config.fee_collector
+    )]
+    /// CHECK: Treasury address validated against the config's fee collector
+    pub treasury: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RecalcRating<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump,
+        has_one = admin @ ChessError::UnauthorizedAdmin
+    )]
+    pub config: Account<'info, ProgramConfig>,
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"rating", player_rating.player.as_ref()],
+        bump
+    )]
+    pub player_rating: Account<'info, PlayerRating>,
+}
+
+#[derive(Accounts)]
+#[instruction(player_a: Pubkey, player_b: Pubkey)]
+pub struct InitializePairHistory<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + PairHistory::SPACE,
+        seeds = [b"pair", player_a.as_ref(), player_b.as_ref()],
+        bump
+    )]
+    pub pair_history: Account<'info, PairHistory>,
     #[account(mut)]
-    pub player: Signer<'info>,
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(series_id: String)]
+pub struct CreateSeries<'info> {
+    #[account(
+        init,
+        payer = creator,
+        space = 8 + MatchSeries::SPACE,
+        seeds = [b"series", series_id.as_bytes()],
+        bump
+    )]
+    pub series: Account<'info, MatchSeries>,
     #[account(
         mut,
-        seeds = [b"vault", game_escrow.key().as_ref()],
+        seeds = [b"series_vault", series.key().as_ref()],
         bump
     )]
-    pub game_vault: SystemAccount<'info>,
+    pub series_vault: SystemAccount<'info>,
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct JoinSeries<'info> {
+    #[account(mut)]
+    pub series: Account<'info, MatchSeries>,
     #[account(
         mut,
-        address = game_escrow.player_white
+        seeds = [b"series_vault", series.key().as_ref()],
+        bump
     )]
-    /// CHECK: White player address validated against game escrow
-    pub player_white: UncheckedAccount<'info>,
+    pub series_vault: SystemAccount<'info>,
+    #[account(mut)]
+    pub player: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ReportSeriesGame<'info> {
+    #[account(mut)]
+    pub series: Account<'info, MatchSeries>,
     #[account(
         mut,
-        address = game_escrow.player_black
+        seeds = [b"series_vault", series.key().as_ref()],
+        bump
     )]
-    /// CHECK: Black player address validated against game escrow
-    pub player_black: UncheckedAccount<'info>,
+    pub series_vault: SystemAccount<'info>,
+    pub game_escrow: Account<'info, GameEscrow>,
+    /// CHECK: paid if they win the series; must be the series creator
+    #[account(mut, address = series.players[0] @ ChessError::UnauthorizedPlayer)]
+    pub player_one: UncheckedAccount<'info>,
+    /// CHECK: paid if they win the series; must be the invited opponent
+    #[account(mut, address = series.players[1] @ ChessError::UnauthorizedPlayer)]
+    pub player_two: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelSeries<'info> {
+    #[account(mut)]
+    pub series: Account<'info, MatchSeries>,
     #[account(
         mut,
-        address = game_escrow.fee_collector
+        seeds = [b"series_vault", series.key().as_ref()],
+        bump
     )]
-    /// CHECK: Fee collector address validated against game escrow
-    pub fee_collector: UncheckedAccount<'info>,
+    pub series_vault: SystemAccount<'info>,
+    #[account(mut, address = series.players[0] @ ChessError::UnauthorizedPlayer)]
+    pub creator: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExpireSeries<'info> {
+    #[account(mut)]
+    pub series: Account<'info, MatchSeries>,
+    #[account(
+        mut,
+        seeds = [b"series_vault", series.key().as_ref()],
+        bump
+    )]
+    pub series_vault: SystemAccount<'info>,
+    /// CHECK: refunded; must be the series creator
+    #[account(mut, address = series.players[0] @ ChessError::UnauthorizedPlayer)]
+    pub player_one: UncheckedAccount<'info>,
+    /// CHECK: refunded; must be the invited opponent
+    #[account(mut, address = series.players[1] @ ChessError::UnauthorizedPlayer)]
+    pub player_two: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(player_a: Pubkey, player_b: Pubkey)]
+pub struct InitializeHeadToHead<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + HeadToHead::SPACE,
+        seeds = [b"h2h", player_a.as_ref(), player_b.as_ref()],
+        bump
+    )]
+    pub head_to_head: Account<'info, HeadToHead>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
     pub system_program: Program<'info, System>,
 }
 
-#[derive(Accounts)]
-pub struct HandleTimeout<'info> {
-    #[account(mut)]
-    pub game_escrow: Account<'info, GameEscrow>,
-    #[account(
-        mut,
-        seeds = [b"vault", game_escrow.key().as_ref()],
-        bump
-    )]
-    pub game_vault: SystemAccount<'info>,
-    #[account(
-        mut,
-        address = game_escrow.player_white
-    )]
-    /// CHECK: White player address validated against game escrow
-    pub player_white: UncheckedAccount<'info>,
-    #[account(
-        mut,
-        address = game_escrow.player_black
-    )]
-    /// CHECK: Black player address validated against game escrow
-    pub player_black: UncheckedAccount<'info>,
-    #[account(
-        mut,
-        address = game_escrow.fee_collector
-    )]
-    /// CHECK: Fee collector address validated against game escrow
-    pub fee_collector: UncheckedAccount<'info>,
-    pub system_program: Program<'info, System>,
+#[derive(Accounts)]
+pub struct GetHeadToHead<'info> {
+    pub head_to_head: Account<'info, HeadToHead>,
+}
+
+#[derive(Accounts)]
+pub struct VerifyCheckmate {}
+
+#[derive(Accounts)]
+pub struct GetFen<'info> {
+    pub game_escrow: Account<'info, GameEscrow>,
+}
+
+#[derive(Accounts)]
+pub struct InitializePlayerStats<'info> {
+    #[account(
+        init,
+        payer = player,
+        space = 8 + PlayerStats::SPACE,
+        seeds = [b"stats", player.key().as_ref()],
+        bump
+    )]
+    pub player_stats: Account<'info, PlayerStats>,
+    #[account(mut)]
+    pub player: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+
+
+// Data Structures
+#[account]
+pub struct GameEscrow {
+    pub room_id: String,                    // 4 + 32 = 36 bytes
+    pub player_white: Pubkey,              // 32 bytes
+    pub player_black: Pubkey,              // 32 bytes
+    pub stake_amount: u64,                 // 8 bytes
+    pub total_deposited: u64,              // 8 bytes
+    pub game_state: GameState,             // 1 byte
+    pub winner: GameWinner,                // 1 byte
+    pub created_at: i64,                   // 8 bytes
+    pub started_at: i64,                   // 8 bytes
+    pub finished_at: i64,                  // 8 bytes
+    pub time_limit_seconds: i64,           // 8 bytes
+    pub fee_collector: Pubkey,             // 32 bytes
+    pub white_deposited: bool,             // 1 byte
+    pub black_deposited: bool,             // 1 byte
+    pub move_count: u32,                   // 4 bytes
+    pub last_move_time: i64,               // 8 bytes
+    
+    // Enhanced features for production
+    pub time_control: TimeControl,         // 16 bytes
+    pub position_hash: [u8; 32],          // 32 bytes
+    pub move_history: Vec<MoveRecord>,     // 4 + MoveRecord::MAX_SPACE per retained move (up to move_history_capacity)
+    pub anti_cheat_flags: u32,            // 4 bytes
+    pub rating_white: u32,                 // 4 bytes
+    pub rating_black: u32,                 // 4 bytes
+    pub tournament_id: Option<String>,     // Variable size
+    pub game_flags: GameFlags,             // 4 bytes
+
+    // Anti-cheat enforcement
+    pub anti_cheat_forfeit_threshold: u8,  // 1 byte (0 = auto-forfeit disabled)
+    pub white_flag_count: u8,              // 1 byte
+    pub black_flag_count: u8,              // 1 byte
+    pub funds_distributed: bool,           // 1 byte
+    pub move_history_capacity: u16,        // 2 bytes
+
+    // Current position, one piece code per square (a1 = 0 .. h8 = 63). Each move is
+    // applied to it in place, so validation never replays `move_history`.
+    pub board: [u8; 64],                   // 64 bytes
+    pub suspicious_move_interval_ms: u32,  // 4 bytes
+    pub emit_board_state: bool,            // 1 byte
+
+    // Cross-asset games: white stakes SOL, black stakes `token_stake_amount` of `token_mint`
+    pub token_mint: Option<Pubkey>,        // 1 + 32 bytes
+    pub token_stake_amount: u64,           // 8 bytes
+    pub token_total_deposited: u64,        // 8 bytes
+    pub token_funds_distributed: bool,     // 1 byte
+
+    // Adjournment: commitment to the sealed move and when the clocks stopped
+    pub sealed_move_hash: [u8; 32],        // 32 bytes
+    pub adjourned_at: i64,                 // 8 bytes
+    pub early_resignation_moves: u16,      // 2 bytes
+    pub en_passant_square: Option<u8>,     // 1 + 1 bytes (square skipped by the last double push)
+
+    // Per-player game clocks, in seconds
+    pub white_time_remaining: u64,         // 8 bytes
+    pub black_time_remaining: u64,         // 8 bytes
+    pub double_flag_rule: DoubleFlagRule,  // 1 byte
+    pub emit_material_events: bool,        // 1 byte
+    pub armageddon: bool,                  // 1 byte (Black has draw odds and less time)
+    pub position_keys: Vec<u64>,           // 4 + 8 bytes per position since the last pawn move or capture
+
+    // Whether the game counts against each player's active-game cap
+    pub white_active_tracked: bool,        // 1 byte
+    pub black_active_tracked: bool,        // 1 byte
+
+    // Anti-collusion floor on declared results
+    pub min_moves_before_result: u16,      // 2 bytes (0 = no minimum)
+
+    // Players who halved their clock for a bigger win
+    pub white_berserk: bool,               // 1 byte
+    pub black_berserk: bool,               // 1 byte
+
+    // Threefold and fifty-move claims
+    pub draw_claims: DrawClaims,           // 1 byte
+    pub halfmove_clock: u16,               // 2 bytes (plies since the last pawn move or capture)
+
+    // State before the last move, so a challenged move can be taken back
+    pub previous_board: [u8; 64],          // 64 bytes
+    pub previous_en_passant_square: Option<u8>, // 1 + 1 bytes
+    pub previous_halfmove_clock: u16,      // 2 bytes
+    pub forfeit_on_illegal_move: bool,     // 1 byte
+
+    // Chess variant and its per-side counters
+    pub variant: Variant,                  // 1 byte
+    pub white_checks: u8,                  // 1 byte (checks given, three-check)
+    pub black_checks: u8,                  // 1 byte
+
+    pub rake_mode: RakeMode,               // 1 byte
+
+    // Time-locked token winnings, released by `claim_winnings`
+    pub winnings_lock_seconds: u32,        // 4 bytes (0 = paid out immediately)
+    pub locked_winnings: u64,              // 8 bytes
+    pub winnings_unlock_at: i64,           // 8 bytes
+
+    // Takebacks by agreement
+    pub max_takebacks: u8,                 // 1 byte (per player; 0 = disabled)
+    pub white_takebacks: u8,               // 1 byte
+    pub black_takebacks: u8,               // 1 byte
+    pub takeback_requested_by: Option<Pubkey>, // 1 + 32 bytes
+    pub can_undo_last_move: bool,          // 1 byte (the previous-position snapshot is for the last move)
+    pub increment_start_move: u16,         // 2 bytes (plies played before increment is credited)
+
+    // Disconnections
+    pub auto_draw_offer_on_disconnect: bool, // 1 byte
+    pub white_disconnected: bool,          // 1 byte
+    pub black_disconnected: bool,          // 1 byte
+    pub draw_offered_by: Option<Pubkey>,   // 1 + 32 bytes
+    pub time_control_phases: Vec<TimeControlPhase>, // 4 + 6 bytes per phase
+    pub rent_beneficiary: Pubkey,          // 32 bytes (receives the rent on `close_game`)
+    pub metadata: Option<Vec<u8>>,         // 1 + 4 + 64 bytes (opaque to the program)
+    pub white_timing: ThinkTimeTotals,     // 20 bytes
+    pub black_timing: ThinkTimeTotals,     // 20 bytes
+
+    // Deposit matching
+    pub deposit_match_window_seconds: u32, // 4 bytes (0 = the time limit, from creation)
+    pub griefing_bond: u64,                // 8 bytes (posted by Black on joining)
+    pub black_bond_posted: bool,           // 1 byte
+    pub first_deposit_at: i64,             // 8 bytes (opens the match window)
+
+    // Moderated spectator chat
+    pub spectator_chat: bool,              // 1 byte
+    pub chat_hashes: Vec<ChatHash>,        // 4 + 72 bytes per retained message
+    pub chat_hash_count: u32,              // 4 bytes (all messages ever posted)
+    pub rated_pair_cooldown_seconds: u32,  // 4 bytes (from the config at creation)
+
+    // Move interval floor
+    pub min_move_interval_ms: u32,         // 4 bytes (0 = no floor)
+    pub strict_move_interval: bool,        // 1 byte (reject rather than flag)
+
+    // Draw payout shares, summing to 10_000
+    pub draw_split_white_bps: u16,         // 2 bytes
+    pub draw_split_black_bps: u16,         // 2 bytes
+
+    // Opt-in auto-resignation thresholds
+    pub white_auto_resign_below_seconds: u32, // 4 bytes (0 = off)
+    pub black_auto_resign_below_seconds: u32, // 4 bytes (0 = off)
+    pub compact_move_events: bool,         // 1 byte (MoveRecordedCompact instead of MoveRecorded)
+    pub audit_rate_pct: u8,                // 1 byte (share of optimistic moves fully validated)
+    pub low_time_warning_seconds: u32,     // 4 bytes (0 = no warning)
+    pub low_time_warned: [bool; 2],        // 2 bytes (white, black; cleared once back above)
+    pub fullmove_number: u16,              // 2 bytes (starts at 1, up after each Black move)
+    pub white_to_move: bool,               // 1 byte
+    pub turn_recorded: bool,               // 1 byte (false on accounts older than white_to_move)
+    pub draw_offered_at: i64,              // 8 bytes (when `draw_offered_by` made the offer)
+    pub think_time_avg_ms: [u32; 2],       // 8 bytes (white, black; rolling over `THINK_TIME_WINDOW` moves)
+    pub fast_move_streak: [u8; 2],         // 2 bytes (white, black; consecutive fast moves in complex positions)
+    pub fee_bps: u16,                      // 2 bytes (platform fee, in basis points)
+    pub rematch_proposed_by: Option<Pubkey>, // 1 + 32 bytes
+    pub castling_rights: u8,               // 1 byte (chess_validation::ALL_CASTLING_RIGHTS bits)
+    pub previous_castling_rights: u8,      // 1 byte (before the last move, for takebacks)
+    pub anti_cheat_review_threshold: u8,   // 1 byte (distinct flags that hold the pot; 0 = never)
+    pub review_required: bool,             // 1 byte (pot held until a moderator clears it)
+    pub side_bet_totals: [u64; 3],         // 24 bytes (staked on White, Black and Draw)
+    pub open_side_bets: u32,               // 4 bytes (placed and not yet settled)
+    pub token_game: bool,                  // 1 byte (both sides stake `token_mint`; no SOL is staked)
+    pub pull_payouts: bool,                // 1 byte (settlement leaves shares in the vault for `claim_payout`)
+    pub claimable: [u64; 2],               // 16 bytes (white, black; settled and not yet claimed)
+    pub timeout_grace_seconds: u32,        // 4 bytes (a flag can be claimed only this far past it)
+    pub dispute_window_seconds: u32,       // 4 bytes (results may be disputed this long; 0 = never)
+    pub disputed_by: Option<Pubkey>,       // 1 + 32 bytes
+    pub payout_policy: PayoutPolicy,       // 3 bytes (winner/loser split of a decisive pot)
+    pub draw_offer_automatic: bool,        // 1 byte (`draw_offered_by` was flagged disconnected, not offering)
+}
+
+// Tournament structures
+#[account]
+pub struct Tournament {
+    pub tournament_id: String,             // Variable size
+    pub name: String,                      // Variable size
+    pub creator: Pubkey,                   // 32 bytes
+    pub entry_fee: u64,                    // 8 bytes
+    pub max_participants: u32,             // 4 bytes
+    pub current_participants: u32,         // 4 bytes
+    pub status: TournamentStatus,          // 1 byte
+    pub time_control: TimeControl,         // 16 bytes
+    pub created_at: i64,                   // 8 bytes
+    pub started_at: i64,                   // 8 bytes
+    pub finished_at: i64,                  // 8 bytes
+    pub prize_pool: u64,                   // 8 bytes
+    pub participants: Vec<Pubkey>,         // Variable size
+    pub brackets: Vec<String>,             // Variable size (room ids of the current round's games)
+    pub seeding_mode: SeedingMode,         // 1 byte
+    pub form_weight_pct: u8,               // 1 byte
+    pub seeds: Vec<Pubkey>,                // Variable size (seed order)
+    pub seeded_round: u16,                 // 2 bytes (last round `reseed_round` ran for)
+    pub flag_tallies: Vec<FlagTally>,      // 4 + 36 bytes per flagged participant
+    pub shuffle_seed: [u8; 32],            // 32 bytes (seed of a `Random` shuffle; zero otherwise)
+    pub tournament_version: u8,            // 1 byte (layout version; see `migrate_tournament`)
+    pub payout_pcts: Vec<u8>,              // 4 + 1 byte per paid place
+    pub guaranteed_pool: u64,              // 8 bytes (advertised pool; 0 = entry fees only)
+    pub late_registration_seconds: u32,    // 4 bytes (joining allowed after the start; 0 = none)
+    pub winners: Vec<Pubkey>,              // 4 + 32 bytes per paid place (final standings order)
+    pub bracket_round: u16,                // 2 bytes (current single-elimination round; 0 = unpaired)
+    pub bracket_pairings: Vec<Pairing>,    // 4 + 64 bytes per match (the games in `brackets`)
+    pub bracket_bye: Option<Pubkey>,       // 1 + 32 bytes (sits out the current round)
+    pub bracket_champion: Option<Pubkey>,  // 1 + 32 bytes (last player left in the bracket)
+}
+
+impl Tournament {
+    pub const MAX_PARTICIPANTS: u32 = 32;
+    pub const MAX_PAYOUT_PLACES: usize = 8;
+    /// Layout version written by `create_tournament`. Version 0 is the unversioned layout
+    /// that `LegacyTournament` reads; version 1 only appended the version byte, version 2
+    /// the payout structure, version 3 the late-registration window, version 4 the
+    /// winners and version 5 the single-elimination bracket.
+    pub const CURRENT_VERSION: u8 = 5;
+    /// Longest room id `bracket_room_id` produces, the same limit `initialize_game` has
+    pub const MAX_BRACKET_ROOM_ID_LEN: usize = 32;
+
+    pub fn space_for(max_participants: u32) -> usize {
+        (4 + 32) + (4 + 64) + 32 + 8 + 4 + 4 + 1 + 25 + 8 + 8 + 8 + 8
+            + (4 + 32 * max_participants as usize)
+            + (4 + (4 + Self::MAX_BRACKET_ROOM_ID_LEN) * (max_participants as usize / 2))
+            + 1 + 1
+            + (4 + 32 * max_participants as usize)
+            + 2
+            + (4 + FlagTally::SPACE * max_participants as usize)
+            + 32
+            + 1
+            + (4 + Self::MAX_PAYOUT_PLACES) + 8
+            + 4
+            + (4 + 32 * Self::MAX_PAYOUT_PLACES)
+            + 2
+            + (4 + 64 * (max_participants as usize / 2))
+            + (1 + 32)
+            + (1 + 32)
+    }
+
+    /// Room id for game `index` of bracket `round`: a prefix of the tournament's address,
+    /// so ids don't collide across tournaments
+    pub fn bracket_room_id(tournament_key: &Pubkey, round: u16, index: usize) -> String {
+        let key = tournament_key.to_string();
+        format!("{}-r{}-m{}", &key[..12], round, index + 1)
+    }
+
+    /// Pair `field` for the next single-elimination round. With an odd field the first
+    /// entrant gets a bye; the rest fold, first against last, so the top seed meets the
+    /// bottom one and later rounds keep the usual bracket order.
+    pub fn pair_bracket_round(&mut self, tournament_key: &Pubkey, field: &[Pubkey]) {
+        let (bye, paired) = match field.split_first() {
+            Some((first, rest)) if field.len() % 2 == 1 => (Some(*first), rest),
+            _ => (None, field),
+        };
+        let matches = paired.len() / 2;
+
+        self.bracket_round += 1;
+        self.bracket_bye = bye;
+        self.bracket_pairings = (0..matches)
+            .map(|i| Pairing { white: paired[i], black: paired[paired.len() - 1 - i] })
+            .collect();
+        self.brackets = (0..matches)
+            .map(|i| Self::bracket_room_id(tournament_key, self.bracket_round, i))
+            .collect();
+    }
+
+    /// Prize for each paid place out of `pool`. With fewer players than paid places the
+    /// unused places' shares are spread over the rest in proportion; rounding dust goes
+    /// to first place, so the prizes always add up to exactly `pool`.
+    pub fn payouts(&self, pool: u64) -> Vec<u64> {
+        let places = self.payout_pcts.len().min(self.participants.len());
+        let shares = &self.payout_pcts[..places];
+        let total: u128 = shares.iter().map(|&pct| pct as u128).sum();
+        if total == 0 {
+            return Vec::new();
+        }
+        let mut payouts: Vec<u64> = shares
+            .iter()
+            .map(|&pct| (pool as u128 * pct as u128 / total) as u64)
+            .collect();
+        payouts[0] += pool - payouts.iter().sum::<u64>();
+        payouts
+    }
+
+    /// Next round's pairings from the seed order: the top half meets the bottom half
+    /// (1 v n/2+1, 2 v n/2+2, ...); with an odd field the last seed sits out
+    pub fn pairings(&self) -> Vec<Pairing> {
+        let half = self.seeds.len() / 2;
+        self.seeds[..half]
+            .iter()
+            .zip(&self.seeds[half..2 * half])
+            .map(|(white, black)| Pairing { white: *white, black: *black })
+            .collect()
+    }
+
+    /// Seeding strength: the rating, nudged by recent form when blending is enabled.
+    /// A perfect recent score is worth up to +400 at full weight, a winless run -400.
+    pub fn seed_score(&self, rating: u32, stats: Option<&PlayerStats>) -> i64 {
+        let form_offset = match (&self.seeding_mode, stats.and_then(|s| s.recent_score_bps())) {
+            (SeedingMode::RatingAndForm, Some(score_bps)) => {
+                (score_bps as i64 - 5_000) * 800 / 10_000 * self.form_weight_pct as i64 / 100
+            },
+            _ => 0,
+        };
+        rating as i64 + form_offset
+    }
+}
+
+/// Tournament settings chosen by the creator at `create_tournament`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct TournamentOptions {
+    /// How `generate_brackets` orders participants
+    pub seeding_mode: SeedingMode,
+    /// Share (0-100) of the form adjustment applied in `RatingAndForm` seeding
+    pub form_weight_pct: u8,
+    /// Percent of the pool paid to each place, first place first; must add up to 100
+    pub payout_pcts: Vec<u8>,
+    /// Advertised pool, paid only as far as the entry fees actually cover it
+    pub guaranteed_pool: u64,
+    /// How long after the start players may still join; 0 closes entry at the start
+    pub late_registration_seconds: u32,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Default)]
+pub enum SeedingMode {
+    #[default]
+    Rating,
+    RatingAndForm,
+    /// Shuffle the field from an on-chain seed instead of ranking it
+    Random,
+}
+
+// Live leaderboard for an active tournament
+#[account]
+pub struct TournamentStandings {
+    pub tournament: Pubkey,                // 32 bytes
+    pub entries: Vec<StandingEntry>,       // 4 + 44 bytes per participant
+    pub results: Vec<ReportedMatch>,       // 4 + 65 bytes per reported match
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct StandingEntry {
+    pub player: Pubkey,
+    pub wins: u16,
+    pub losses: u16,
+    pub draws: u16,
+    pub points: u16,                       // half-points: 2 per win, 1 per draw
+    pub sonneborn_berger: u32,             // quarter-points, see `update_tiebreaks`
+}
+
+/// `Tournament` as laid out before `tournament_version` was added; only read by
+/// `migrate_tournament`
+#[derive(AnchorDeserialize)]
+pub struct LegacyTournament {
+    pub tournament_id: String,
+    pub name: String,
+    pub creator: Pubkey,
+    pub entry_fee: u64,
+    pub max_participants: u32,
+    pub current_participants: u32,
+    pub status: TournamentStatus,
+    pub time_control: TimeControl,
+    pub created_at: i64,
+    pub started_at: i64,
+    pub finished_at: i64,
+    pub prize_pool: u64,
+    pub participants: Vec<Pubkey>,
+    pub brackets: Vec<String>,
+    pub seeding_mode: SeedingMode,
+    pub form_weight_pct: u8,
+    pub seeds: Vec<Pubkey>,
+    pub seeded_round: u16,
+    pub flag_tallies: Vec<FlagTally>,
+    pub shuffle_seed: [u8; 32],
+}
+
+impl LegacyTournament {
+    pub fn into_current(self) -> Tournament {
+        Tournament {
+            tournament_id: self.tournament_id,
+            name: self.name,
+            creator: self.creator,
+            entry_fee: self.entry_fee,
+            max_participants: self.max_participants,
+            current_participants: self.current_participants,
+            status: self.status,
+            time_control: self.time_control,
+            created_at: self.created_at,
+            started_at: self.started_at,
+            finished_at: self.finished_at,
+            prize_pool: self.prize_pool,
+            participants: self.participants,
+            brackets: self.brackets,
+            seeding_mode: self.seeding_mode,
+            form_weight_pct: self.form_weight_pct,
+            seeds: self.seeds,
+            seeded_round: self.seeded_round,
+            flag_tallies: self.flag_tallies,
+            shuffle_seed: self.shuffle_seed,
+            tournament_version: Tournament::CURRENT_VERSION,
+            // Winner takes all, from the entry fees alone
+            payout_pcts: vec![100],
+            guaranteed_pool: 0,
+            late_registration_seconds: 0,
+            winners: Vec::new(),
+            bracket_round: 0,
+            bracket_pairings: Vec::new(),
+            bracket_bye: None,
+            bracket_champion: None,
+        }
+    }
+}
+
+/// Anti-cheat flags a participant has collected across their linked tournament games
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct FlagTally {
+    pub player: Pubkey,
+    pub flags: u32,
+}
+
+impl FlagTally {
+    pub const SPACE: usize = 32 + 4;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct Pairing {
+    pub white: Pubkey,
+    pub black: Pubkey,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ReportedMatch {
+    pub game: Pubkey,
+    pub white: Pubkey,
+    pub black: Pubkey,
+    pub result: GameWinner,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq)]
+pub enum MatchOutcome {
+    Win,
+    Loss,
+    Draw,
+}
+
+impl TournamentStandings {
+    pub const MATCH_SPACE: usize = 32 + 32 + 32 + 1;
+
+    pub fn space_for(participants: u32) -> usize {
+        32 + 4 + 44 * participants as usize + 4
+    }
+
+    /// Account size once one more match result is stored; the creator pays the added rent
+    pub fn space_for_next_result(tournament: &Tournament, standings: &TournamentStandings) -> usize {
+        8 + Self::space_for(tournament.current_participants)
+            + Self::MATCH_SPACE * (standings.results.len() + 1)
+    }
+
+    pub fn record(&mut self, player: &Pubkey, outcome: MatchOutcome) -> Result<()> {
+        let entry = self
+            .entries
+            .iter_mut()
+            .find(|entry| entry.player == *player)
+            .ok_or(ChessError::PlayerNotInTournament)?;
+
+        match outcome {
+            MatchOutcome::Win => {
+                entry.wins += 1;
+                entry.points += 2;
+            },
+            MatchOutcome::Loss => entry.losses += 1,
+            MatchOutcome::Draw => {
+                entry.draws += 1;
+                entry.points += 1;
+            },
+        }
+
+        Ok(())
+    }
+
+    fn points_of(&self, player: &Pubkey) -> u32 {
+        self.entries
+            .iter()
+            .find(|entry| entry.player == *player)
+            .map_or(0, |entry| entry.points as u32)
+    }
+
+    /// Recompute every Sonneborn-Berger score from the reported matches: the current
+    /// points of each opponent beaten plus half those of each opponent drawn. Points
+    /// are half-points, so scores are kept in quarter-points to stay integral.
+    pub fn update_tiebreaks(&mut self) {
+        let scores: Vec<u32> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                self.results
+                    .iter()
+                    .filter_map(|reported| {
+                        let (white, black) = reported.result.outcomes()?;
+                        let (opponent, outcome) = if reported.white == entry.player {
+                            (reported.black, white)
+                        } else if reported.black == entry.player {
+                            (reported.white, black)
+                        } else {
+                            return None;
+                        };
+                        match outcome {
+                            MatchOutcome::Win => Some(2 * self.points_of(&opponent)),
+                            MatchOutcome::Draw => Some(self.points_of(&opponent)),
+                            MatchOutcome::Loss => None,
+                        }
+                    })
+                    .sum()
+            })
+            .collect();
+
+        for (entry, score) in self.entries.iter_mut().zip(scores) {
+            entry.sonneborn_berger = score;
+        }
+    }
+
+    /// Points first, then Sonneborn-Berger, then wins, then pubkey bytes so ties
+    /// always resolve the same way
+    pub fn sort(&mut self) {
+        self.entries.sort_by(|a, b| {
+            b.points
+                .cmp(&a.points)
+                .then(b.sonneborn_berger.cmp(&a.sonneborn_berger))
+                .then(b.wins.cmp(&a.wins))
+                .then(a.player.to_bytes().cmp(&b.player.to_bytes()))
+        });
+    }
+}
+
+// Rating structure
+#[account]
+pub struct PlayerRating {
+    pub player: Pubkey,                    // 32 bytes
+    pub rating: u32,                       // 4 bytes
+    pub games_played: u32,                 // 4 bytes
+    pub last_updated: i64,                 // 8 bytes
+    pub last_game: String,                 // 32 bytes
+    pub peak_rating: u32,                  // 4 bytes
+    pub rating_floor_gap: u32,             // 4 bytes (0 = no floor)
+}
+
+impl PlayerRating {
+    pub const DEFAULT_RATING: u32 = 1500;
+    // Range the config may set the starting rating within
+    pub const MIN_DEFAULT_RATING: u32 = 100;
+    pub const MAX_DEFAULT_RATING: u32 = 3000;
+    // K-factor while a rating is provisional, then once established, then for veterans
+    pub const K_FACTOR: f64 = 32.0;
+    pub const ESTABLISHED_K_FACTOR: f64 = 24.0;
+    pub const VETERAN_K_FACTOR: f64 = 16.0;
+    pub const PROVISIONAL_GAMES: u32 = 30;
+    pub const VETERAN_GAMES: u32 = 100;
+    pub const SPACE: usize = 32 + 4 + 4 + 8 + (4 + 32) + 4 + 4;
+    // Most games one recalculation may replay
+    pub const MAX_RECALC_GAMES: usize = 32;
+
+    /// K-factor for a player with `games_played` rated games behind them: ratings settle
+    /// down as the sample grows
+    pub fn k_factor(games_played: u32) -> f64 {
+        if games_played < Self::PROVISIONAL_GAMES {
+            Self::K_FACTOR
+        } else if games_played < Self::VETERAN_GAMES {
+            Self::ESTABLISHED_K_FACTOR
+        } else {
+            Self::VETERAN_K_FACTOR
+        }
+    }
+
+    /// Apply an Elo change, tracking the peak; with a floor gap set, losses stop at
+    /// `rating_floor_gap` below the peak so a player can't sandbag their way down
+    pub fn apply_delta(&mut self, delta: i32) {
+        let floor = match self.rating_floor_gap {
+            0 => 0,
+            gap => self.peak_rating.saturating_sub(gap),
+        };
+        self.rating = self.rating.saturating_add_signed(delta).max(floor.min(self.rating));
+        self.peak_rating = self.peak_rating.max(self.rating);
+    }
+}
+
+/// One finished rated game, as replayed by `recalc_rating_from_games`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct RatedGameResult {
+    /// Game escrow address; each game may appear once
+    pub game: Pubkey,
+    /// Opponent's rating before the game
+    pub opponent_rating: u32,
+    pub outcome: MatchOutcome,
+    /// Resigned before the game's early-resignation threshold
+    pub early_resignation: bool,
+}
+
+// Program-wide settings, managed by the admin
+#[account]
+pub struct ProgramConfig {
+    pub admin: Pubkey,                     // 32 bytes
+    pub fee_collector: Pubkey,             // 32 bytes (default for new games)
+    pub fee_exempt: Vec<Pubkey>,           // 4 + 32 bytes per player (games they play pay no fee)
+    pub referee: Pubkey,                   // 32 bytes (signs declare_result_signed; default = none)
+    pub moderators: Vec<Pubkey>,           // 4 + 32 bytes per moderator (may also sign results)
+    pub default_rating: u32,               // 4 bytes (starting rating for new players)
+    pub rating_floor_gap: u32,             // 4 bytes (new players' floor below peak; 0 = no floor)
+    pub rated_pair_cooldown_seconds: u32,  // 4 bytes (between rated games of one pair; 0 = none)
+    pub min_rated_initial_time: u32,       // 4 bytes (seconds on each clock for rated games; 0 = any)
+    pub allowed_mints: Vec<Pubkey>,        // 4 + 32 bytes per mint (token stakes; empty = any)
+    pub dead_vault_window_seconds: u32,    // 4 bytes (settled games older than this may be swept; 0 = never)
+    pub enforce_fee_collector: bool,       // 1 byte (new games must use `fee_collector`)
+    pub paused: bool,                      // 1 byte (no new games, joins or deposits)
+    pub min_stake: u64,                    // 8 bytes (lamports a new SOL game must stake; 0 = the rent floor only)
+    pub max_stake: u64,                    // 8 bytes (most lamports a new SOL game may stake; 0 = no cap)
+}
+
+impl ProgramConfig {
+    pub const MAX_FEE_EXEMPT: usize = 16;
+    pub const MAX_MODERATORS: usize = 8;
+    pub const MAX_ALLOWED_MINTS: usize = 8;
+    pub const SPACE: usize = 32 + 32 + 4 + 32 * Self::MAX_FEE_EXEMPT + 32
+        + 4 + 32 * Self::MAX_MODERATORS
+        + 4
+        + 4
+        + 4
+        + 4
+        + 4 + 32 * Self::MAX_ALLOWED_MINTS
+        + 4
+        + 1
+        + 1
+        + 8 + 8;
+
+    /// Whether `key` may sign results for `declare_result_signed`
+    pub fn is_result_authority(&self, key: &Pubkey) -> bool {
+        (*key != Pubkey::default() && *key == self.referee) || self.moderators.contains(key)
+    }
+
+    /// Whether new games may take token stakes in `mint`
+    pub fn is_mint_allowed(&self, mint: &Pubkey) -> bool {
+        self.allowed_mints.is_empty() || self.allowed_mints.contains(mint)
+    }
+}
+
+/// Config fields to change; `None` leaves a value as it is
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct ConfigUpdate {
+    pub fee_collector: Option<Pubkey>,
+    /// Replaces the whole fee-exempt list
+    pub fee_exempt: Option<Vec<Pubkey>>,
+    /// Key trusted to sign results; `Pubkey::default()` unregisters it
+    pub referee: Option<Pubkey>,
+    /// Rating new `PlayerRating` accounts start from
+    pub default_rating: Option<u32>,
+    /// How far below their peak new `PlayerRating` accounts may fall; 0 disables the floor
+    pub rating_floor_gap: Option<u32>,
+    /// Minimum time between rated games of the same two players; 0 disables the cooldown
+    pub rated_pair_cooldown_seconds: Option<u32>,
+    /// Shortest initial clock (seconds) a rated game may be created with; 0 allows any
+    pub min_rated_initial_time: Option<u32>,
+}
+
+/// A spectator's bet on the outcome of a game, paid from the game's side-bet vault
+#[account]
+pub struct SideBet {
+    pub game: Pubkey,                      // 32 bytes
+    pub bettor: Pubkey,                    // 32 bytes
+    pub predicted_winner: GameWinner,      // 1 byte (White, Black or Draw)
+    pub amount: u64,                       // 8 bytes
+    pub placed_at: i64,                    // 8 bytes
+}
+
+impl SideBet {
+    pub const SPACE: usize = 32 + 32 + 1 + 8 + 8;
+}
+
+/// Rated games played between two players, for the rematch cooldown
+#[account]
+pub struct PairHistory {
+    pub players: [Pubkey; 2],              // 64 bytes (ascending key order)
+    pub last_rated_game_at: i64,           // 8 bytes
+    pub rated_games: u32,                  // 4 bytes
+}
+
+impl PairHistory {
+    pub const SPACE: usize = 32 * 2 + 8 + 4;
+
+    pub fn ordered(a: Pubkey, b: Pubkey) -> [Pubkey; 2] {
+        if a < b { [a, b] } else { [b, a] }
+    }
+}
+
+/// Results of every settled game between two players, whatever colours they played
+#[account]
+pub struct HeadToHead {
+    pub players: [Pubkey; 2],              // 64 bytes (ascending key order)
+    pub wins: [u32; 2],                    // 8 bytes (indexed like `players`)
+    pub draws: u32,                        // 4 bytes
+    pub last_game_at: i64,                 // 8 bytes
+}
+
+impl HeadToHead {
+    pub const SPACE: usize = 32 * 2 + 4 * 2 + 4 + 8;
+}
+
+/// A best-of-N match between two players, settled from their finished game escrows.
+/// Both stakes are held in the `series_vault` PDA until one side wins the series, or
+/// until it is cancelled before the opponent joins or expires after going idle.
+#[account]
+pub struct MatchSeries {
+    pub series_id: String,                 // 4 + 32 bytes
+    pub players: [Pubkey; 2],              // 64 bytes (creator, opponent)
+    pub stake_amount: u64,                 // 8 bytes (per player)
+    pub wins_required: u8,                 // 1 byte
+    pub flag_forfeit_threshold: u8,        // 1 byte (0 = no forfeit)
+    pub status: SeriesStatus,              // 1 byte
+    pub wins: [u8; 2],                     // 2 bytes
+    pub draws: u8,                         // 1 byte
+    pub flags: [u16; 2],                   // 4 bytes (anti-cheat flags across the series)
+    pub games: Vec<Pubkey>,                // 4 + 32 * MAX_GAMES bytes (counted escrows)
+    pub winner: Option<Pubkey>,            // 1 + 32 bytes
+    pub created_at: i64,                   // 8 bytes
+    pub started_at: i64,                   // 8 bytes
+    pub finished_at: i64,                  // 8 bytes
+    pub last_activity_at: i64,             // 8 bytes (creation, start or last reported game)
+}
+
+impl MatchSeries {
+    pub const MAX_WINS_REQUIRED: u8 = 4;
+    // A series with no game reported for a week can be refunded
+    pub const IDLE_TIMEOUT_SECONDS: i64 = 7 * 24 * 60 * 60;
+    // Room for a best-of-7 plus drawn games
+    pub const MAX_GAMES: usize = 12;
+    pub const SPACE: usize = (4 + 32) + 32 * 2 + 8 + 1 + 1 + 1 + 2 + 1 + 2 * 2
+        + (4 + 32 * Self::MAX_GAMES)
+        + (1 + 32)
+        + 8 + 8 + 8 + 8;
+
+    pub fn index_of(&self, player: &Pubkey) -> Option<usize> {
+        self.players.iter().position(|listed| listed == player)
+    }
+
+    /// The winning player's index and whether it was by forfeit, once the series is decided.
+    /// A flag forfeit takes precedence over the win count.
+    pub fn decided(&self) -> Option<(usize, bool)> {
+        let threshold = self.flag_forfeit_threshold as u16;
+        if threshold > 0 {
+            if let Some(flagged) = (0..2).find(|&i| self.flags[i] >= threshold) {
+                return Some((1 - flagged, true));
+            }
+        }
+        (0..2)
+            .find(|&i| self.wins[i] >= self.wins_required)
+            .map(|winner| (winner, false))
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub enum SeriesStatus {
+    WaitingForOpponent,
+    InProgress,
+    Finished,
+    Cancelled,
+}
+
+// Lifetime results plus a rolling window of recent games
+#[account]
+pub struct PlayerStats {
+    pub player: Pubkey,                    // 32 bytes
+    pub games_played: u32,                 // 4 bytes
+    pub wins: u32,                         // 4 bytes
+    pub losses: u32,                       // 4 bytes
+    pub draws: u32,                        // 4 bytes
+    pub recent_wins: u16,                  // 2 bytes (bit 0 = latest game)
+    pub recent_draws: u16,                 // 2 bytes (bit 0 = latest game)
+    pub recent_games: u8,                  // 1 byte
+    pub last_game_at: i64,                 // 8 bytes
+    pub active_games: u8,                  // 1 byte (started games not yet finished)
+}
+
+impl PlayerStats {
+    pub const SPACE: usize = 32 + 4 + 4 + 4 + 4 + 2 + 2 + 1 + 8 + 1;
+    pub const RECENT_GAMES_WINDOW: u8 = 10;
+    // Most games a player may have in progress at once
+    pub const MAX_ACTIVE_GAMES: u8 = 5;
+
+    pub fn start_game(&mut self) -> Result<()> {
+        require!(
+            self.active_games < Self::MAX_ACTIVE_GAMES,
+            ChessError::TooManyActiveGames
+        );
+        self.active_games += 1;
+        Ok(())
+    }
+
+    pub fn record(&mut self, outcome: MatchOutcome, finished_at: i64) {
+        let window_mask = (1u16 << Self::RECENT_GAMES_WINDOW) - 1;
+
+        self.games_played += 1;
+        match outcome {
+            MatchOutcome::Win => self.wins += 1,
+            MatchOutcome::Loss => self.losses += 1,
+            MatchOutcome::Draw => self.draws += 1,
+        }
+        self.recent_wins = ((self.recent_wins << 1) | (outcome == MatchOutcome::Win) as u16) & window_mask;
+        self.recent_draws = ((self.recent_draws << 1) | (outcome == MatchOutcome::Draw) as u16) & window_mask;
+        self.recent_games = (self.recent_games + 1).min(Self::RECENT_GAMES_WINDOW);
+        self.last_game_at = finished_at;
+    }
+
+    /// Score over the recent window in basis points (win = 1, draw = 0.5), if any games
+    pub fn recent_score_bps(&self) -> Option<u32> {
+        if self.recent_games == 0 {
+            return None;
+        }
+        let half_points = 2 * self.recent_wins.count_ones() + self.recent_draws.count_ones();
+        Some(half_points * 10_000 / (2 * self.recent_games as u32))
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub enum TournamentStatus {
+    Registration,
+    Active,
+    Finished,
+    Cancelled,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct TimeControl {
+    pub initial_time: u64,     // in seconds
+    pub increment: u64,        // in seconds
+    pub delay: u64,           // in seconds
+    pub time_control_type: TimeControlType,
+}
+
+/// A later period of a multi-phase time control: each player gets `added_seconds` on
+/// completing their `after_move`-th move
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct TimeControlPhase {
+    pub after_move: u16,
+    pub added_seconds: u32,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Default, InitSpace)]
+pub enum TimeControlType {
+    Rapid,    // 10+ minutes
+    Blitz,    // 3-10 minutes
+    Bullet,   // <3 minutes
+    #[default]
+    Custom,
+}
+
+/// How a timeout is scored when the flagged player's opponent is also out of time
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Default)]
+pub enum DoubleFlagRule {
+    #[default]
+    Draw,
+    LastMoverWins,
+}
+
+/// Which player may claim a draw by threefold repetition or the fifty-move rule
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Default)]
+pub enum DrawClaims {
+    #[default]
+    Disabled,
+    /// Either player, at any time
+    AnyPlayer,
+    /// Only the player on move, as under FIDE rules
+    SideToMove,
+}
+
+/// Rule set a game is played under
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Default)]
+pub enum Variant {
+    #[default]
+    Standard,
+    /// The first side to give check three times wins
+    ThreeCheck,
+    /// Bringing your king to a centre square wins
+    KingOfTheHill,
+    /// Captures explode, taking out neighbouring pieces; exploding the enemy king wins
+    Atomic,
+}
+
+/// Which side the creator of a game plays
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Default)]
+pub enum CreatorColor {
+    #[default]
+    White,
+    Black,
+    /// Drawn from the latest slot hash when the game is created
+    Random,
+}
+
+/// How the pot of a decisive game, after the fee, is divided
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Default, InitSpace)]
+pub enum PayoutPolicy {
+    #[default]
+    WinnerTakeAll,
+    /// The loser gets `loser_bps` of the pot back and the winner the rest
+    ConsolationSplit { loser_bps: u16 },
+}
+
+/// What the 2% platform fee is taken from
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Default)]
+pub enum RakeMode {
+    /// The whole pot, whatever the result
+    #[default]
+    PotRake,
+    /// Only the winner's gain above their own stake; draws pay no fee
+    WinningsRake,
+}
+
+/// A legal move as `list_legal_moves` reports it: squares indexed a1 = 0 .. h8 = 63 and
+/// the promotion piece as its board code
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct LegalMove {
+    pub from: u8,
+    pub to: u8,
+    pub promotion: Option<u8>,
+}
+
+impl LegalMove {
+    // Moves per `LegalMoves` event, keeping each log line short
+    pub const PER_EVENT: usize = 16;
+}
+
+/// Which clocks `extend_time` adds to
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq)]
+pub enum ExtendedClocks {
+    Both,
+    White,
+    Black,
+}
+
+/// Grounds for a draw claim
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub enum DrawClaim {
+    Repetition,
+    FiftyMoves,
+}
+
+impl TimeControlType {
+    /// Whether a clock of `initial_time` seconds fits this type; `Custom` takes any
+    pub fn admits_initial_time(&self, initial_time: u64) -> bool {
+        match self {
+            TimeControlType::Bullet => (1..180).contains(&initial_time),
+            TimeControlType::Blitz => (180..600).contains(&initial_time),
+            TimeControlType::Rapid => initial_time >= 600,
+            TimeControlType::Custom => initial_time > 0,
+        }
+    }
+
+    /// Reported think time (ms) below which a move is flagged as suspicious
+    pub fn suspicious_move_interval_ms(&self) -> u32 {
+        match self {
+            TimeControlType::Bullet => 200,
+            TimeControlType::Blitz => 500,
+            TimeControlType::Rapid | TimeControlType::Custom => 1_000,
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct MoveRecord {
+    pub move_number: u32,
+    #[max_len(2)]
+    pub from_square: String,
+    #[max_len(2)]
+    pub to_square: String,
+    #[max_len(1)]
+    pub piece: String,
+    #[max_len(1)]
+    pub captured_piece: Option<String>,
+    #[max_len(10)]
+    pub move_notation: String,
+    pub position_hash: [u8; 32],
+    pub timestamp: i64,
+    pub time_spent: u64,
+    pub is_check: bool,
+    pub is_checkmate: bool,
+    pub is_castle: bool,
+    pub is_en_passant: bool,
+    pub is_promotion: bool,
+    #[max_len(1)]
+    pub promotion_piece: Option<String>,
+    pub prev_hash: [u8; 32],
+}
+
+/// A move as clients submit it, before it is parsed against the board
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct CandidateMove {
+    pub from_square: String,
+    pub to_square: String,
+    pub piece: String,
+    pub captured_piece: Option<String>,
+    pub is_castle: bool,
+    pub is_en_passant: bool,
+    pub is_promotion: bool,
+    pub promotion_piece: Option<String>,
+}
+
+impl CandidateMove {
+    pub(crate) fn parse(&self) -> Result<chess_validation::Move> {
+        Ok(chess_validation::Move {
+            from: chess_validation::parse_square(&self.from_square)?,
+            to: chess_validation::parse_square(&self.to_square)?,
+            piece: chess_validation::parse_piece(&self.piece)?,
+            captured_piece: self
+                .captured_piece
+                .as_deref()
+                .map(chess_validation::parse_piece)
+                .transpose()?,
+            is_castle: self.is_castle,
+            is_en_passant: self.is_en_passant,
+            is_promotion: self.is_promotion,
+            promotion_piece: self
+                .promotion_piece
+                .as_deref()
+                .map(chess_validation::parse_piece)
+                .transpose()?,
+        })
+    }
+}
+
+/// One move of a `record_moves_batch`, with the think time its player reported
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct BatchedMove {
+    pub submission: MoveSubmission,
+    pub time_spent: u64,
+}
+
+/// A full move submission; also the payload a sealed move commits to
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct MoveSubmission {
+    pub move_notation: String,
+    pub game_position_hash: [u8; 32],
+    pub candidate: CandidateMove,
+    pub is_check: bool,
+    pub is_checkmate: bool,
+}
+
+impl MoveSubmission {
+    /// Stored records are budgeted at MoveRecord::MAX_SPACE, so bound every string field
+    pub fn validate_bounds(&self) -> Result<()> {
+        let candidate = &self.candidate;
+        require!(self.move_notation.len() <= 10, ChessError::MoveNotationTooLong);
+        // Squares are read byte by byte, so multi-byte characters are turned away up front
+        require!(
+            candidate.from_square.is_ascii() && candidate.to_square.is_ascii(),
+            ChessError::InvalidSquareFormat
+        );
+        require!(
+            candidate.from_square.len() <= 2
+                && candidate.to_square.len() <= 2
+                && candidate.piece.len() <= 1,
+            ChessError::InvalidMoveFormat
+        );
+        require!(
+            candidate.captured_piece.as_deref().unwrap_or("").len() <= 1
+                && candidate.promotion_piece.as_deref().unwrap_or("").len() <= 1,
+            ChessError::InvalidPiece
+        );
+        Ok(())
+    }
+
+    /// Commitment stored by `seal_move`: sha256 of the borsh-encoded submission, then the salt
+    pub fn seal_hash(&self, salt: &[u8; 32]) -> Result<[u8; 32]> {
+        let encoded = self.try_to_vec()?;
+        Ok(anchor_lang::solana_program::hash::hashv(&[&encoded, salt]).to_bytes())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct GameFlags {
+    pub is_tournament_game: bool,
+    pub is_rated: bool,
+    pub allow_draw_offers: bool,
+    pub allow_resignation: bool,
+    pub require_move_validation: bool,
+    pub enable_anti_cheat: bool,
+    pub auto_draw_on_repetition: bool,
+}
+
+/// Per-game settings chosen by the creator at `initialize_game`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct GameOptions {
+    /// Suspicious-pattern strikes after which a player forfeits; 0 disables auto-forfeit
+    pub anti_cheat_forfeit_threshold: u8,
+    /// Most recent moves kept in `move_history`; older moves are only emitted via events
+    pub move_history_capacity: u16,
+    /// Speed class of the game; drives the anti-cheat timing threshold
+    pub time_control_type: TimeControlType,
+    /// Overrides the speed-derived suspicious think time (ms) when non-zero
+    pub suspicious_move_interval_ms: u32,
+    /// Emit a `BoardState` event with the full board after every move
+    pub emit_board_state: bool,
+    /// Opt-in cross-asset bet: black stakes this token instead of SOL
+    pub cross_asset_stake: Option<CrossAssetStake>,
+    /// Update both players' `PlayerRating` accounts when the game finishes
+    pub is_rated: bool,
+    /// Resignations before this many plies only move ratings by a reduced amount; 0 disables
+    pub early_resignation_moves: u16,
+    /// Result of a timeout claim when both clocks have run out
+    pub double_flag_rule: DoubleFlagRule,
+    /// Emit `MaterialLeadChanged` for streaming overlays
+    pub emit_material_events: bool,
+    /// Decisive tiebreak game: a draw counts as a Black win and Black gets less time
+    pub armageddon: bool,
+    /// Warn on the second occurrence of a position and draw on the third
+    pub auto_draw_on_repetition: bool,
+    /// Plies required before `declare_result` accepts anything but a resignation or timeout; 0 disables
+    pub min_moves_before_result: u16,
+    /// Who may claim a threefold-repetition or fifty-move draw, if anyone
+    pub draw_claims: DrawClaims,
+    /// Skip on-chain move validation; the opponent polices moves with `challenge_last_move`
+    pub optimistic_validation: bool,
+    /// A successfully challenged move forfeits the game instead of just being taken back
+    pub forfeit_on_illegal_move: bool,
+    /// Rule set the game is played under
+    pub variant: Variant,
+    /// What the platform fee is charged on
+    pub rake_mode: RakeMode,
+    /// Hold a cross-asset winner's tokens for this long before `claim_winnings`; 0 pays at once
+    pub winnings_lock_seconds: u32,
+    /// Moves each player may take back; `None` uses `DEFAULT_MAX_TAKEBACKS`, 0 disables
+    pub max_takebacks_per_player: Option<u8>,
+    /// Seconds added to the mover's clock after each move
+    pub increment_seconds: u32,
+    /// Increment is only credited once `move_count` exceeds this; 0 credits it from the first move
+    pub increment_start_move: u16,
+    /// Offer a draw on a player's behalf when their opponent reports them disconnected
+    pub auto_draw_offer_on_disconnect: bool,
+    /// Extra time periods, e.g. 30 minutes after move 40; up to `MAX_TIME_CONTROL_PHASES`
+    pub time_control_phases: Vec<TimeControlPhase>,
+    /// Platform-defined bytes (label, client version, region...), up to `MAX_METADATA_LEN`
+    pub metadata: Option<Vec<u8>>,
+    /// Once one player deposits, how long the other has to match before the first can
+    /// `reclaim_unmatched_deposit`; with 0, reclaiming opens `time_limit_seconds` after
+    /// the game is created
+    pub deposit_match_window_seconds: u32,
+    /// Lamports Black posts on joining, forfeited to White if Black never deposits; it
+    /// counts towards Black's stake otherwise. Not available with a cross-asset stake.
+    pub griefing_bond: u64,
+    /// Accept `post_chat_hash` for a moderated spectator chat log
+    pub spectator_chat: bool,
+    /// Think time (ms) below which a move is implausibly fast; 0 disables the floor
+    pub min_move_interval_ms: u32,
+    /// Reject moves under the floor with `MoveTooSoon` instead of flagging them
+    pub strict_move_interval: bool,
+    /// Shares of a drawn SOL pot (after fees) paid to each side; must add up to 10_000.
+    /// Cross-asset draws always return each stake to its owner.
+    pub draw_split_white_bps: u16,
+    pub draw_split_black_bps: u16,
+    /// Emit `MoveRecordedCompact` instead of `MoveRecorded` for every move
+    pub compact_move_events: bool,
+    /// With `optimistic_validation`, the percentage of moves (0-100) picked for full
+    /// validation anyway; see `GameEscrow::is_audited_ply`
+    pub audit_rate_pct: u8,
+    /// Emit `LowTimeWarning` when a mover's clock falls below this many seconds; 0 disables it
+    pub low_time_warning_seconds: u32,
+    /// A friendly game with a `stake_amount` of 0: it skips the deposit step and starts
+    /// when Black joins, and settling it moves no funds
+    pub free_game: bool,
+    /// Platform fee in basis points, up to `MAX_FEE_BPS`; `None` uses `DEFAULT_FEE_BPS`
+    pub fee_bps: Option<u16>,
+    /// Distinct anti-cheat flag kinds after which the pot is held for a moderator's review;
+    /// 0 never holds it
+    pub anti_cheat_review_threshold: u8,
+    /// US delay: seconds each move may take before the mover's main clock starts running
+    pub delay_seconds: u32,
+    /// Both players stake `stake_amount` of this SPL token instead of SOL; settled through
+    /// `settle_token_stake`
+    pub token_stake_mint: Option<Pubkey>,
+    /// Settlement records each player's share in `claimable` instead of sending it; each
+    /// player then withdraws it with `claim_payout`
+    pub pull_payouts: bool,
+    /// Seconds past a flag before the timeout can be claimed; `None` uses
+    /// `DEFAULT_TIMEOUT_GRACE_SECONDS`
+    pub timeout_grace_seconds: Option<u32>,
+    /// Seconds after a declared result during which either player may dispute it; the pot
+    /// is paid out by `settle_game` once they pass. 0 pays results out at once.
+    pub dispute_window_seconds: u32,
+    /// Seat the creator takes; the player who joins gets the other one
+    pub creator_color: CreatorColor,
+    /// How a decisive result's pot is divided between winner and loser
+    pub payout_policy: PayoutPolicy,
+}
+
+/// Hash of one spectator chat message, as posted to `post_chat_hash`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct ChatHash {
+    pub sender: Pubkey,
+    pub message_hash: [u8; 32],
+    pub posted_at: i64,
+}
+
+/// Running think-time totals for one side, from each move's reported `time_spent`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default, InitSpace)]
+pub struct ThinkTimeTotals {
+    pub moves: u32,
+    pub total_ms: u64,
+    pub longest_ms: u64,
+}
+
+impl ThinkTimeTotals {
+    pub fn record(&mut self, time_spent: u64) {
+        self.moves += 1;
+        self.total_ms = self.total_ms.saturating_add(time_spent);
+        self.longest_ms = self.longest_ms.max(time_spent);
+    }
+
+    pub fn summary(&self) -> PlayerTiming {
+        PlayerTiming {
+            moves: self.moves,
+            total_ms: self.total_ms,
+            average_ms: self.total_ms.checked_div(self.moves as u64).unwrap_or(0),
+            longest_ms: self.longest_ms,
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct PlayerTiming {
+    pub moves: u32,
+    pub total_ms: u64,
+    pub average_ms: u64,
+    pub longest_ms: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct CrossAssetStake {
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
+impl GameEscrow {
+    // Everything but move_history's records, with each variable-length field budgeted at its
+    // maximum (tournament_id at 32 bytes). Not derived with InitSpace: the account is created
+    // at `space_for(0)` and realloc'd one MoveRecord at a time up to its own capacity, so the
+    // history has no single `max_len`. Nested types derive InitSpace so their sizes track
+    // their fields
+    pub const INIT_SPACE: usize = 36 + 32 + 32 + 8 + 8 + 1 + 1 + 8 + 8 + 8 + 8 + 32 + 1 + 1 + 4 + 8 // core fields
+        + TimeControl::INIT_SPACE + 32 + 4 + 4 + 4 + 4 + (1 + 4 + 32) + GameFlags::INIT_SPACE // enhanced features
+        + 1 + 1 + 1 + 1 + 2 // anti-cheat enforcement and retention
+        + 64 // board
+        + 4 // suspicious move interval
+        + 1 // board state events
+        + (1 + 32) + 8 + 8 + 1 // cross-asset token stake
+        + 32 + 8 // sealed move
+        + 2 // early resignation threshold
+        + (1 + 1) // en-passant target
+        + 8 + 8 + 1 // player clocks
+        + 1 // material lead events
+        + 1 // armageddon
+        + 4 // repetition keys
+        + 1 + 1 // active-game tracking
+        + 2 // minimum moves before a result
+        + 1 + 1 // berserk
+        + 1 + 2 // draw claims
+        + 64 + (1 + 1) + 2 + 1 // move challenges
+        + 1 + 1 + 1 // variant
+        + 1 // rake mode
+        + 4 + 8 + 8 // time-locked winnings
+        + 1 + 1 + 1 + (1 + 32) + 1 // takebacks
+        + 2 // increment start move
+        + 1 + 1 + 1 + (1 + 32) // disconnections
+        + (4 + TimeControlPhase::INIT_SPACE * Self::MAX_TIME_CONTROL_PHASES) // time control phases
+        + 32 // rent beneficiary
+        + (1 + 4 + Self::MAX_METADATA_LEN) // metadata
+        + ThinkTimeTotals::INIT_SPACE * 2 // think-time totals
+        + 4 + 8 + 1 + 8 // deposit matching
+        + 1 + (4 + ChatHash::INIT_SPACE * Self::MAX_CHAT_HASHES) + 4 // spectator chat
+        + 4 // rated pair cooldown
+        + 4 + 1 // move interval floor
+        + 2 + 2 // draw split
+        + 4 + 4 // auto-resign thresholds
+        + 1 // compact move events
+        + 1 // audit rate
+        + 4 + 2 // low time warning
+        + 2 // fullmove number
+        + 1 + 1 // side to move
+        + 8 // draw offered at
+        + 8 + 2 // think time tracking
+        + 2 // fee bps
+        + 1 + 32 // rematch proposal
+        + 1 + 1 // castling rights
+        + 1 + 1 // anti-cheat review
+        + 8 * 3 + 4 // side bets
+        + 1 // token game
+        + 1 + 8 * 2 // pull payouts
+        + 4 // timeout grace
+        + 4 + (1 + 32) // disputes
+        + PayoutPolicy::INIT_SPACE // payout policy
+        + 1; // automatic draw offer
+
+    // A full history window must fit in 10 KiB, the most a single allocation can add.
+    // This bounds the account, not the game: once the window is full each new record
+    // evicts the oldest, and `position_hash` chains every move ever played, so a game
+    // may run as long as `move_count` (u32) can count
+    pub const MAX_MOVE_HISTORY_CAPACITY: u16 =
+        ((10_240 - 8 - Self::INIT_SPACE) / MoveRecord::MAX_SPACE) as u16;
+
+    // The vault is a bare system account, so each stake must clear its rent-exempt
+    // minimum with room to spare for the fee split
+    pub const MIN_STAKE_RENT_MULTIPLE: u64 = 2;
+
+    /// (fee, White's share, Black's share) of a token game's `pot`, charged and split like a
+    /// SOL pot. Cancelled and aborted games hand each deposit back without a fee, as do
+    /// `fee_exempt` ones.
+    pub fn token_game_shares(&self, pot: u64, fee_exempt: bool) -> Result<(u64, u64, u64)> {
+        if self.game_state == GameState::Cancelled {
+            let refund = |deposited: bool| if deposited { self.token_stake_amount } else { 0 };
+            return Ok((0, refund(self.white_deposited), refund(self.black_deposited)));
+        }
+        let fee = if fee_exempt { 0 } else { self.platform_fee(pot, &self.winner) };
+        let remaining = pot.saturating_sub(fee);
+        Ok(match self.winner {
+            GameWinner::White => {
+                let (winner_amount, loser_amount) = self.decisive_split(pot, fee);
+                (fee, winner_amount, loser_amount)
+            }
+            GameWinner::Black => {
+                let (winner_amount, loser_amount) = self.decisive_split(pot, fee);
+                (fee, loser_amount, winner_amount)
+            }
+            GameWinner::Draw => {
+                let (white_amount, black_amount) = self.draw_split(remaining);
+                (fee, white_amount, black_amount)
+            }
+            GameWinner::None | GameWinner::Aborted => {
+                return Err(ChessError::InvalidWinnerDeclaration.into())
+            }
+        })
+    }
+
+    /// (winner, loser) shares of a decisive result's `pot` once `fee` is taken, under the
+    /// payout policy. Pot rake splits what the fee leaves; winnings rake never charged the
+    /// consolation, so the loser gets it out of the whole pot. The loser's share rounds down
+    /// and the winner takes the rest, so nothing is left over.
+    pub fn decisive_split(&self, pot: u64, fee: u64) -> (u64, u64) {
+        let remaining = pot.saturating_sub(fee);
+        let loser_amount = match self.rake_mode {
+            RakeMode::PotRake => self.consolation(remaining),
+            RakeMode::WinningsRake => self.consolation(pot),
+        };
+        (remaining - loser_amount, loser_amount)
+    }
+
+    /// The loser's share of `amount` under the payout policy, rounded down
+    pub fn consolation(&self, amount: u64) -> u64 {
+        match self.payout_policy {
+            PayoutPolicy::WinnerTakeAll => 0,
+            PayoutPolicy::ConsolationSplit { loser_bps } => {
+                (amount as u128 * loser_bps as u128 / 10_000) as u64
+            }
+        }
+    }
+
+    /// (white, black) amounts of a drawn `pot` under the agreed split. White's share rounds
+    /// down and Black takes the rest, so nothing is left over.
+    pub fn draw_split(&self, pot: u64) -> (u64, u64) {
+        let white_amount = (pot as u128 * self.draw_split_white_bps as u128 / 10_000) as u64;
+        (white_amount, pot - white_amount)
+    }
+
+    // Largest consolation a loser may get back: half the pot
+    pub const MAX_CONSOLATION_BPS: u16 = 5_000;
+
+    pub fn min_stake(rent: &Rent) -> u64 {
+        rent.minimum_balance(0).saturating_mul(Self::MIN_STAKE_RENT_MULTIPLE)
+    }
+
+    // Positions kept for repetition counting; the fifty-move rule bounds how many can recur
+    pub const REPETITION_WINDOW: usize = 100;
+
+    // Most moves a `record_moves_batch` takes, keeping both signatures and every submission
+    // inside one transaction packet and their validation inside the compute budget
+    pub const MAX_BATCH_MOVES: usize = 6;
+
+    // Slack on a reported move time, since the on-chain clock only ticks in whole seconds
+    pub const MOVE_TIME_GRACE_MS: u64 = 1_000;
+
+    // Black's clock in an armageddon game, as a share of White's
+    pub const ARMAGEDDON_BLACK_TIME_PCT: u64 = 80;
+
+    // Plies without a pawn move or capture after which either side may claim a draw
+    pub const FIFTY_MOVE_PLIES: u16 = 100;
+
+    /// Whether positions are recorded, for automatic draws or for claims
+    pub fn tracks_repetition(&self) -> bool {
+        self.game_flags.auto_draw_on_repetition || self.draw_claims != DrawClaims::Disabled
+    }
+
+    /// Result as scored, after draw odds: a drawn armageddon game is a Black win
+    pub fn scored_result(&self, result: GameWinner) -> GameWinner {
+        if self.armageddon && result == GameWinner::Draw {
+            GameWinner::Black
+        } else {
+            result
+        }
+    }
+
+    // Share of the normal rating change applied after an early resignation
+    pub const EARLY_RESIGNATION_RATING_PCT: i32 = 50;
+    // A berserker's rating gain on a win, as a share of the normal gain
+    pub const BERSERK_WIN_RATING_PCT: i32 = 150;
+
+    // Longest a clock may be pushed to by `extend_time`: 30 days
+    pub const MAX_EXTENDED_CLOCK_SECONDS: u64 = 30 * 24 * 60 * 60;
+
+    // Multiple of the combined clocks after which an untouched game is abandoned
+    pub const ABANDONMENT_WINDOW_FACTOR: u64 = 2;
+    // Grace past a flag when the creator doesn't choose one, so a last move that landed a
+    // moment late through RPC lag isn't lost on time
+    pub const DEFAULT_TIMEOUT_GRACE_SECONDS: u32 = 2;
+    // Seconds past the grace after which anyone, not only the opponent, may claim a timeout
+    pub const OPEN_TIMEOUT_CLAIM_SECONDS: i64 = 10 * 60;
+
+    // Mutual aborts are only allowed while the game is still in its opening plies
+    pub const MUTUAL_ABORT_MOVE_LIMIT: u32 = 10;
+    // 0.1% of each stake is kept as the abort fee
+    pub const ABORT_FEE_BPS: u64 = 10;
+
+    // Takebacks each player gets when the creator doesn't choose
+    pub const DEFAULT_MAX_TAKEBACKS: u8 = 2;
+
+    // Longest a winner's tokens may be time-locked: 30 days
+    pub const MAX_WINNINGS_LOCK_SECONDS: u32 = 30 * 24 * 60 * 60;
+    pub const MAX_TIME_CONTROL_PHASES: usize = 4;
+    pub const MAX_METADATA_LEN: usize = 64;
+    // Chat hashes kept on the game; older ones are only in `ChatHashPosted` events
+    pub const MAX_CHAT_HASHES: usize = 8;
+    // `anti_cheat_flags` bit set when a move beats the move interval floor
+    pub const BELOW_MOVE_FLOOR_FLAG: u32 = 1 << 1;
+    // `anti_cheat_flags` bit set when an audited move turns out to be illegal
+    pub const FAILED_AUDIT_FLAG: u32 = 1 << 2;
+    // `anti_cheat_flags` bit set when a player keeps answering complex positions instantly
+    pub const TIMING_ANOMALY_FLAG: u32 = 1 << 3;
+    // Moves the think-time average is taken over
+    pub const THINK_TIME_WINDOW: u32 = 8;
+    // Consecutive fast moves in complex positions that make a timing anomaly
+    pub const TIMING_ANOMALY_STREAK: u8 = 3;
+    // Legal moves from which a position counts as complex
+    pub const COMPLEX_POSITION_MOVES: usize = 20;
+
+    // Platform fee, in basis points of whatever `rake_mode` charges it on, when the
+    // creator doesn't choose one
+    pub const DEFAULT_FEE_BPS: u16 = 200;
+    pub const MAX_FEE_BPS: u16 = 1_000;
+
+    /// Fee owed on a SOL `pot` paid out for `winner`. Under `WinningsRake` it is charged only
+    /// on what the winner gets beyond their own SOL stake, so draws and undecided games owe
+    /// nothing; in a cross-asset game only White staked SOL.
+    pub fn platform_fee(&self, pot: u64, winner: &GameWinner) -> u64 {
+        let charged = match (&self.rake_mode, winner) {
+            (RakeMode::PotRake, _) => pot,
+            (RakeMode::WinningsRake, GameWinner::White) => self.winnings(pot),
+            (RakeMode::WinningsRake, GameWinner::Black) if self.token_mint.is_none() || self.token_game => {
+                self.winnings(pot)
+            },
+            (RakeMode::WinningsRake, GameWinner::Black) => pot,
+            (RakeMode::WinningsRake, GameWinner::Draw | GameWinner::None) => 0,
+            (RakeMode::WinningsRake, GameWinner::Aborted) => 0,
+        };
+        // Rounds down, in the players' favour
+        ((charged as u128 * self.fee_bps as u128) / 10_000) as u64
+    }
+
+    /// What the winner of a single-asset `pot` takes beyond their own stake. A consolation
+    /// is the loser's stake coming back, so it isn't counted.
+    fn winnings(&self, pot: u64) -> u64 {
+        pot.saturating_sub(self.stake_amount).saturating_sub(self.consolation(pot))
+    }
+
+    /// Platform fee on a cross-asset game's token pot, Black's stake. It is all winnings
+    /// when White wins and goes back to Black otherwise, so winnings rake only charges the
+    /// former; pot rake charges it either way, as on the SOL pot.
+    pub fn token_stake_fee(&self, pot: u64) -> u64 {
+        let charged = match (&self.rake_mode, &self.winner) {
+            (RakeMode::PotRake, _) => pot,
+            (RakeMode::WinningsRake, GameWinner::White) => pot,
+            (RakeMode::WinningsRake, _) => 0,
+        };
+        ((charged as u128 * self.fee_bps as u128) / 10_000) as u64
+    }
+
+    /// Whether the next move is picked for a full validation audit. The pick is derived
+    /// from the move-chain head and the ply number, so anyone can reproduce it but nobody
+    /// can know it before the previous move lands.
+    pub fn is_audited_ply(&self) -> bool {
+        if self.audit_rate_pct == 0 {
+            return false;
+        }
+        let seed = anchor_lang::solana_program::hash::hashv(&[
+            &self.position_hash,
+            &self.move_count.to_le_bytes(),
+        ])
+        .to_bytes();
+        (u16::from_le_bytes([seed[0], seed[1]]) % 100) < self.audit_rate_pct as u16
+    }
+
+    /// Whether White is on move. Accounts created before the turn was stored fall back
+    /// to the parity of `move_count` until their next move records it.
+    pub fn is_white_turn(&self) -> bool {
+        if self.turn_recorded {
+            self.white_to_move
+        } else {
+            self.move_count.is_multiple_of(2)
+        }
+    }
+
+    /// Record whose turn it is
+    pub fn set_turn(&mut self, white_to_move: bool) {
+        self.white_to_move = white_to_move;
+        self.turn_recorded = true;
+    }
+
+    pub fn side_to_move(&self) -> Pubkey {
+        if self.is_white_turn() { self.player_white } else { self.player_black }
+    }
+
+    /// After this time a game nobody has touched counts as abandoned by both players:
+    /// twice the time left on both clocks, past the last move
+    pub fn abandonment_deadline(&self) -> i64 {
+        let clocks = self.white_time_remaining.saturating_add(self.black_time_remaining);
+        self.last_move_time
+            .saturating_add(clocks.saturating_mul(Self::ABANDONMENT_WINDOW_FACTOR) as i64)
+    }
+
+    /// Seconds the mover's main clock has run by `now`: the time since the last move, less
+    /// the time control's delay
+    pub fn mover_clock_elapsed(&self, now: i64) -> i64 {
+        (now - self.last_move_time - self.time_control.delay as i64).max(0)
+    }
+
+    /// The player holding a seat while the other is open: the creator, until someone joins
+    pub fn seated_player(&self) -> Pubkey {
+        if self.player_white == Pubkey::default() {
+            self.player_black
+        } else {
+            self.player_white
+        }
+    }
+
+    /// Whether a finished game's result may still be disputed at `now`; its funds stay in
+    /// the vault until then
+    pub fn dispute_window_open(&self, now: i64) -> bool {
+        self.dispute_window_seconds > 0
+            && self.game_state == GameState::Finished
+            && !self.funds_distributed
+            && now < self.finished_at.saturating_add(self.dispute_window_seconds as i64)
+    }
+
+    /// Whether the player to move has run out of time by `now`
+    pub fn mover_flagged(&self, now: i64) -> bool {
+        self.mover_overtime(now) > 0
+    }
+
+    /// Seconds the player to move has run past their clock by `now`; negative while time remains
+    pub fn mover_overtime(&self, now: i64) -> i64 {
+        let (mover_remaining, _) = self.clocks_by_turn();
+        self.mover_clock_elapsed(now) - mover_remaining as i64
+    }
+
+    /// The result the board itself shows, if the player to move has no legal move: the
+    /// last mover wins by checkmate, or it is stalemate. Atomic positions aren't judged,
+    /// since its kings can stand in check.
+    pub fn board_result(&self) -> Option<(GameWinner, GameEndReason)> {
+        if self.variant == Variant::Atomic {
+            return None;
+        }
+        let position = self.position();
+        if !chess_validation::generate_legal_moves(&position).is_empty() {
+            return None;
+        }
+        Some(if !position.in_check() {
+            (GameWinner::Draw, GameEndReason::Stalemate)
+        } else if position.white_to_move {
+            (GameWinner::Black, GameEndReason::Checkmate)
+        } else {
+            (GameWinner::White, GameEndReason::Checkmate)
+        })
+    }
+
+    /// (player to move, opponent) seconds left on their clocks
+    pub fn clocks_by_turn(&self) -> (u64, u64) {
+        if self.is_white_turn() {
+            (self.white_time_remaining, self.black_time_remaining)
+        } else {
+            (self.black_time_remaining, self.white_time_remaining)
+        }
+    }
+
+    /// Deduct `elapsed` seconds, less the time control's delay, from the mover's clock and
+    /// add the increment, once the game is past `increment_start_move`, plus the time of any
+    /// phase this move completes. Fails if the mover has already flagged, or if the
+    /// `time_spent` they report (ms) runs past their remaining clock and delay by more than
+    /// `MOVE_TIME_GRACE_MS`.
+    pub fn charge_mover_clock(&mut self, elapsed: i64, time_spent: u64) -> Result<()> {
+        let delay = self.time_control.delay;
+        let elapsed = (elapsed.max(0) as u64).saturating_sub(delay);
+        // The move being charged takes `move_count` to `move_count + 1`
+        let increment = if self.move_count >= self.increment_start_move as u32 {
+            self.time_control.increment
+        } else {
+            0
+        };
+        // The mover's own move number, counting from 1
+        let mover_move = self.move_count / 2 + 1;
+        let phase_time: u64 = self
+            .time_control_phases
+            .iter()
+            .filter(|phase| phase.after_move as u32 == mover_move)
+            .map(|phase| phase.added_seconds as u64)
+            .sum();
+        let remaining = if self.is_white_turn() {
+            &mut self.white_time_remaining
+        } else {
+            &mut self.black_time_remaining
+        };
+        require!(elapsed <= *remaining, ChessError::MoveTimeExceeded);
+        require!(
+            time_spent
+                <= remaining
+                    .saturating_add(delay)
+                    .saturating_mul(1_000)
+                    .saturating_add(Self::MOVE_TIME_GRACE_MS),
+            ChessError::MoveTimeExceeded
+        );
+        *remaining = *remaining - elapsed + increment + phase_time;
+        Ok(())
+    }
+
+    /// A fresh game between the same players with colours swapped, on the same stake and
+    /// settings, waiting for both deposits
+    pub fn rematch(&self, room_id: String, rent_beneficiary: Pubkey, now: i64) -> GameEscrow {
+        let mut game = self.clone();
+        game.room_id = room_id;
+        game.player_white = self.player_black;
+        game.player_black = self.player_white;
+        game.total_deposited = 0;
+        game.game_state = GameState::WaitingForDeposits;
+        game.winner = GameWinner::None;
+        game.created_at = now;
+        game.started_at = 0;
+        game.finished_at = 0;
+        game.white_deposited = false;
+        game.black_deposited = false;
+        game.move_count = 0;
+        game.last_move_time = 0;
+        game.position_hash = [0u8; 32];
+        game.move_history = Vec::new();
+        game.anti_cheat_flags = 0;
+        game.rating_white = 1500;
+        game.rating_black = 1500;
+        game.tournament_id = None;
+        game.game_flags.is_tournament_game = false;
+        game.game_flags.allow_draw_offers = true;
+        game.game_flags.allow_resignation = true;
+        game.game_flags.enable_anti_cheat = true;
+        game.white_flag_count = 0;
+        game.black_flag_count = 0;
+        game.funds_distributed = false;
+        game.think_time_avg_ms = [0; 2];
+        game.fast_move_streak = [0; 2];
+        game.board = chess_validation::starting_board();
+        game.token_total_deposited = 0;
+        game.token_funds_distributed = false;
+        game.sealed_move_hash = [0u8; 32];
+        game.adjourned_at = 0;
+        game.en_passant_square = None;
+        game.white_time_remaining = self.time_limit_seconds as u64;
+        game.black_time_remaining = if self.armageddon {
+            (self.time_limit_seconds as u64 * Self::ARMAGEDDON_BLACK_TIME_PCT / 100).max(1)
+        } else {
+            self.time_limit_seconds as u64
+        };
+        game.position_keys = Vec::new();
+        game.white_active_tracked = false;
+        game.black_active_tracked = false;
+        game.white_berserk = false;
+        game.black_berserk = false;
+        game.halfmove_clock = 0;
+        game.previous_board = game.board;
+        game.previous_en_passant_square = None;
+        game.previous_halfmove_clock = 0;
+        game.castling_rights = chess_validation::ALL_CASTLING_RIGHTS;
+        game.previous_castling_rights = chess_validation::ALL_CASTLING_RIGHTS;
+        game.white_checks = 0;
+        game.black_checks = 0;
+        game.locked_winnings = 0;
+        game.winnings_unlock_at = 0;
+        game.white_takebacks = 0;
+        game.black_takebacks = 0;
+        game.takeback_requested_by = None;
+        game.can_undo_last_move = false;
+        game.white_disconnected = false;
+        game.black_disconnected = false;
+        game.draw_offered_by = None;
+        game.draw_offer_automatic = false;
+        game.draw_offered_at = 0;
+        game.rent_beneficiary = rent_beneficiary;
+        game.white_timing = ThinkTimeTotals::default();
+        game.black_timing = ThinkTimeTotals::default();
+        // Both seats are taken, so there is no joining Black to post a bond
+        game.griefing_bond = 0;
+        game.black_bond_posted = false;
+        game.first_deposit_at = 0;
+        game.chat_hashes = Vec::new();
+        game.chat_hash_count = 0;
+        game.white_auto_resign_below_seconds = 0;
+        game.black_auto_resign_below_seconds = 0;
+        game.low_time_warned = [false; 2];
+        game.fullmove_number = 1;
+        game.set_turn(true);
+        game.rematch_proposed_by = None;
+        game.review_required = false;
+        game.side_bet_totals = [0; 3];
+        game.open_side_bets = 0;
+        game.claimable = [0; 2];
+        game.disputed_by = None;
+        game
+    }
+
+    /// When a deposit left unmatched may be reclaimed: `deposit_match_window_seconds` after
+    /// the first deposit, or without a window, `time_limit_seconds` after the game was
+    /// created
+    pub fn deposit_deadline(&self) -> i64 {
+        if self.deposit_match_window_seconds > 0 {
+            self.first_deposit_at.saturating_add(self.deposit_match_window_seconds as i64)
+        } else {
+            self.created_at.saturating_add(self.time_limit_seconds)
+        }
+    }
+
+    /// Fold a move's reported think time into the mover's rolling average and streak of
+    /// fast moves in complex positions. True once the streak reaches
+    /// `TIMING_ANOMALY_STREAK` while the average is itself under the suspicious interval.
+    pub fn record_think_time(&mut self, is_white: bool, time_spent: u64, fast_and_complex: bool) -> bool {
+        let side = if is_white { 0 } else { 1 };
+        let time_spent = time_spent.min(u32::MAX as u64) as u32;
+        // A player's first move seeds the average
+        self.think_time_avg_ms[side] = if self.move_count < 2 {
+            time_spent
+        } else {
+            let window = Self::THINK_TIME_WINDOW as u64;
+            ((self.think_time_avg_ms[side] as u64 * (window - 1) + time_spent as u64) / window) as u32
+        };
+        self.fast_move_streak[side] = if fast_and_complex {
+            self.fast_move_streak[side].saturating_add(1)
+        } else {
+            0
+        };
+
+        self.fast_move_streak[side] >= Self::TIMING_ANOMALY_STREAK
+            && self.think_time_avg_ms[side] < self.suspicious_move_interval_ms
+    }
+
+    /// After a move has been charged, the mover's remaining seconds if this move took their
+    /// clock below the low-time threshold. A side is warned once per crossing: climbing back
+    /// over the threshold on increments re-arms the warning.
+    pub fn low_time_crossing(&mut self, mover_is_white: bool) -> Option<u64> {
+        if self.low_time_warning_seconds == 0 {
+            return None;
+        }
+        let (remaining, warned) = if mover_is_white {
+            (self.white_time_remaining, &mut self.low_time_warned[0])
+        } else {
+            (self.black_time_remaining, &mut self.low_time_warned[1])
+        };
+        let below = remaining < self.low_time_warning_seconds as u64;
+        let crossed = below && !*warned;
+        *warned = below;
+        crossed.then_some(remaining)
+    }
+
+    // Checks a side must give to win a three-check game
+    pub const THREE_CHECK_LIMIT: u8 = 3;
+    // d4, e4, d5 and e5: a king reaching any of them wins King of the Hill
+    pub const HILL_SQUARES: [u8; 4] = [27, 28, 35, 36];
+
+    /// Apply the variant's rules to the position the mover just reached, returning the
+    /// reason the mover has won, if they have
+    pub(crate) fn variant_win(
+        &mut self,
+        position: &chess_validation::Position,
+        mover_is_white: bool,
+    ) -> Option<GameEndReason> {
+        match self.variant {
+            Variant::Standard => None,
+            Variant::ThreeCheck => {
+                if !position.in_check() {
+                    return None;
+                }
+                let checks = if mover_is_white { &mut self.white_checks } else { &mut self.black_checks };
+                *checks += 1;
+                (*checks >= Self::THREE_CHECK_LIMIT).then_some(GameEndReason::ThreeChecks)
+            }
+            Variant::KingOfTheHill => position
+                .king_square(mover_is_white)
+                .is_some_and(|square| Self::HILL_SQUARES.contains(&square))
+                .then_some(GameEndReason::KingOfTheHill),
+            Variant::Atomic => position
+                .king_square(!mover_is_white)
+                .is_none()
+                .then_some(GameEndReason::KingExploded),
+        }
+    }
+
+    /// Undo `last`, the final history record, from the snapshot `commit_move` took. The mover
+    /// is on move again with their clock restarted at `now` and any result the move gave is
+    /// cleared. Callers check `can_undo_last_move`; the snapshot only covers one ply.
+    pub(crate) fn undo_last_move(&mut self, last: &MoveRecord, now: i64) {
+        let mover_is_white = !self.is_white_turn();
+        if self.variant == Variant::ThreeCheck && self.position().in_check() {
+            let checks = if mover_is_white { &mut self.white_checks } else { &mut self.black_checks };
+            *checks = checks.saturating_sub(1);
+        }
+        self.fullmove_number = self.previous_position().fullmove_number;
+        self.board = self.previous_board;
+        self.en_passant_square = self.previous_en_passant_square;
+        self.castling_rights = self.previous_castling_rights;
+        self.halfmove_clock = self.previous_halfmove_clock;
+        self.move_history.pop();
+        self.position_hash = last.prev_hash;
+        if self.tracks_repetition() {
+            self.position_keys.pop();
+        }
+        self.move_count -= 1;
+        self.set_turn(mover_is_white);
+        self.last_move_time = now;
+        self.game_state = GameState::InProgress;
+        self.winner = GameWinner::None;
+        self.finished_at = 0;
+        self.can_undo_last_move = false;
+        self.takeback_requested_by = None;
+    }
+
+    /// The position the last move was played from
+    pub(crate) fn previous_position(&self) -> chess_validation::Position {
+        let last_mover_is_white = !self.is_white_turn();
+        let mut position =
+            chess_validation::Position::from_board(&self.previous_board, last_mover_is_white);
+        position.en_passant_square = self.previous_en_passant_square;
+        position.set_castling_rights(self.previous_castling_rights);
+        position.halfmove_clock = self.previous_halfmove_clock;
+        // Only a Black move advanced the number
+        position.fullmove_number = if last_mover_is_white {
+            self.fullmove_number
+        } else {
+            self.fullmove_number.saturating_sub(1)
+        };
+        position
+    }
+
+    /// Rebuild the current position from the stored board, en-passant target, castling
+    /// rights and move counters
+    pub(crate) fn position(&self) -> chess_validation::Position {
+        let mut position =
+            chess_validation::Position::from_board(&self.board, self.is_white_turn());
+        position.en_passant_square = self.en_passant_square;
+        position.set_castling_rights(self.castling_rights);
+        position.halfmove_clock = self.halfmove_clock;
+        position.fullmove_number = self.fullmove_number;
+        position
+    }
+
+    /// Side bets are taken until the first move is played
+    pub fn side_bets_open(&self) -> bool {
+        match self.game_state {
+            GameState::WaitingForPlayers | GameState::WaitingForDeposits => true,
+            GameState::InProgress => self.move_count == 0,
+            _ => false,
+        }
+    }
+
+    /// What a side bet of `amount` on `predicted` collects, or `None` while the game is
+    /// still undecided. Winners split the losing stakes in proportion to their own; with no
+    /// winning bets, or no result, every bet is refunded.
+    pub fn side_bet_payout(&self, predicted: &GameWinner, amount: u64) -> Option<u64> {
+        if self.game_state == GameState::Cancelled || self.winner == GameWinner::Aborted {
+            return Some(amount);
+        }
+        if self.game_state != GameState::Finished {
+            return None;
+        }
+        let outcome = self.winner.side_bet_index()?;
+        let winning = self.side_bet_totals[outcome];
+        if winning == 0 {
+            return Some(amount);
+        }
+        if predicted.side_bet_index() != Some(outcome) {
+            return Some(0);
+        }
+        let losing = self.side_bet_totals.iter().sum::<u64>() - winning;
+        Some(amount + (losing as u128 * amount as u128 / winning as u128) as u64)
+    }
+
+    /// Fold `move_history` into a position, starting from the standard setup. Fails once
+    /// the window has evicted a move, as the replay would start mid-game.
+    pub(crate) fn replayed_position(&self) -> Result<chess_validation::Position> {
+        require!(
+            self.move_history.len() == self.move_count as usize,
+            ChessError::MoveHistoryIncomplete
+        );
+        let mut position =
+            chess_validation::Position::from_board(&chess_validation::starting_board(), true);
+        for record in &self.move_history {
+            chess_validation::apply_move(&mut position, &record.candidate().parse()?)?;
+        }
+        Ok(position)
+    }
+
+    /// Board codes packed two squares per byte; the even square sits in the low nibble
+    pub fn packed_board(&self) -> [u8; 32] {
+        let mut packed = [0u8; 32];
+        for (byte, squares) in packed.iter_mut().zip(self.board.chunks(2)) {
+            *byte = squares[0] | (squares[1] << 4);
+        }
+        packed
+    }
+
+    pub fn space_for(move_history_capacity: u16) -> usize {
+        let capacity = move_history_capacity.min(Self::MAX_MOVE_HISTORY_CAPACITY) as usize;
+        Self::INIT_SPACE + capacity * MoveRecord::MAX_SPACE
+    }
+
+    /// Account size once the next move is stored. The escrow starts with an empty
+    /// history and grows one record per move until the window is full; the moving
+    /// player pays the added rent when `record_move` grows the account.
+    pub fn space_for_next_move(game_escrow: &Account<GameEscrow>) -> usize {
+        Self::space_for_moves(game_escrow, 1)
+    }
+
+    /// Account size once the next `moves` moves are stored, for `record_moves_batch`
+    pub fn space_for_moves(game_escrow: &Account<GameEscrow>, moves: usize) -> usize {
+        let retained = (game_escrow.move_history.len() + moves)
+            .min(game_escrow.move_history_capacity as usize);
+        // The first tracked move also stores the position it started from
+        let keys = if game_escrow.tracks_repetition() {
+            (game_escrow.position_keys.len().max(1) + moves).min(Self::REPETITION_WINDOW)
+        } else {
+            0
+        };
+        (8 + Self::space_for(retained as u16) + 8 * keys)
+            .max(game_escrow.to_account_info().data_len())
+    }
+}
+
+impl MoveRecord {
+    /// The move in UCI long algebraic notation, e.g. `e2e4` or `e7e8q`
+    pub fn uci(&self) -> String {
+        let promotion = self.promotion_piece.as_deref().unwrap_or("").to_ascii_lowercase();
+        format!("{}{}{}", self.from_square, self.to_square, promotion)
+    }
+
+    /// Link in the move chain: `hash(prev_hash || move_data)`, where move_data is the move
+    /// number, each string field length-prefixed (absent pieces as empty), the timestamp,
+    /// time spent and the five flags. Changing, dropping or reordering a record breaks
+    /// every link after it.
+    pub fn chain_hash(&self) -> [u8; 32] {
+        let mut move_data = Vec::with_capacity(Self::MAX_SPACE);
+        move_data.extend_from_slice(&self.move_number.to_le_bytes());
+        for field in [
+            self.from_square.as_str(),
+            self.to_square.as_str(),
+            self.piece.as_str(),
+            self.captured_piece.as_deref().unwrap_or(""),
+            self.promotion_piece.as_deref().unwrap_or(""),
+            self.move_notation.as_str(),
+        ] {
+            move_data.push(field.len() as u8);
+            move_data.extend_from_slice(field.as_bytes());
+        }
+        move_data.extend_from_slice(&self.timestamp.to_le_bytes());
+        move_data.extend_from_slice(&self.time_spent.to_le_bytes());
+        move_data.extend_from_slice(&[
+            self.is_check as u8,
+            self.is_checkmate as u8,
+            self.is_castle as u8,
+            self.is_en_passant as u8,
+            self.is_promotion as u8,
+        ]);
+        anchor_lang::solana_program::hash::hashv(&[&self.prev_hash, &move_data]).to_bytes()
+    }
+    pub fn candidate(&self) -> CandidateMove {
+        CandidateMove {
+            from_square: self.from_square.clone(),
+            to_square: self.to_square.clone(),
+            piece: self.piece.clone(),
+            captured_piece: self.captured_piece.clone(),
+            is_castle: self.is_castle,
+            is_en_passant: self.is_en_passant,
+            is_promotion: self.is_promotion,
+            promotion_piece: self.promotion_piece.clone(),
+        }
+    }
+
+    // Squares are 2 bytes, pieces 1 byte and notation at most 10 bytes
+    pub const MAX_SPACE: usize = Self::INIT_SPACE;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub enum GameState {
+    WaitingForPlayers,
+    WaitingForDeposits,
+    InProgress,
+    Finished,
+    Cancelled,
+    Paused,
+    /// A player contested the declared result; the pot waits for `resolve_dispute`
+    Disputed,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub enum GameWinner {
+    None,
+    White,
+    Black,
+    Draw,
+    /// Called off without a result (mutual abort or a cancel before the start)
+    Aborted,
+}
+
+impl GameWinner {
+    /// (white, black) results, or `None` while the game has no result or was aborted
+    pub fn outcomes(&self) -> Option<(MatchOutcome, MatchOutcome)> {
+        match self {
+            GameWinner::White => Some((MatchOutcome::Win, MatchOutcome::Loss)),
+            GameWinner::Black => Some((MatchOutcome::Loss, MatchOutcome::Win)),
+            GameWinner::Draw => Some((MatchOutcome::Draw, MatchOutcome::Draw)),
+            GameWinner::None | GameWinner::Aborted => None,
+        }
+    }
+
+    /// Slot in `GameEscrow::side_bet_totals`, or `None` for results a side bet can't back
+    pub fn side_bet_index(&self) -> Option<usize> {
+        match self {
+            GameWinner::White => Some(0),
+            GameWinner::Black => Some(1),
+            GameWinner::Draw => Some(2),
+            GameWinner::None | GameWinner::Aborted => None,
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+pub enum GameEndReason {
+    Checkmate,
+    Resignation,
+    Timeout,
+    Agreement,
+    Stalemate,
+    Abandonment,
+    Forfeit,
+    Repetition,
+    FiftyMoveRule,
+    ThreeChecks,
+    KingOfTheHill,
+    KingExploded,
+    InsufficientMaterial,
+}
+
+impl GameEndReason {
+    /// Whether this reason ends a game drawn rather than won
+    pub fn is_drawn(&self) -> bool {
+        matches!(
+            self,
+            GameEndReason::Agreement
+                | GameEndReason::Stalemate
+                | GameEndReason::Repetition
+                | GameEndReason::FiftyMoveRule
+                | GameEndReason::InsufficientMaterial
+        )
+    }
+}
+
+// Events
+#[event]
+pub struct GameCreated {
+    pub room_id: String,
+    pub player_white: Pubkey,
+    pub player_black: Pubkey,
+    pub stake_amount: u64,
+    pub created_at: i64,
+    pub metadata: Option<Vec<u8>>,
+}
+
+#[event]
+pub struct PlayerJoined {
+    pub room_id: String,
+    pub player_white: Pubkey,
+    pub player_black: Pubkey,
+    pub joined_at: i64,
+}
+
+#[event]
+pub struct StakeDeposited {
+    pub room_id: String,
+    pub player: Pubkey,
+    pub amount: u64,
+}
+
+// Both stakes are locked in the vault; `token_pot` is only non-zero in cross-asset games
+#[event]
+pub struct BothDepositsReceived {
+    pub room_id: String,
+    pub total_pot: u64,
+    pub token_pot: u64,
+}
+
+#[event]
+pub struct GameStarted {
+    pub room_id: String,
+    pub started_at: i64,
+}
+
+#[event]
+pub struct MoveRecorded {
+    pub room_id: String,
+    pub player: Pubkey,
+    pub move_count: u32,
+    pub move_notation: String,
+    pub position_hash: [u8; 32],
+    pub timestamp: i64,
+}
+
+/// A game was resigned for `player` by a relayer holding their signed authorization;
+/// followed by the usual `GameFinished`
+#[event]
+pub struct ResignationAuthorized {
+    pub room_id: String,
+    pub player: Pubkey,
+    pub relayer: Pubkey,
+}
+
+/// The mover's clock has just dropped below the game's low-time threshold
+#[event]
+pub struct LowTimeWarning {
+    pub room_id: String,
+    pub player: Pubkey,
+    pub time_remaining: u64,
+    pub threshold: u32,
+}
+
+/// Result of a spot check on a move recorded under optimistic validation
+#[event]
+pub struct MoveAudited {
+    pub room_id: String,
+    pub move_number: u32,
+    pub player: Pubkey,
+    pub passed: bool,
+}
+
+/// `MoveRecorded` for bandwidth-sensitive indexers, emitted instead of it when the game
+/// opts in. Squares are 0 (a1) to 63 (h8); the mover follows from `move_count`'s parity
+/// and the notation from the move itself.
+#[event]
+pub struct MoveRecordedCompact {
+    pub room_id: String,
+    pub move_count: u32,
+    pub from: u8,
+    pub to: u8,
+    pub bits: u16,                         // see `MoveRecordedCompact::bits`
+    pub timestamp: i64,
+}
+
+impl MoveRecordedCompact {
+    pub const CAPTURE: u16 = 1 << 8;
+    pub const CHECK: u16 = 1 << 9;
+    pub const CHECKMATE: u16 = 1 << 10;
+    pub const CASTLE: u16 = 1 << 11;
+    pub const EN_PASSANT: u16 = 1 << 12;
+
+    /// Piece code in bits 0-3, promotion piece code in bits 4-7 (0 = no promotion),
+    /// then the capture, check, checkmate, castle and en-passant flags
+    pub fn bits(mv: &chess_validation::Move, is_check: bool, is_checkmate: bool) -> u16 {
+        let promotion = mv.promotion_piece.map_or(0, |piece| piece.code());
+        let flags = [
+            (mv.captured_piece.is_some(), Self::CAPTURE),
+            (is_check, Self::CHECK),
+            (is_checkmate, Self::CHECKMATE),
+            (mv.is_castle, Self::CASTLE),
+            (mv.is_en_passant, Self::EN_PASSANT),
+        ];
+        flags
+            .iter()
+            .filter(|(set, _)| *set)
+            .fold(mv.piece.code() as u16 | (promotion as u16) << 4, |bits, (_, flag)| bits | flag)
+    }
+}
+
+#[event]
+pub struct UciMoves {
+    pub room_id: String,
+    pub first_move_number: u32,
+    pub moves: Vec<String>,
+}
+
+#[event]
+pub struct MoveChainVerified {
+    pub room_id: String,
+    pub moves_checked: u32,
+    pub head: [u8; 32],
+    pub broken_at: Option<u32>,
+}
+
+// Opt-in snapshot for clients that render without tracking the position themselves
+#[event]
+pub struct BoardState {
+    pub room_id: String,
+    pub move_count: u32,
+    pub board: [u8; 32],
+}
+
+// A position has occurred for the second time; a third occurrence draws the game
+#[event]
+pub struct RepetitionWarning {
+    pub room_id: String,
+    pub move_count: u32,
+}
+
+// Opt-in overlay event: the first capture of the game, or the material lead
+// changing hands. `balance` is White's material minus Black's, in pawns.
+#[event]
+pub struct MaterialLeadChanged {
+    pub room_id: String,
+    pub move_count: u32,
+    pub balance: i16,
+    pub first_capture: bool,
+}
+
+#[event]
+pub struct MoveSealed {
+    pub room_id: String,
+    pub sealed_by: Pubkey,
+    pub move_count: u32,
+    pub adjourned_at: i64,
+}
+
+#[event]
+pub struct MoveLegality {
+    pub room_id: String,
+    pub move_count: u32,
+    pub from_square: String,
+    pub to_square: String,
+    pub is_legal: bool,
+    pub reason_code: u32,                  // ChessError code, 0 when legal
 }
 
-#[derive(Accounts)]
-pub struct CancelGame<'info> {
-    #[account(mut)]
-    pub game_escrow: Account<'info, GameEscrow>,
-    #[account(mut)]
-    pub player: Signer<'info>,
-    #[account(
-        mut,
-        seeds = [b"vault", game_escrow.key().as_ref()],
-        bump
-    )]
-    pub game_vault: SystemAccount<'info>,
-    #[account(
-        mut,
-        address = game_escrow.player_white
-    )]
-    /// CHECK: White player address validated against game escrow
-    pub player_white: UncheckedAccount<'info>,
-    #[account(
-        mut,
-        address = game_escrow.player_black
-    )]
-    /// CHECK: Black player address validated against game escrow
-    pub player_black: UncheckedAccount<'info>,
-    pub system_program: Program<'info, System>,
+#[event]
+pub struct PlayerDisconnected {
+    pub room_id: String,
+    pub player: Pubkey,
+    pub reported_by: Pubkey,
+    pub reported_at: i64,
 }
 
-// Tournament account structures
-#[derive(Accounts)]
-pub struct CreateTournament<'info> {
-    #[account(
-        init, 
-        payer = creator, 
-        space = 8 + 32 + 32 + 64 + 8 + 4 + 4 + 1 + 16 + 8 + 8 + 8 + 8 + 4 + 4,
-        seeds = [b"tournament"],
-        bump
-    )]
-    pub tournament: Account<'info, Tournament>,
-    #[account(mut)]
-    pub creator: Signer<'info>,
-    pub system_program: Program<'info, System>,
+#[event]
+pub struct PlayerReconnected {
+    pub room_id: String,
+    pub player: Pubkey,
+    pub draw_offer_withdrawn: bool,
+    pub reconnected_at: i64,
 }
 
-#[derive(Accounts)]
-pub struct JoinTournament<'info> {
-    #[account(mut)]
-    pub tournament: Account<'info, Tournament>,
-    #[account(mut)]
-    pub player: Signer<'info>,
+#[event]
+pub struct DrawOffered {
+    pub room_id: String,
+    pub offered_by: Pubkey,
+    pub automatic: bool,
 }
 
-#[derive(Accounts)]
-pub struct StartTournament<'info> {
-    #[account(mut)]
-    pub tournament: Account<'info, Tournament>,
-    #[account(mut)]
-    pub creator: Signer<'info>,
+#[event]
+pub struct RematchProposed {
+    pub room_id: String,
+    pub proposed_by: Pubkey,
 }
 
+#[event]
+pub struct RematchWithdrawn {
+    pub room_id: String,
+    pub proposed_by: Pubkey,
+}
 
+#[event]
+pub struct RematchAccepted {
+    pub room_id: String,
+    pub rematch_room_id: String,
+    pub player_white: Pubkey,
+    pub player_black: Pubkey,
+    pub stake_amount: u64,
+}
 
-// Data Structures
-#[account]
-pub struct GameEscrow {
-    pub room_id: String,                    // 4 + 32 = 36 bytes
-    pub player_white: Pubkey,              // 32 bytes
-    pub player_black: Pubkey,              // 32 bytes
-    pub stake_amount: u64,                 // 8 bytes
-    pub total_deposited: u64,              // 8 bytes
-    pub game_state: GameState,             // 1 byte
-    pub winner: GameWinner,                // 1 byte
-    pub created_at: i64,                   // 8 bytes
-    pub started_at: i64,                   // 8 bytes
-    pub finished_at: i64,                  // 8 bytes
-    pub time_limit_seconds: i64,           // 8 bytes
-    pub fee_collector: Pubkey,             // 32 bytes
-    pub white_deposited: bool,             // 1 byte
-    pub black_deposited: bool,             // 1 byte
-    pub move_count: u32,                   // 4 bytes
-    pub last_move_time: i64,               // 8 bytes
-    
-    // Enhanced features for production
-    pub time_control: TimeControl,         // 16 bytes
-    pub position_hash: [u8; 32],          // 32 bytes
-    pub move_history: Vec<MoveRecord>,     // Variable size
-    pub anti_cheat_flags: u32,            // 4 bytes
-    pub rating_white: u32,                 // 4 bytes
-    pub rating_black: u32,                 // 4 bytes
-    pub tournament_id: Option<String>,     // Variable size
-    pub game_flags: GameFlags,             // 4 bytes
+#[event]
+pub struct DrawOfferRevoked {
+    pub room_id: String,
+    pub offered_by: Pubkey,
 }
 
-// Tournament structures
-#[account]
-pub struct Tournament {
-    pub tournament_id: String,             // Variable size
-    pub name: String,                      // Variable size
-    pub creator: Pubkey,                   // 32 bytes
-    pub entry_fee: u64,                    // 8 bytes
-    pub max_participants: u32,             // 4 bytes
-    pub current_participants: u32,         // 4 bytes
-    pub status: TournamentStatus,          // 1 byte
-    pub time_control: TimeControl,         // 16 bytes
-    pub created_at: i64,                   // 8 bytes
-    pub started_at: i64,                   // 8 bytes
-    pub finished_at: i64,                  // 8 bytes
-    pub prize_pool: u64,                   // 8 bytes
-    pub participants: Vec<Pubkey>,         // Variable size
-    pub brackets: Vec<String>,             // Variable size (game IDs)
+#[event]
+pub struct ChatHashPosted {
+    pub room_id: String,
+    pub sender: Pubkey,
+    pub message_hash: [u8; 32],
+    pub sequence: u32,
+    pub posted_at: i64,
 }
 
-// Rating structure
-#[account]
-pub struct PlayerRating {
-    pub player: Pubkey,                    // 32 bytes
-    pub rating: u32,                       // 4 bytes
-    pub games_played: u32,                 // 4 bytes
-    pub last_updated: i64,                 // 8 bytes
-    pub last_game: String,                 // 32 bytes
+#[event]
+pub struct RentBeneficiaryChanged {
+    pub room_id: String,
+    pub previous: Pubkey,
+    pub rent_beneficiary: Pubkey,
+    pub changed_at: i64,
+}
+
+#[event]
+pub struct AutoResignSet {
+    pub room_id: String,
+    pub player: Pubkey,
+    pub below_seconds: u32,
+}
+
+#[event]
+pub struct GameClosed {
+    pub room_id: String,
+    pub rent_beneficiary: Pubkey,
+    pub rent_reclaimed: u64,
+}
+
+#[event]
+pub struct TakebackRequested {
+    pub room_id: String,
+    pub player: Pubkey,
+    pub move_number: u32,
+}
+
+#[event]
+pub struct TakebackAccepted {
+    pub room_id: String,
+    pub player: Pubkey,
+    pub move_number: u32,
+    pub takebacks_used: u8,
+}
+
+#[event]
+pub struct MoveChallenged {
+    pub room_id: String,
+    pub challenger: Pubkey,
+    pub move_number: u32,
+    pub reason_code: u32,                  // ChessError code the move failed with
+    pub forfeited: bool,
+}
+
+#[event]
+pub struct EscrowHealth {
+    pub room_id: String,
+    pub vault_balance: u64,
+    pub total_deposited: u64,
+    pub obligations: u64,                  // lamports the vault must still pay out
+    pub expected_payout: u64,
+    pub solvent: bool,
+    pub discrepancy: i64,                  // vault_balance - obligations; 0 when exact
+}
+
+#[event]
+pub struct TimingStats {
+    pub room_id: String,
+    pub white: PlayerTiming,
+    pub black: PlayerTiming,
+}
+
+#[event]
+pub struct GameHeader {
+    pub room_id: String,
+    pub player_white: Pubkey,
+    pub player_black: Pubkey,
+    pub rating_white: u32,
+    pub rating_black: u32,
+    pub stake_amount: u64,
+    pub initial_time: u64,
+    pub increment: u64,
+    pub time_control_type: TimeControlType,
+    pub variant: Variant,
+    pub game_state: GameState,
+    pub winner: GameWinner,
+    pub move_count: u32,
+    pub tournament_id: Option<String>,
+    pub created_at: i64,
+    pub started_at: i64,
+    pub finished_at: i64,
+}
+
+/// Lobby-card view of a game; clocks are as of the last move
+#[event]
+pub struct GameSummary {
+    pub room_id: String,
+    pub player_white: Pubkey,
+    pub player_black: Pubkey,
+    pub stake_amount: u64,
+    pub game_state: GameState,
+    pub winner: GameWinner,
+    pub move_count: u32,
+    pub white_time_remaining: u64,
+    pub black_time_remaining: u64,
+    pub white_to_move: bool,
+    pub last_move_time: i64,
+}
+
+#[event]
+pub struct LegalMoves {
+    pub room_id: String,
+    pub move_count: u32,
+    pub chunk: u8,
+    pub total_chunks: u8,
+    pub total_moves: u16,
+    pub moves: Vec<LegalMove>,
+}
+
+#[event]
+pub struct CheckStatus {
+    pub room_id: String,
+    pub move_count: u32,
+    pub white_to_move: bool,
+    pub in_check: bool,
+}
+
+#[event]
+pub struct MoveArchived {
+    pub room_id: String,
+    pub record: MoveRecord,
+}
+
+#[event]
+pub struct GameFinished {
+    pub room_id: String,
+    pub winner: GameWinner,
+    pub reason: GameEndReason,
+    pub finished_at: i64,
+    /// Think time each side reported over the game, from `white_timing`/`black_timing`
+    pub white_total_time_ms: u64,
+    pub black_total_time_ms: u64,
+}
+
+#[event]
+pub struct TokenPotSettled {
+    pub room_id: String,
+    pub white_amount: u64,
+    pub black_amount: u64,
+    pub fee_amount: u64,
+    pub settled_at: i64,
+}
+
+#[event]
+pub struct TokenStakeSettled {
+    pub room_id: String,
+    pub recipient: Pubkey,
+    pub amount: u64,
+    pub fee_amount: u64,
+    pub settled_at: i64,
+}
+
+#[event]
+pub struct WinningsLocked {
+    pub room_id: String,
+    pub recipient: Pubkey,
+    pub amount: u64,
+    pub unlock_at: i64,
+}
+
+#[event]
+pub struct GameDoublyAbandoned {
+    pub room_id: String,
+    pub last_move_time: i64,
+    pub refund_per_player: u64,
+    pub fee_per_player: u64,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
-pub enum TournamentStatus {
-    Registration,
-    Active,
-    Finished,
-    Cancelled,
+#[event]
+pub struct GameMutuallyAborted {
+    pub room_id: String,
+    pub move_count: u32,
+    pub refund_per_player: u64,
+    pub aborted_at: i64,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
-pub struct TimeControl {
-    pub initial_time: u64,     // in seconds
-    pub increment: u64,        // in seconds
-    pub delay: u64,           // in seconds
-    pub time_control_type: TimeControlType,
+#[event]
+pub struct TimeExtended {
+    pub room_id: String,
+    pub seconds: u64,
+    pub clocks: ExtendedClocks,
+    pub white_time_remaining: u64,
+    pub black_time_remaining: u64,
+    pub extended_at: i64,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
-pub enum TimeControlType {
-    Rapid,    // 10+ minutes
-    Blitz,    // 3-10 minutes
-    Bullet,   // <3 minutes
-    Custom,
+#[event]
+pub struct TimeControlAdjusted {
+    pub room_id: String,
+    pub time_control: TimeControl,
+    pub white_time_remaining: u64,
+    pub black_time_remaining: u64,
+    pub adjusted_at: i64,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
-pub struct MoveRecord {
-    pub move_number: u32,
-    pub from_square: String,
-    pub to_square: String,
-    pub piece: String,
-    pub captured_piece: Option<String>,
-    pub move_notation: String,
-    pub position_hash: [u8; 32],
-    pub timestamp: i64,
-    pub time_spent: u64,
-    pub is_check: bool,
-    pub is_checkmate: bool,
-    pub is_castle: bool,
-    pub is_en_passant: bool,
-    pub is_promotion: bool,
-    pub promotion_piece: Option<String>,
+#[event]
+pub struct PlayerBerserked {
+    pub room_id: String,
+    pub player: Pubkey,
+    pub time_remaining: u64,
+    pub berserked_at: i64,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
-pub struct GameFlags {
-    pub is_tournament_game: bool,
-    pub is_rated: bool,
-    pub allow_draw_offers: bool,
-    pub allow_resignation: bool,
-    pub require_move_validation: bool,
-    pub enable_anti_cheat: bool,
+#[event]
+pub struct AntiCheatFlagged {
+    pub room_id: String,
+    pub player: Pubkey,
+    pub flag_bits: u32,                    // bits this move newly raised
+    pub move_count: u32,
 }
 
-impl GameEscrow {
-    pub const INIT_SPACE: usize = 36 + 32 + 32 + 8 + 8 + 1 + 1 + 8 + 8 + 8 + 8 + 32 + 1 + 1 + 4 + 8 + 16 + 32 + 4 + 4 + 4 + 4 + 4 + 4 + 5 + 32; // 256 bytes + variable size for move_history and tournament_id
+#[event]
+pub struct AntiCheatReviewRequired {
+    pub room_id: String,
+    pub anti_cheat_flags: u32,
+    pub move_count: u32,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
-pub enum GameState {
-    WaitingForPlayers,
-    WaitingForDeposits,
-    InProgress,
-    Finished,
-    Cancelled,
+#[event]
+pub struct AntiCheatReviewCleared {
+    pub room_id: String,
+    pub moderator: Pubkey,
+    pub anti_cheat_flags: u32,
+    pub cleared_at: i64,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
-pub enum GameWinner {
-    None,
-    White,
-    Black,
-    Draw,
+#[event]
+pub struct SideBetPlaced {
+    pub room_id: String,
+    pub bettor: Pubkey,
+    pub predicted_winner: GameWinner,
+    pub amount: u64,
+    pub pool_total: u64,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
-pub enum GameEndReason {
-    Checkmate,
-    Resignation,
-    Timeout,
-    Agreement,
-    Stalemate,
-    Abandonment,
+#[event]
+pub struct SideBetSettled {
+    pub room_id: String,
+    pub bettor: Pubkey,
+    pub predicted_winner: GameWinner,
+    pub amount: u64,
+    pub payout: u64,
 }
 
-// Events
 #[event]
-pub struct GameCreated {
+pub struct AntiCheatDisabled {
     pub room_id: String,
-    pub player_white: Pubkey,
-    pub stake_amount: u64,
-    pub created_at: i64,
+    pub move_count: u32,
+    pub disabled_at: i64,
 }
 
 #[event]
-pub struct PlayerJoined {
+pub struct UnmatchedDepositReclaimed {
     pub room_id: String,
-    pub player_black: Pubkey,
-    pub joined_at: i64,
+    pub player: Pubkey,
+    pub refund: u64,
+    pub bond_forfeited: u64,
+    pub reclaimed_at: i64,
 }
 
+/// One player's lamports returned from the game vault, whichever path refunded them
 #[event]
-pub struct StakeDeposited {
+pub struct DepositRefunded {
     pub room_id: String,
     pub player: Pubkey,
     pub amount: u64,
 }
 
 #[event]
-pub struct GameStarted {
+pub struct ResultDisputed {
     pub room_id: String,
-    pub started_at: i64,
+    pub disputed_by: Pubkey,
+    pub declared_winner: GameWinner,
+    pub disputed_at: i64,
 }
 
 #[event]
-pub struct MoveRecorded {
+pub struct DisputeResolved {
     pub room_id: String,
-    pub player: Pubkey,
-    pub move_count: u32,
-    pub move_notation: String,
-    pub position_hash: [u8; 32],
-    pub timestamp: i64,
+    pub arbiter: Pubkey,
+    pub winner: GameWinner,
+    pub resolved_at: i64,
 }
 
+/// Lamports found in a game vault beyond the deposits, sent to the fee collector at
+/// settlement or when the stakes are refunded
 #[event]
-pub struct GameFinished {
+pub struct VaultSurplusSwept {
     pub room_id: String,
-    pub winner: GameWinner,
-    pub reason: GameEndReason,
-    pub finished_at: i64,
+    pub amount: u64,
+}
+
+#[event]
+pub struct PayoutClaimed {
+    pub room_id: String,
+    pub player: Pubkey,
+    pub amount: u64,
+    pub claimed_at: i64,
 }
 
 #[event]
@@ -1339,6 +11079,249 @@ pub struct TournamentStarted {
     pub prize_pool: u64,
 }
 
+#[event]
+pub struct TournamentFinalized {
+    pub tournament_id: String,
+    pub prize_pool: u64,
+    pub shortfall: u64,                    // advertised pool the vault couldn't cover
+    pub winners: Vec<Pubkey>,
+    pub payouts: Vec<u64>,
+    pub finalized_at: i64,
+}
+
+#[event]
+pub struct TournamentCancelled {
+    pub tournament_id: String,
+    pub refunded: Vec<Pubkey>,
+    pub entry_fee: u64,                    // refunded to each participant
+    pub cancelled_at: i64,
+}
+
+#[event]
+pub struct PositionReplayed {
+    pub room_id: String,
+    pub move_count: u32,
+    pub board: [u8; 64],
+    pub matches_stored: bool,
+}
+
+#[event]
+pub struct PositionFen {
+    pub room_id: String,
+    pub move_count: u32,
+    pub fen: String,
+}
+
+#[event]
+pub struct CheckmateVerified {
+    pub fen: String,
+    pub is_checkmate: bool,
+}
+
+#[event]
+pub struct HeadToHeadRecord {
+    pub players: [Pubkey; 2],
+    pub wins: [u32; 2],
+    pub draws: u32,
+    pub games: u32,
+    pub last_game_at: i64,
+}
+
+#[event]
+pub struct SeriesCreated {
+    pub series_id: String,
+    pub players: [Pubkey; 2],
+    pub stake_amount: u64,
+    pub wins_required: u8,
+    pub flag_forfeit_threshold: u8,
+    pub created_at: i64,
+}
+
+#[event]
+pub struct SeriesStarted {
+    pub series_id: String,
+    pub started_at: i64,
+}
+
+#[event]
+pub struct SeriesGameReported {
+    pub series_id: String,
+    pub game: Pubkey,
+    pub result: GameWinner,
+    pub wins: [u8; 2],
+    pub flags: [u16; 2],
+    pub reported_at: i64,
+}
+
+#[event]
+pub struct SeriesFinished {
+    pub series_id: String,
+    pub winner: Pubkey,
+    pub wins: [u8; 2],
+    pub forfeit: bool,                     // decided by the flag threshold
+    pub payout: u64,
+    pub finished_at: i64,
+}
+
+#[event]
+pub struct SeriesCancelled {
+    pub series_id: String,
+    pub refunds: [u64; 2],                 // (creator, opponent)
+    pub cancelled_at: i64,
+}
+
+#[event]
+pub struct TournamentClosed {
+    pub tournament_id: String,
+    pub creator: Pubkey,
+    pub rent_reclaimed: u64,
+}
+
+#[event]
+pub struct MatchResultReported {
+    pub tournament_id: String,
+    pub game: Pubkey,
+    pub white: Pubkey,
+    pub black: Pubkey,
+    pub result: GameWinner,
+    pub reported_at: i64,
+}
+
+#[event]
+pub struct TournamentStandingsSnapshot {
+    pub tournament_id: String,
+    pub entries: Vec<StandingEntry>,
+}
+
+#[event]
+pub struct BracketsGenerated {
+    pub tournament_id: String,
+    pub seeds: Vec<Pubkey>,
+    pub shuffle_seed: [u8; 32],
+    pub generated_at: i64,
+}
+
+#[event]
+pub struct BracketRoundPaired {
+    pub tournament_id: String,
+    pub round: u16,
+    pub room_ids: Vec<String>,
+    pub pairings: Vec<Pairing>,
+    pub bye: Option<Pubkey>,
+    pub paired_at: i64,
+}
+
+#[event]
+pub struct BracketDecided {
+    pub tournament_id: String,
+    pub champion: Pubkey,
+    pub rounds: u16,
+    pub decided_at: i64,
+}
+
+#[event]
+pub struct BracketShufflePreview {
+    pub tournament_id: String,
+    pub seed: [u8; 32],
+    pub seeds: Vec<Pubkey>,
+}
+
+#[event]
+pub struct TournamentMigrated {
+    pub tournament_id: String,
+    pub from_version: u8,
+    pub to_version: u8,
+    pub migrated_at: i64,
+}
+
+#[event]
+pub struct TournamentFlagReported {
+    pub tournament_id: String,
+    pub room_id: String,
+    pub player: Pubkey,
+    pub game_flags: u8,
+    pub total_flags: u32,
+    pub reported_at: i64,
+}
+
+#[event]
+pub struct RoundReseeded {
+    pub tournament_id: String,
+    pub round: u16,
+    pub seeds: Vec<Pubkey>,
+    pub pairings: Vec<Pairing>,
+    pub reseeded_at: i64,
+}
+
+#[event]
+pub struct ConfigUpdated {
+    pub admin: Pubkey,
+    pub fee_collector: Pubkey,
+    pub updated_at: i64,
+}
+
+#[event]
+pub struct ModeratorAdded {
+    pub moderator: Pubkey,
+    pub moderators: u8,
+    pub updated_at: i64,
+}
+
+#[event]
+pub struct ModeratorRemoved {
+    pub moderator: Pubkey,
+    pub moderators: u8,
+    pub updated_at: i64,
+}
+
+#[event]
+pub struct AllowedMintAdded {
+    pub mint: Pubkey,
+    pub allowed_mints: u8,
+    pub updated_at: i64,
+}
+
+#[event]
+pub struct AllowedMintRemoved {
+    pub mint: Pubkey,
+    pub allowed_mints: u8,
+    pub updated_at: i64,
+}
+
+#[event]
+pub struct ProgramPauseSet {
+    pub paused: bool,
+    pub updated_at: i64,
+}
+
+#[event]
+pub struct FeeCollectorEnforcementSet {
+    pub fee_collector: Pubkey,
+    pub enforced: bool,
+    pub updated_at: i64,
+}
+
+#[event]
+pub struct StakeBoundsSet {
+    pub min_stake: u64,
+    pub max_stake: u64,
+    pub updated_at: i64,
+}
+
+#[event]
+pub struct DeadVaultWindowSet {
+    pub seconds: u32,
+    pub updated_at: i64,
+}
+
+#[event]
+pub struct DeadVaultSwept {
+    pub room_id: String,
+    pub treasury: Pubkey,
+    pub amount: u64,
+    pub swept_at: i64,
+}
+
 // Rating events
 #[event]
 pub struct RatingUpdated {
@@ -1348,6 +11331,15 @@ pub struct RatingUpdated {
     pub updated_at: i64,
 }
 
+#[event]
+pub struct RatingRecalculated {
+    pub player: Pubkey,
+    pub previous_rating: u32,
+    pub new_rating: u32,
+    pub games_replayed: u32,
+    pub recalculated_at: i64,
+}
+
 // Error Codes
 #[error_code]
 pub enum ChessError {
@@ -1407,4 +11399,276 @@ pub enum ChessError {
     TournamentAlreadyStarted,
     #[msg("Invalid rating")]
     InvalidRating,
+    #[msg("Game is not finished")]
+    GameNotFinished,
+    #[msg("Funds have already been distributed")]
+    FundsAlreadyDistributed,
+    #[msg("Too many tournament participants")]
+    TooManyParticipants,
+    #[msg("Tournament is not active")]
+    TournamentNotActive,
+    #[msg("Move history capacity exceeds the account size budget")]
+    MoveHistoryCapacityTooLarge,
+    #[msg("Captured piece does not match the board")]
+    CaptureMismatch,
+    #[msg("Too many moves have been played to abort")]
+    AbortWindowClosed,
+    #[msg("Opponent has not joined the game")]
+    OpponentNotJoined,
+    #[msg("Form weight must be between 0 and 100")]
+    InvalidSeedingWeight,
+    #[msg("Brackets have already been generated")]
+    BracketsAlreadyGenerated,
+    #[msg("Expected a rating and stats account for every participant, in order")]
+    SeedingAccountsMismatch,
+    #[msg("Only one move per game can be recorded each second")]
+    MoveTooSoon,
+    #[msg("Stake deposited in the wrong asset for this game")]
+    StakeAssetMismatch,
+    #[msg("Stake is too small to keep the game vault rent-exempt")]
+    StakeBelowMinimum,
+    #[msg("The moving piece is not on the source square")]
+    PieceNotOnSquare,
+    #[msg("Game is not adjourned")]
+    GameNotPaused,
+    #[msg("Revealed move does not match the sealed move")]
+    SealedMoveMismatch,
+    #[msg("Rated games need both players' rating accounts")]
+    RatingAccountMissing,
+    #[msg("Only the program admin can do this")]
+    UnauthorizedAdmin,
+    #[msg("No fee collector given and no program config to inherit one from")]
+    FeeCollectorMissing,
+    #[msg("Prize pool does not match the entry fees held in the tournament vault")]
+    PrizePoolMismatch,
+    #[msg("Anti-cheat cannot be disabled in a rated game")]
+    RatedGameAntiCheat,
+    #[msg("Player already has the maximum number of games in progress")]
+    TooManyActiveGames,
+    #[msg("PlayerStats account required to release this game from the active count")]
+    PlayerStatsMissing,
+    #[msg("Too many fee-exempt players in the config")]
+    TooManyFeeExemptPlayers,
+    #[msg("No referee or moderator is registered in the program config")]
+    RefereeNotRegistered,
+    #[msg("Missing or invalid referee signature for this result")]
+    InvalidRefereeSignature,
+    #[msg("Not enough moves have been played to declare this result")]
+    TooFewMoves,
+    #[msg("Too many games to replay in one rating recalculation")]
+    TooManyRecalcGames,
+    #[msg("The same game appears twice in a rating recalculation")]
+    DuplicateRecalcGame,
+    #[msg("Berserk is only allowed before the player's first move")]
+    BerserkTooLate,
+    #[msg("Player has already berserked in this game")]
+    AlreadyBerserked,
+    #[msg("Draw claims are not enabled for this game")]
+    DrawClaimsDisabled,
+    #[msg("Only the player on move may claim a draw in this game")]
+    NotYourClaim,
+    #[msg("The position does not support this draw claim")]
+    DrawClaimInvalid,
+    #[msg("No recorded move to challenge")]
+    NoMoveToChallenge,
+    #[msg("The challenged move is legal")]
+    ChallengedMoveLegal,
+    #[msg("Brackets must be generated before the field can be re-seeded")]
+    SeedsNotGenerated,
+    #[msg("Rounds must be re-seeded in order")]
+    RoundOutOfOrder,
+    #[msg("Game has already been linked to a tournament")]
+    GameAlreadyLinked,
+    #[msg("Moderator list is full")]
+    TooManyModerators,
+    #[msg("Key is already a moderator")]
+    ModeratorAlreadyListed,
+    #[msg("Key is not a moderator")]
+    ModeratorNotListed,
+    #[msg("Invalid moderator key")]
+    InvalidModerator,
+    #[msg("Time extension must be positive and keep clocks within the cap")]
+    InvalidTimeExtension,
+    #[msg("Random seeding needs the SlotHashes sysvar")]
+    SlotHashesRequired,
+    #[msg("Winnings can only be time-locked in token-staked games")]
+    WinningsLockNeedsTokenStake,
+    #[msg("Winnings lock is too long")]
+    WinningsLockTooLong,
+    #[msg("No time-locked winnings to claim")]
+    NoLockedWinnings,
+    #[msg("Winnings are still time-locked")]
+    WinningsStillLocked,
+    #[msg("Takebacks are disabled for this game")]
+    TakebacksDisabled,
+    #[msg("No move to take back")]
+    NoMoveToTakeBack,
+    #[msg("No takeback has been requested")]
+    NoTakebackRequested,
+    #[msg("Takeback limit reached")]
+    TooManyTakebacks,
+    #[msg("Game has not been abandoned long enough")]
+    GameNotAbandoned,
+    #[msg("Default rating is out of range")]
+    InvalidDefaultRating,
+    #[msg("Account is not a tournament")]
+    InvalidTournamentAccount,
+    #[msg("Tournament is already on the current layout")]
+    TournamentAlreadyMigrated,
+    #[msg("Player is already marked disconnected")]
+    PlayerAlreadyDisconnected,
+    #[msg("Player is not marked disconnected")]
+    PlayerNotDisconnected,
+    #[msg("Time control phases must add time after strictly increasing moves")]
+    InvalidTimeControlPhases,
+    #[msg("Invalid rent beneficiary")]
+    InvalidRentBeneficiary,
+    #[msg("Game still holds funds or hasn't finished")]
+    GameNotSettled,
+    #[msg("Payout percentages must be positive and add up to 100")]
+    InvalidPayoutStructure,
+    #[msg("Payout accounts don't match the final standings")]
+    PayoutAccountsMismatch,
+    #[msg("Game metadata is too long")]
+    MetadataTooLong,
+    #[msg("Griefing bond must not exceed the stake or be used with a token stake")]
+    InvalidGriefingBond,
+    #[msg("Game vault and system program are required to post the griefing bond")]
+    BondAccountsMissing,
+    #[msg("The opponent can still match the deposit")]
+    DepositWindowOpen,
+    #[msg("Spectator chat is not enabled for this game")]
+    ChatDisabled,
+    #[msg("Player pair must be two distinct keys in ascending order")]
+    InvalidPlayerPair,
+    #[msg("Pair history account missing or for another pair")]
+    PairHistoryMissing,
+    #[msg("These players played a rated game too recently")]
+    RatedPairCooldown,
+    #[msg("Series must need between 1 and 4 wins")]
+    InvalidSeriesLength,
+    #[msg("Series is not in the right state for this action")]
+    SeriesNotInProgress,
+    #[msg("Game was not played between the series players during the series")]
+    SeriesGameMismatch,
+    #[msg("Game has already been counted for this series")]
+    SeriesGameAlreadyCounted,
+    #[msg("Series has reached its game limit")]
+    SeriesGameLimitReached,
+    #[msg("Draw split shares must add up to 10000 basis points")]
+    InvalidDrawSplit,
+    #[msg("Tournament must be finished or cancelled, with its standings, to close")]
+    TournamentNotClosable,
+    #[msg("Tournament vault still holds unpaid funds")]
+    TournamentFundsRemaining,
+    #[msg("Standings account is required to join a started tournament")]
+    StandingsAccountMissing,
+    #[msg("Head-to-head record is for another pair of players")]
+    HeadToHeadMismatch,
+    #[msg("Time control is too fast for a rated game")]
+    TimeControlTooFastForRated,
+    #[msg("Audit rate must be between 0 and 100")]
+    InvalidAuditRate,
+    #[msg("Missing or invalid resignation authorization from a player of this game")]
+    InvalidResignationAuthorization,
+    #[msg("Player to move has flagged; only a result confirmed by the board can be declared")]
+    TimeoutTakesPrecedence,
+    #[msg("Mint is not on the config's list of allowed stake mints")]
+    MintNotAllowed,
+    #[msg("Mint is already allowed")]
+    MintAlreadyAllowed,
+    #[msg("Allowed mint list is full")]
+    TooManyAllowedMints,
+    #[msg("Invalid FEN record")]
+    InvalidFen,
+    #[msg("Stored move number is out of step with the moves played")]
+    FullmoveNumberMismatch,
+    #[msg("Draw offers are disabled for this game")]
+    DrawOffersDisabled,
+    #[msg("A draw offer is already pending")]
+    DrawAlreadyOffered,
+    #[msg("No draw offer is pending")]
+    NoDrawOffer,
+    #[msg("A player cannot accept their own draw offer")]
+    CannotAcceptOwnDrawOffer,
+    #[msg("Dead vault sweeping is disabled")]
+    DeadVaultSweepDisabled,
+    #[msg("The game finished too recently for its vault to be swept")]
+    DeadVaultWindowNotElapsed,
+    #[msg("Resignation is disabled for this game")]
+    ResignationDisabled,
+    #[msg("Fee is above the maximum basis points")]
+    InvalidFeeBps,
+    #[msg("A rematch has already been proposed")]
+    RematchAlreadyProposed,
+    #[msg("No rematch has been proposed")]
+    NoRematchProposed,
+    #[msg("A player cannot accept their own rematch proposal")]
+    CannotAcceptOwnRematch,
+    #[msg("Promotion flag or piece doesn't fit the move")]
+    InvalidPromotion,
+    #[msg("Move history no longer holds every move played")]
+    MoveHistoryIncomplete,
+    #[msg("Position hash doesn't match the position after the move")]
+    PositionHashMismatch,
+    #[msg("Payouts are held until a moderator reviews the game's anti-cheat flags")]
+    AntiCheatReviewPending,
+    #[msg("Game is not held for anti-cheat review")]
+    NoAntiCheatReview,
+    #[msg("Side bets close once the first move is played")]
+    SideBetsClosed,
+    #[msg("The players of a game cannot side-bet on it")]
+    PlayersCannotSideBet,
+    #[msg("A side bet must back White, Black or a draw")]
+    InvalidSideBetPrediction,
+    #[msg("The side to move still has a legal move, so this is not stalemate")]
+    NotStalemate,
+    #[msg("The move does not deliver checkmate")]
+    NotCheckmate,
+    #[msg("Time control type does not match its initial time")]
+    InconsistentTimeControl,
+    #[msg("Game already has an opponent")]
+    GameAlreadyHasOpponent,
+    #[msg("Fee collector must be the config's collector")]
+    FeeCollectorNotAllowed,
+    #[msg("Program is paused: no new games, joins or deposits")]
+    ProgramPaused,
+    #[msg("No payout to claim for this player")]
+    NothingToClaim,
+    #[msg("No stake deposited to withdraw")]
+    NoStakeToWithdraw,
+    #[msg("Move was submitted against an earlier move count")]
+    StaleMove,
+    #[msg("Only the opponent may claim this timeout yet")]
+    TimeoutClaimNotAllowed,
+    #[msg("Stake is below the configured minimum")]
+    StakeBelowConfigMinimum,
+    #[msg("Stake is above the configured maximum")]
+    StakeAboveConfigMaximum,
+    #[msg("Minimum stake exceeds the maximum")]
+    InvalidStakeBounds,
+    #[msg("Result can still be disputed")]
+    DisputeWindowOpen,
+    #[msg("Result can no longer be disputed")]
+    DisputeWindowClosed,
+    #[msg("Game result is not disputed")]
+    NoDispute,
+    #[msg("Winners must name one player from each of the round's pairings, in order")]
+    BracketWinnersMismatch,
+    #[msg("Bracket already has a champion")]
+    BracketAlreadyDecided,
+    #[msg("A side still has enough material to mate")]
+    MatingMaterialRemains,
+    #[msg("Consolation share must be between 1 and 5000 basis points, in a single-asset game")]
+    InvalidPayoutPolicy,
+    #[msg("A move batch must hold between 1 and 6 moves")]
+    InvalidBatchSize,
+    #[msg("Series stake is too large for the pot to hold both stakes")]
+    SeriesStakeTooLarge,
+    #[msg("Series has seen a game too recently to be refunded")]
+    SeriesNotExpired,
+    #[msg("This game's result has already been reported")]
+    MatchAlreadyReported,
+    #[msg("A draw offered for a disconnected player can't be accepted")]
+    AutomaticDrawOffer,
 }
\ No newline at end of file
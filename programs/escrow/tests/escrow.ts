@@ -2,7 +2,23 @@ import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
 import { ChessEscrow } from "../target/types/chess_escrow";
 import { assert } from "chai";
-import { PublicKey, Keypair, SystemProgram, LAMPORTS_PER_SOL } from "@solana/web3.js";
+import { createHash } from "crypto";
+import {
+  createMint,
+  getAccount,
+  getAssociatedTokenAddressSync,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+} from "@solana/spl-token";
+import {
+  PublicKey,
+  Keypair,
+  SystemProgram,
+  LAMPORTS_PER_SOL,
+  Transaction,
+  Ed25519Program,
+  SYSVAR_INSTRUCTIONS_PUBKEY,
+} from "@solana/web3.js";
 
 describe("chess_escrow", () => {
   // Configure the client to use the local cluster
@@ -27,6 +43,14 @@ describe("chess_escrow", () => {
   let gameVaultPda: PublicKey;
   let gameEscrowBump: number;
   let gameVaultBump: number;
+  const [configPda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("config")],
+    program.programId
+  );
+  const [programDataPda] = PublicKey.findProgramAddressSync(
+    [program.programId.toBuffer()],
+    new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+  );
 
   before(async () => {
     // Create test keypairs
@@ -54,12 +78,376 @@ describe("chess_escrow", () => {
       [Buffer.from("vault"), gameEscrowPda.toBuffer()],
       program.programId
     );
+
+    // The provider wallet deployed the program, so it is the config admin
+    await program.methods
+      .initializeConfig(feeCollector.publicKey)
+      .accounts({
+        config: configPda,
+        admin: provider.wallet.publicKey,
+        program: program.programId,
+        programData: programDataPda,
+      })
+      .rpc();
+  });
+
+  // Shared helpers for tests that need a running game
+  const defaultGameOptions = () => ({
+    antiCheatForfeitThreshold: 0,
+    moveHistoryCapacity: 32,
+    timeControlType: { custom: {} },
+    suspiciousMoveIntervalMs: 0,
+    emitBoardState: false,
+    crossAssetStake: null,
+    isRated: false,
+    earlyResignationMoves: 0,
+    doubleFlagRule: { draw: {} },
+    emitMaterialEvents: false,
+    armageddon: false,
+    autoDrawOnRepetition: false,
+    minMovesBeforeResult: 0,
+    drawClaims: { disabled: {} },
+    optimisticValidation: false,
+    forfeitOnIllegalMove: false,
+    variant: { standard: {} },
+    rakeMode: { potRake: {} },
+    winningsLockSeconds: 0,
+    maxTakebacksPerPlayer: null,
+    incrementSeconds: 0,
+    incrementStartMove: 0,
+    autoDrawOfferOnDisconnect: false,
+    timeControlPhases: [],
+    metadata: null,
+    depositMatchWindowSeconds: 0,
+    griefingBond: new anchor.BN(0),
+    spectatorChat: false,
+    minMoveIntervalMs: 0,
+    strictMoveInterval: false,
+    drawSplitWhiteBps: 5000,
+    drawSplitBlackBps: 5000,
+    compactMoveEvents: false,
+    auditRatePct: 0,
+    lowTimeWarningSeconds: 0,
+    freeGame: false,
+    feeBps: null,
+    antiCheatReviewThreshold: 0,
+    delaySeconds: 0,
+    tokenStakeMint: null,
+    pullPayouts: false,
+    timeoutGraceSeconds: 0,
+    disputeWindowSeconds: 0,
+    creatorColor: { white: {} },
+    payoutPolicy: { winnerTakeAll: {} },
+  });
+
+  const deriveGamePdas = (room: string) => {
+    const [escrow] = PublicKey.findProgramAddressSync(
+      [Buffer.from("game"), Buffer.from(room)],
+      program.programId
+    );
+    const [vault] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), escrow.toBuffer()],
+      program.programId
+    );
+    return { escrow, vault };
+  };
+
+  const startGame = async (
+    room: string,
+    options = defaultGameOptions(),
+    white: Keypair = playerWhite,
+    black: Keypair = playerBlack,
+    timeLimit = timeLimitSeconds
+  ) => {
+    const pdas = deriveGamePdas(room);
+    await program.methods
+      .initializeGame(room, stakeAmount, timeLimit, options)
+      .accounts({
+        gameEscrow: pdas.escrow,
+        player: white.publicKey,
+        feeCollector: feeCollector.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([white])
+      .rpc();
+    await program.methods
+      .joinGame()
+      .accounts({
+        gameEscrow: pdas.escrow,
+        player: black.publicKey,
+        playerRating: options.isRated ? ratingPdaOf(black.publicKey) : null,
+      })
+      .signers([black])
+      .rpc();
+    for (const player of [white, black]) {
+      await program.methods
+        .depositStake()
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: player.publicKey,
+          gameVault: pdas.vault,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([player])
+        .rpc();
+    }
+    return pdas;
+  };
+
+  type MoveExtras = {
+    timeSpent?: number;
+    captured?: string;
+    notation?: string;
+    check?: boolean;
+    checkmate?: boolean;
+    castle?: boolean;
+    enPassant?: boolean;
+    promotion?: string;
+    moveCount?: number;
+    hash?: number[];
+  };
+
+  // Waits until the cluster clock has moved past the game's last move, since
+  // record_move accepts at most one move per game per second
+  const waitForNextSecond = async (escrow: PublicKey) => {
+    const game = await program.account.gameEscrow.fetchNullable(escrow);
+    if (!game || game.moveCount === 0) return;
+    for (;;) {
+      const slot = await provider.connection.getSlot();
+      const blockTime = await provider.connection.getBlockTime(slot);
+      if (blockTime !== null && blockTime > game.lastMoveTime.toNumber()) return;
+      await new Promise((resolve) => setTimeout(resolve, 200));
+    }
+  };
+
+  // The MoveSubmission for a ply, claiming `hash` as the position it leaves
+  const moveSubmission = (from: string, to: string, piece: string, hash: number[], extra: MoveExtras = {}) => ({
+    moveNotation: extra.notation ?? `${from}${to}`,
+    gamePositionHash: hash,
+    candidate: {
+      fromSquare: from,
+      toSquare: to,
+      piece,
+      capturedPiece: extra.captured ?? null,
+      isCastle: extra.castle ?? false,
+      isEnPassant: extra.enPassant ?? false,
+      isPromotion: extra.promotion !== undefined,
+      promotionPiece: extra.promotion ?? null,
+    },
+    isCheck: extra.check ?? false,
+    isCheckmate: extra.checkmate ?? false,
+  });
+
+  // Builds a record_move call; the expected move count and the position hash are read from
+  // the game unless given
+  const recordMoveIx = async (
+    escrow: PublicKey,
+    player: Keypair,
+    from: string,
+    to: string,
+    piece: string,
+    extra: MoveExtras = {}
+  ) => {
+    const game = await program.account.gameEscrow.fetch(escrow);
+    const moveCount = extra.moveCount ?? game.moveCount;
+    return program.methods
+      .recordMove(
+        moveSubmission(
+          from,
+          to,
+          piece,
+          extra.hash ?? positionDigest(playOnBoard(boardState(game), from, to, piece, extra)),
+          extra
+        ),
+        new anchor.BN(extra.timeSpent ?? 1000),
+        moveCount
+      )
+      .accounts({ gameEscrow: escrow, player: player.publicKey })
+      .signers([player]);
+  };
+
+  const recordMove = async (
+    escrow: PublicKey,
+    player: Keypair,
+    from: string,
+    to: string,
+    piece: string,
+    extra: MoveExtras = {}
+  ) => {
+    await waitForNextSecond(escrow);
+    return (await recordMoveIx(escrow, player, from, to, piece, extra)).rpc();
+  };
+
+  // Plays a list of [from, to, piece, extras] plies, alternating White and Black
+  const playMoves = async (
+    escrow: PublicKey,
+    moves: [string, string, string, MoveExtras?][]
+  ) => {
+    for (const [i, [from, to, piece, extra]] of moves.entries()) {
+      const player = i % 2 === 0 ? playerWhite : playerBlack;
+      await recordMove(escrow, player, from, to, piece, extra ?? {});
+    }
+  };
+
+  // Board codes stored on GameEscrow (a1 = 0 .. h8 = 63)
+  const PIECE_CODES = " PRNBQKprnbqk";
+  const squareIndex = (square: string) =>
+    (square.charCodeAt(1) - 49) * 8 + (square.charCodeAt(0) - 97);
+  const pieceAt = (board: number[], square: string) =>
+    PIECE_CODES[board[squareIndex(square)]].trim();
+
+  // The position a move's hash commits to, as record_move reconstructs it
+  type BoardState = {
+    board: number[];
+    whiteToMove: boolean;
+    enPassant: number | null;
+    castlingRights: number; // bits 0-3: White kingside, White queenside, Black kingside, Black queenside
+    atomic: boolean;
+  };
+  const boardState = (game: any): BoardState => ({
+    board: Array.from(game.board) as number[],
+    whiteToMove: game.turnRecorded ? game.whiteToMove : game.moveCount % 2 === 0,
+    enPassant: game.enPassantSquare,
+    castlingRights: game.castlingRights,
+    atomic: "atomic" in game.variant,
   });
+  // Home squares of kings and rooks, and the castling rights lost when one is vacated or captured
+  const CASTLING_SQUARES: [number, number][] = [[0, 2], [7, 1], [4, 3], [56, 8], [63, 4], [60, 12]];
+  const CASTLE_ROOKS: Record<number, [number, number]> = { 6: [7, 5], 2: [0, 3], 62: [63, 61], 58: [56, 59] };
+  // Plays a move the way chess_validation::apply_move does, plus the atomic explosion
+  const playOnBoard = (state: BoardState, from: string, to: string, piece: string, extra: MoveExtras = {}) => {
+    const board = [...state.board];
+    const [origin, target] = [squareIndex(from), squareIndex(to)];
+    if (extra.enPassant) {
+      board[Math.floor(origin / 8) * 8 + (target % 8)] = 0;
+    }
+    if (extra.castle) {
+      const [rookFrom, rookTo] = CASTLE_ROOKS[target];
+      board[rookTo] = board[rookFrom];
+      board[rookFrom] = 0;
+    }
+    board[target] = extra.promotion ? PIECE_CODES.indexOf(extra.promotion) : board[origin];
+    board[origin] = 0;
+    let castlingRights = state.castlingRights;
+    for (const [square, rights] of CASTLING_SQUARES) {
+      if (square === origin || square === target) castlingRights &= ~rights;
+    }
+    if (state.atomic && extra.captured) {
+      board[target] = 0;
+      for (const df of [-1, 0, 1]) {
+        for (const dr of [-1, 0, 1]) {
+          const [file, rank] = [(target % 8) + df, Math.floor(target / 8) + dr];
+          if ((df || dr) && file >= 0 && file < 8 && rank >= 0 && rank < 8) {
+            const square = rank * 8 + file;
+            if (board[square] !== 1 && board[square] !== 7) board[square] = 0;
+          }
+        }
+      }
+    }
+    const doublePush = piece.toLowerCase() === "p" && Math.abs(origin - target) === 16;
+    return {
+      ...state,
+      board,
+      whiteToMove: !state.whiteToMove,
+      enPassant: doublePush ? (origin + target) / 2 : null,
+      castlingRights,
+    };
+  };
+  // Position hash record_move checks: board codes, side to move, en-passant square (64 = none)
+  // and castling rights
+  const positionDigest = (state: BoardState) =>
+    Array.from(
+      createHash("sha256")
+        .update(Buffer.from(state.board))
+        .update(Buffer.from([state.whiteToMove ? 1 : 0, state.enPassant ?? 64, state.castlingRights]))
+        .digest()
+    );
+  // Hash of the position `escrow`'s game reaches with the given move
+  const digestAfter = async (escrow: PublicKey, from: string, to: string, piece: string, extra: MoveExtras = {}) =>
+    positionDigest(playOnBoard(boardState(await program.account.gameEscrow.fetch(escrow)), from, to, piece, extra));
+
+  // The player entitled to claim a timeout: the one not on the move
+  const timeoutClaimant = async (escrow: PublicKey) =>
+    (await program.account.gameEscrow.fetch(escrow)).whiteToMove ? playerBlack : playerWhite;
+
+  // Runs `action` and returns every `name` event the program emitted meanwhile
+  const collectEvents = async (name: string, action: () => Promise<unknown>) => {
+    const events: any[] = [];
+    const listener = program.addEventListener(name as any, (event) => events.push(event));
+    try {
+      await action();
+      await new Promise((resolve) => setTimeout(resolve, 1000));
+    } finally {
+      await program.removeEventListener(listener);
+    }
+    return events;
+  };
+
+  // Knight shuffle that returns to the starting position every four plies
+  const knightShuffle = (
+    plies: number,
+    white: Keypair = playerWhite,
+    black: Keypair = playerBlack
+  ): [Keypair, string, string, string][] => {
+    const cycle: [Keypair, string, string, string][] = [
+      [white, "g1", "f3", "N"],
+      [black, "g8", "f6", "n"],
+      [white, "f3", "g1", "N"],
+      [black, "f6", "g8", "n"],
+    ];
+    return Array.from({ length: plies }, (_, i) => cycle[i % cycle.length]);
+  };
+
+  const fundedKeypair = async (sol = 10) => {
+    const keypair = Keypair.generate();
+    const signature = await provider.connection.requestAirdrop(
+      keypair.publicKey,
+      sol * LAMPORTS_PER_SOL
+    );
+    await provider.connection.confirmTransaction(signature);
+    return keypair;
+  };
+
+  const ratingPdaOf = (player: PublicKey) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("rating"), player.toBuffer()],
+      program.programId
+    )[0];
+
+  // Creates the player's rating account unless an earlier test already did
+  const ensurePlayerRating = async (player: Keypair) => {
+    if (await program.account.playerRating.fetchNullable(ratingPdaOf(player.publicKey))) return;
+    await program.methods
+      .initializePlayerRating()
+      .accounts({ player: player.publicKey })
+      .signers([player])
+      .rpc();
+  };
+
+  const offerDraw = (escrow: PublicKey, player: Keypair) =>
+    program.methods
+      .offerDraw()
+      .accounts({ gameEscrow: escrow, player: player.publicKey })
+      .signers([player])
+      .rpc();
+
+  const settleGame = (pdas: { escrow: PublicKey; vault: PublicKey }) =>
+    program.methods
+      .settleGame()
+      .accounts({
+        gameEscrow: pdas.escrow,
+        gameVault: pdas.vault,
+        playerWhite: playerWhite.publicKey,
+        playerBlack: playerBlack.publicKey,
+        feeCollector: feeCollector.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
 
   describe("initialize_game", () => {
     it("should initialize a new game", async () => {
       const tx = await program.methods
-        .initializeGame(roomId, stakeAmount, timeLimitSeconds)
+        .initializeGame(roomId, stakeAmount, timeLimitSeconds, defaultGameOptions())
         .accounts({
           gameEscrow: gameEscrowPda,
           player: playerWhite.publicKey,
@@ -98,7 +486,7 @@ describe("chess_escrow", () => {
         
         // If we get here, try to initialize (though we shouldn't get here)
         await program.methods
-          .initializeGame(longRoomId, stakeAmount, timeLimitSeconds)
+          .initializeGame(longRoomId, stakeAmount, timeLimitSeconds, defaultGameOptions())
           .accounts({
             gameEscrow: longGameEscrowPda,
             player: playerWhite.publicKey,
@@ -127,7 +515,7 @@ describe("chess_escrow", () => {
       
       try {
         await program.methods
-          .initializeGame(newRoomId, zeroStake, timeLimitSeconds)
+          .initializeGame(newRoomId, zeroStake, timeLimitSeconds, defaultGameOptions())
           .accounts({
             gameEscrow: newGameEscrowPda,
             player: playerWhite.publicKey,
@@ -141,6 +529,43 @@ describe("chess_escrow", () => {
         assert.include(error.toString(), "InvalidStakeAmount");
       }
     });
+
+    it("should reject a dust stake below the vault rent minimum", async () => {
+      const room = `dust-stake-${Date.now()}`;
+      try {
+        await program.methods
+          .initializeGame(room, new anchor.BN(1_000), timeLimitSeconds, defaultGameOptions())
+          .accounts({
+            gameEscrow: deriveGamePdas(room).escrow,
+            player: playerWhite.publicKey,
+            feeCollector: feeCollector.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([playerWhite])
+          .rpc();
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "StakeBelowMinimum");
+      }
+    });
+
+    it("should accept a small stake that clears the vault rent minimum", async () => {
+      const room = `small-stake-${Date.now()}`;
+      const smallStake = new anchor.BN(LAMPORTS_PER_SOL / 100);
+      await program.methods
+        .initializeGame(room, smallStake, timeLimitSeconds, defaultGameOptions())
+        .accounts({
+          gameEscrow: deriveGamePdas(room).escrow,
+          player: playerWhite.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+
+      const game = await program.account.gameEscrow.fetch(deriveGamePdas(room).escrow);
+      assert.equal(game.stakeAmount.toString(), smallStake.toString());
+    });
   });
 
   describe("join_game", () => {
@@ -169,7 +594,7 @@ describe("chess_escrow", () => {
       
       // Initialize a new game
       await program.methods
-        .initializeGame(selfPlayRoomId, stakeAmount, timeLimitSeconds)
+        .initializeGame(selfPlayRoomId, stakeAmount, timeLimitSeconds, defaultGameOptions())
         .accounts({
           gameEscrow: selfPlayGameEscrowPda,
           player: playerWhite.publicKey,
@@ -194,6 +619,41 @@ describe("chess_escrow", () => {
         assert.include(error.toString(), "CannotPlayAgainstSelf");
       }
     });
+
+    it("should not let a second joiner take the seat", async () => {
+      const room = `second-join-${Date.now()}`;
+      const { escrow } = deriveGamePdas(room);
+      await program.methods
+        .initializeGame(room, stakeAmount, timeLimitSeconds, defaultGameOptions())
+        .accounts({
+          gameEscrow: escrow,
+          player: playerWhite.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+      await program.methods
+        .joinGame()
+        .accounts({ gameEscrow: escrow, player: playerBlack.publicKey, playerRating: null })
+        .signers([playerBlack])
+        .rpc();
+
+      const latecomer = await fundedKeypair();
+      try {
+        await program.methods
+          .joinGame()
+          .accounts({ gameEscrow: escrow, player: latecomer.publicKey, playerRating: null })
+          .signers([latecomer])
+          .rpc();
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "GameAlreadyHasOpponent");
+      }
+
+      const game = await program.account.gameEscrow.fetch(escrow);
+      assert.ok(game.playerBlack.equals(playerBlack.publicKey));
+    });
   });
 
   describe("deposit_stake", () => {
@@ -215,7 +675,7 @@ describe("chess_escrow", () => {
       
       // Initialize a fresh game
       await program.methods
-        .initializeGame(depositRoomId, stakeAmount, timeLimitSeconds)
+        .initializeGame(depositRoomId, stakeAmount, timeLimitSeconds, defaultGameOptions())
         .accounts({
           gameEscrow: depositGameEscrowPda,
           player: playerWhite.publicKey,
@@ -342,7 +802,7 @@ describe("chess_escrow", () => {
 
   describe("record_move", () => {
     const moveNotation = "e2e4";
-    const gamePositionHash = Array(32).fill(1);
+    let gamePositionHash: number[];
     let moveRoomId: string;
     let moveGameEscrowPda: PublicKey;
     let moveGameVaultPda: PublicKey;
@@ -361,7 +821,7 @@ describe("chess_escrow", () => {
       
       // Initialize and fully start the game
       await program.methods
-        .initializeGame(moveRoomId, stakeAmount, timeLimitSeconds)
+        .initializeGame(moveRoomId, stakeAmount, timeLimitSeconds, defaultGameOptions())
         .accounts({
           gameEscrow: moveGameEscrowPda,
           player: playerWhite.publicKey,
@@ -401,24 +861,16 @@ describe("chess_escrow", () => {
         })
         .signers([playerBlack])
         .rpc();
+
+      gamePositionHash = await digestAfter(moveGameEscrowPda, "e2", "e4", "P");
     });
 
     it("should record a move", async () => {
       const tx = await program.methods
         .recordMove(
-          moveNotation, 
-          gamePositionHash,
-          "e2", // from_square
-          "e4", // to_square
-          "P",  // piece
-          undefined, // captured_piece
-          1000, // time_spent
-          false, // is_check
-          false, // is_checkmate
-          false, // is_castle
-          false, // is_en_passant
-          false, // is_promotion
-          undefined   // promotion_piece
+          moveSubmission("e2", "e4", "P", gamePositionHash, { notation: moveNotation }),
+          new anchor.BN(1000), // time_spent
+          0 // expected_move_count
         )
         .accounts({
           gameEscrow: moveGameEscrowPda,
@@ -441,7 +893,7 @@ describe("chess_escrow", () => {
       );
       
       await program.methods
-        .initializeGame(notStartedRoomId, stakeAmount, timeLimitSeconds)
+        .initializeGame(notStartedRoomId, stakeAmount, timeLimitSeconds, defaultGameOptions())
         .accounts({
           gameEscrow: notStartedGameEscrowPda,
           player: playerWhite.publicKey,
@@ -454,19 +906,9 @@ describe("chess_escrow", () => {
       try {
         await program.methods
           .recordMove(
-            moveNotation, 
-            gamePositionHash,
-            "e2", // from_square
-            "e4", // to_square
-            "P",  // piece
-            undefined, // captured_piece
-            1000, // time_spent
-            false, // is_check
-            false, // is_checkmate
-            false, // is_castle
-            false, // is_en_passant
-            false, // is_promotion
-            undefined   // promotion_piece
+            moveSubmission("e2", "e4", "P", gamePositionHash, { notation: moveNotation }),
+            new anchor.BN(1000), // time_spent
+            0 // expected_move_count
           )
           .accounts({
             gameEscrow: notStartedGameEscrowPda,
@@ -484,19 +926,9 @@ describe("chess_escrow", () => {
       try {
         await program.methods
           .recordMove(
-            moveNotation, 
-            gamePositionHash,
-            "e2", // from_square
-            "e4", // to_square
-            "P",  // piece
-            undefined, // captured_piece
-            1000, // time_spent
-            false, // is_check
-            false, // is_checkmate
-            false, // is_castle
-            false, // is_en_passant
-            false, // is_promotion
-            undefined   // promotion_piece
+            moveSubmission("e2", "e4", "P", gamePositionHash, { notation: moveNotation }),
+            new anchor.BN(1000), // time_spent
+            0 // expected_move_count
           )
           .accounts({
             gameEscrow: moveGameEscrowPda,
@@ -530,7 +962,7 @@ describe("chess_escrow", () => {
       
       // Initialize and start game
       await program.methods
-        .initializeGame(resultRoomId, stakeAmount, timeLimitSeconds)
+        .initializeGame(resultRoomId, stakeAmount, timeLimitSeconds, defaultGameOptions())
         .accounts({
           gameEscrow: resultGameEscrowPda,
           player: playerWhite.publicKey,
@@ -636,7 +1068,7 @@ describe("chess_escrow", () => {
       
       // Initialize game
       await program.methods
-        .initializeGame(timeoutRoomId, stakeAmount, shortTimeLimit)
+        .initializeGame(timeoutRoomId, stakeAmount, shortTimeLimit, defaultGameOptions())
         .accounts({
           gameEscrow: timeoutGameEscrowPda,
           player: playerWhite.publicKey,
@@ -684,16 +1116,19 @@ describe("chess_escrow", () => {
     });
 
     it("should handle timeout correctly", async () => {
+      const claimant = await timeoutClaimant(timeoutGameEscrowPda);
       const tx = await program.methods
         .handleTimeout()
         .accounts({
           gameEscrow: timeoutGameEscrowPda,
+          claimant: claimant.publicKey,
           gameVault: timeoutGameVaultPda,
           playerWhite: playerWhite.publicKey,
           playerBlack: playerBlack.publicKey,
           feeCollector: feeCollector.publicKey,
           systemProgram: SystemProgram.programId,
         })
+        .signers([claimant])
         .rpc();
       
       const gameEscrow = await program.account.gameEscrow.fetch(timeoutGameEscrowPda);
@@ -724,7 +1159,7 @@ describe("chess_escrow", () => {
       
       // Initialize game
       await program.methods
-        .initializeGame(cancelRoomId, stakeAmount, timeLimitSeconds)
+        .initializeGame(cancelRoomId, stakeAmount, timeLimitSeconds, defaultGameOptions())
         .accounts({
           gameEscrow: cancelGameEscrowPda,
           player: playerWhite.publicKey,
@@ -762,7 +1197,7 @@ describe("chess_escrow", () => {
       
       // Initialize game
       await program.methods
-        .initializeGame(cancelWithBlackRoomId, stakeAmount, timeLimitSeconds)
+        .initializeGame(cancelWithBlackRoomId, stakeAmount, timeLimitSeconds, defaultGameOptions())
         .accounts({
           gameEscrow: cancelWithBlackGameEscrowPda,
           player: playerWhite.publicKey,
@@ -805,6 +1240,7 @@ describe("chess_escrow", () => {
           gameVault: cancelWithBlackGameVaultPda,
           playerWhite: playerWhite.publicKey,
           playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
           systemProgram: SystemProgram.programId,
         })
         .signers([playerWhite])
@@ -836,7 +1272,7 @@ describe("chess_escrow", () => {
       
       // Initialize and start the game
       await program.methods
-        .initializeGame(inProgressRoomId, stakeAmount, timeLimitSeconds)
+        .initializeGame(inProgressRoomId, stakeAmount, timeLimitSeconds, defaultGameOptions())
         .accounts({
           gameEscrow: inProgressGameEscrowPda,
           player: playerWhite.publicKey,
@@ -887,6 +1323,7 @@ describe("chess_escrow", () => {
             gameVault: inProgressGameVaultPda,
             playerWhite: playerWhite.publicKey,
             playerBlack: playerBlack.publicKey,
+            feeCollector: feeCollector.publicKey,
             systemProgram: SystemProgram.programId,
           })
           .signers([playerWhite])
@@ -917,7 +1354,7 @@ describe("chess_escrow", () => {
       
       // Set up the game
       await program.methods
-        .initializeGame(edgeRoomId, stakeAmount, timeLimitSeconds)
+        .initializeGame(edgeRoomId, stakeAmount, timeLimitSeconds, defaultGameOptions())
         .accounts({
           gameEscrow: edgeGameEscrowPda,
           player: playerWhite.publicKey,
@@ -961,19 +1398,9 @@ describe("chess_escrow", () => {
       try {
         await program.methods
           .recordMove(
-            longMoveNotation, 
-            gamePositionHash,
-            "e2", // from_square
-            "e4", // to_square
-            "P",  // piece
-            undefined, // captured_piece
-            1000, // time_spent
-            false, // is_check
-            false, // is_checkmate
-            false, // is_castle
-            false, // is_en_passant
-            false, // is_promotion
-            undefined   // promotion_piece
+            moveSubmission("e2", "e4", "P", gamePositionHash, { notation: longMoveNotation }),
+            new anchor.BN(1000), // time_spent
+            0 // expected_move_count
           )
           .accounts({
             gameEscrow: edgeGameEscrowPda,
@@ -1010,7 +1437,7 @@ describe("chess_escrow", () => {
       
       // Set up the game
       await program.methods
-        .initializeGame(drawRoomId, stakeAmount, timeLimitSeconds)
+        .initializeGame(drawRoomId, stakeAmount, timeLimitSeconds, defaultGameOptions())
         .accounts({
           gameEscrow: drawGameEscrowPda,
           player: drawPlayerWhite.publicKey,
@@ -1054,7 +1481,8 @@ describe("chess_escrow", () => {
       const whiteBalanceBefore = await provider.connection.getBalance(drawPlayerWhite.publicKey);
       const blackBalanceBefore = await provider.connection.getBalance(drawPlayerBlack.publicKey);
       
-      // Declare draw
+      // Black offers a draw and White declares it
+      await offerDraw(drawGameEscrowPda, drawPlayerBlack);
       await program.methods
         .declareResult(
           { draw: {} },  // GameWinner::Draw
@@ -1096,4 +1524,9371 @@ describe("chess_escrow", () => {
       );
     });
   });
-});
\ No newline at end of file
+
+  describe("anti-cheat auto-forfeit", () => {
+    // Knight shuffle where Black's replies are reported as instant (0 ms)
+    const playFastBlack = async (escrow: PublicKey) => {
+      for (const [player, from, to, piece] of knightShuffle(4)) {
+        const timeSpent = player === playerBlack ? 0 : 5000;
+        await recordMove(escrow, player, from, to, piece, { timeSpent });
+      }
+    };
+
+    it("should forfeit the flagged player once the threshold is reached", async () => {
+      const pdas = await startGame(`forfeit-${Date.now()}`, {
+        ...defaultGameOptions(),
+        antiCheatForfeitThreshold: 2,
+      });
+
+      await playFastBlack(pdas.escrow);
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { finished: {} });
+      // Black's second instant move reaches the threshold and is discarded
+      assert.equal(game.blackFlagCount, 2);
+      assert.equal(game.whiteFlagCount, 0);
+      assert.deepEqual(game.winner, { white: {} });
+      assert.equal(game.moveCount, 3);
+
+      const whiteBefore = await provider.connection.getBalance(playerWhite.publicKey);
+      await settleGame(pdas);
+      const whiteAfter = await provider.connection.getBalance(playerWhite.publicKey);
+      assert.isAbove(whiteAfter, whiteBefore);
+      assert.equal(await provider.connection.getBalance(pdas.vault), 0);
+    });
+
+    it("should only flag when auto-forfeit is disabled", async () => {
+      const pdas = await startGame(`no-forfeit-${Date.now()}`);
+
+      await playFastBlack(pdas.escrow);
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { inProgress: {} });
+      assert.equal(game.moveCount, 4);
+      assert.equal(game.blackFlagCount, 2);
+      assert.equal(game.antiCheatFlags & 1, 1);
+    });
+
+    it("should reject settling a game that is still in progress", async () => {
+      const pdas = await startGame(`settle-early-${Date.now()}`);
+      try {
+        await settleGame(pdas);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "GameNotFinished");
+      }
+    });
+  });
+
+  describe("tournament standings", () => {
+    const tournamentId = "standings-cup";
+    let creator: Keypair;
+    let players: Keypair[];
+    let tournamentPda: PublicKey;
+    let tournamentVaultPda: PublicKey;
+    let standingsPda: PublicKey;
+
+    let gamesPlayed = 0;
+    let lastReported: PublicKey;
+
+    // Play a game to `result`: the loser resigns, or White accepts Black's draw offer
+    const finishedGame = async (white: Keypair, black: Keypair, result: object) => {
+      const pdas = await startGame(`standings-${gamesPlayed++}-${Date.now()}`, defaultGameOptions(), white, black);
+      const isDraw = "draw" in result;
+      if (isDraw) await offerDraw(pdas.escrow, black);
+      const declarer = "white" in result ? black : white;
+      await program.methods
+        .declareResult(result, isDraw ? { agreement: {} } : { resignation: {} })
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: declarer.publicKey,
+          gameVault: pdas.vault,
+          playerWhite: white.publicKey,
+          playerBlack: black.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([declarer])
+        .rpc();
+      return pdas.escrow;
+    };
+
+    const reportGame = (gameEscrow: PublicKey, signer: Keypair = creator) =>
+      program.methods
+        .reportMatchResult()
+        .accounts({
+          tournament: tournamentPda,
+          standings: standingsPda,
+          gameEscrow,
+          creator: signer.publicKey,
+        })
+        .signers([signer])
+        .rpc();
+
+    const report = async (white: Keypair, black: Keypair, result: object) => {
+      lastReported = await finishedGame(white, black, result);
+      await reportGame(lastReported);
+    };
+
+    before(async () => {
+      creator = await fundedKeypair();
+      players = [];
+      for (let i = 0; i < 4; i++) players.push(await fundedKeypair());
+
+      [tournamentPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("tournament"), Buffer.from(tournamentId)],
+        program.programId
+      );
+      [tournamentVaultPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("tournament_vault"), tournamentPda.toBuffer()],
+        program.programId
+      );
+      [standingsPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("standings"), tournamentPda.toBuffer()],
+        program.programId
+      );
+
+      await program.methods
+        .createTournament(tournamentId, "Standings Cup", new anchor.BN(1000), 4, {
+          initialTime: new anchor.BN(600),
+          increment: new anchor.BN(0),
+          delay: new anchor.BN(0),
+          timeControlType: { rapid: {} },
+        }, {
+          seedingMode: { ratingAndForm: {} },
+          formWeightPct: 50,
+          // Five paid places and a pool the four entries can't cover
+          payoutPcts: [40, 25, 15, 10, 10],
+          guaranteedPool: new anchor.BN(10_000),
+          lateRegistrationSeconds: 0,
+        })
+        .accounts({
+          tournament: tournamentPda,
+          tournamentVault: tournamentVaultPda,
+          creator: creator.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([creator])
+        .rpc();
+
+      for (const player of players) {
+        await program.methods
+          .joinTournament()
+          .accounts({
+            tournament: tournamentPda,
+            tournamentVault: tournamentVaultPda,
+            player: player.publicKey,
+            systemProgram: SystemProgram.programId,
+            standings: null,
+          })
+          .signers([player])
+          .rpc();
+      }
+    });
+
+    const startTournamentIx = () =>
+      program.methods
+        .startTournament()
+        .accounts({
+          tournament: tournamentPda,
+          tournamentVault: tournamentVaultPda,
+          standings: standingsPda,
+          creator: creator.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([creator]);
+
+    it("should hold every entry fee in the vault", async () => {
+      const tournament = await program.account.tournament.fetch(tournamentPda);
+      const rentReserve = await provider.connection.getMinimumBalanceForRentExemption(0);
+      const vaultBalance = await provider.connection.getBalance(tournamentVaultPda);
+      assert.equal(tournament.currentParticipants, 4);
+      assert.equal(vaultBalance - rentReserve, tournament.entryFee.toNumber() * 4);
+    });
+
+    it("should refuse to start when the vault disagrees with the prize pool", async () => {
+      // Inflate the vault in the same transaction, so the failed start rolls it back too
+      const tx = new Transaction().add(
+        SystemProgram.transfer({
+          fromPubkey: creator.publicKey,
+          toPubkey: tournamentVaultPda,
+          lamports: 1000,
+        }),
+        await startTournamentIx().instruction()
+      );
+      try {
+        await provider.sendAndConfirm(tx, [creator]);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(String(error.logs ?? error), "PrizePoolMismatch");
+      }
+
+      const tournament = await program.account.tournament.fetch(tournamentPda);
+      assert.deepEqual(tournament.status, { registration: {} });
+    });
+
+    it("should start with the prize pool held in the vault", async () => {
+      await startTournamentIx().rpc();
+
+      const tournament = await program.account.tournament.fetch(tournamentPda);
+      const rentReserve = await provider.connection.getMinimumBalanceForRentExemption(0);
+      const vaultBalance = await provider.connection.getBalance(tournamentVaultPda);
+      assert.equal(tournament.prizePool.toNumber(), 4000);
+      assert.equal(vaultBalance - rentReserve, 4000);
+    });
+
+    it("should start every participant on zero points", async () => {
+      const standings = await program.account.tournamentStandings.fetch(standingsPda);
+      assert.equal(standings.entries.length, 4);
+      standings.entries.forEach((entry) => assert.equal(entry.points, 0));
+    });
+
+    it("should update standings after each reported match", async () => {
+      const [a, b, c, d] = players;
+
+      await report(a, b, { white: {} });
+      let standings = await program.account.tournamentStandings.fetch(standingsPda);
+      assert.equal(standings.entries[0].player.toString(), a.publicKey.toString());
+      assert.equal(standings.entries[0].points, 2);
+      assert.equal(standings.entries[0].wins, 1);
+
+      await report(c, d, { draw: {} });
+      await report(d, b, { black: {} });
+      standings = await program.account.tournamentStandings.fetch(standingsPda);
+
+      const byPlayer = new Map(standings.entries.map((e) => [e.player.toString(), e]));
+      assert.equal(byPlayer.get(a.publicKey.toString()).points, 2);
+      assert.equal(byPlayer.get(b.publicKey.toString()).points, 2);
+      assert.equal(byPlayer.get(b.publicKey.toString()).losses, 1);
+      assert.equal(byPlayer.get(c.publicKey.toString()).draws, 1);
+      assert.equal(byPlayer.get(d.publicKey.toString()).points, 1);
+    });
+
+    it("should order tied players by Sonneborn-Berger", async () => {
+      const [a, b] = players;
+      const standings = await program.account.tournamentStandings.fetch(standingsPda);
+      const points = standings.entries.map((e) => e.points);
+      assert.deepEqual(points, [...points].sort((x, y) => y - x));
+
+      // a and b are level on points and wins, but a beat b (2 points) while b
+      // beat d (1 point); scores are in quarter-points
+      const [first, second] = standings.entries;
+      assert.equal(first.player.toString(), a.publicKey.toString());
+      assert.equal(first.sonnebornBerger, 4);
+      assert.equal(second.player.toString(), b.publicKey.toString());
+      assert.equal(second.sonnebornBerger, 2);
+    });
+
+    it("should rank a finished round robin's co-leaders by Sonneborn-Berger", async () => {
+      const [a, b, c, d] = players;
+
+      // Complete the round robin: a-b, c-d and d-b were reported above
+      await report(a, c, { black: {} });
+      await report(a, d, { white: {} });
+      await report(b, c, { draw: {} });
+
+      const standings = await program.account.tournamentStandings.fetch(standingsPda);
+      assert.equal(standings.results.length, 6);
+      const order = standings.entries.map((e) => e.player.toString());
+      assert.deepEqual(order, [c, a, b, d].map((p) => p.publicKey.toString()));
+
+      // a and c both score 2/3; a has more wins, but c's results came against
+      // stronger opposition (SB 3 vs 2 points)
+      const [leader, runnerUp] = standings.entries;
+      assert.equal(leader.points, runnerUp.points);
+      assert.isBelow(leader.wins, runnerUp.wins);
+      assert.equal(leader.sonnebornBerger, 12);
+      assert.equal(runnerUp.sonnebornBerger, 8);
+    });
+
+    it("should reject a game whose result has already been reported", async () => {
+      try {
+        await reportGame(lastReported);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "MatchAlreadyReported");
+      }
+    });
+
+    it("should reject a game that hasn't finished", async () => {
+      const pdas = await startGame(`standings-open-${Date.now()}`, defaultGameOptions(), players[0], players[1]);
+      try {
+        await reportGame(pdas.escrow);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "GameNotFinished");
+      }
+    });
+
+    it("should reject results involving non-participants", async () => {
+      try {
+        await report(players[0], unauthorizedPlayer, { white: {} });
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "PlayerNotInTournament");
+      }
+    });
+
+    it("should reject results reported by anyone but the creator", async () => {
+      const game = await finishedGame(players[0], players[1], { white: {} });
+      try {
+        await reportGame(game, players[0]);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "ConstraintHasOne");
+      }
+    });
+
+    describe("seeding", () => {
+      const playerPda = (seed: string, player: Keypair) =>
+        PublicKey.findProgramAddressSync(
+          [Buffer.from(seed), player.publicKey.toBuffer()],
+          program.programId
+        )[0];
+
+      const seedingAccounts = () =>
+        players.flatMap((player) => [
+          { pubkey: playerPda("rating", player), isWritable: false, isSigner: false },
+          { pubkey: playerPda("stats", player), isWritable: false, isSigner: false },
+        ]);
+
+      const generateBrackets = () =>
+        program.methods
+          .generateBrackets()
+          .accounts({ tournament: tournamentPda, creator: creator.publicKey })
+          .remainingAccounts(seedingAccounts())
+          .signers([creator])
+          .rpc();
+
+      it("should seed an in-form player above inactive ones", async () => {
+        const [a, , c, d] = players;
+        await program.methods
+          .initializePlayerRating()
+          .accounts({ player: a.publicKey })
+          .signers([a])
+          .rpc();
+        for (const player of [c, d]) {
+          await program.methods
+            .initializePlayerStats()
+            .accounts({ player: player.publicKey })
+            .signers([player])
+            .rpc();
+        }
+
+        // c wins two stake games against d; a and b play none
+        for (let i = 0; i < 2; i++) {
+          const pdas = await startGame(`form-${i}-${Date.now()}`, defaultGameOptions(), c, d);
+          await program.methods
+            .declareResult({ white: {} }, { resignation: {} })
+            .accounts({
+              gameEscrow: pdas.escrow,
+              player: d.publicKey,
+              gameVault: pdas.vault,
+              playerWhite: c.publicKey,
+              playerBlack: d.publicKey,
+              feeCollector: feeCollector.publicKey,
+              systemProgram: SystemProgram.programId,
+              whiteStats: playerPda("stats", c),
+              blackStats: playerPda("stats", d),
+            })
+            .signers([d])
+            .rpc();
+        }
+
+        const cStats = await program.account.playerStats.fetch(playerPda("stats", c));
+        assert.equal(cStats.wins, 2);
+        assert.equal(cStats.recentGames, 2);
+        const aRating = await program.account.playerRating.fetch(playerPda("rating", a));
+        assert.equal(aRating.rating, 1500);
+
+        await generateBrackets();
+
+        // Everyone is still rated 1500, so form alone separates the field:
+        // c gains +200 at 50% weight, d loses 200, a and b tie on pubkey order
+        const tournament = await program.account.tournament.fetch(tournamentPda);
+        const seeds = tournament.seeds.map((key) => key.toString());
+        assert.equal(seeds.length, 4);
+        assert.equal(seeds[0], c.publicKey.toString());
+        assert.equal(seeds[3], d.publicKey.toString());
+        const middle = tournament.seeds.slice(1, 3).map((key) => key.toBuffer());
+        assert.isBelow(Buffer.compare(middle[0], middle[1]), 0);
+      });
+
+      it("should only generate brackets once", async () => {
+        try {
+          await generateBrackets();
+          assert.fail("Should have thrown an error");
+        } catch (error) {
+          assert.include(error.toString(), "BracketsAlreadyGenerated");
+        }
+      });
+
+      const reseedRound = (round: number) =>
+        program.methods
+          .reseedRound(round)
+          .accounts({ tournament: tournamentPda, standings: standingsPda, creator: creator.publicKey })
+          .signers([creator])
+          .rpc();
+
+      const pairingKeys = (pairings: { white: PublicKey; black: PublicKey }[]) =>
+        pairings.map((p) => [p.white.toString(), p.black.toString()]);
+
+      it("should re-seed by score, keeping seed order within a score group", async () => {
+        const [a, b, c, d] = players;
+
+        // Round robin so far: a 4, c 4, b 3, d 1 half-points; c was seeded above a
+        const [event] = await collectEvents("roundReseeded", () => reseedRound(1));
+        const keys = (list: Keypair[]) => list.map((p) => p.publicKey.toString());
+        assert.deepEqual(event.seeds.map((k) => k.toString()), keys([c, a, b, d]));
+        assert.deepEqual(pairingKeys(event.pairings), [keys([c, b]), keys([a, d])]);
+      });
+
+      it("should re-seed each round only once", async () => {
+        const [a, b, c, d] = players;
+        await report(b, a, { white: {} });
+
+        // Round 1 is already seeded, so b's extra win does not move it yet
+        await reseedRound(1);
+        let tournament = await program.account.tournament.fetch(tournamentPda);
+        assert.equal(tournament.seededRound, 1);
+        assert.equal(tournament.seeds[0].toString(), c.publicKey.toString());
+
+        // b now leads on 5, so round 2 pairs b with a and c with d
+        const [event] = await collectEvents("roundReseeded", () => reseedRound(2));
+        assert.deepEqual(pairingKeys(event.pairings), [
+          [b.publicKey.toString(), a.publicKey.toString()],
+          [c.publicKey.toString(), d.publicKey.toString()],
+        ]);
+        tournament = await program.account.tournament.fetch(tournamentPda);
+        assert.equal(tournament.seededRound, 2);
+      });
+
+      it("should reject skipping a round", async () => {
+        try {
+          await reseedRound(4);
+          assert.fail("Should have thrown an error");
+        } catch (error) {
+          assert.include(error.toString(), "RoundOutOfOrder");
+        }
+      });
+    });
+
+    describe("random brackets", () => {
+      const previewShuffle = async (seed: number[]) => {
+        const [event] = await collectEvents("bracketShufflePreview", () =>
+          program.methods.previewBracketShuffle(seed).accounts({ tournament: tournamentPda }).rpc()
+        );
+        return event.seeds.map((player: PublicKey) => player.toString());
+      };
+      const seedOf = (byte: number) => Array.from({ length: 32 }, (_, i) => (i === 0 ? byte : 0));
+
+      it("should shuffle the field the same way for the same seed", async () => {
+        const first = await previewShuffle(seedOf(7));
+        const second = await previewShuffle(seedOf(7));
+        assert.deepEqual(first, second);
+        assert.sameMembers(first, players.map((player) => player.publicKey.toString()));
+      });
+
+      it("should shuffle differently for different seeds", async () => {
+        const orders = new Set<string>();
+        for (let byte = 1; byte <= 4; byte++) {
+          orders.add((await previewShuffle(seedOf(byte))).join(","));
+        }
+        assert.isAbove(orders.size, 1);
+      });
+    });
+
+    describe("anti-cheat flags", () => {
+      const reportFlags = (gameEscrow: PublicKey) =>
+        program.methods
+          .reportTournamentFlag()
+          .accounts({ tournament: tournamentPda, gameEscrow, creator: creator.publicKey })
+          .signers([creator])
+          .rpc();
+
+      // A finished c-vs-d game where white makes `fastMoves` suspiciously quick moves
+      const flaggedGame = async (fastMoves: number) => {
+        const [, , c, d] = players;
+        const pdas = await startGame(`flags-${fastMoves}-${Date.now()}`, {
+          ...defaultGameOptions(),
+          suspiciousMoveIntervalMs: 1000,
+        }, c, d);
+        const moves: [Keypair, string, string, string][] = [
+          [c, "g1", "f3", "N"], [d, "g8", "f6", "N"],
+          [c, "f3", "g1", "N"], [d, "f6", "g8", "N"],
+        ];
+        for (const [player, from, to, piece] of moves) {
+          const fast = player === c && fastMoves-- > 0;
+          await recordMove(pdas.escrow, player, from, to, piece, { timeSpent: fast ? 200 : 1500 });
+        }
+        await program.methods
+          .declareResult({ black: {} }, { resignation: {} })
+          .accounts({
+            gameEscrow: pdas.escrow,
+            player: c.publicKey,
+            gameVault: pdas.vault,
+            playerWhite: c.publicKey,
+            playerBlack: d.publicKey,
+            feeCollector: feeCollector.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([c])
+          .rpc();
+        return pdas;
+      };
+
+      it("should add each linked game's flags to the participant's tally", async () => {
+        const [, , c] = players;
+        const first = await flaggedGame(1);
+        const second = await flaggedGame(2);
+
+        const [event] = await collectEvents("tournamentFlagReported", () => reportFlags(first.escrow));
+        assert.equal(event.player.toString(), c.publicKey.toString());
+        assert.equal(event.gameFlags, 1);
+        assert.equal(event.totalFlags, 1);
+
+        await reportFlags(second.escrow);
+        const tournament = await program.account.tournament.fetch(tournamentPda);
+        const tally = tournament.flagTallies.find((t) => t.player.equals(c.publicKey));
+        assert.equal(tally.flags, 3);
+        assert.lengthOf(tournament.flagTallies, 1);
+
+        const game = await program.account.gameEscrow.fetch(second.escrow);
+        assert.equal(game.tournamentId, tournamentId);
+        assert.isTrue(game.gameFlags.isTournamentGame);
+      });
+
+      it("should not count the same game twice", async () => {
+        const pdas = await flaggedGame(1);
+        await reportFlags(pdas.escrow);
+        try {
+          await reportFlags(pdas.escrow);
+          assert.fail("Should have thrown an error");
+        } catch (error) {
+          assert.include(error.toString(), "GameAlreadyLinked");
+        }
+      });
+
+      it("should reject games involving non-participants", async () => {
+        const pdas = await startGame(`flags-outsider-${Date.now()}`, defaultGameOptions());
+        await program.methods
+          .declareResult({ black: {} }, { resignation: {} })
+          .accounts({
+            gameEscrow: pdas.escrow,
+            player: playerWhite.publicKey,
+            gameVault: pdas.vault,
+            playerWhite: playerWhite.publicKey,
+            playerBlack: playerBlack.publicKey,
+            feeCollector: feeCollector.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([playerWhite])
+          .rpc();
+        try {
+          await reportFlags(pdas.escrow);
+          assert.fail("Should have thrown an error");
+        } catch (error) {
+          assert.include(error.toString(), "PlayerNotInTournament");
+        }
+      });
+    });
+
+    describe("layout migration", () => {
+      it("should stamp new tournaments with the current layout version", async () => {
+        const tournament = await program.account.tournament.fetch(tournamentPda);
+        assert.equal(tournament.tournamentVersion, 5);
+      });
+
+      it("should reject migrating a tournament already on the current layout", async () => {
+        const before = await program.account.tournament.fetch(tournamentPda);
+        try {
+          await program.methods
+            .migrateTournament()
+            .accounts({
+              tournament: tournamentPda,
+              creator: creator.publicKey,
+              systemProgram: SystemProgram.programId,
+            })
+            .signers([creator])
+            .rpc();
+          assert.fail("Should have thrown an error");
+        } catch (error) {
+          assert.include(error.toString(), "TournamentAlreadyMigrated");
+        }
+
+        const after = await program.account.tournament.fetch(tournamentPda);
+        assert.deepEqual(after.participants, before.participants);
+        assert.deepEqual(after.seeds, before.seeds);
+        assert.equal(after.seededRound, before.seededRound);
+      });
+    });
+
+    // Runs last: finalizing ends the tournament
+    const closeTournament = () =>
+      program.methods
+        .closeTournament()
+        .accounts({
+          tournament: tournamentPda,
+          tournamentVault: tournamentVaultPda,
+          standings: standingsPda,
+          creator: creator.publicKey,
+        })
+        .signers([creator])
+        .rpc();
+
+    describe("prize payouts", () => {
+      it("should refuse to close the tournament while prizes are unpaid", async () => {
+        try {
+          await closeTournament();
+          assert.fail("Should have thrown an error");
+        } catch (error) {
+          assert.include(error.toString(), "TournamentNotClosable");
+        }
+      });
+
+      it("should refuse to pay a non-participant listed among the winners", async () => {
+        const standings = await program.account.tournamentStandings.fetch(standingsPda);
+        const paid = standings.entries.slice(0, 4).map((entry) => entry.player);
+        paid[1] = Keypair.generate().publicKey;
+        try {
+          await program.methods
+            .finalizeTournament()
+            .accounts({
+              tournament: tournamentPda,
+              tournamentVault: tournamentVaultPda,
+              standings: standingsPda,
+              creator: creator.publicKey,
+              systemProgram: SystemProgram.programId,
+            })
+            .remainingAccounts(paid.map((pubkey) => ({ pubkey, isSigner: false, isWritable: true })))
+            .signers([creator])
+            .rpc();
+          assert.fail("Should have thrown an error");
+        } catch (error) {
+          assert.include(error.toString(), "PayoutAccountsMismatch");
+        }
+
+        const tournament = await program.account.tournament.fetch(tournamentPda);
+        assert.deepEqual(tournament.status, { active: {} });
+      });
+
+      it("should pay an under-subscribed field only the real pool", async () => {
+        const standings = await program.account.tournamentStandings.fetch(standingsPda);
+        const paid = standings.entries.slice(0, 4).map((entry) => entry.player);
+        const before = await Promise.all(paid.map((key) => provider.connection.getBalance(key)));
+        const vaultBefore = await provider.connection.getBalance(tournamentVaultPda);
+
+        const [finalized] = await collectEvents("tournamentFinalized", () =>
+          program.methods
+            .finalizeTournament()
+            .accounts({
+              tournament: tournamentPda,
+              tournamentVault: tournamentVaultPda,
+              standings: standingsPda,
+              creator: creator.publicKey,
+              systemProgram: SystemProgram.programId,
+            })
+            .remainingAccounts(paid.map((pubkey) => ({ pubkey, isSigner: false, isWritable: true })))
+            .signers([creator])
+            .rpc()
+        );
+
+        // 4 x 1000 paid in against 10_000 advertised; the fifth place's share is spread
+        // over the four players in proportion (40:25:15:10 of 4000)
+        assert.equal(finalized.prizePool.toNumber(), 4000);
+        assert.equal(finalized.shortfall.toNumber(), 6000);
+        const expected = [1779, 1111, 666, 444];
+        assert.deepEqual(finalized.payouts.map((amount) => amount.toNumber()), expected);
+        const after = await Promise.all(paid.map((key) => provider.connection.getBalance(key)));
+        after.forEach((balance, i) => assert.equal(balance - before[i], expected[i]));
+        assert.equal(vaultBefore - (await provider.connection.getBalance(tournamentVaultPda)), 4000);
+
+        const tournament = await program.account.tournament.fetch(tournamentPda);
+        assert.deepEqual(tournament.status, { finished: {} });
+
+        // The result stays queryable on the tournament after the event
+        assert.deepEqual(finalized.winners.map(String), paid.map(String));
+        assert.deepEqual(tournament.winners.map(String), paid.map(String));
+        assert.equal(tournament.finishedAt.toNumber(), finalized.finalizedAt.toNumber());
+      });
+    });
+
+    describe("closing", () => {
+      it("should return the finished tournament's rent to the creator", async () => {
+        const rentHeld = (
+          await Promise.all(
+            [tournamentPda, tournamentVaultPda, standingsPda].map((key) =>
+              provider.connection.getBalance(key)
+            )
+          )
+        ).reduce((sum, lamports) => sum + lamports, 0);
+        const creatorBefore = await provider.connection.getBalance(creator.publicKey);
+
+        const [closed] = await collectEvents("tournamentClosed", closeTournament);
+
+        assert.equal(closed.rentReclaimed.toNumber(), rentHeld);
+        const creatorAfter = await provider.connection.getBalance(creator.publicKey);
+        // The creator pays the transaction fee out of the reclaimed rent
+        assert.approximately(creatorAfter - creatorBefore, rentHeld, 10000);
+        assert.isNull(await provider.connection.getAccountInfo(tournamentPda));
+        assert.isNull(await provider.connection.getAccountInfo(standingsPda));
+        assert.equal(await provider.connection.getBalance(tournamentVaultPda), 0);
+      });
+    });
+  });
+
+  describe("tournament cancellation", () => {
+    const tournamentId = "called-off-open";
+    let creator: Keypair;
+    let players: Keypair[];
+    let tournamentPda: PublicKey;
+    let tournamentVaultPda: PublicKey;
+
+    const cancelTournament = (refunded: PublicKey[]) =>
+      program.methods
+        .cancelTournament()
+        .accounts({
+          tournament: tournamentPda,
+          tournamentVault: tournamentVaultPda,
+          creator: creator.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .remainingAccounts(refunded.map((pubkey) => ({ pubkey, isSigner: false, isWritable: true })))
+        .signers([creator])
+        .rpc();
+
+    before(async () => {
+      creator = await fundedKeypair();
+      players = [];
+      for (let i = 0; i < 3; i++) players.push(await fundedKeypair());
+
+      [tournamentPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("tournament"), Buffer.from(tournamentId)],
+        program.programId
+      );
+      [tournamentVaultPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("tournament_vault"), tournamentPda.toBuffer()],
+        program.programId
+      );
+
+      await program.methods
+        .createTournament(tournamentId, "Called Off Open", new anchor.BN(5000), 8, {
+          initialTime: new anchor.BN(600),
+          increment: new anchor.BN(0),
+          delay: new anchor.BN(0),
+          timeControlType: { rapid: {} },
+        }, {
+          seedingMode: { rating: {} },
+          formWeightPct: 0,
+          payoutPcts: [100],
+          guaranteedPool: new anchor.BN(0),
+          lateRegistrationSeconds: 0,
+        })
+        .accounts({
+          tournament: tournamentPda,
+          tournamentVault: tournamentVaultPda,
+          creator: creator.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([creator])
+        .rpc();
+
+      for (const player of players) {
+        await program.methods
+          .joinTournament()
+          .accounts({
+            tournament: tournamentPda,
+            tournamentVault: tournamentVaultPda,
+            player: player.publicKey,
+            systemProgram: SystemProgram.programId,
+            standings: null,
+          })
+          .signers([player])
+          .rpc();
+      }
+    });
+
+    it("should reject refund accounts that don't match the participants", async () => {
+      const refunded = players.map((player) => player.publicKey).reverse();
+      try {
+        await cancelTournament(refunded);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "PayoutAccountsMismatch");
+      }
+    });
+
+    it("should refund every participant's entry fee", async () => {
+      const refunded = players.map((player) => player.publicKey);
+      const before = await Promise.all(refunded.map((key) => provider.connection.getBalance(key)));
+
+      const [cancelled] = await collectEvents("tournamentCancelled", () => cancelTournament(refunded));
+
+      assert.equal(cancelled.entryFee.toNumber(), 5000);
+      assert.deepEqual(cancelled.refunded.map(String), refunded.map(String));
+      const after = await Promise.all(refunded.map((key) => provider.connection.getBalance(key)));
+      after.forEach((balance, i) => assert.equal(balance - before[i], 5000));
+      const rentReserve = await provider.connection.getMinimumBalanceForRentExemption(0);
+      assert.equal(await provider.connection.getBalance(tournamentVaultPda), rentReserve);
+      const tournament = await program.account.tournament.fetch(tournamentPda);
+      assert.deepEqual(tournament.status, { cancelled: {} });
+    });
+
+    it("should close the cancelled tournament and sweep its vault to the creator", async () => {
+      // Lamports sent to the vault after the refunds don't hold the tournament open
+      await provider.sendAndConfirm(
+        new Transaction().add(
+          SystemProgram.transfer({
+            fromPubkey: provider.wallet.publicKey,
+            toPubkey: tournamentVaultPda,
+            lamports: 1,
+          })
+        )
+      );
+
+      await program.methods
+        .closeTournament()
+        .accounts({
+          tournament: tournamentPda,
+          tournamentVault: tournamentVaultPda,
+          standings: null,
+          creator: creator.publicKey,
+        })
+        .signers([creator])
+        .rpc();
+
+      assert.equal(await provider.connection.getBalance(tournamentVaultPda), 0);
+      assert.isNull(await provider.connection.getAccountInfo(tournamentPda));
+    });
+  });
+
+  describe("move history retention", () => {
+    const playTenMoves = async (capacity: number) => {
+      const pdas = await startGame(`retention-${capacity}-${Date.now()}`, {
+        ...defaultGameOptions(),
+        moveHistoryCapacity: capacity,
+      });
+      const archived = await collectEvents("moveArchived", async () => {
+        for (const [player, from, to, piece] of knightShuffle(10)) {
+          await recordMove(pdas.escrow, player, from, to, piece);
+        }
+      });
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      return { game, archived };
+    };
+
+    it("should keep only the most recent 8 moves and emit the evicted ones", async () => {
+      const { game, archived } = await playTenMoves(8);
+
+      assert.equal(game.moveCount, 10);
+      assert.equal(game.moveHistory.length, 8);
+      assert.equal(game.moveHistory[0].moveNumber, 3);
+      assert.equal(game.moveHistory[7].moveNumber, 10);
+
+      assert.deepEqual(
+        archived.map((e) => e.record.moveNumber),
+        [1, 2]
+      );
+      assert.equal(archived[0].record.fromSquare, "g1");
+    });
+
+    it("should stop growing the account once the window is full", async () => {
+      const pdas = await startGame(`retention-size-${Date.now()}`, {
+        ...defaultGameOptions(),
+        moveHistoryCapacity: 4,
+      });
+      const sizes: number[] = [];
+      for (const [player, from, to, piece] of knightShuffle(12)) {
+        await recordMove(pdas.escrow, player, from, to, piece);
+        sizes.push((await provider.connection.getAccountInfo(pdas.escrow)).data.length);
+      }
+
+      // Growth stops at the fourth move and the game plays on past it
+      assert.isBelow(sizes[2], sizes[3]);
+      assert.equal(new Set(sizes.slice(3)).size, 1);
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.moveCount, 12);
+      assert.equal(game.moveHistory.length, 4);
+      assert.equal(game.moveHistory[3].moveNumber, 12);
+    });
+
+    it("should fit a full window in the space it reserves", async () => {
+      const pdas = await startGame(`retention-fill-${Date.now()}`, {
+        ...defaultGameOptions(),
+        moveHistoryCapacity: 16,
+      });
+      const initial = (await provider.connection.getAccountInfo(pdas.escrow)).data.length;
+      const sizes: number[] = [];
+      for (const [player, from, to, piece] of knightShuffle(16)) {
+        await recordMove(pdas.escrow, player, from, to, piece);
+        sizes.push((await provider.connection.getAccountInfo(pdas.escrow)).data.length);
+      }
+
+      // Every record grows the account by the same reserved amount, up to capacity
+      const recordSpace = sizes[0] - initial;
+      assert.isAbove(recordSpace, 0);
+      sizes.forEach((size, i) => assert.equal(size, initial + (i + 1) * recordSpace));
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.moveHistory.length, 16);
+      assert.equal(game.moveHistory[0].moveNumber, 1);
+    });
+
+    it("should keep the whole game when it fits in a 32 move window", async () => {
+      const { game, archived } = await playTenMoves(32);
+
+      assert.equal(game.moveHistory.length, 10);
+      assert.equal(game.moveHistory[0].moveNumber, 1);
+      assert.equal(archived.length, 0);
+    });
+
+    it("should reject a retention window beyond the account size budget", async () => {
+      const room = `retention-too-big-${Date.now()}`;
+      try {
+        await program.methods
+          .initializeGame(room, stakeAmount, timeLimitSeconds, {
+            ...defaultGameOptions(),
+            moveHistoryCapacity: 1000,
+          })
+          .accounts({
+            gameEscrow: deriveGamePdas(room).escrow,
+            player: playerWhite.publicKey,
+            feeCollector: feeCollector.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([playerWhite])
+          .rpc();
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "MoveHistoryCapacityTooLarge");
+      }
+    });
+  });
+
+  describe("capture validation", () => {
+    it("should accept a capture that matches the board", async () => {
+      const pdas = await startGame(`capture-ok-${Date.now()}`);
+      await playMoves(pdas.escrow, [
+        ["e2", "e4", "P"],
+        ["d7", "d5", "p"],
+        ["e4", "d5", "P", { captured: "p" }],
+      ]);
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.moveCount, 3);
+      assert.equal(pieceAt(game.board, "d5"), "P");
+      assert.equal(pieceAt(game.board, "e4"), "");
+    });
+
+    it("should reject a phantom capture on an empty square", async () => {
+      const pdas = await startGame(`capture-phantom-${Date.now()}`);
+      try {
+        await recordMove(pdas.escrow, playerWhite, "e2", "e4", "P", { captured: "p" });
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "CaptureMismatch");
+      }
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.moveCount, 0);
+    });
+
+    it("should reject an undeclared capture", async () => {
+      const pdas = await startGame(`capture-missing-${Date.now()}`);
+      await playMoves(pdas.escrow, [
+        ["e2", "e4", "P"],
+        ["d7", "d5", "p"],
+      ]);
+      try {
+        await recordMove(pdas.escrow, playerWhite, "e4", "d5", "P");
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "CaptureMismatch");
+      }
+    });
+
+    it("should validate an en-passant capture against the passed pawn", async () => {
+      const pdas = await startGame(`capture-ep-${Date.now()}`);
+      await playMoves(pdas.escrow, [
+        ["e2", "e4", "P"],
+        ["a7", "a6", "p"],
+        ["e4", "e5", "P"],
+        ["d7", "d5", "p"],
+        ["e5", "d6", "P", { captured: "p", enPassant: true }],
+      ]);
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(pieceAt(game.board, "d6"), "P");
+      assert.equal(pieceAt(game.board, "d5"), "");
+      assert.equal(pieceAt(game.board, "e5"), "");
+    });
+  });
+
+  describe("mutual_abort", () => {
+    const mutualAbort = (pdas: { escrow: PublicKey; vault: PublicKey }, black: Keypair = playerBlack) =>
+      program.methods
+        .mutualAbort()
+        .accounts({
+          gameEscrow: pdas.escrow,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: black.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite, black])
+        .rpc();
+
+    it("should refund both stakes in full and report the game as aborted", async () => {
+      const pdas = await startGame(`abort-${Date.now()}`);
+      await playMoves(pdas.escrow, [["e2", "e4", "P"]]);
+
+      const feeBefore = await provider.connection.getBalance(feeCollector.publicKey);
+      const [aborted] = await collectEvents("gameMutuallyAborted", () => mutualAbort(pdas));
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { cancelled: {} });
+      assert.deepEqual(game.winner, { aborted: {} });
+      assert.equal(game.fundsDistributed, true);
+
+      // Aborts are not raked
+      assert.equal(aborted.refundPerPlayer.toString(), stakeAmount.toString());
+      const feeAfter = await provider.connection.getBalance(feeCollector.publicKey);
+      assert.equal(feeAfter, feeBefore);
+      assert.equal(await provider.connection.getBalance(pdas.vault), 0);
+    });
+
+    it("should log one refund per deposited stake", async () => {
+      const pdas = await startGame(`abort-refund-events-${Date.now()}`);
+      const refunds = await collectEvents("depositRefunded", () => mutualAbort(pdas));
+
+      assert.lengthOf(refunds, 2);
+      assert.sameMembers(
+        refunds.map((refund) => refund.player.toBase58()),
+        [playerWhite.publicKey.toBase58(), playerBlack.publicKey.toBase58()]
+      );
+      refunds.forEach((refund) => assert.equal(refund.amount.toString(), stakeAmount.toString()));
+    });
+
+    it("should report a game cancelled before the start as aborted", async () => {
+      const room = `abort-cancel-${Date.now()}`;
+      const pdas = deriveGamePdas(room);
+      await program.methods
+        .initializeGame(room, stakeAmount, timeLimitSeconds, defaultGameOptions())
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerWhite.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+      await program.methods
+        .joinGame()
+        .accounts({ gameEscrow: pdas.escrow, player: playerBlack.publicKey, playerRating: null })
+        .signers([playerBlack])
+        .rpc();
+      await program.methods
+        .depositStake()
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerBlack.publicKey,
+          gameVault: pdas.vault,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerBlack])
+        .rpc();
+
+      const blackBefore = await provider.connection.getBalance(playerBlack.publicKey);
+      const [refund] = await collectEvents("depositRefunded", () =>
+        program.methods
+          .cancelGame()
+          .accounts({
+            gameEscrow: pdas.escrow,
+            player: playerWhite.publicKey,
+            gameVault: pdas.vault,
+            playerWhite: playerWhite.publicKey,
+            playerBlack: playerBlack.publicKey,
+            feeCollector: feeCollector.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([playerWhite])
+          .rpc()
+      );
+      assert.ok(refund.player.equals(playerBlack.publicKey));
+      assert.equal(refund.amount.toString(), stakeAmount.toString());
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.winner, { aborted: {} });
+      assert.equal(
+        (await provider.connection.getBalance(playerBlack.publicKey)) - blackBefore,
+        stakeAmount.toNumber()
+      );
+    });
+
+    it("should require both players to sign", async () => {
+      const pdas = await startGame(`abort-one-signer-${Date.now()}`);
+      try {
+        await program.methods
+          .mutualAbort()
+          .accounts({
+            gameEscrow: pdas.escrow,
+            gameVault: pdas.vault,
+            playerWhite: playerWhite.publicKey,
+            playerBlack: playerBlack.publicKey,
+            feeCollector: feeCollector.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([playerWhite])
+          .rpc();
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "Signature verification failed");
+      }
+    });
+
+    it("should reject an abort after the move cap", async () => {
+      const pdas = await startGame(`abort-late-${Date.now()}`);
+      for (const [player, from, to, piece] of knightShuffle(11)) {
+        await recordMove(pdas.escrow, player, from, to, piece);
+      }
+
+      try {
+        await mutualAbort(pdas);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "AbortWindowClosed");
+      }
+    });
+  });
+
+  describe("time-control aware anti-cheat timing", () => {
+    const halfSecondMove = async (timeControlType: object) => {
+      const pdas = await startGame(`timing-${Object.keys(timeControlType)[0]}-${Date.now()}`, {
+        ...defaultGameOptions(),
+        timeControlType,
+      });
+      await recordMove(pdas.escrow, playerWhite, "e2", "e4", "P", { timeSpent: 500 });
+      return program.account.gameEscrow.fetch(pdas.escrow);
+    };
+
+    it("should accept a 0.5s move in bullet", async () => {
+      const game = await halfSecondMove({ bullet: {} });
+      assert.equal(game.suspiciousMoveIntervalMs, 200);
+      assert.equal(game.antiCheatFlags, 0);
+      assert.equal(game.whiteFlagCount, 0);
+    });
+
+    it("should flag a 0.5s move in rapid", async () => {
+      const game = await halfSecondMove({ rapid: {} });
+      assert.equal(game.suspiciousMoveIntervalMs, 1000);
+      assert.equal(game.antiCheatFlags & 1, 1);
+      assert.equal(game.whiteFlagCount, 1);
+    });
+
+    it("should honour an explicit interval override", async () => {
+      const pdas = await startGame(`timing-override-${Date.now()}`, {
+        ...defaultGameOptions(),
+        timeControlType: { rapid: {} },
+        suspiciousMoveIntervalMs: 100,
+      });
+      await recordMove(pdas.escrow, playerWhite, "e2", "e4", "P", { timeSpent: 500 });
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.suspiciousMoveIntervalMs, 100);
+      assert.equal(game.antiCheatFlags, 0);
+    });
+  });
+
+  describe("opponent join guard", () => {
+    // black_deposited can only be set by the black player's own signature, so
+    // the closest reachable degenerate state is a creator who deposits before
+    // anyone has joined: player_black is still the default key.
+    it("should not start a game whose opponent slot is empty", async () => {
+      const room = `unjoined-${Date.now()}`;
+      const pdas = deriveGamePdas(room);
+      await program.methods
+        .initializeGame(room, stakeAmount, timeLimitSeconds, defaultGameOptions())
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerWhite.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+      const deposit = (player: Keypair) =>
+        program.methods
+          .depositStake()
+          .accounts({
+            gameEscrow: pdas.escrow,
+            player: player.publicKey,
+            gameVault: pdas.vault,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([player])
+          .rpc();
+
+      await deposit(playerWhite);
+      let game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { waitingForPlayers: {} });
+      assert.isTrue(game.playerBlack.equals(PublicKey.default));
+      assert.isFalse(game.blackDeposited);
+
+      try {
+        await deposit(playerWhite);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "AlreadyDeposited");
+      }
+      game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { waitingForPlayers: {} });
+
+      await program.methods
+        .joinGame()
+        .accounts({ gameEscrow: pdas.escrow, player: playerBlack.publicKey })
+        .signers([playerBlack])
+        .rpc();
+      await deposit(playerBlack);
+      game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { inProgress: {} });
+      assert.isTrue(game.playerBlack.equals(playerBlack.publicKey));
+    });
+  });
+
+  describe("board state events", () => {
+    const unpack = (packed: number[]) =>
+      packed.flatMap((byte) => [byte & 0x0f, byte >> 4]);
+
+    it("should emit the packed post-move board when enabled", async () => {
+      const pdas = await startGame(`board-state-${Date.now()}`, {
+        ...defaultGameOptions(),
+        emitBoardState: true,
+      });
+
+      const events = await collectEvents("boardState", () =>
+        playMoves(pdas.escrow, [
+          ["e2", "e4", "P"],
+          ["d7", "d5", "p"],
+          ["e4", "d5", "P", { captured: "p" }],
+        ])
+      );
+      assert.equal(events.length, 3);
+
+      const last = events.find((event) => event.moveCount === 3);
+      const board = unpack(last.board);
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(board, game.board);
+      assert.equal(pieceAt(board, "d5"), "P");
+      assert.equal(pieceAt(board, "e4"), "");
+      assert.equal(pieceAt(board, "e8"), "k");
+    });
+
+    it("should stay silent by default", async () => {
+      const pdas = await startGame(`board-state-off-${Date.now()}`);
+      const events = await collectEvents("boardState", () =>
+        recordMove(pdas.escrow, playerWhite, "e2", "e4", "P")
+      );
+      assert.equal(events.length, 0);
+    });
+  });
+
+  describe("move rate limiting", () => {
+    it("should reject a second move in the same second", async () => {
+      const pdas = await startGame(`rate-limit-${Date.now()}`);
+      await recordMove(pdas.escrow, playerWhite, "e2", "e4", "P");
+
+      // Both instructions in one transaction see the same clock
+      await waitForNextSecond(pdas.escrow);
+      const tx = new Transaction().add(
+        await (await recordMoveIx(pdas.escrow, playerBlack, "e7", "e5", "p")).instruction(),
+        await (await recordMoveIx(pdas.escrow, playerWhite, "g1", "f3", "N", { moveCount: 2 })).instruction()
+      );
+      try {
+        await provider.sendAndConfirm(tx, [playerWhite, playerBlack]);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(String(error.logs ?? error), "MoveTooSoon");
+      }
+
+      let game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.moveCount, 1);
+
+      // Spaced out, the same moves go through
+      await recordMove(pdas.escrow, playerBlack, "e7", "e5", "p");
+      await recordMove(pdas.escrow, playerWhite, "g1", "f3", "N");
+      game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.moveCount, 3);
+    });
+  });
+
+  describe("cross-asset stakes", () => {
+    const tokenStake = 500_000;
+    let mint: PublicKey;
+    let payer: Keypair;
+    const tokenAccounts = new Map<string, PublicKey>();
+    const tokenAccountOf = (owner: Keypair) => tokenAccounts.get(owner.publicKey.toString());
+    const tokenBalance = async (address: PublicKey) =>
+      Number((await getAccount(provider.connection, address)).amount);
+
+    before(async () => {
+      payer = (provider.wallet as anchor.Wallet).payer;
+      mint = await createMint(provider.connection, payer, payer.publicKey, null, 6);
+      for (const owner of [playerWhite, playerBlack, feeCollector]) {
+        const account = await getOrCreateAssociatedTokenAccount(
+          provider.connection,
+          payer,
+          mint,
+          owner.publicKey
+        );
+        tokenAccounts.set(owner.publicKey.toString(), account.address);
+      }
+      await mintTo(provider.connection, payer, mint, tokenAccountOf(playerBlack), payer, 10 * tokenStake);
+    });
+
+    const crossAssetOptions = () => ({
+      ...defaultGameOptions(),
+      crossAssetStake: { mint, amount: new anchor.BN(tokenStake) },
+    });
+
+    // White stakes SOL at creation, Black joins and stakes the token
+    const startCrossAssetGame = async (room: string, options = crossAssetOptions()) => {
+      const pdas = deriveGamePdas(room);
+      const vaultTokenAccount = getAssociatedTokenAddressSync(mint, pdas.vault, true);
+      await program.methods
+        .initializeGame(room, stakeAmount, timeLimitSeconds, options)
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerWhite.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+      await program.methods
+        .joinGame()
+        .accounts({ gameEscrow: pdas.escrow, player: playerBlack.publicKey })
+        .signers([playerBlack])
+        .rpc();
+      await program.methods
+        .depositStake()
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerWhite.publicKey,
+          gameVault: pdas.vault,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+      await program.methods
+        .depositTokenStake()
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerBlack.publicKey,
+          gameVault: pdas.vault,
+          mint,
+          playerTokenAccount: tokenAccountOf(playerBlack),
+          vaultTokenAccount,
+        })
+        .signers([playerBlack])
+        .rpc();
+      return { ...pdas, vaultTokenAccount };
+    };
+
+    const declare = (pdas: { escrow: PublicKey; vault: PublicKey }, declarer: Keypair, winner: object, reason: object) =>
+      program.methods
+        .declareResult(winner, reason)
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: declarer.publicKey,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([declarer])
+        .rpc();
+
+    const settleTokenStake = (pdas: { escrow: PublicKey; vault: PublicKey; vaultTokenAccount: PublicKey }) =>
+      program.methods
+        .settleTokenStake()
+        .accounts({
+          gameEscrow: pdas.escrow,
+          gameVault: pdas.vault,
+          mint,
+          vaultTokenAccount: pdas.vaultTokenAccount,
+          whiteTokenAccount: tokenAccountOf(playerWhite),
+          blackTokenAccount: tokenAccountOf(playerBlack),
+          feeTokenAccount: tokenAccountOf(feeCollector),
+        })
+        .rpc();
+
+    it("should pay the winner both the SOL and the token pot", async () => {
+      const pdas = await startCrossAssetGame(`cross-asset-${Date.now()}`);
+      let game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { inProgress: {} });
+      assert.equal(game.tokenTotalDeposited.toNumber(), tokenStake);
+
+      const blackSolBefore = await provider.connection.getBalance(playerBlack.publicKey);
+      const blackTokensBefore = await tokenBalance(tokenAccountOf(playerBlack));
+      const feeTokensBefore = await tokenBalance(tokenAccountOf(feeCollector));
+
+      await declare(pdas, playerWhite, { black: {} }, { resignation: {} });
+      await settleTokenStake(pdas);
+
+      const blackSolAfter = await provider.connection.getBalance(playerBlack.publicKey);
+      assert.equal(blackSolAfter - blackSolBefore, stakeAmount.toNumber() * 0.98);
+      assert.equal(
+        (await tokenBalance(tokenAccountOf(playerBlack))) - blackTokensBefore,
+        tokenStake * 0.98
+      );
+      assert.equal(
+        (await tokenBalance(tokenAccountOf(feeCollector))) - feeTokensBefore,
+        tokenStake * 0.02
+      );
+      assert.equal(await tokenBalance(pdas.vaultTokenAccount), 0);
+
+      game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.isTrue(game.tokenFundsDistributed);
+      try {
+        await settleTokenStake(pdas);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "FundsAlreadyDistributed");
+      }
+    });
+
+    it("should keep a game open until its token pot is settled", async () => {
+      const pdas = await startCrossAssetGame(`cross-asset-close-${Date.now()}`);
+      await declare(pdas, playerWhite, { black: {} }, { resignation: {} });
+      const closeGame = () =>
+        program.methods
+          .closeGame()
+          .accounts({ gameEscrow: pdas.escrow, rentBeneficiary: playerWhite.publicKey })
+          .rpc();
+
+      try {
+        await closeGame();
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "GameNotSettled");
+      }
+
+      await settleTokenStake(pdas);
+      await closeGame();
+      assert.isNull(await provider.connection.getAccountInfo(pdas.escrow));
+    });
+
+    it("should return each asset to its staker on a draw", async () => {
+      const pdas = await startCrossAssetGame(`cross-asset-draw-${Date.now()}`);
+      const whiteTokensBefore = await tokenBalance(tokenAccountOf(playerWhite));
+      const blackTokensBefore = await tokenBalance(tokenAccountOf(playerBlack));
+      const blackSolBefore = await provider.connection.getBalance(playerBlack.publicKey);
+
+      await offerDraw(pdas.escrow, playerBlack);
+      await declare(pdas, playerWhite, { draw: {} }, { agreement: {} });
+      await settleTokenStake(pdas);
+
+      assert.equal(await provider.connection.getBalance(playerBlack.publicKey), blackSolBefore);
+      assert.equal(await tokenBalance(tokenAccountOf(playerWhite)), whiteTokensBefore);
+      assert.equal(
+        (await tokenBalance(tokenAccountOf(playerBlack))) - blackTokensBefore,
+        tokenStake * 0.98
+      );
+    });
+
+    it("should charge the token pot on the game's fee terms", async () => {
+      // 5% winnings rake: Black's returned stake is free, White's won tokens are not
+      const options = { ...crossAssetOptions(), rakeMode: { winningsRake: {} }, feeBps: 500 };
+      for (const [label, winner, expectedFee] of [
+        ["black", { black: {} }, 0],
+        ["white", { white: {} }, tokenStake * 0.05],
+      ] as const) {
+        const pdas = await startCrossAssetGame(`cross-asset-rake-${label}-${Date.now()}`, options);
+        const loser = "white" in winner ? playerBlack : playerWhite;
+        const feeTokensBefore = await tokenBalance(tokenAccountOf(feeCollector));
+
+        await declare(pdas, loser, winner, { resignation: {} });
+        await settleTokenStake(pdas);
+
+        assert.equal(
+          (await tokenBalance(tokenAccountOf(feeCollector))) - feeTokensBefore,
+          expectedFee
+        );
+      }
+    });
+
+    it("should reject a SOL deposit from the token side", async () => {
+      const room = `cross-asset-sol-${Date.now()}`;
+      const pdas = deriveGamePdas(room);
+      await program.methods
+        .initializeGame(room, stakeAmount, timeLimitSeconds, crossAssetOptions())
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerWhite.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+      await program.methods
+        .joinGame()
+        .accounts({ gameEscrow: pdas.escrow, player: playerBlack.publicKey })
+        .signers([playerBlack])
+        .rpc();
+
+      try {
+        await program.methods
+          .depositStake()
+          .accounts({
+            gameEscrow: pdas.escrow,
+            player: playerBlack.publicKey,
+            gameVault: pdas.vault,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([playerBlack])
+          .rpc();
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "StakeAssetMismatch");
+      }
+    });
+
+    describe("time-locked winnings", () => {
+      const claimWinnings = (pdas: { escrow: PublicKey; vault: PublicKey; vaultTokenAccount: PublicKey }) =>
+        program.methods
+          .claimWinnings()
+          .accounts({
+            gameEscrow: pdas.escrow,
+            gameVault: pdas.vault,
+            mint,
+            vaultTokenAccount: pdas.vaultTokenAccount,
+            winnerTokenAccount: tokenAccountOf(playerWhite),
+            winner: playerWhite.publicKey,
+          })
+          .signers([playerWhite])
+          .rpc();
+
+      it("should hold a winner's tokens until the unlock time", async () => {
+        const pdas = await startCrossAssetGame(`winnings-lock-${Date.now()}`, {
+          ...crossAssetOptions(),
+          winningsLockSeconds: 2,
+        });
+        const whiteTokensBefore = await tokenBalance(tokenAccountOf(playerWhite));
+
+        await declare(pdas, playerBlack, { white: {} }, { resignation: {} });
+        const [locked] = await collectEvents("winningsLocked", () => settleTokenStake(pdas));
+        assert.equal(locked.amount.toNumber(), tokenStake * 0.98);
+        assert.equal(await tokenBalance(tokenAccountOf(playerWhite)), whiteTokensBefore);
+        assert.equal(await tokenBalance(pdas.vaultTokenAccount), tokenStake * 0.98);
+
+        try {
+          await claimWinnings(pdas);
+          assert.fail("Should have thrown an error");
+        } catch (error) {
+          assert.include(error.toString(), "WinningsStillLocked");
+        }
+
+        await new Promise((resolve) => setTimeout(resolve, 3000));
+        await claimWinnings(pdas);
+        assert.equal(
+          (await tokenBalance(tokenAccountOf(playerWhite))) - whiteTokensBefore,
+          tokenStake * 0.98
+        );
+
+        const game = await program.account.gameEscrow.fetch(pdas.escrow);
+        assert.equal(game.lockedWinnings.toNumber(), 0);
+        try {
+          await claimWinnings(pdas);
+          assert.fail("Should have thrown an error");
+        } catch (error) {
+          assert.include(error.toString(), "NoLockedWinnings");
+        }
+      });
+
+      it("should pay a winner immediately by default", async () => {
+        const pdas = await startCrossAssetGame(`winnings-unlocked-${Date.now()}`);
+        const whiteTokensBefore = await tokenBalance(tokenAccountOf(playerWhite));
+
+        await declare(pdas, playerBlack, { white: {} }, { resignation: {} });
+        await settleTokenStake(pdas);
+        assert.equal(
+          (await tokenBalance(tokenAccountOf(playerWhite))) - whiteTokensBefore,
+          tokenStake * 0.98
+        );
+      });
+
+      it("should only allow a lock on token-staked games", async () => {
+        try {
+          await startGame(`winnings-sol-${Date.now()}`, { ...defaultGameOptions(), winningsLockSeconds: 60 });
+          assert.fail("Should have thrown an error");
+        } catch (error) {
+          assert.include(error.toString(), "WinningsLockNeedsTokenStake");
+        }
+      });
+    });
+  });
+
+  describe("check_move_legal", () => {
+    const candidate = (from: string, to: string, piece: string, captured: string | null = null) => ({
+      fromSquare: from,
+      toSquare: to,
+      piece,
+      capturedPiece: captured,
+      isCastle: false,
+      isEnPassant: false,
+      isPromotion: false,
+      promotionPiece: null,
+    });
+
+    const errorCode = (name: string) =>
+      program.idl.errors.find((error) => error.name.toLowerCase() === name.toLowerCase()).code;
+
+    const verdict = async (escrow: PublicKey, move: ReturnType<typeof candidate>) => {
+      const [event] = await collectEvents("moveLegality", () =>
+        program.methods.checkMoveLegal(move).accounts({ gameEscrow: escrow }).rpc()
+      );
+      return event;
+    };
+
+    it("should report a legal candidate without recording it", async () => {
+      const pdas = await startGame(`legality-${Date.now()}`);
+      const event = await verdict(pdas.escrow, candidate("e2", "e4", "P"));
+
+      assert.isTrue(event.isLegal);
+      assert.equal(event.reasonCode, 0);
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.moveCount, 0);
+      assert.equal(pieceAt(game.board, "e2"), "P");
+    });
+
+    it("should report why an illegal candidate would be rejected", async () => {
+      const pdas = await startGame(`illegality-${Date.now()}`);
+
+      let event = await verdict(pdas.escrow, candidate("e2", "e4", "N"));
+      assert.isFalse(event.isLegal);
+      assert.equal(event.reasonCode, errorCode("PieceNotOnSquare"));
+
+      event = await verdict(pdas.escrow, candidate("e2", "e4", "P", "p"));
+      assert.isFalse(event.isLegal);
+      assert.equal(event.reasonCode, errorCode("CaptureMismatch"));
+
+      // The same move is refused by record_move with the same error
+      try {
+        await recordMove(pdas.escrow, playerWhite, "e2", "e4", "N");
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "PieceNotOnSquare");
+      }
+    });
+  });
+
+  describe("sealed-move adjournment", () => {
+    const submission = (from: string, to: string, piece: string, hash: number[] = Array(32).fill(0)) => ({
+      moveNotation: `${from}${to}`,
+      gamePositionHash: hash,
+      candidate: {
+        fromSquare: from,
+        toSquare: to,
+        piece,
+        capturedPiece: null,
+        isCastle: false,
+        isEnPassant: false,
+        isPromotion: false,
+        promotionPiece: null,
+      },
+      isCheck: false,
+      isCheckmate: false,
+    });
+
+    // Borsh layout of MoveSubmission, followed by the salt
+    const sealHash = (move: ReturnType<typeof submission>, salt: number[]) => {
+      const str = (value: string) => {
+        const bytes = Buffer.from(value);
+        const length = Buffer.alloc(4);
+        length.writeUInt32LE(bytes.length);
+        return Buffer.concat([length, bytes]);
+      };
+      const flag = (value: boolean) => Buffer.from([value ? 1 : 0]);
+      const { candidate } = move;
+      const encoded = Buffer.concat([
+        str(move.moveNotation),
+        Buffer.from(move.gamePositionHash),
+        str(candidate.fromSquare),
+        str(candidate.toSquare),
+        str(candidate.piece),
+        Buffer.from([0]), // captured_piece: None
+        flag(candidate.isCastle),
+        flag(candidate.isEnPassant),
+        flag(candidate.isPromotion),
+        Buffer.from([0]), // promotion_piece: None
+        flag(move.isCheck),
+        flag(move.isCheckmate),
+      ]);
+      return Array.from(createHash("sha256").update(encoded).update(Buffer.from(salt)).digest());
+    };
+
+    const salt = Array.from({ length: 32 }, (_, i) => i + 1);
+
+    const seal = (escrow: PublicKey, player: Keypair, hash: number[]) =>
+      program.methods
+        .sealMove(hash)
+        .accounts({ gameEscrow: escrow, player: player.publicKey })
+        .signers([player])
+        .rpc();
+
+    const resume = (escrow: PublicKey, player: Keypair, move: ReturnType<typeof submission>) =>
+      program.methods
+        .resumeFromSeal(move, salt)
+        .accounts({ gameEscrow: escrow, player: player.publicKey })
+        .signers([player])
+        .rpc();
+
+    it("should pause on seal and play the revealed move on resume", async () => {
+      const pdas = await startGame(`adjourn-${Date.now()}`);
+      const sealed = submission("e2", "e4", "P", await digestAfter(pdas.escrow, "e2", "e4", "P"));
+      await seal(pdas.escrow, playerWhite, sealHash(sealed, salt));
+
+      let game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { paused: {} });
+      try {
+        await recordMove(pdas.escrow, playerWhite, "d2", "d4", "P");
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "GameNotInProgress");
+      }
+
+      await resume(pdas.escrow, playerWhite, sealed);
+      game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { inProgress: {} });
+      assert.equal(game.moveCount, 1);
+      assert.equal(pieceAt(game.board, "e4"), "P");
+      assert.equal(game.moveHistory[0].toSquare, "e4");
+      assert.deepEqual(game.sealedMoveHash, Array(32).fill(0));
+
+      // Play carries on normally from the revealed move
+      await recordMove(pdas.escrow, playerBlack, "e7", "e5", "p");
+    });
+
+    it("should reject a reveal that differs from the sealed move", async () => {
+      const pdas = await startGame(`adjourn-mismatch-${Date.now()}`);
+      await seal(pdas.escrow, playerWhite, sealHash(submission("e2", "e4", "P"), salt));
+
+      try {
+        await resume(pdas.escrow, playerWhite, submission("d2", "d4", "P"));
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "SealedMoveMismatch");
+      }
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { paused: {} });
+      assert.equal(game.moveCount, 0);
+    });
+
+    it("should only let the player to move seal", async () => {
+      const pdas = await startGame(`adjourn-turn-${Date.now()}`);
+      try {
+        await seal(pdas.escrow, playerBlack, sealHash(submission("e7", "e5", "p"), salt));
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "NotPlayerTurn");
+      }
+    });
+  });
+
+  describe("early-resignation rating protection", () => {
+    const ratingPda = (player: Keypair) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("rating"), player.publicKey.toBuffer()],
+        program.programId
+      )[0];
+
+    // Fresh players at the default rating, then White resigns after `plies`
+    const ratedResignation = async (plies: number) => {
+      const white = await fundedKeypair();
+      const black = await fundedKeypair();
+      for (const player of [white, black]) {
+        await program.methods
+          .initializePlayerRating()
+          .accounts({ player: player.publicKey })
+          .signers([player])
+          .rpc();
+      }
+
+      const pdas = await startGame(
+        `early-resign-${plies}-${Date.now()}`,
+        { ...defaultGameOptions(), isRated: true, earlyResignationMoves: 10 },
+        white,
+        black
+      );
+      for (const [player, from, to, piece] of knightShuffle(plies, white, black)) {
+        await recordMove(pdas.escrow, player, from, to, piece);
+      }
+
+      await program.methods
+        .declareResult({ black: {} }, { resignation: {} })
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: white.publicKey,
+          gameVault: pdas.vault,
+          playerWhite: white.publicKey,
+          playerBlack: black.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+          whiteRating: ratingPda(white),
+          blackRating: ratingPda(black),
+        })
+        .signers([white])
+        .rpc();
+
+      const whiteRating = await program.account.playerRating.fetch(ratingPda(white));
+      const blackRating = await program.account.playerRating.fetch(ratingPda(black));
+      assert.equal(whiteRating.gamesPlayed, 1);
+      assert.equal(blackRating.gamesPlayed, 1);
+      return { white: whiteRating.rating - 1500, black: blackRating.rating - 1500 };
+    };
+
+    it("should halve rating changes for a resignation before the threshold", async () => {
+      const deltas = await ratedResignation(2);
+      assert.equal(deltas.white, -8);
+      assert.equal(deltas.black, 8);
+    });
+
+    it("should apply full rating changes once the threshold has passed", async () => {
+      const deltas = await ratedResignation(10);
+      assert.equal(deltas.white, -16);
+      assert.equal(deltas.black, 16);
+    });
+
+    it("should refuse to settle a rated game without rating accounts", async () => {
+      const pdas = await startGame(`rated-missing-${Date.now()}`, {
+        ...defaultGameOptions(),
+        isRated: true,
+      });
+      try {
+        await program.methods
+          .declareResult({ black: {} }, { resignation: {} })
+          .accounts({
+            gameEscrow: pdas.escrow,
+            player: playerWhite.publicKey,
+            gameVault: pdas.vault,
+            playerWhite: playerWhite.publicKey,
+            playerBlack: playerBlack.publicKey,
+            feeCollector: feeCollector.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([playerWhite])
+          .rpc();
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "RatingAccountMissing");
+      }
+    });
+  });
+
+  describe("program config fee collector", () => {
+    const createGame = async (room: string, collector: PublicKey | null) => {
+      const pdas = deriveGamePdas(room);
+      await program.methods
+        .initializeGame(room, stakeAmount, timeLimitSeconds, defaultGameOptions())
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerWhite.publicKey,
+          feeCollector: collector,
+          config: configPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+      return program.account.gameEscrow.fetch(pdas.escrow);
+    };
+
+    const setCollector = (collector: PublicKey) =>
+      program.methods
+        .updateConfig({ feeCollector: collector, feeExempt: null, referee: null, defaultRating: null, ratingFloorGap: null, ratedPairCooldownSeconds: null, minRatedInitialTime: null })
+        .accounts({ config: configPda, admin: provider.wallet.publicKey })
+        .rpc();
+
+    it("should inherit the config's collector when none is given", async () => {
+      const game = await createGame(`config-fee-${Date.now()}`, null);
+      assert.isTrue(game.feeCollector.equals(feeCollector.publicKey));
+    });
+
+    it("should apply a rotated collector only to games created afterwards", async () => {
+      const before = `config-before-${Date.now()}`;
+      await createGame(before, null);
+
+      const rotated = Keypair.generate().publicKey;
+      await setCollector(rotated);
+      try {
+        const existing = await program.account.gameEscrow.fetch(deriveGamePdas(before).escrow);
+        assert.isTrue(existing.feeCollector.equals(feeCollector.publicKey));
+
+        const inherited = await createGame(`config-after-${Date.now()}`, null);
+        assert.isTrue(inherited.feeCollector.equals(rotated));
+
+        // An explicit per-game collector still takes precedence
+        const explicit = await createGame(`config-explicit-${Date.now()}`, unauthorizedPlayer.publicKey);
+        assert.isTrue(explicit.feeCollector.equals(unauthorizedPlayer.publicKey));
+      } finally {
+        await setCollector(feeCollector.publicKey);
+      }
+    });
+
+    it("should only let the admin update the config", async () => {
+      try {
+        await program.methods
+          .updateConfig({ feeCollector: unauthorizedPlayer.publicKey, feeExempt: null, referee: null, defaultRating: null, ratingFloorGap: null, ratedPairCooldownSeconds: null, minRatedInitialTime: null })
+          .accounts({ config: configPda, admin: unauthorizedPlayer.publicKey })
+          .signers([unauthorizedPlayer])
+          .rpc();
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "UnauthorizedAdmin");
+      }
+    });
+  });
+
+  describe("en-passant target", () => {
+    it("should set the target on a double push and clear it on the next move", async () => {
+      const pdas = await startGame(`en-passant-${Date.now()}`);
+
+      await recordMove(pdas.escrow, playerWhite, "e2", "e4", "P");
+      let game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.enPassantSquare, squareIndex("e3"));
+
+      await recordMove(pdas.escrow, playerBlack, "g8", "f6", "n");
+      game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.isNull(game.enPassantSquare);
+
+      // A single pawn step never opens en passant
+      await recordMove(pdas.escrow, playerWhite, "d2", "d3", "P");
+      game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.isNull(game.enPassantSquare);
+    });
+
+    it("should only allow en passant immediately after the double push", async () => {
+      const pdas = await startGame(`en-passant-capture-${Date.now()}`);
+      await playMoves(pdas.escrow, [
+        ["e2", "e4", "P"],
+        ["a7", "a6", "p"],
+        ["e4", "e5", "P"],
+        ["d7", "d5", "p"],
+      ]);
+
+      let game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.enPassantSquare, squareIndex("d6"));
+
+      await recordMove(pdas.escrow, playerWhite, "e5", "d6", "P", {
+        captured: "p",
+        enPassant: true,
+      });
+      game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(pieceAt(game.board, "d6"), "P");
+      assert.equal(pieceAt(game.board, "d5"), "");
+      assert.isNull(game.enPassantSquare);
+    });
+
+    it("should reject en passant once the target has lapsed", async () => {
+      const pdas = await startGame(`en-passant-lapsed-${Date.now()}`);
+      await playMoves(pdas.escrow, [
+        ["e2", "e4", "P"],
+        ["a7", "a6", "p"],
+        ["e4", "e5", "P"],
+        ["d7", "d5", "p"],
+        ["g1", "f3", "N"],
+        ["a6", "a5", "p"],
+      ]);
+
+      try {
+        await recordMove(pdas.escrow, playerWhite, "e5", "d6", "P", {
+          captured: "p",
+          enPassant: true,
+        });
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "IllegalMove");
+      }
+    });
+
+    describe("without full validation", () => {
+      const optimisticGame = (label: string) =>
+        startGame(`en-passant-${label}-${Date.now()}`, {
+          ...defaultGameOptions(),
+          optimisticValidation: true,
+        });
+      const toFifthRank: [string, string, string][] = [
+        ["e2", "e4", "P"],
+        ["a7", "a6", "p"],
+        ["e4", "e5", "P"],
+      ];
+
+      it("should accept a legal en-passant capture", async () => {
+        const pdas = await optimisticGame("optimistic");
+        await playMoves(pdas.escrow, [...toFifthRank, ["d7", "d5", "p"]]);
+
+        await recordMove(pdas.escrow, playerWhite, "e5", "d6", "P", {
+          captured: "p",
+          enPassant: true,
+        });
+        const game = await program.account.gameEscrow.fetch(pdas.escrow);
+        assert.equal(pieceAt(game.board, "d6"), "P");
+        assert.equal(pieceAt(game.board, "d5"), "");
+        assert.isNull(game.enPassantSquare);
+      });
+
+      it("should reject en passant claimed a move too late", async () => {
+        const pdas = await optimisticGame("optimistic-late");
+        await playMoves(pdas.escrow, [
+          ...toFifthRank,
+          ["d7", "d5", "p"],
+          ["g1", "f3", "N"],
+          ["a6", "a5", "p"],
+        ]);
+
+        let game = await program.account.gameEscrow.fetch(pdas.escrow);
+        assert.isNull(game.enPassantSquare);
+        try {
+          await recordMove(pdas.escrow, playerWhite, "e5", "d6", "P", {
+            captured: "p",
+            enPassant: true,
+          });
+          assert.fail("Should have thrown an error");
+        } catch (error) {
+          assert.include(error.toString(), "IllegalMove");
+        }
+        game = await program.account.gameEscrow.fetch(pdas.escrow);
+        assert.equal(pieceAt(game.board, "d5"), "p");
+      });
+
+      it("should reject a capture onto a square the last push did not skip", async () => {
+        const pdas = await optimisticGame("optimistic-square");
+        await playMoves(pdas.escrow, [
+          ["e2", "e4", "P"],
+          ["d7", "d5", "p"],
+          ["e4", "e5", "P"],
+          ["f7", "f5", "p"],
+        ]);
+
+        // Black's last push skipped f6, not d6
+        try {
+          await recordMove(pdas.escrow, playerWhite, "e5", "d6", "P", {
+            captured: "p",
+            enPassant: true,
+          });
+          assert.fail("Should have thrown an error");
+        } catch (error) {
+          assert.include(error.toString(), "IllegalMove");
+        }
+      });
+
+      it("should require a pawn stepping onto the target to claim the capture", async () => {
+        const pdas = await optimisticGame("optimistic-unflagged");
+        await playMoves(pdas.escrow, [...toFifthRank, ["d7", "d5", "p"]]);
+
+        try {
+          await recordMove(pdas.escrow, playerWhite, "e5", "d6", "P");
+          assert.fail("Should have thrown an error");
+        } catch (error) {
+          assert.include(error.toString(), "IllegalMove");
+        }
+      });
+
+      it("should clear the target after any other reply", async () => {
+        const pdas = await optimisticGame("optimistic-clear");
+        await playMoves(pdas.escrow, [...toFifthRank, ["d7", "d5", "p"]]);
+
+        let game = await program.account.gameEscrow.fetch(pdas.escrow);
+        assert.equal(game.enPassantSquare, squareIndex("d6"));
+        await recordMove(pdas.escrow, playerWhite, "h2", "h3", "P");
+        game = await program.account.gameEscrow.fetch(pdas.escrow);
+        assert.isNull(game.enPassantSquare);
+      });
+    });
+  });
+
+  describe("castling", () => {
+    // Clears f1 and g1 for White, leaving both castling rights in place
+    const kingsideReady: [string, string, string][] = [
+      ["e2", "e4", "P"],
+      ["e7", "e5", "p"],
+      ["g1", "f3", "N"],
+      ["b8", "c6", "n"],
+      ["f1", "c4", "B"],
+      ["g8", "f6", "n"],
+    ];
+
+    it("should castle kingside and revoke White's rights", async () => {
+      const pdas = await startGame(`castle-kingside-${Date.now()}`);
+      await playMoves(pdas.escrow, kingsideReady);
+
+      await recordMove(pdas.escrow, playerWhite, "e1", "g1", "K", { castle: true });
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(pieceAt(game.board, "g1"), "K");
+      assert.equal(pieceAt(game.board, "f1"), "R");
+      assert.equal(pieceAt(game.board, "h1"), "");
+      assert.equal(game.castlingRights, 0b1100);
+    });
+
+    for (const optimisticValidation of [false, true]) {
+      it(`should reject castling through an attacked square${optimisticValidation ? " without full validation" : ""}`, async () => {
+        const pdas = await startGame(`castle-attacked-${optimisticValidation}-${Date.now()}`, {
+          ...defaultGameOptions(),
+          optimisticValidation,
+        });
+        // The a6 bishop eyes f1 along the diagonal e4 opened
+        await playMoves(pdas.escrow, [
+          ["e2", "e4", "P"],
+          ["b7", "b6", "p"],
+          ["g2", "g3", "P"],
+          ["c8", "a6", "b"],
+          ["f1", "g2", "B"],
+          ["b8", "c6", "n"],
+          ["g1", "f3", "N"],
+          ["g8", "f6", "n"],
+        ]);
+
+        try {
+          await recordMove(pdas.escrow, playerWhite, "e1", "g1", "K", { castle: true });
+          assert.fail("Should have thrown an error");
+        } catch (error) {
+          assert.include(error.toString(), "IllegalMove");
+        }
+        const game = await program.account.gameEscrow.fetch(pdas.escrow);
+        assert.equal(pieceAt(game.board, "e1"), "K");
+        assert.equal(pieceAt(game.board, "h1"), "R");
+      });
+    }
+
+    it("should refuse to castle once the king has moved", async () => {
+      const pdas = await startGame(`castle-king-moved-${Date.now()}`);
+      await playMoves(pdas.escrow, [
+        ...kingsideReady,
+        ["e1", "e2", "K"],
+        ["f8", "e7", "b"],
+        ["e2", "e1", "K"],
+        ["e8", "g8", "k", { castle: true }],
+      ]);
+
+      // King and rook are home again, but the right went with the king's first step
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.castlingRights, 0);
+      try {
+        await recordMove(pdas.escrow, playerWhite, "e1", "g1", "K", { castle: true });
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "IllegalMove");
+      }
+    });
+
+    it("should require the castle flag on the king's two-square step", async () => {
+      const pdas = await startGame(`castle-unflagged-${Date.now()}`, {
+        ...defaultGameOptions(),
+        optimisticValidation: true,
+      });
+      await playMoves(pdas.escrow, kingsideReady);
+
+      try {
+        await recordMove(pdas.escrow, playerWhite, "e1", "g1", "K");
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "IllegalMove");
+      }
+    });
+  });
+
+  describe("double flag timeouts", () => {
+    // Starts a one-second game and has White move on the last second of their
+    // clock, so White is left with zero time while Black's clock runs down
+    const flagWhiteOnLastSecond = async (doubleFlagRule: object) => {
+      for (let attempt = 0; attempt < 5; attempt++) {
+        const room = `double-flag-${Date.now()}`;
+        const pdas = deriveGamePdas(room);
+        await program.methods
+          .initializeGame(room, stakeAmount, new anchor.BN(1), {
+            ...defaultGameOptions(),
+            doubleFlagRule,
+          })
+          .accounts({
+            gameEscrow: pdas.escrow,
+            player: playerWhite.publicKey,
+            feeCollector: feeCollector.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([playerWhite])
+          .rpc();
+        await program.methods
+          .joinGame()
+          .accounts({ gameEscrow: pdas.escrow, player: playerBlack.publicKey })
+          .signers([playerBlack])
+          .rpc();
+        for (const player of [playerWhite, playerBlack]) {
+          await program.methods
+            .depositStake()
+            .accounts({
+              gameEscrow: pdas.escrow,
+              player: player.publicKey,
+              gameVault: pdas.vault,
+              systemProgram: SystemProgram.programId,
+            })
+            .signers([player])
+            .rpc();
+        }
+
+        const game = await program.account.gameEscrow.fetch(pdas.escrow);
+        for (;;) {
+          const blockTime = await provider.connection.getBlockTime(
+            await provider.connection.getSlot()
+          );
+          if (blockTime !== null && blockTime > game.lastMoveTime.toNumber()) break;
+          await new Promise((resolve) => setTimeout(resolve, 100));
+        }
+        try {
+          await (
+            await recordMoveIx(pdas.escrow, playerWhite, "e2", "e4", "P", { moveCount: game.moveCount })
+          ).rpc();
+        } catch (error) {
+          // Landed a second too late; try again with a fresh game
+          if (!error.toString().includes("MoveTimeExceeded")) throw error;
+          continue;
+        }
+
+        const afterMove = await program.account.gameEscrow.fetch(pdas.escrow);
+        assert.equal(afterMove.whiteTimeRemaining.toNumber(), 0);
+        await new Promise((resolve) => setTimeout(resolve, 3000));
+        return pdas;
+      }
+      throw new Error("could not land a move on the last second of the clock");
+    };
+
+    const claimTimeout = async (pdas: { escrow: PublicKey; vault: PublicKey }) => {
+      const claimant = await timeoutClaimant(pdas.escrow);
+      return program.methods
+        .handleTimeout()
+        .accounts({
+          gameEscrow: pdas.escrow,
+          claimant: claimant.publicKey,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([claimant])
+        .rpc();
+    };
+
+    it("should score a draw when both clocks are exhausted by default", async () => {
+      const pdas = await flagWhiteOnLastSecond({ draw: {} });
+      await claimTimeout(pdas);
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { finished: {} });
+      assert.deepEqual(game.winner, { draw: {} });
+    });
+
+    it("should award the last mover when configured", async () => {
+      const pdas = await flagWhiteOnLastSecond({ lastMoverWins: {} });
+      await claimTimeout(pdas);
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.winner, { white: {} });
+    });
+
+    it("should charge each move's think time to the mover's clock", async () => {
+      const pdas = await startGame(`clock-charge-${Date.now()}`);
+      const started = await program.account.gameEscrow.fetch(pdas.escrow);
+
+      await recordMove(pdas.escrow, playerWhite, "e2", "e4", "P");
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      const elapsed = game.lastMoveTime.toNumber() - started.lastMoveTime.toNumber();
+      assert.equal(game.whiteTimeRemaining.toNumber(), timeLimitSeconds.toNumber() - elapsed);
+      assert.equal(game.blackTimeRemaining.toNumber(), timeLimitSeconds.toNumber());
+    });
+
+    it("should reject a move reporting more time than is left on the clock", async () => {
+      const pdas = await startGame(`clock-overrun-${Date.now()}`);
+      const overrun = timeLimitSeconds.toNumber() * 1000 + 1001;
+      try {
+        await recordMove(pdas.escrow, playerWhite, "e2", "e4", "P", { timeSpent: overrun });
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "MoveTimeExceeded");
+      }
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.moveCount, 0);
+      assert.equal(game.whiteTimeRemaining.toNumber(), timeLimitSeconds.toNumber());
+    });
+
+    it("should allow a reported time within the grace period", async () => {
+      const pdas = await startGame(`clock-grace-${Date.now()}`);
+      const withinGrace = timeLimitSeconds.toNumber() * 1000 + 500;
+      await recordMove(pdas.escrow, playerWhite, "e2", "e4", "P", { timeSpent: withinGrace });
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.moveCount, 1);
+    });
+  });
+
+  describe("material lead events", () => {
+    it("should announce the first capture with the new balance", async () => {
+      const pdas = await startGame(`material-${Date.now()}`, {
+        ...defaultGameOptions(),
+        emitMaterialEvents: true,
+      });
+
+      const events = await collectEvents("materialLeadChanged", () =>
+        playMoves(pdas.escrow, [
+          ["e2", "e4", "P"],
+          ["d7", "d5", "p"],
+          ["e4", "d5", "P", { captured: "p" }],
+          ["d8", "d5", "q", { captured: "P" }],
+        ])
+      );
+      assert.equal(events.length, 2);
+
+      assert.equal(events[0].moveCount, 3);
+      assert.equal(events[0].balance, 1);
+      assert.isTrue(events[0].firstCapture);
+
+      // The recapture levels material, so the lead changes hands again
+      assert.equal(events[1].moveCount, 4);
+      assert.equal(events[1].balance, 0);
+      assert.isFalse(events[1].firstCapture);
+    });
+
+    it("should stay silent unless broadcasting is enabled", async () => {
+      const pdas = await startGame(`material-off-${Date.now()}`);
+      const events = await collectEvents("materialLeadChanged", () =>
+        playMoves(pdas.escrow, [
+          ["e2", "e4", "P"],
+          ["d7", "d5", "p"],
+          ["e4", "d5", "P", { captured: "p" }],
+        ])
+      );
+      assert.equal(events.length, 0);
+    });
+  });
+
+  describe("disable_anti_cheat", () => {
+    const disableAntiCheat = (escrow: PublicKey) =>
+      program.methods
+        .disableAntiCheat()
+        .accounts({
+          gameEscrow: escrow,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+        })
+        .signers([playerWhite, playerBlack])
+        .rpc();
+
+    it("should clear anti-cheat when both players sign", async () => {
+      const pdas = await startGame(`no-anti-cheat-${Date.now()}`);
+      await recordMove(pdas.escrow, playerWhite, "g1", "f3", "N", { timeSpent: 5000 });
+      await recordMove(pdas.escrow, playerBlack, "g8", "f6", "n", { timeSpent: 0 });
+      let game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.antiCheatFlags, 1);
+
+      const events = await collectEvents("antiCheatDisabled", () => disableAntiCheat(pdas.escrow));
+      assert.equal(events.length, 1);
+      assert.equal(events[0].moveCount, 2);
+
+      game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.isFalse(game.gameFlags.enableAntiCheat);
+      assert.equal(game.antiCheatFlags, 0);
+
+      // Instant replies are no longer flagged
+      await recordMove(pdas.escrow, playerWhite, "f3", "g1", "N", { timeSpent: 0 });
+      game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.antiCheatFlags, 0);
+      assert.equal(game.whiteFlagCount, 0);
+    });
+
+    it("should keep anti-cheat on in rated games", async () => {
+      await ensurePlayerRating(playerBlack);
+      const pdas = await startGame(`rated-anti-cheat-${Date.now()}`, {
+        ...defaultGameOptions(),
+        isRated: true,
+      });
+      try {
+        await disableAntiCheat(pdas.escrow);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "RatedGameAntiCheat");
+      }
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.isTrue(game.gameFlags.enableAntiCheat);
+    });
+  });
+
+  describe("checkmate winner", () => {
+    it("should award Black a mate delivered by a black piece", async () => {
+      const pdas = await startGame(`fools-mate-${Date.now()}`);
+      await playMoves(pdas.escrow, [
+        ["f2", "f3", "P"],
+        ["e7", "e5", "p"],
+        ["g2", "g4", "P"],
+        ["d8", "h4", "q", { check: true, checkmate: true }],
+      ]);
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { finished: {} });
+      assert.deepEqual(game.winner, { black: {} });
+    });
+
+    it("should award White a mate delivered by a white piece", async () => {
+      const pdas = await startGame(`scholars-mate-${Date.now()}`);
+      await playMoves(pdas.escrow, [
+        ["e2", "e4", "P"],
+        ["e7", "e5", "p"],
+        ["f1", "c4", "B"],
+        ["b8", "c6", "n"],
+        ["d1", "h5", "Q"],
+        ["g8", "f6", "n"],
+        ["h5", "f7", "Q", { captured: "p", check: true, checkmate: true }],
+      ]);
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { finished: {} });
+      assert.deepEqual(game.winner, { white: {} });
+    });
+
+    it("should accept a back-rank mate", async () => {
+      const pdas = await startGame(`back-rank-mate-${Date.now()}`);
+      // Black's back rank is emptied towards h8 while the queen, d7, e7 and f7 box the king in
+      await playMoves(pdas.escrow, [
+        ["e2", "e3", "P"],
+        ["g7", "g6", "p"],
+        ["d1", "h5", "Q"],
+        ["f8", "g7", "b"],
+        ["h5", "h7", "Q", { captured: "p" }],
+        ["g7", "h6", "b"],
+        ["a2", "a3", "P"],
+        ["h6", "g5", "b"],
+        ["a3", "a4", "P"],
+        ["g8", "f6", "n"],
+        ["a4", "a5", "P"],
+        ["f6", "d5", "n"],
+        ["h7", "h8", "Q", { captured: "r", check: true, checkmate: true }],
+      ]);
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { finished: {} });
+      assert.deepEqual(game.winner, { white: {} });
+    });
+
+    it("should reject a checkmate claim the board does not bear out", async () => {
+      const pdas = await startGame(`false-mate-${Date.now()}`);
+      await playMoves(pdas.escrow, [
+        ["e2", "e4", "P"],
+        ["e7", "e5", "p"],
+        ["d1", "h5", "Q"],
+        ["b8", "c6", "n"],
+      ]);
+      // Check, but the king simply takes the unprotected queen
+      try {
+        await recordMove(pdas.escrow, playerWhite, "h5", "f7", "Q", {
+          captured: "p",
+          check: true,
+          checkmate: true,
+        });
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "NotCheckmate");
+      }
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { inProgress: {} });
+      assert.equal(game.moveCount, 4);
+    });
+
+    it("should reject a declared mate or flag the game does not bear out", async () => {
+      const pdas = await startGame(`false-declared-mate-${Date.now()}`);
+      await playMoves(pdas.escrow, [
+        ["e2", "e4", "P"],
+        ["e7", "e5", "p"],
+      ]);
+      const declare = (winner: object, reason: object, declarer: Keypair) =>
+        program.methods
+          .declareResult(winner, reason)
+          .accounts({
+            gameEscrow: pdas.escrow,
+            player: declarer.publicKey,
+            gameVault: pdas.vault,
+            playerWhite: playerWhite.publicKey,
+            playerBlack: playerBlack.publicKey,
+            feeCollector: feeCollector.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([declarer])
+          .rpc();
+
+      for (const [winner, reason, declarer, expected] of [
+        [{ white: {} }, { checkmate: {} }, playerWhite, "NotCheckmate"],
+        [{ black: {} }, { timeout: {} }, playerBlack, "TimeNotExceeded"],
+      ] as [object, object, Keypair, string][]) {
+        try {
+          await declare(winner, reason, declarer);
+          assert.fail("Should have thrown an error");
+        } catch (error) {
+          assert.include(error.toString(), expected);
+        }
+      }
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { inProgress: {} });
+    });
+  });
+
+  describe("move history rent", () => {
+    it("should grow the escrow one record per move with the mover paying the rent", async () => {
+      const pdas = await startGame(`history-rent-${Date.now()}`, {
+        ...defaultGameOptions(),
+        moveHistoryCapacity: 8,
+      });
+      const accountSize = async () =>
+        (await provider.connection.getAccountInfo(pdas.escrow)).data.length;
+      const initialSize = await accountSize();
+
+      const whiteBefore = await provider.connection.getBalance(playerWhite.publicKey);
+      await recordMove(pdas.escrow, playerWhite, "g1", "f3", "N");
+      const whiteAfter = await provider.connection.getBalance(playerWhite.publicKey);
+      const recordSpace = (await accountSize()) - initialSize;
+      assert.isAbove(recordSpace, 0);
+
+      // The mover covered the added rent on top of the transaction fee
+      const addedRent =
+        (await provider.connection.getMinimumBalanceForRentExemption(initialSize + recordSpace)) -
+        (await provider.connection.getMinimumBalanceForRentExemption(initialSize));
+      assert.isAtLeast(whiteBefore - whiteAfter, addedRent);
+
+      // A long game keeps growing until the window is full, then stays put
+      for (const [player, from, to, piece] of knightShuffle(15).slice(1)) {
+        await recordMove(pdas.escrow, player, from, to, piece);
+      }
+      const finalSize = await accountSize();
+      assert.equal(finalSize, initialSize + 8 * recordSpace);
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.moveCount, 15);
+      assert.equal(game.moveHistory.length, 8);
+      const balance = await provider.connection.getBalance(pdas.escrow);
+      assert.isAtLeast(
+        balance,
+        await provider.connection.getMinimumBalanceForRentExemption(finalSize)
+      );
+    });
+  });
+
+  describe("armageddon games", () => {
+    const declareDraw = async (pdas: { escrow: PublicKey; vault: PublicKey }) => {
+      await offerDraw(pdas.escrow, playerBlack);
+      return program.methods
+        .declareResult({ draw: {} }, { agreement: {} })
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerWhite.publicKey,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+    };
+
+    it("should give Black less time on the clock", async () => {
+      const pdas = await startGame(`armageddon-clock-${Date.now()}`, {
+        ...defaultGameOptions(),
+        armageddon: true,
+      });
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.isTrue(game.armageddon);
+      assert.equal(game.whiteTimeRemaining.toNumber(), timeLimitSeconds.toNumber());
+      assert.equal(game.blackTimeRemaining.toNumber(), (timeLimitSeconds.toNumber() * 80) / 100);
+    });
+
+    it("should award Black a drawn game", async () => {
+      const pdas = await startGame(`armageddon-draw-${Date.now()}`, {
+        ...defaultGameOptions(),
+        armageddon: true,
+      });
+      await playMoves(pdas.escrow, [
+        ["e2", "e4", "P"],
+        ["e7", "e5", "p"],
+      ]);
+
+      const blackBefore = await provider.connection.getBalance(playerBlack.publicKey);
+      await declareDraw(pdas);
+      const blackAfter = await provider.connection.getBalance(playerBlack.publicKey);
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { finished: {} });
+      assert.deepEqual(game.winner, { black: {} });
+      // Black takes the whole pot less the platform fee
+      assert.isAbove(blackAfter - blackBefore, stakeAmount.toNumber());
+    });
+
+    it("should keep draws as draws in normal games", async () => {
+      const pdas = await startGame(`armageddon-off-${Date.now()}`);
+      await declareDraw(pdas);
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.winner, { draw: {} });
+    });
+  });
+
+  describe("incremental board snapshot", () => {
+    // Client-side replay of a move list from the starting position
+    const replay = (history: any[]) => {
+      const board = Array(64).fill(0);
+      "RNBQKBNR".split("").forEach((piece, file) => {
+        board[file] = PIECE_CODES.indexOf(piece);
+        board[8 + file] = PIECE_CODES.indexOf("P");
+        board[48 + file] = PIECE_CODES.indexOf("p");
+        board[56 + file] = PIECE_CODES.indexOf(piece.toLowerCase());
+      });
+      for (const record of history) {
+        const from = squareIndex(record.fromSquare);
+        const to = squareIndex(record.toSquare);
+        if (record.isEnPassant) board[Math.floor(from / 8) * 8 + (to % 8)] = 0;
+        if (record.isCastle) {
+          const [rookFrom, rookTo] = { 6: [7, 5], 2: [0, 3], 62: [63, 61], 58: [56, 59] }[to];
+          board[rookTo] = board[rookFrom];
+          board[rookFrom] = 0;
+        }
+        board[to] = record.isPromotion ? PIECE_CODES.indexOf(record.promotionPiece) : board[from];
+        board[from] = 0;
+      }
+      return board;
+    };
+
+    it("should match a full replay of the game after every move", async () => {
+      const pdas = await startGame(`snapshot-${Date.now()}`);
+      const moves: [string, string, string, MoveExtras?][] = [
+        ["e2", "e4", "P"],
+        ["e7", "e5", "p"],
+        ["g1", "f3", "N"],
+        ["b8", "c6", "n"],
+        ["f1", "c4", "B"],
+        ["f8", "c5", "b"],
+        ["e1", "g1", "K", { castle: true }],
+        ["g8", "f6", "n"],
+        ["d2", "d4", "P"],
+        ["e5", "d4", "p", { captured: "P" }],
+        ["f3", "d4", "N", { captured: "p" }],
+      ];
+
+      for (const [i, [from, to, piece, extra]] of moves.entries()) {
+        const player = i % 2 === 0 ? playerWhite : playerBlack;
+        await recordMove(pdas.escrow, player, from, to, piece, extra ?? {});
+
+        const game = await program.account.gameEscrow.fetch(pdas.escrow);
+        assert.equal(game.moveHistory.length, i + 1);
+        assert.deepEqual(game.board, replay(game.moveHistory));
+      }
+    });
+  });
+
+  describe("auto-draw on repetition", () => {
+    it("should warn on the second occurrence and draw on the third", async () => {
+      const pdas = await startGame(`repetition-${Date.now()}`, {
+        ...defaultGameOptions(),
+        autoDrawOnRepetition: true,
+      });
+
+      // Each knight tour of four plies returns to the starting position
+      const warnings = await collectEvents("repetitionWarning", async () => {
+        for (const [player, from, to, piece] of knightShuffle(7)) {
+          await recordMove(pdas.escrow, player, from, to, piece);
+        }
+      });
+      assert.deepEqual(
+        warnings.map((event) => event.moveCount),
+        [4, 5, 6, 7]
+      );
+      let game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { inProgress: {} });
+
+      const finished = await collectEvents("gameFinished", () =>
+        recordMove(pdas.escrow, playerBlack, "f6", "g8", "n")
+      );
+      assert.equal(finished.length, 1);
+      assert.deepEqual(finished[0].reason, { repetition: {} });
+
+      game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { finished: {} });
+      assert.deepEqual(game.winner, { draw: {} });
+      assert.equal(game.moveCount, 8);
+
+      await settleGame(pdas);
+      assert.equal(await provider.connection.getBalance(pdas.vault), 0);
+    });
+
+    it("should leave repeated positions alone by default", async () => {
+      const pdas = await startGame(`repetition-off-${Date.now()}`);
+      for (const [player, from, to, piece] of knightShuffle(8)) {
+        await recordMove(pdas.escrow, player, from, to, piece);
+      }
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { inProgress: {} });
+    });
+  });
+
+  describe("rated game joins", () => {
+    const createRatedGame = async (room: string) => {
+      const pdas = deriveGamePdas(room);
+      await program.methods
+        .initializeGame(room, stakeAmount, timeLimitSeconds, {
+          ...defaultGameOptions(),
+          isRated: true,
+        })
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerWhite.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+      return pdas;
+    };
+
+    const join = (escrow: PublicKey, player: Keypair, playerRating: PublicKey | null) =>
+      program.methods
+        .joinGame()
+        .accounts({ gameEscrow: escrow, player: player.publicKey, playerRating })
+        .signers([player])
+        .rpc();
+
+    it("should require the joining player's rating account", async () => {
+      const black = await fundedKeypair();
+      const pdas = await createRatedGame(`rated-join-missing-${Date.now()}`);
+      try {
+        await join(pdas.escrow, black, null);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "RatingAccountMissing");
+      }
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { waitingForPlayers: {} });
+    });
+
+    it("should accept a joining player who has a rating account", async () => {
+      const black = await fundedKeypair();
+      await ensurePlayerRating(black);
+      const pdas = await createRatedGame(`rated-join-${Date.now()}`);
+      await join(pdas.escrow, black, ratingPdaOf(black.publicKey));
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.isTrue(game.playerBlack.equals(black.publicKey));
+      assert.deepEqual(game.gameState, { waitingForDeposits: {} });
+    });
+  });
+
+  describe("active game cap", () => {
+    let player: Keypair;
+    const games: { escrow: PublicKey; vault: PublicKey }[] = [];
+    const statsPda = (key: PublicKey) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from("stats"), key.toBuffer()],
+        program.programId
+      )[0];
+
+    const activeGames = async () =>
+      (await program.account.playerStats.fetch(statsPda(player.publicKey))).activeGames;
+
+    // Black's deposit starts the game, counting it against White's cap
+    const depositBlack = (pdas: { escrow: PublicKey; vault: PublicKey }) =>
+      program.methods
+        .depositStake()
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerBlack.publicKey,
+          gameVault: pdas.vault,
+          systemProgram: SystemProgram.programId,
+          whiteStats: statsPda(player.publicKey),
+        })
+        .signers([playerBlack])
+        .rpc();
+
+    const openGame = async (room: string) => {
+      const pdas = deriveGamePdas(room);
+      await program.methods
+        .initializeGame(room, stakeAmount, timeLimitSeconds, defaultGameOptions())
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: player.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([player])
+        .rpc();
+      await program.methods
+        .joinGame()
+        .accounts({ gameEscrow: pdas.escrow, player: playerBlack.publicKey })
+        .signers([playerBlack])
+        .rpc();
+      await program.methods
+        .depositStake()
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: player.publicKey,
+          gameVault: pdas.vault,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([player])
+        .rpc();
+      return pdas;
+    };
+
+    before(async () => {
+      player = await fundedKeypair(20);
+      await program.methods
+        .initializePlayerStats()
+        .accounts({ player: player.publicKey })
+        .signers([player])
+        .rpc();
+    });
+
+    it("should reject starting a game beyond the cap until one finishes", async () => {
+      for (let i = 0; i < 5; i++) {
+        const pdas = await openGame(`active-cap-${i}-${Date.now()}`);
+        await depositBlack(pdas);
+        games.push(pdas);
+      }
+      assert.equal(await activeGames(), 5);
+
+      const sixth = await openGame(`active-cap-5-${Date.now()}`);
+      try {
+        await depositBlack(sixth);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "TooManyActiveGames");
+      }
+
+      // Black resigns the first game, which frees a slot
+      await program.methods
+        .declareResult({ white: {} }, { resignation: {} })
+        .accounts({
+          gameEscrow: games[0].escrow,
+          player: playerBlack.publicKey,
+          gameVault: games[0].vault,
+          playerWhite: player.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+          whiteStats: statsPda(player.publicKey),
+        })
+        .signers([playerBlack])
+        .rpc();
+      assert.equal(await activeGames(), 4);
+
+      await depositBlack(sixth);
+      const game = await program.account.gameEscrow.fetch(sixth.escrow);
+      assert.deepEqual(game.gameState, { inProgress: {} });
+      assert.equal(await activeGames(), 5);
+    });
+
+    it("should need the counted player's stats to settle a finished game", async () => {
+      const pdas = await openGame(`active-cap-release-${Date.now()}`);
+      // A mutual abort also frees a slot so this game can start
+      await program.methods
+        .mutualAbort()
+        .accounts({
+          gameEscrow: games[1].escrow,
+          gameVault: games[1].vault,
+          playerWhite: player.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+          whiteStats: statsPda(player.publicKey),
+          blackStats: null,
+        })
+        .signers([player, playerBlack])
+        .rpc();
+      assert.equal(await activeGames(), 4);
+
+      await depositBlack(pdas);
+      try {
+        await program.methods
+          .declareResult({ white: {} }, { resignation: {} })
+          .accounts({
+            gameEscrow: pdas.escrow,
+            player: playerBlack.publicKey,
+            gameVault: pdas.vault,
+            playerWhite: player.publicKey,
+            playerBlack: playerBlack.publicKey,
+            feeCollector: feeCollector.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([playerBlack])
+          .rpc();
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "PlayerStatsMissing");
+      }
+    });
+
+    it("should count and record a game for a player with stats the caller didn't name", async () => {
+      const tracked = await fundedKeypair(5);
+      await program.methods
+        .initializePlayerStats()
+        .accounts({ player: tracked.publicKey })
+        .signers([tracked])
+        .rpc();
+
+      // startGame leaves the stats accounts to be derived from the game
+      const pdas = await startGame(`stats-derived-${Date.now()}`, defaultGameOptions(), tracked, playerBlack);
+      let stats = await program.account.playerStats.fetch(statsPda(tracked.publicKey));
+      assert.equal(stats.activeGames, 1);
+
+      await program.methods
+        .declareResult({ white: {} }, { resignation: {} })
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerBlack.publicKey,
+          gameVault: pdas.vault,
+          playerWhite: tracked.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+          whiteStats: statsPda(tracked.publicKey),
+        })
+        .signers([playerBlack])
+        .rpc();
+      stats = await program.account.playerStats.fetch(statsPda(tracked.publicKey));
+      assert.equal(stats.activeGames, 0);
+      assert.equal(stats.wins, 1);
+    });
+  });
+
+  describe("deposit progress events", () => {
+    it("should announce both deposits exactly once, on the second deposit", async () => {
+      const room = `both-deposits-${Date.now()}`;
+      const pdas = deriveGamePdas(room);
+      await program.methods
+        .initializeGame(room, stakeAmount, timeLimitSeconds, defaultGameOptions())
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerWhite.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+      await program.methods
+        .joinGame()
+        .accounts({ gameEscrow: pdas.escrow, player: playerBlack.publicKey })
+        .signers([playerBlack])
+        .rpc();
+
+      const deposit = (player: Keypair) =>
+        program.methods
+          .depositStake()
+          .accounts({
+            gameEscrow: pdas.escrow,
+            player: player.publicKey,
+            gameVault: pdas.vault,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([player])
+          .rpc();
+
+      const afterFirst = await collectEvents("bothDepositsReceived", () => deposit(playerWhite));
+      assert.equal(afterFirst.length, 0);
+
+      const afterSecond = await collectEvents("bothDepositsReceived", () => deposit(playerBlack));
+      assert.equal(afterSecond.length, 1);
+      assert.equal(afterSecond[0].roomId, room);
+      assert.equal(afterSecond[0].totalPot.toString(), stakeAmount.muln(2).toString());
+      assert.equal(afterSecond[0].tokenPot.toNumber(), 0);
+    });
+  });
+
+  describe("fee-exempt players", () => {
+    let sponsored: Keypair;
+
+    const setFeeExempt = (players: PublicKey[]) =>
+      program.methods
+        .updateConfig({ feeCollector: null, feeExempt: players, referee: null, defaultRating: null, ratingFloorGap: null, ratedPairCooldownSeconds: null, minRatedInitialTime: null })
+        .accounts({ config: configPda, admin: provider.wallet.publicKey })
+        .rpc();
+
+    // Plays a game to a White win by resignation and returns the fee collected
+    const feeForWhiteWin = async (white: Keypair) => {
+      const pdas = await startGame(`fee-exempt-${Date.now()}`, defaultGameOptions(), white);
+      const before = await provider.connection.getBalance(feeCollector.publicKey);
+      await program.methods
+        .declareResult({ white: {} }, { resignation: {} })
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerBlack.publicKey,
+          gameVault: pdas.vault,
+          playerWhite: white.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+          config: configPda,
+        })
+        .signers([playerBlack])
+        .rpc();
+      return (await provider.connection.getBalance(feeCollector.publicKey)) - before;
+    };
+
+    before(async () => {
+      sponsored = await fundedKeypair();
+      await setFeeExempt([sponsored.publicKey]);
+    });
+
+    after(async () => {
+      await setFeeExempt([]);
+    });
+
+    it("should waive the fee when either player is exempt", async () => {
+      assert.equal(await feeForWhiteWin(sponsored), 0);
+    });
+
+    it("should charge the standard fee otherwise", async () => {
+      const fee = await feeForWhiteWin(playerWhite);
+      assert.equal(fee, (2 * stakeAmount.toNumber() * 2) / 100);
+    });
+
+    it("should bound the exempt list", async () => {
+      const tooMany = Array.from({ length: 17 }, () => Keypair.generate().publicKey);
+      try {
+        await setFeeExempt(tooMany);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "TooManyFeeExemptPlayers");
+      }
+    });
+  });
+
+  describe("referee-signed results", () => {
+    const referee = Keypair.generate();
+
+    // Escrow address, then the winner and reason enum indexes
+    const refereeMessage = (escrow: PublicKey, winner: number, reason: number) =>
+      Buffer.concat([escrow.toBuffer(), Buffer.from([winner, reason])]);
+
+    const settleBySignature = (pdas: { escrow: PublicKey; vault: PublicKey }, signer: Keypair, message: Buffer) =>
+      program.methods
+        .declareResultSigned({ black: {} }, { forfeit: {} })
+        .accounts({
+          gameEscrow: pdas.escrow,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          config: configPda,
+          instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+          systemProgram: SystemProgram.programId,
+        })
+        .preInstructions([
+          Ed25519Program.createInstructionWithPrivateKey({ privateKey: signer.secretKey, message }),
+        ])
+        .rpc();
+
+    before(async () => {
+      await program.methods
+        .updateConfig({ feeCollector: null, feeExempt: null, referee: referee.publicKey, defaultRating: null, ratingFloorGap: null, ratedPairCooldownSeconds: null, minRatedInitialTime: null })
+        .accounts({ config: configPda, admin: provider.wallet.publicKey })
+        .rpc();
+    });
+
+    after(async () => {
+      await program.methods
+        .updateConfig({ feeCollector: null, feeExempt: null, referee: PublicKey.default, defaultRating: null, ratingFloorGap: null, ratedPairCooldownSeconds: null, minRatedInitialTime: null })
+        .accounts({ config: configPda, admin: provider.wallet.publicKey })
+        .rpc();
+    });
+
+    it("should settle the game on a valid referee signature", async () => {
+      const pdas = await startGame(`referee-${Date.now()}`);
+      const blackBefore = await provider.connection.getBalance(playerBlack.publicKey);
+
+      // GameWinner::Black = 2, GameEndReason::Forfeit = 6
+      await settleBySignature(pdas, referee, refereeMessage(pdas.escrow, 2, 6));
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { finished: {} });
+      assert.deepEqual(game.winner, { black: {} });
+      assert.isTrue(game.fundsDistributed);
+      const pot = 2 * stakeAmount.toNumber();
+      assert.equal(
+        (await provider.connection.getBalance(playerBlack.publicKey)) - blackBefore,
+        pot - (pot * 2) / 100
+      );
+    });
+
+    it("should reject a result signed by anyone else", async () => {
+      const pdas = await startGame(`referee-forged-${Date.now()}`);
+      try {
+        await settleBySignature(pdas, Keypair.generate(), refereeMessage(pdas.escrow, 2, 6));
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "InvalidRefereeSignature");
+      }
+    });
+
+    it("should reject a referee signature over a different outcome", async () => {
+      const pdas = await startGame(`referee-outcome-${Date.now()}`);
+      try {
+        // Signed for a White win, submitted as a Black win
+        await settleBySignature(pdas, referee, refereeMessage(pdas.escrow, 1, 6));
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "InvalidRefereeSignature");
+      }
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { inProgress: {} });
+    });
+  });
+
+  describe("result moderators", () => {
+    const moderator = Keypair.generate();
+
+    const signedBlackForfeit = (pdas: { escrow: PublicKey; vault: PublicKey }, signer: Keypair) =>
+      program.methods
+        .declareResultSigned({ black: {} }, { forfeit: {} })
+        .accounts({
+          gameEscrow: pdas.escrow,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          config: configPda,
+          instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+          systemProgram: SystemProgram.programId,
+        })
+        .preInstructions([
+          Ed25519Program.createInstructionWithPrivateKey({
+            privateKey: signer.secretKey,
+            // GameWinner::Black = 2, GameEndReason::Forfeit = 6
+            message: Buffer.concat([pdas.escrow.toBuffer(), Buffer.from([2, 6])]),
+          }),
+        ])
+        .rpc();
+
+    before(async () => {
+      await program.methods
+        .addModerator(moderator.publicKey)
+        .accounts({ config: configPda, admin: provider.wallet.publicKey })
+        .rpc();
+    });
+
+    it("should let a listed moderator settle a game", async () => {
+      const pdas = await startGame(`moderator-${Date.now()}`);
+      await signedBlackForfeit(pdas, moderator);
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { finished: {} });
+      assert.deepEqual(game.winner, { black: {} });
+      assert.isTrue(game.fundsDistributed);
+    });
+
+    it("should reject a key that is not listed", async () => {
+      const pdas = await startGame(`moderator-unlisted-${Date.now()}`);
+      try {
+        await signedBlackForfeit(pdas, Keypair.generate());
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "InvalidRefereeSignature");
+      }
+    });
+
+    it("should only let the admin manage the list", async () => {
+      try {
+        await program.methods
+          .addModerator(unauthorizedPlayer.publicKey)
+          .accounts({ config: configPda, admin: unauthorizedPlayer.publicKey })
+          .signers([unauthorizedPlayer])
+          .rpc();
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "UnauthorizedAdmin");
+      }
+    });
+
+    it("should stop accepting a removed moderator", async () => {
+      await program.methods
+        .removeModerator(moderator.publicKey)
+        .accounts({ config: configPda, admin: provider.wallet.publicKey })
+        .rpc();
+      const config = await program.account.programConfig.fetch(configPda);
+      assert.lengthOf(config.moderators, 0);
+
+      const pdas = await startGame(`moderator-removed-${Date.now()}`);
+      try {
+        await signedBlackForfeit(pdas, moderator);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "RefereeNotRegistered");
+      }
+    });
+  });
+
+  describe("check queries", () => {
+    const checkStatus = async (escrow: PublicKey) => {
+      const [event] = await collectEvents("checkStatus", () =>
+        program.methods.isInCheck().accounts({ gameEscrow: escrow }).rpc()
+      );
+      return event;
+    };
+
+    it("should report check after a queen lands on the king's diagonal", async () => {
+      const pdas = await startGame(`in-check-${Date.now()}`);
+      await playMoves(pdas.escrow, [
+        ["e2", "e4", "P"],
+        ["f7", "f5", "p"],
+        ["d1", "h5", "Q"],
+      ]);
+
+      const event = await checkStatus(pdas.escrow);
+      assert.equal(event.moveCount, 3);
+      assert.isFalse(event.whiteToMove);
+      assert.isTrue(event.inCheck);
+    });
+
+    it("should report no check when the line is blocked", async () => {
+      const pdas = await startGame(`not-in-check-${Date.now()}`);
+      await playMoves(pdas.escrow, [
+        ["e2", "e4", "P"],
+        ["e7", "e5", "p"],
+        ["d1", "h5", "Q"],
+      ]);
+
+      const event = await checkStatus(pdas.escrow);
+      assert.isFalse(event.whiteToMove);
+      assert.isFalse(event.inCheck);
+    });
+  });
+
+  describe("minimum moves before a result", () => {
+    const options = () => ({ ...defaultGameOptions(), minMovesBeforeResult: 4 });
+
+    const declare = (pdas: { escrow: PublicKey; vault: PublicKey }, declarer: Keypair, winner: object, reason: object) =>
+      program.methods
+        .declareResult(winner, reason)
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: declarer.publicKey,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([declarer])
+        .rpc();
+
+    it("should reject an agreed draw before the minimum", async () => {
+      const pdas = await startGame(`min-moves-early-${Date.now()}`, options());
+      for (const [player, from, to, piece] of knightShuffle(3)) {
+        await recordMove(pdas.escrow, player, from, to, piece);
+      }
+
+      try {
+        await offerDraw(pdas.escrow, playerBlack);
+        await declare(pdas, playerWhite, { draw: {} }, { agreement: {} });
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "TooFewMoves");
+      }
+    });
+
+    it("should allow an agreed draw once the minimum is reached", async () => {
+      const pdas = await startGame(`min-moves-reached-${Date.now()}`, options());
+      for (const [player, from, to, piece] of knightShuffle(4)) {
+        await recordMove(pdas.escrow, player, from, to, piece);
+      }
+
+      await offerDraw(pdas.escrow, playerBlack);
+      await declare(pdas, playerWhite, { draw: {} }, { agreement: {} });
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.winner, { draw: {} });
+    });
+
+    it("should still allow resigning straight away", async () => {
+      const pdas = await startGame(`min-moves-resign-${Date.now()}`, options());
+
+      await declare(pdas, playerBlack, { white: {} }, { resignation: {} });
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.winner, { white: {} });
+    });
+  });
+
+  describe("rating recalculation", () => {
+    let hero: Keypair;
+    let villain: Keypair;
+    const history: { game: PublicKey; opponentRating: number; outcome: object; earlyResignation: boolean }[] = [];
+
+    const recalc = (games: typeof history) =>
+      program.methods
+        .recalcRatingFromGames(1500, games)
+        .accounts({
+          config: configPda,
+          admin: provider.wallet.publicKey,
+          playerRating: ratingPdaOf(hero.publicKey),
+        })
+        .rpc();
+
+    // Hero plays White; `declarer` ends the game with `winner` for `reason`
+    const ratedGame = async (declarer: Keypair, winner: object, reason: object, outcome: object) => {
+      const pdas = await startGame(
+        `recalc-${history.length}-${Date.now()}`,
+        { ...defaultGameOptions(), isRated: true },
+        hero,
+        villain
+      );
+      // An agreed draw stands on the opponent's offer
+      if ("agreement" in reason) {
+        await offerDraw(pdas.escrow, declarer === hero ? villain : hero);
+      }
+      await program.methods
+        .declareResult(winner, reason)
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: declarer.publicKey,
+          gameVault: pdas.vault,
+          playerWhite: hero.publicKey,
+          playerBlack: villain.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+          whiteRating: ratingPdaOf(hero.publicKey),
+          blackRating: ratingPdaOf(villain.publicKey),
+        })
+        .signers([declarer])
+        .rpc();
+
+      // The escrow keeps both pre-game ratings for exactly this kind of audit
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      history.push({ game: pdas.escrow, opponentRating: game.ratingBlack, outcome, earlyResignation: false });
+    };
+
+    before(async () => {
+      hero = await fundedKeypair();
+      villain = await fundedKeypair();
+      await ensurePlayerRating(hero);
+      await ensurePlayerRating(villain);
+
+      await ratedGame(villain, { white: {} }, { resignation: {} }, { win: {} });
+      await ratedGame(hero, { black: {} }, { resignation: {} }, { loss: {} });
+      await ratedGame(hero, { draw: {} }, { agreement: {} }, { draw: {} });
+    });
+
+    it("should reproduce the incremental rating from the game history", async () => {
+      const incremental = (await program.account.playerRating.fetch(ratingPdaOf(hero.publicKey))).rating;
+      await recalc(history);
+      const recalculated = await program.account.playerRating.fetch(ratingPdaOf(hero.publicKey));
+      assert.equal(recalculated.rating, incremental);
+      assert.equal(recalculated.gamesPlayed, 3);
+    });
+
+    it("should apply an overturned result without compounding on repeat", async () => {
+      const overturned = history.map((game, i) => (i === 1 ? { ...game, outcome: { win: {} } } : game));
+      await recalc(overturned);
+      const first = (await program.account.playerRating.fetch(ratingPdaOf(hero.publicKey))).rating;
+      await recalc(overturned);
+      const second = (await program.account.playerRating.fetch(ratingPdaOf(hero.publicKey))).rating;
+
+      assert.isAbove(first, 1500 + 16);
+      assert.equal(second, first);
+    });
+
+    it("should reject a game listed twice", async () => {
+      try {
+        await recalc([...history, history[0]]);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "DuplicateRecalcGame");
+      }
+    });
+
+    it("should only let the admin recalculate", async () => {
+      try {
+        await program.methods
+          .recalcRatingFromGames(1500, history)
+          .accounts({
+            config: configPda,
+            admin: hero.publicKey,
+            playerRating: ratingPdaOf(hero.publicKey),
+          })
+          .signers([hero])
+          .rpc();
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "UnauthorizedAdmin");
+      }
+    });
+  });
+
+  describe("berserk", () => {
+    const berserk = (escrow: PublicKey, player: Keypair) =>
+      program.methods
+        .berserk()
+        .accounts({ gameEscrow: escrow, player: player.publicKey })
+        .signers([player])
+        .rpc();
+
+    it("should halve the berserker's clock only", async () => {
+      const pdas = await startGame(`berserk-clock-${Date.now()}`);
+      await berserk(pdas.escrow, playerWhite);
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.isTrue(game.whiteBerserk);
+      assert.isFalse(game.blackBerserk);
+      assert.equal(game.whiteTimeRemaining.toNumber(), timeLimitSeconds.toNumber() / 2);
+      assert.equal(game.blackTimeRemaining.toNumber(), timeLimitSeconds.toNumber());
+    });
+
+    it("should only allow berserking before the player's first move", async () => {
+      const pdas = await startGame(`berserk-late-${Date.now()}`);
+      await recordMove(pdas.escrow, playerWhite, "e2", "e4", "P");
+
+      // Black has not moved yet, White has
+      await berserk(pdas.escrow, playerBlack);
+      try {
+        await berserk(pdas.escrow, playerWhite);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "BerserkTooLate");
+      }
+    });
+
+    it("should boost the berserker's rating gain on a win", async () => {
+      const white = await fundedKeypair();
+      const black = await fundedKeypair();
+      await ensurePlayerRating(white);
+      await ensurePlayerRating(black);
+      const pdas = await startGame(
+        `berserk-win-${Date.now()}`,
+        { ...defaultGameOptions(), isRated: true },
+        white,
+        black
+      );
+      await berserk(pdas.escrow, white);
+
+      await program.methods
+        .declareResult({ white: {} }, { resignation: {} })
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: black.publicKey,
+          gameVault: pdas.vault,
+          playerWhite: white.publicKey,
+          playerBlack: black.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+          whiteRating: ratingPdaOf(white.publicKey),
+          blackRating: ratingPdaOf(black.publicKey),
+        })
+        .signers([black])
+        .rpc();
+
+      // Equal ratings: a normal win is +16, a berserk win 150% of that
+      const whiteRating = await program.account.playerRating.fetch(ratingPdaOf(white.publicKey));
+      const blackRating = await program.account.playerRating.fetch(ratingPdaOf(black.publicKey));
+      assert.equal(whiteRating.rating, 1524);
+      assert.equal(blackRating.rating, 1484);
+    });
+  });
+
+  describe("draw claims", () => {
+    const claimDraw = (escrow: PublicKey, player: Keypair, claim: object = { repetition: {} }) =>
+      program.methods
+        .claimDraw(claim)
+        .accounts({ gameEscrow: escrow, player: player.publicKey })
+        .signers([player])
+        .rpc();
+
+    // Eight knight moves bring the starting position round for the third time, White to move
+    const threefold = async (room: string, drawClaims: object) => {
+      const pdas = await startGame(`${room}-${Date.now()}`, { ...defaultGameOptions(), drawClaims });
+      for (const [player, from, to, piece] of knightShuffle(8)) {
+        await recordMove(pdas.escrow, player, from, to, piece);
+      }
+      return pdas;
+    };
+
+    const expectClaimError = async (claim: Promise<unknown>, code: string) => {
+      try {
+        await claim;
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), code);
+      }
+    };
+
+    it("should let the player on move claim when restricted to the side to move", async () => {
+      const pdas = await threefold("claim-on-turn", { sideToMove: {} });
+      await claimDraw(pdas.escrow, playerWhite);
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { finished: {} });
+      assert.deepEqual(game.winner, { draw: {} });
+    });
+
+    it("should refuse an off-turn claim when restricted to the side to move", async () => {
+      const pdas = await threefold("claim-off-turn", { sideToMove: {} });
+      await expectClaimError(claimDraw(pdas.escrow, playerBlack), "NotYourClaim");
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { inProgress: {} });
+    });
+
+    it("should accept on-turn and off-turn claims when unrestricted", async () => {
+      const offTurn = await threefold("claim-any-off", { anyPlayer: {} });
+      await claimDraw(offTurn.escrow, playerBlack);
+      const onTurn = await threefold("claim-any-on", { anyPlayer: {} });
+      await claimDraw(onTurn.escrow, playerWhite);
+
+      for (const pdas of [offTurn, onTurn]) {
+        const game = await program.account.gameEscrow.fetch(pdas.escrow);
+        assert.deepEqual(game.winner, { draw: {} });
+      }
+    });
+
+    it("should reject claims the position does not support", async () => {
+      const pdas = await startGame(`claim-early-${Date.now()}`, {
+        ...defaultGameOptions(),
+        drawClaims: { anyPlayer: {} },
+      });
+      for (const [player, from, to, piece] of knightShuffle(4)) {
+        await recordMove(pdas.escrow, player, from, to, piece);
+      }
+
+      await expectClaimError(claimDraw(pdas.escrow, playerWhite), "DrawClaimInvalid");
+      await expectClaimError(claimDraw(pdas.escrow, playerWhite, { fiftyMoves: {} }), "DrawClaimInvalid");
+    });
+
+    it("should allow a fifty-move claim only once 100 plies pass without a capture or pawn move", async () => {
+      const pdas = await startGame(`claim-fifty-${Date.now()}`, {
+        ...defaultGameOptions(),
+        drawClaims: { anyPlayer: {} },
+      });
+      const plies = knightShuffle(100);
+      for (const [player, from, to, piece] of plies.slice(0, 99)) {
+        await recordMove(pdas.escrow, player, from, to, piece);
+      }
+      assert.equal((await program.account.gameEscrow.fetch(pdas.escrow)).halfmoveClock, 99);
+      await expectClaimError(claimDraw(pdas.escrow, playerWhite, { fiftyMoves: {} }), "DrawClaimInvalid");
+
+      const [player, from, to, piece] = plies[99];
+      await recordMove(pdas.escrow, player, from, to, piece);
+      const [finished] = await collectEvents("gameFinished", () =>
+        claimDraw(pdas.escrow, playerWhite, { fiftyMoves: {} })
+      );
+
+      assert.deepEqual(finished.reason, { fiftyMoveRule: {} });
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.halfmoveClock, 100);
+      assert.deepEqual(game.winner, { draw: {} });
+    });
+
+    it("should not count a repeat after the king has lost its castling rights", async () => {
+      const pdas = await startGame(`claim-castling-${Date.now()}`, {
+        ...defaultGameOptions(),
+        drawClaims: { anyPlayer: {} },
+      });
+      const kingWalk: [string, string, string][] = [
+        ["e1", "e2", "K"], ["g8", "f6", "n"],
+        ["e2", "e1", "K"], ["f6", "g8", "n"],
+      ];
+      await playMoves(pdas.escrow, [["e2", "e3", "P"], ["e7", "e6", "p"], ...kingWalk, ...kingWalk]);
+
+      // The board is back to the one after 1...e6 three times, but White could castle only then
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.castlingRights, 0b1100);
+      await expectClaimError(claimDraw(pdas.escrow, playerWhite), "DrawClaimInvalid");
+
+      await playMoves(pdas.escrow, kingWalk);
+      await claimDraw(pdas.escrow, playerWhite);
+      assert.deepEqual((await program.account.gameEscrow.fetch(pdas.escrow)).winner, { draw: {} });
+    });
+
+    it("should reject claims in games without draw claims", async () => {
+      const pdas = await threefold("claim-disabled", { disabled: {} });
+      await expectClaimError(claimDraw(pdas.escrow, playerWhite), "DrawClaimsDisabled");
+    });
+  });
+
+  describe("game header", () => {
+    it("should emit the game's stored metadata without the moves", async () => {
+      const pdas = await startGame(`header-${Date.now()}`, {
+        ...defaultGameOptions(),
+        timeControlType: { blitz: {} },
+        incrementSeconds: 2,
+      });
+      await playMoves(pdas.escrow, [["e2", "e4", "P"], ["e7", "e5", "P"]]);
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      const [header] = await collectEvents("gameHeader", () =>
+        program.methods.getGameHeader().accounts({ gameEscrow: pdas.escrow }).rpc()
+      );
+
+      assert.equal(header.roomId, game.roomId);
+      assert.ok(header.playerWhite.equals(playerWhite.publicKey));
+      assert.ok(header.playerBlack.equals(playerBlack.publicKey));
+      assert.equal(header.stakeAmount.toString(), stakeAmount.toString());
+      assert.equal(header.initialTime.toNumber(), timeLimitSeconds.toNumber());
+      assert.equal(header.increment.toNumber(), 2);
+      assert.deepEqual(header.timeControlType, { blitz: {} });
+      assert.deepEqual(header.variant, { standard: {} });
+      assert.deepEqual(header.gameState, game.gameState);
+      assert.deepEqual(header.winner, game.winner);
+      assert.equal(header.moveCount, 2);
+      assert.equal(header.createdAt.toNumber(), game.createdAt.toNumber());
+      assert.equal(header.startedAt.toNumber(), game.startedAt.toNumber());
+      assert.equal(header.finishedAt.toNumber(), 0);
+      assert.notProperty(header, "moveHistory");
+    });
+  });
+
+  describe("game summary", () => {
+    it("should reflect the game after a few moves", async () => {
+      const pdas = await startGame(`summary-${Date.now()}`);
+      await playMoves(pdas.escrow, [
+        ["e2", "e4", "P"],
+        ["e7", "e5", "p"],
+        ["g1", "f3", "N"],
+      ]);
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      const [summary] = await collectEvents("gameSummary", () =>
+        program.methods.getGameSummary().accounts({ gameEscrow: pdas.escrow }).rpc()
+      );
+
+      assert.equal(summary.roomId, game.roomId);
+      assert.ok(summary.playerWhite.equals(playerWhite.publicKey));
+      assert.ok(summary.playerBlack.equals(playerBlack.publicKey));
+      assert.equal(summary.stakeAmount.toNumber(), stakeAmount.toNumber());
+      assert.deepEqual(summary.gameState, { inProgress: {} });
+      assert.deepEqual(summary.winner, { none: {} });
+      assert.equal(summary.moveCount, 3);
+      assert.isFalse(summary.whiteToMove);
+      assert.equal(summary.whiteTimeRemaining.toNumber(), game.whiteTimeRemaining.toNumber());
+      assert.equal(summary.blackTimeRemaining.toNumber(), game.blackTimeRemaining.toNumber());
+      assert.equal(summary.lastMoveTime.toNumber(), game.lastMoveTime.toNumber());
+      assert.notProperty(summary, "moveHistory");
+    });
+  });
+
+  describe("escrow health", () => {
+    const escrowHealth = async (pdas: { escrow: PublicKey; vault: PublicKey }) => {
+      const [event] = await collectEvents("escrowHealth", () =>
+        program.methods
+          .getEscrowHealth()
+          .accounts({ gameEscrow: pdas.escrow, gameVault: pdas.vault })
+          .rpc()
+      );
+      return event;
+    };
+
+    it("should report a funded game as exactly covered", async () => {
+      const pdas = await startGame(`health-funded-${Date.now()}`);
+      const pot = 2 * stakeAmount.toNumber();
+
+      const health = await escrowHealth(pdas);
+      assert.equal(health.vaultBalance.toNumber(), pot);
+      assert.equal(health.totalDeposited.toNumber(), pot);
+      assert.equal(health.obligations.toNumber(), pot);
+      assert.equal(health.expectedPayout.toNumber(), pot - (pot * 2) / 100);
+      assert.isTrue(health.solvent);
+      assert.equal(health.discrepancy.toNumber(), 0);
+    });
+
+    it("should flag lamports the escrow does not account for", async () => {
+      const pdas = await startGame(`health-surplus-${Date.now()}`);
+      await provider.sendAndConfirm(
+        new Transaction().add(
+          SystemProgram.transfer({
+            fromPubkey: provider.wallet.publicKey,
+            toPubkey: pdas.vault,
+            lamports: 5000,
+          })
+        )
+      );
+
+      const health = await escrowHealth(pdas);
+      assert.isTrue(health.solvent);
+      assert.equal(health.discrepancy.toNumber(), 5000);
+    });
+
+    it("should owe nothing once the game is paid out", async () => {
+      const pdas = await startGame(`health-settled-${Date.now()}`);
+      await program.methods
+        .declareResult({ white: {} }, { resignation: {} })
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerBlack.publicKey,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerBlack])
+        .rpc();
+
+      const health = await escrowHealth(pdas);
+      assert.equal(health.obligations.toNumber(), 0);
+      assert.equal(health.vaultBalance.toNumber(), 0);
+      assert.isTrue(health.solvent);
+    });
+  });
+
+  describe("move challenges", () => {
+    const challenge = (escrow: PublicKey, player: Keypair) =>
+      program.methods
+        .challengeLastMove()
+        .accounts({ gameEscrow: escrow, player: player.publicKey })
+        .signers([player])
+        .rpc();
+
+    // Without on-chain validation a knight "move" from d1 slips through, carrying the queen
+    const injectIllegalMove = async (room: string, forfeitOnIllegalMove: boolean) => {
+      const pdas = await startGame(`${room}-${Date.now()}`, {
+        ...defaultGameOptions(),
+        optimisticValidation: true,
+        forfeitOnIllegalMove,
+      });
+      await recordMove(pdas.escrow, playerWhite, "e2", "e4", "P");
+      await recordMove(pdas.escrow, playerBlack, "e7", "e5", "p");
+      await recordMove(pdas.escrow, playerWhite, "d1", "h5", "N");
+      return pdas;
+    };
+
+    it("should take back an illegal move", async () => {
+      const pdas = await injectIllegalMove("challenge-rollback", false);
+      const [event] = await collectEvents("moveChallenged", () => challenge(pdas.escrow, playerBlack));
+      assert.equal(event.moveNumber, 3);
+      assert.isFalse(event.forfeited);
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { inProgress: {} });
+      assert.equal(game.moveCount, 2);
+      assert.equal(game.moveHistory.length, 2);
+      assert.equal(pieceAt(game.board, "d1"), "Q");
+      assert.equal(pieceAt(game.board, "h5"), "");
+
+      // White is on move again and can play a legal move instead
+      await recordMove(pdas.escrow, playerWhite, "g1", "f3", "N");
+    });
+
+    it("should forfeit the offender when configured", async () => {
+      const pdas = await injectIllegalMove("challenge-forfeit", true);
+      await challenge(pdas.escrow, playerBlack);
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { finished: {} });
+      assert.deepEqual(game.winner, { black: {} });
+    });
+
+    it("should reject a challenge to a legal move", async () => {
+      const pdas = await startGame(`challenge-legal-${Date.now()}`, {
+        ...defaultGameOptions(),
+        optimisticValidation: true,
+      });
+      await recordMove(pdas.escrow, playerWhite, "e2", "e4", "P");
+      try {
+        await challenge(pdas.escrow, playerBlack);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "ChallengedMoveLegal");
+      }
+    });
+
+    it("should only let the offender's opponent challenge", async () => {
+      const pdas = await injectIllegalMove("challenge-self", false);
+      try {
+        await challenge(pdas.escrow, playerWhite);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "NotPlayerTurn");
+      }
+    });
+  });
+
+  describe("three-check variant", () => {
+    // White checks on moves 2, 3 and 5: Bb5+, Bxc6+ and Qxf7+
+    const threeChecks: [string, string, string, MoveExtras?][] = [
+      ["e2", "e4", "P"],
+      ["d7", "d6", "p"],
+      ["f1", "b5", "B"],
+      ["c7", "c6", "p"],
+      ["b5", "c6", "B", { captured: "p" }],
+      ["b8", "c6", "n", { captured: "B" }],
+      ["d1", "h5", "Q"],
+      ["a7", "a6", "p"],
+      ["h5", "f7", "Q", { captured: "p" }],
+    ];
+
+    it("should count checks and award the third to the checker", async () => {
+      const pdas = await startGame(`three-check-${Date.now()}`, {
+        ...defaultGameOptions(),
+        variant: { threeCheck: {} },
+      });
+      await playMoves(pdas.escrow, threeChecks.slice(0, 8));
+      let game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.whiteChecks, 2);
+      assert.equal(game.blackChecks, 0);
+      assert.deepEqual(game.gameState, { inProgress: {} });
+
+      const [finished] = await collectEvents("gameFinished", () =>
+        playMoves(pdas.escrow, threeChecks.slice(8))
+      );
+      assert.deepEqual(finished.winner, { white: {} });
+      assert.deepEqual(finished.reason, { threeChecks: {} });
+
+      game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.whiteChecks, 3);
+      assert.deepEqual(game.gameState, { finished: {} });
+    });
+
+    it("should leave standard games running after three checks", async () => {
+      const pdas = await startGame(`three-check-standard-${Date.now()}`);
+      await playMoves(pdas.escrow, threeChecks);
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { inProgress: {} });
+      assert.equal(game.whiteChecks, 0);
+    });
+  });
+
+  describe("king of the hill variant", () => {
+    // Both kings walk up the board; White's reaches e4 on the seventh ply
+    const kingWalk: [string, string, string, MoveExtras?][] = [
+      ["e2", "e3", "P"],
+      ["e7", "e6", "p"],
+      ["e1", "e2", "K"],
+      ["e8", "e7", "k"],
+      ["e2", "d3", "K"],
+      ["e7", "d6", "k"],
+      ["d3", "e4", "K"],
+    ];
+
+    it("should not end the game for king moves short of the centre", async () => {
+      const pdas = await startGame(`hill-approach-${Date.now()}`, {
+        ...defaultGameOptions(),
+        variant: { kingOfTheHill: {} },
+      });
+      await playMoves(pdas.escrow, kingWalk.slice(0, 6));
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { inProgress: {} });
+    });
+
+    it("should award the game to the side whose king reaches the centre", async () => {
+      const pdas = await startGame(`hill-summit-${Date.now()}`, {
+        ...defaultGameOptions(),
+        variant: { kingOfTheHill: {} },
+      });
+      const [finished] = await collectEvents("gameFinished", () => playMoves(pdas.escrow, kingWalk));
+      assert.deepEqual(finished.winner, { white: {} });
+      assert.deepEqual(finished.reason, { kingOfTheHill: {} });
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { finished: {} });
+      assert.equal(pieceAt(game.board, "e4"), "K");
+    });
+
+    it("should ignore the centre in standard games", async () => {
+      const pdas = await startGame(`hill-standard-${Date.now()}`);
+      await playMoves(pdas.escrow, kingWalk);
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { inProgress: {} });
+    });
+  });
+
+  describe("atomic variant", () => {
+    const atomicGame = (room: string) =>
+      startGame(`${room}-${Date.now()}`, { ...defaultGameOptions(), variant: { atomic: {} } });
+
+    it("should remove both pieces of a capture in open space", async () => {
+      const pdas = await atomicGame("atomic-open");
+      await playMoves(pdas.escrow, [
+        ["e2", "e4", "P"],
+        ["d7", "d5", "p"],
+        ["e4", "d5", "P", { captured: "p" }],
+      ]);
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(pieceAt(game.board, "d5"), "");
+      assert.equal(pieceAt(game.board, "e4"), "");
+      assert.equal(pieceAt(game.board, "d8"), "q");
+      assert.deepEqual(game.gameState, { inProgress: {} });
+    });
+
+    it("should blow up neighbouring pieces but spare pawns, winning on the king", async () => {
+      const pdas = await atomicGame("atomic-king");
+      const [finished] = await collectEvents("gameFinished", () =>
+        playMoves(pdas.escrow, [
+          ["g1", "f3", "N"],
+          ["a7", "a6", "p"],
+          ["f3", "g5", "N"],
+          ["a6", "a5", "p"],
+          ["g5", "f7", "N", { captured: "p" }],
+        ])
+      );
+      assert.deepEqual(finished.winner, { white: {} });
+      assert.deepEqual(finished.reason, { kingExploded: {} });
+
+      // Knight, pawn, king, bishop and knight are gone; the neighbouring pawns survive
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      for (const square of ["f7", "e8", "f8", "g8"]) assert.equal(pieceAt(game.board, square), "");
+      for (const square of ["e7", "g7"]) assert.equal(pieceAt(game.board, square), "p");
+      assert.equal(pieceAt(game.board, "d8"), "q");
+    });
+
+    it("should keep standard captures intact", async () => {
+      const pdas = await startGame(`atomic-standard-${Date.now()}`);
+      await playMoves(pdas.escrow, [
+        ["g1", "f3", "N"],
+        ["a7", "a6", "p"],
+        ["f3", "g5", "N"],
+        ["a6", "a5", "p"],
+        ["g5", "f7", "N", { captured: "p" }],
+      ]);
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(pieceAt(game.board, "f7"), "N");
+      assert.equal(pieceAt(game.board, "e8"), "k");
+    });
+  });
+
+  describe("time extensions", () => {
+    const extendTime = (escrow: PublicKey, seconds: number, clocks: object, signers: Keypair[]) =>
+      program.methods
+        .extendTime(new anchor.BN(seconds), clocks)
+        .accounts({
+          gameEscrow: escrow,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+        })
+        .signers(signers)
+        .rpc();
+
+    it("should add time to both clocks when both players sign", async () => {
+      const { escrow } = await startGame(`extend-${Date.now()}`);
+      const [event] = await collectEvents("timeExtended", () =>
+        extendTime(escrow, 600, { both: {} }, [playerWhite, playerBlack])
+      );
+      assert.equal(event.seconds.toNumber(), 600);
+
+      const game = await program.account.gameEscrow.fetch(escrow);
+      assert.equal(game.whiteTimeRemaining.toNumber(), timeLimitSeconds.toNumber() + 600);
+      assert.equal(game.blackTimeRemaining.toNumber(), timeLimitSeconds.toNumber() + 600);
+    });
+
+    it("should extend a single clock", async () => {
+      const { escrow } = await startGame(`extend-black-${Date.now()}`);
+      await extendTime(escrow, 120, { black: {} }, [playerWhite, playerBlack]);
+
+      const game = await program.account.gameEscrow.fetch(escrow);
+      assert.equal(game.whiteTimeRemaining.toNumber(), timeLimitSeconds.toNumber());
+      assert.equal(game.blackTimeRemaining.toNumber(), timeLimitSeconds.toNumber() + 120);
+    });
+
+    it("should require both signatures", async () => {
+      const { escrow } = await startGame(`extend-single-${Date.now()}`);
+      try {
+        await extendTime(escrow, 600, { white: {} }, [playerWhite]);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "Signature verification failed");
+      }
+    });
+
+    it("should cap the extended clock", async () => {
+      const { escrow } = await startGame(`extend-cap-${Date.now()}`);
+      try {
+        await extendTime(escrow, 31 * 24 * 60 * 60, { both: {} }, [playerWhite, playerBlack]);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "InvalidTimeExtension");
+      }
+    });
+  });
+
+  describe("time control adjustment", () => {
+    const timeControl = (initialTime: number, timeControlType: object) => ({
+      initialTime: new anchor.BN(initialTime),
+      increment: new anchor.BN(5),
+      delay: new anchor.BN(0),
+      timeControlType,
+    });
+
+    const adjust = (escrow: PublicKey, control: object, signers: Keypair[]) =>
+      program.methods
+        .adjustTimeControl(control as any)
+        .accounts({
+          gameEscrow: escrow,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+          config: null,
+        })
+        .signers(signers)
+        .rpc();
+
+    it("should switch to the agreed time control and restart both clocks", async () => {
+      const { escrow } = await startGame(`adjust-clock-${Date.now()}`);
+      await playMoves(escrow, [
+        ["e2", "e4", "P"],
+        ["e7", "e5", "p"],
+      ]);
+      const [event] = await collectEvents("timeControlAdjusted", () =>
+        adjust(escrow, timeControl(900, { rapid: {} }), [playerWhite, playerBlack])
+      );
+      assert.equal(event.whiteTimeRemaining.toNumber(), 900);
+
+      const game = await program.account.gameEscrow.fetch(escrow);
+      assert.deepEqual(game.timeControl.timeControlType, { rapid: {} });
+      assert.equal(game.timeControl.increment.toNumber(), 5);
+      assert.equal(game.whiteTimeRemaining.toNumber(), 900);
+      assert.equal(game.blackTimeRemaining.toNumber(), 900);
+    });
+
+    it("should require both signatures", async () => {
+      const { escrow } = await startGame(`adjust-single-${Date.now()}`);
+      try {
+        await adjust(escrow, timeControl(900, { rapid: {} }), [playerWhite]);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "Signature verification failed");
+      }
+    });
+
+    it("should reject a type that does not fit the clock", async () => {
+      const { escrow } = await startGame(`adjust-bullet-${Date.now()}`);
+      try {
+        await adjust(escrow, timeControl(30 * 60, { bullet: {} }), [playerWhite, playerBlack]);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "InconsistentTimeControl");
+      }
+    });
+  });
+
+  describe("rake modes", () => {
+    // Plays a game to `winner` under `rakeMode` and returns the fee collected
+    const feeFor = async (rakeMode: object, winner: object, reason: object) => {
+      const pdas = await startGame(`rake-${Object.keys(rakeMode)[0]}-${Date.now()}`, {
+        ...defaultGameOptions(),
+        rakeMode,
+      });
+      if ("agreement" in reason) {
+        await offerDraw(pdas.escrow, playerWhite);
+      }
+      const before = await provider.connection.getBalance(feeCollector.publicKey);
+      await program.methods
+        .declareResult(winner, reason)
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerBlack.publicKey,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerBlack])
+        .rpc();
+      return (await provider.connection.getBalance(feeCollector.publicKey)) - before;
+    };
+
+    it("should rake the whole pot on a win under PotRake", async () => {
+      const fee = await feeFor({ potRake: {} }, { white: {} }, { resignation: {} });
+      assert.equal(fee, (2 * stakeAmount.toNumber() * 2) / 100);
+    });
+
+    it("should rake only the winner's gain under WinningsRake", async () => {
+      const fee = await feeFor({ winningsRake: {} }, { white: {} }, { resignation: {} });
+      assert.equal(fee, (stakeAmount.toNumber() * 2) / 100);
+    });
+
+    it("should still rake a draw under PotRake", async () => {
+      const fee = await feeFor({ potRake: {} }, { draw: {} }, { agreement: {} });
+      assert.equal(fee, (2 * stakeAmount.toNumber() * 2) / 100);
+    });
+
+    it("should take no fee from a draw under WinningsRake", async () => {
+      const fee = await feeFor({ winningsRake: {} }, { draw: {} }, { agreement: {} });
+      assert.equal(fee, 0);
+    });
+  });
+
+  describe("move chain", () => {
+    // Mirrors MoveRecord::chain_hash: sha256(prev_hash || move_data)
+    const chainHash = (record: any) => {
+      const field = (value: string | null) => {
+        const bytes = Buffer.from(value ?? "");
+        return Buffer.concat([Buffer.from([bytes.length]), bytes]);
+      };
+      const moveNumber = Buffer.alloc(4);
+      moveNumber.writeUInt32LE(record.moveNumber);
+      const timing = Buffer.alloc(16);
+      timing.writeBigInt64LE(BigInt(record.timestamp.toString()), 0);
+      timing.writeBigUInt64LE(BigInt(record.timeSpent.toString()), 8);
+      const flags = [record.isCheck, record.isCheckmate, record.isCastle, record.isEnPassant, record.isPromotion];
+      return Array.from(
+        createHash("sha256")
+          .update(Buffer.from(record.prevHash))
+          .update(Buffer.concat([
+            moveNumber,
+            field(record.fromSquare),
+            field(record.toSquare),
+            field(record.piece),
+            field(record.capturedPiece),
+            field(record.promotionPiece),
+            field(record.moveNotation),
+            timing,
+            Buffer.from(flags.map((flag) => (flag ? 1 : 0))),
+          ]))
+          .digest()
+      );
+    };
+
+    // Move number of the first record whose link doesn't re-derive, or null
+    const firstBrokenLink = (history: any[]) => {
+      let prev = history[0].prevHash;
+      for (const record of history) {
+        if (!Buffer.from(record.prevHash).equals(Buffer.from(prev))) return record.moveNumber;
+        if (!Buffer.from(record.positionHash).equals(Buffer.from(chainHash(record)))) return record.moveNumber;
+        prev = record.positionHash;
+      }
+      return null;
+    };
+
+    let escrow: PublicKey;
+    let history: any[];
+
+    before(async () => {
+      ({ escrow } = await startGame(`move-chain-${Date.now()}`));
+      await playMoves(escrow, [["e2", "e4", "P"], ["e7", "e5", "P"], ["g1", "f3", "N"], ["b8", "c6", "N"]]);
+      history = (await program.account.gameEscrow.fetch(escrow)).moveHistory;
+    });
+
+    it("should link each record to the one before it", async () => {
+      assert.deepEqual(history[0].prevHash, Array(32).fill(0));
+      for (let i = 1; i < history.length; i++) {
+        assert.deepEqual(history[i].prevHash, history[i - 1].positionHash);
+      }
+      assert.isNull(firstBrokenLink(history));
+
+      const [event] = await collectEvents("moveChainVerified", () =>
+        program.methods.verifyMoveChain().accounts({ gameEscrow: escrow }).rpc()
+      );
+      assert.equal(event.movesChecked, 4);
+      assert.isNull(event.brokenAt);
+      assert.deepEqual(event.head, history[3].positionHash);
+    });
+
+    it("should detect an altered middle move", () => {
+      const tampered = history.map((record) => ({ ...record }));
+      tampered[1].toSquare = "e6";
+      assert.equal(firstBrokenLink(tampered), 2);
+    });
+
+    it("should detect reordered moves", () => {
+      const reordered = [history[0], history[2], history[1], history[3]];
+      assert.equal(firstBrokenLink(reordered), 3);
+    });
+  });
+
+  describe("takebacks", () => {
+    const takeback = (method: "requestTakeback" | "acceptTakeback", escrow: PublicKey, player: Keypair) =>
+      program.methods[method]()
+        .accounts({ gameEscrow: escrow, player: player.publicKey })
+        .signers([player])
+        .rpc();
+
+    it("should default to a small per-player budget", async () => {
+      const { escrow } = await startGame(`takeback-default-${Date.now()}`);
+      const game = await program.account.gameEscrow.fetch(escrow);
+      assert.equal(game.maxTakebacks, 2);
+    });
+
+    it("should restore the position on an accepted takeback", async () => {
+      const { escrow } = await startGame(`takeback-${Date.now()}`);
+      await playMoves(escrow, [["e2", "e4", "P"]]);
+      await takeback("requestTakeback", escrow, playerWhite);
+      const [event] = await collectEvents("takebackAccepted", () =>
+        takeback("acceptTakeback", escrow, playerBlack)
+      );
+      assert.equal(event.moveNumber, 1);
+      assert.equal(event.takebacksUsed, 1);
+
+      const game = await program.account.gameEscrow.fetch(escrow);
+      assert.equal(game.moveCount, 0);
+      assert.equal(game.moveHistory.length, 0);
+      assert.equal(pieceAt(game.board, "e2"), "P");
+      assert.equal(pieceAt(game.board, "e4"), "");
+    });
+
+    it("should only let the player who just moved ask", async () => {
+      const { escrow } = await startGame(`takeback-turn-${Date.now()}`);
+      await playMoves(escrow, [["e2", "e4", "P"]]);
+      try {
+        await takeback("requestTakeback", escrow, playerBlack);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "NotPlayerTurn");
+      }
+    });
+
+    it("should reject takebacks beyond the budget", async () => {
+      const { escrow } = await startGame(`takeback-budget-${Date.now()}`, {
+        ...defaultGameOptions(),
+        maxTakebacksPerPlayer: 1,
+      });
+      await playMoves(escrow, [["e2", "e4", "P"]]);
+      await takeback("requestTakeback", escrow, playerWhite);
+      await takeback("acceptTakeback", escrow, playerBlack);
+
+      await recordMove(escrow, playerWhite, "d2", "d4", "P");
+      await takeback("requestTakeback", escrow, playerWhite);
+      try {
+        await takeback("acceptTakeback", escrow, playerBlack);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "TooManyTakebacks");
+      }
+
+      const game = await program.account.gameEscrow.fetch(escrow);
+      assert.equal(game.moveCount, 1);
+      assert.equal(game.whiteTakebacks, 1);
+    });
+  });
+
+  describe("double abandonment", () => {
+    // A game with one-second clocks, so the abandonment window is four seconds
+    const startShortGame = async (room: string) => {
+      const pdas = deriveGamePdas(room);
+      await program.methods
+        .initializeGame(room, stakeAmount, new anchor.BN(1), defaultGameOptions())
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerWhite.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+      await program.methods
+        .joinGame()
+        .accounts({ gameEscrow: pdas.escrow, player: playerBlack.publicKey })
+        .signers([playerBlack])
+        .rpc();
+      for (const player of [playerWhite, playerBlack]) {
+        await program.methods
+          .depositStake()
+          .accounts({
+            gameEscrow: pdas.escrow,
+            player: player.publicKey,
+            gameVault: pdas.vault,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([player])
+          .rpc();
+      }
+      return pdas;
+    };
+
+    const finalize = (pdas: { escrow: PublicKey; vault: PublicKey }) =>
+      program.methods
+        .finalizeDoubleAbandonment()
+        .accounts({
+          gameEscrow: pdas.escrow,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+    it("should refuse to finalize inside the window", async () => {
+      const pdas = await startShortGame(`abandon-early-${Date.now()}`);
+      try {
+        await finalize(pdas);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "GameNotAbandoned");
+      }
+    });
+
+    it("should refund both stakes as a draw after the window", async () => {
+      const pdas = await startShortGame(`abandon-${Date.now()}`);
+      await new Promise((resolve) => setTimeout(resolve, 6000));
+
+      const whiteBefore = await provider.connection.getBalance(playerWhite.publicKey);
+      const blackBefore = await provider.connection.getBalance(playerBlack.publicKey);
+      const [finished] = await collectEvents("gameFinished", () => finalize(pdas));
+      assert.deepEqual(finished.reason, { abandonment: {} });
+
+      const refund = stakeAmount.toNumber() - (stakeAmount.toNumber() * 10) / 10_000;
+      assert.equal((await provider.connection.getBalance(playerWhite.publicKey)) - whiteBefore, refund);
+      assert.equal((await provider.connection.getBalance(playerBlack.publicKey)) - blackBefore, refund);
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { finished: {} });
+      assert.deepEqual(game.winner, { draw: {} });
+      assert.isTrue(game.fundsDistributed);
+    });
+  });
+
+  describe("configurable starting rating", () => {
+    const setDefaultRating = (defaultRating: number) =>
+      program.methods
+        .updateConfig({ feeCollector: null, feeExempt: null, referee: null, defaultRating, ratingFloorGap: null, ratedPairCooldownSeconds: null, minRatedInitialTime: null })
+        .accounts({ config: configPda, admin: provider.wallet.publicKey })
+        .rpc();
+
+    after(async () => {
+      await setDefaultRating(1500);
+    });
+
+    it("should start new players at the configured rating", async () => {
+      await setDefaultRating(1200);
+      const newcomer = await fundedKeypair();
+      await program.methods
+        .initializePlayerRating()
+        .accounts({ player: newcomer.publicKey, config: configPda })
+        .signers([newcomer])
+        .rpc();
+
+      const rating = await program.account.playerRating.fetch(ratingPdaOf(newcomer.publicKey));
+      assert.equal(rating.rating, 1200);
+    });
+
+    it("should reject an out-of-range default", async () => {
+      try {
+        await setDefaultRating(5000);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "InvalidDefaultRating");
+      }
+    });
+  });
+
+  describe("rating floor", () => {
+    let sandbagger: Keypair;
+    let opponent: Keypair;
+
+    const setRatingFloorGap = (ratingFloorGap: number) =>
+      program.methods
+        .updateConfig({ feeCollector: null, feeExempt: null, referee: null, defaultRating: null, ratingFloorGap, ratedPairCooldownSeconds: null, minRatedInitialTime: null })
+        .accounts({ config: configPda, admin: provider.wallet.publicKey })
+        .rpc();
+
+    before(async () => {
+      await setRatingFloorGap(20);
+      sandbagger = await fundedKeypair();
+      opponent = await fundedKeypair();
+      for (const player of [sandbagger, opponent]) {
+        await program.methods
+          .initializePlayerRating()
+          .accounts({ player: player.publicKey, config: configPda })
+          .signers([player])
+          .rpc();
+      }
+    });
+
+    after(async () => {
+      await setRatingFloorGap(0);
+    });
+
+    it("should stop a string of losses at the floor below the peak", async () => {
+      const start = await program.account.playerRating.fetch(ratingPdaOf(sandbagger.publicKey));
+      assert.equal(start.ratingFloorGap, 20);
+
+      for (let i = 0; i < 4; i++) {
+        const pdas = await startGame(
+          `floor-${i}-${Date.now()}`,
+          { ...defaultGameOptions(), isRated: true },
+          sandbagger,
+          opponent
+        );
+        await program.methods
+          .declareResult({ black: {} }, { resignation: {} })
+          .accounts({
+            gameEscrow: pdas.escrow,
+            player: sandbagger.publicKey,
+            gameVault: pdas.vault,
+            playerWhite: sandbagger.publicKey,
+            playerBlack: opponent.publicKey,
+            feeCollector: feeCollector.publicKey,
+            systemProgram: SystemProgram.programId,
+            whiteRating: ratingPdaOf(sandbagger.publicKey),
+            blackRating: ratingPdaOf(opponent.publicKey),
+          })
+          .signers([sandbagger])
+          .rpc();
+      }
+
+      const rating = await program.account.playerRating.fetch(ratingPdaOf(sandbagger.publicKey));
+      assert.equal(rating.gamesPlayed, 4);
+      assert.equal(rating.peakRating, start.rating);
+      assert.equal(rating.rating, start.rating - 20);
+
+      // The winner's peak follows their rating up
+      const winner = await program.account.playerRating.fetch(ratingPdaOf(opponent.publicKey));
+      assert.equal(winner.peakRating, winner.rating);
+      assert.isAbove(winner.rating, start.rating);
+    });
+  });
+
+  describe("legal move listing", () => {
+    const legalMoves = async (escrow: PublicKey) => {
+      const chunks = await collectEvents("legalMoves", () =>
+        program.methods.listLegalMoves().accounts({ gameEscrow: escrow }).rpc()
+      );
+      return { chunks, moves: chunks.flatMap((chunk) => chunk.moves) };
+    };
+    const pair = (from: string, to: string) => ({ from: squareIndex(from), to: squareIndex(to) });
+    const pairs = (moves: any[]) => moves.map((mv) => ({ from: mv.from, to: mv.to }));
+
+    it("should list the twenty opening moves across chunks", async () => {
+      const { escrow } = await startGame(`legal-start-${Date.now()}`);
+      const { chunks, moves } = await legalMoves(escrow);
+
+      // 16 moves to an event
+      assert.lengthOf(chunks, 2);
+      assert.equal(chunks[0].totalChunks, 2);
+      assert.equal(chunks[0].totalMoves, 20);
+      assert.lengthOf(moves, 20);
+      assert.deepInclude(pairs(moves), pair("e2", "e4"));
+      assert.deepInclude(pairs(moves), pair("g1", "f3"));
+      assert.notDeepInclude(pairs(moves), pair("e2", "e5"));
+    });
+
+    it("should only list moves that answer a check", async () => {
+      const { escrow } = await startGame(`legal-check-${Date.now()}`);
+      await playMoves(escrow, [["e2", "e4", "P"], ["f7", "f5", "p"], ["d1", "h5", "Q"]]);
+
+      // 2.Qh5+ leaves Black a single reply, g6
+      const { moves } = await legalMoves(escrow);
+      assert.deepEqual(pairs(moves), [pair("g7", "g6")]);
+    });
+
+    it("should emit a single empty chunk when checkmated", async () => {
+      const { escrow } = await startGame(`legal-mate-${Date.now()}`);
+      await playMoves(escrow, [["f2", "f3", "P"], ["e7", "e5", "p"], ["g2", "g4", "P"], ["d8", "h4", "q"]]);
+
+      const { chunks, moves } = await legalMoves(escrow);
+      assert.lengthOf(chunks, 1);
+      assert.equal(chunks[0].totalMoves, 0);
+      assert.lengthOf(moves, 0);
+    });
+  });
+
+  describe("delayed increment", () => {
+    it("should only credit increment once the game is past the start move", async () => {
+      const pdas = await startGame(`increment-start-${Date.now()}`, {
+        ...defaultGameOptions(),
+        incrementSeconds: 30,
+        incrementStartMove: 2,
+      });
+      const limit = timeLimitSeconds.toNumber();
+      let previous = await program.account.gameEscrow.fetch(pdas.escrow);
+
+      const thinkTime = async (player: Keypair, from: string, to: string, piece: string) => {
+        await recordMove(pdas.escrow, player, from, to, piece);
+        const game = await program.account.gameEscrow.fetch(pdas.escrow);
+        const elapsed = game.lastMoveTime.toNumber() - previous.lastMoveTime.toNumber();
+        previous = game;
+        return { game, elapsed };
+      };
+
+      // Plies 1 and 2: no increment
+      const first = await thinkTime(playerWhite, "e2", "e4", "P");
+      assert.equal(first.game.whiteTimeRemaining.toNumber(), limit - first.elapsed);
+      const second = await thinkTime(playerBlack, "e7", "e5", "P");
+      assert.equal(second.game.blackTimeRemaining.toNumber(), limit - second.elapsed);
+
+      // Ply 3 takes move_count past 2, so White is credited
+      const third = await thinkTime(playerWhite, "g1", "f3", "N");
+      assert.equal(
+        third.game.whiteTimeRemaining.toNumber(),
+        limit - first.elapsed - third.elapsed + 30
+      );
+    });
+  });
+
+  describe("disconnections", () => {
+    const reportDisconnect = (escrow: PublicKey, reporter: Keypair) =>
+      program.methods
+        .reportDisconnect()
+        .accounts({ gameEscrow: escrow, player: reporter.publicKey })
+        .signers([reporter])
+        .rpc();
+
+    const reconnect = (escrow: PublicKey, player: Keypair) =>
+      program.methods
+        .reconnect()
+        .accounts({ gameEscrow: escrow, player: player.publicKey })
+        .signers([player])
+        .rpc();
+
+    it("should offer a draw for a disconnected player when enabled", async () => {
+      const pdas = await startGame(`disconnect-offer-${Date.now()}`, {
+        ...defaultGameOptions(),
+        autoDrawOfferOnDisconnect: true,
+      });
+      const [offer] = await collectEvents("drawOffered", () =>
+        reportDisconnect(pdas.escrow, playerWhite)
+      );
+
+      assert.ok(offer.offeredBy.equals(playerBlack.publicKey));
+      assert.isTrue(offer.automatic);
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.isTrue(game.blackDisconnected);
+      assert.ok(game.drawOfferedBy.equals(playerBlack.publicKey));
+    });
+
+    it("should not let the reporter accept the draw their report offered", async () => {
+      const pdas = await startGame(`disconnect-accept-${Date.now()}`, {
+        ...defaultGameOptions(),
+        autoDrawOfferOnDisconnect: true,
+      });
+      await reportDisconnect(pdas.escrow, playerWhite);
+
+      const drawAccounts = {
+        gameEscrow: pdas.escrow,
+        player: playerWhite.publicKey,
+        gameVault: pdas.vault,
+        playerWhite: playerWhite.publicKey,
+        playerBlack: playerBlack.publicKey,
+        feeCollector: feeCollector.publicKey,
+        systemProgram: SystemProgram.programId,
+      };
+      try {
+        await program.methods.acceptDraw().accounts(drawAccounts).signers([playerWhite]).rpc();
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "AutomaticDrawOffer");
+      }
+      try {
+        await program.methods
+          .declareResult({ draw: {} }, { agreement: {} })
+          .accounts(drawAccounts)
+          .signers([playerWhite])
+          .rpc();
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "AutomaticDrawOffer");
+      }
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { inProgress: {} });
+      assert.isTrue(game.drawOfferAutomatic);
+    });
+
+    it("should only flag the player when auto draw offers are off", async () => {
+      const pdas = await startGame(`disconnect-plain-${Date.now()}`);
+      await reportDisconnect(pdas.escrow, playerWhite);
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.isTrue(game.blackDisconnected);
+      assert.isNull(game.drawOfferedBy);
+    });
+
+    it("should withdraw the automatic offer on reconnect", async () => {
+      const pdas = await startGame(`disconnect-reconnect-${Date.now()}`, {
+        ...defaultGameOptions(),
+        autoDrawOfferOnDisconnect: true,
+      });
+      await reportDisconnect(pdas.escrow, playerWhite);
+      const [reconnected] = await collectEvents("playerReconnected", () =>
+        reconnect(pdas.escrow, playerBlack)
+      );
+
+      assert.isTrue(reconnected.drawOfferWithdrawn);
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.isFalse(game.blackDisconnected);
+      assert.isNull(game.drawOfferedBy);
+    });
+
+    it("should reject reconnecting a player who isn't disconnected", async () => {
+      const pdas = await startGame(`disconnect-none-${Date.now()}`);
+      try {
+        await reconnect(pdas.escrow, playerBlack);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "PlayerNotDisconnected");
+      }
+    });
+  });
+
+  describe("time control phases", () => {
+    it("should add a phase's time when a player completes its move", async () => {
+      const pdas = await startGame(`phases-${Date.now()}`, {
+        ...defaultGameOptions(),
+        timeControlPhases: [{ afterMove: 2, addedSeconds: 600 }],
+      });
+      const limit = timeLimitSeconds.toNumber();
+      const started = await program.account.gameEscrow.fetch(pdas.escrow);
+
+      await playMoves(pdas.escrow, [["e2", "e4", "P"], ["e7", "e5", "P"]]);
+      const beforeBoundary = await program.account.gameEscrow.fetch(pdas.escrow);
+      const whiteFirst = limit - beforeBoundary.whiteTimeRemaining.toNumber();
+      assert.isAtMost(beforeBoundary.whiteTimeRemaining.toNumber(), limit);
+      assert.isAtMost(beforeBoundary.blackTimeRemaining.toNumber(), limit);
+
+      // White's second move completes move 2
+      await recordMove(pdas.escrow, playerWhite, "g1", "f3", "N");
+      const afterBoundary = await program.account.gameEscrow.fetch(pdas.escrow);
+      const elapsed = afterBoundary.lastMoveTime.toNumber() - beforeBoundary.lastMoveTime.toNumber();
+      assert.equal(
+        afterBoundary.whiteTimeRemaining.toNumber(),
+        limit - whiteFirst - elapsed + 600
+      );
+      assert.isAtMost(afterBoundary.blackTimeRemaining.toNumber(), limit);
+      assert.isAbove(afterBoundary.lastMoveTime.toNumber(), started.lastMoveTime.toNumber());
+    });
+
+    it("should reject phases that are out of order", async () => {
+      try {
+        await startGame(`phases-unordered-${Date.now()}`, {
+          ...defaultGameOptions(),
+          timeControlPhases: [
+            { afterMove: 40, addedSeconds: 1800 },
+            { afterMove: 20, addedSeconds: 600 },
+          ],
+        });
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "InvalidTimeControlPhases");
+      }
+    });
+  });
+
+  describe("rent beneficiary", () => {
+    const resign = (pdas: { escrow: PublicKey; vault: PublicKey }) =>
+      program.methods
+        .declareResult({ black: {} }, { resignation: {} })
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerWhite.publicKey,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+
+    const closeGame = (escrow: PublicKey, rentBeneficiary: PublicKey) =>
+      program.methods.closeGame().accounts({ gameEscrow: escrow, rentBeneficiary }).rpc();
+
+    it("should send the reclaimed rent to the updated beneficiary", async () => {
+      const pdas = await startGame(`rent-beneficiary-${Date.now()}`);
+      const beneficiary = Keypair.generate();
+      await program.methods
+        .setRentBeneficiary(beneficiary.publicKey)
+        .accounts({ gameEscrow: pdas.escrow, rentBeneficiary: playerWhite.publicKey })
+        .signers([playerWhite])
+        .rpc();
+      await resign(pdas);
+
+      const rent = await provider.connection.getBalance(pdas.escrow);
+      const whiteBefore = await provider.connection.getBalance(playerWhite.publicKey);
+      await closeGame(pdas.escrow, beneficiary.publicKey);
+
+      assert.equal(await provider.connection.getBalance(beneficiary.publicKey), rent);
+      assert.equal(await provider.connection.getBalance(playerWhite.publicKey), whiteBefore);
+      assert.isNull(await provider.connection.getAccountInfo(pdas.escrow));
+    });
+
+    it("should only let the current beneficiary redirect the rent", async () => {
+      const pdas = await startGame(`rent-beneficiary-auth-${Date.now()}`);
+      try {
+        await program.methods
+          .setRentBeneficiary(playerBlack.publicKey)
+          .accounts({ gameEscrow: pdas.escrow, rentBeneficiary: playerBlack.publicKey })
+          .signers([playerBlack])
+          .rpc();
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "UnauthorizedPlayer");
+      }
+    });
+
+    it("should not close a game that is still in progress", async () => {
+      const pdas = await startGame(`rent-close-early-${Date.now()}`);
+      try {
+        await closeGame(pdas.escrow, playerWhite.publicKey);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "GameNotSettled");
+      }
+    });
+  });
+
+  describe("game metadata", () => {
+    const createGame = (room: string, metadata: Buffer | null) =>
+      program.methods
+        .initializeGame(room, stakeAmount, timeLimitSeconds, { ...defaultGameOptions(), metadata })
+        .accounts({
+          gameEscrow: deriveGamePdas(room).escrow,
+          player: playerWhite.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+
+    it("should store the metadata and emit it in GameCreated", async () => {
+      const room = `metadata-${Date.now()}`;
+      const metadata = Buffer.from(JSON.stringify({ label: "blitz-arena", client: "2.4.1", region: "eu" }));
+      const [created] = await collectEvents("gameCreated", () => createGame(room, metadata));
+
+      assert.deepEqual(Buffer.from(created.metadata), metadata);
+      const game = await program.account.gameEscrow.fetch(deriveGamePdas(room).escrow);
+      assert.deepEqual(Buffer.from(game.metadata), metadata);
+    });
+
+    it("should leave the metadata empty when none is given", async () => {
+      const room = `metadata-none-${Date.now()}`;
+      await createGame(room, null);
+
+      const game = await program.account.gameEscrow.fetch(deriveGamePdas(room).escrow);
+      assert.isNull(game.metadata);
+    });
+
+    it("should reject metadata over 64 bytes", async () => {
+      try {
+        await createGame(`metadata-long-${Date.now()}`, Buffer.alloc(65, 1));
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "MetadataTooLong");
+      }
+    });
+  });
+
+  describe("timing stats", () => {
+    const timingStats = async (escrow: PublicKey) => {
+      const [event] = await collectEvents("timingStats", () =>
+        program.methods.getTimingStats().accounts({ gameEscrow: escrow }).rpc()
+      );
+      return event;
+    };
+
+    it("should total each player's think time across the whole game", async () => {
+      // A two-move window: the totals must not depend on the retained history
+      const pdas = await startGame(`timing-${Date.now()}`, {
+        ...defaultGameOptions(),
+        moveHistoryCapacity: 2,
+      });
+      await playMoves(pdas.escrow, [
+        ["e2", "e4", "P", { timeSpent: 1500 }],
+        ["e7", "e5", "P", { timeSpent: 3000 }],
+        ["g1", "f3", "N", { timeSpent: 4000 }],
+        ["b8", "c6", "N", { timeSpent: 1000 }],
+        ["f1", "c4", "B", { timeSpent: 2500 }],
+      ]);
+      await program.methods
+        .declareResult({ white: {} }, { resignation: {} })
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerBlack.publicKey,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerBlack])
+        .rpc();
+
+      const stats = await timingStats(pdas.escrow);
+      assert.equal(stats.white.moves, 3);
+      assert.equal(stats.white.totalMs.toNumber(), 8000);
+      assert.equal(stats.white.averageMs.toNumber(), 2666);
+      assert.equal(stats.white.longestMs.toNumber(), 4000);
+      assert.equal(stats.black.moves, 2);
+      assert.equal(stats.black.totalMs.toNumber(), 4000);
+      assert.equal(stats.black.averageMs.toNumber(), 2000);
+      assert.equal(stats.black.longestMs.toNumber(), 3000);
+    });
+
+    it("should reject a game that is still in progress", async () => {
+      const pdas = await startGame(`timing-live-${Date.now()}`);
+      try {
+        await timingStats(pdas.escrow);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "GameNotFinished");
+      }
+    });
+  });
+
+  describe("deposit matching", () => {
+    const bond = new anchor.BN(LAMPORTS_PER_SOL / 10);
+
+    // White deposits, Black joins (posting any bond) but never matches
+    const unmatchedGame = async (
+      room: string,
+      griefingBond: anchor.BN,
+      depositMatchWindowSeconds = 1,
+      timeLimit = timeLimitSeconds
+    ) => {
+      const pdas = deriveGamePdas(room);
+      await program.methods
+        .initializeGame(room, stakeAmount, timeLimit, {
+          ...defaultGameOptions(),
+          depositMatchWindowSeconds,
+          griefingBond,
+        })
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerWhite.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+      await program.methods
+        .joinGame()
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerBlack.publicKey,
+          playerRating: null,
+          gameVault: pdas.vault,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerBlack])
+        .rpc();
+      await program.methods
+        .depositStake()
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerWhite.publicKey,
+          gameVault: pdas.vault,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+      return pdas;
+    };
+
+    const reclaim = (pdas: { escrow: PublicKey; vault: PublicKey }) =>
+      program.methods
+        .reclaimUnmatchedDeposit()
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerWhite.publicKey,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+
+    const waitOutWindow = () => new Promise((resolve) => setTimeout(resolve, 2000));
+
+    it("should refund the first depositor once the match window expires", async () => {
+      const pdas = await unmatchedGame(`match-window-${Date.now()}`, new anchor.BN(0));
+      await waitOutWindow();
+
+      const whiteBefore = await provider.connection.getBalance(playerWhite.publicKey);
+      const [reclaimed] = await collectEvents("unmatchedDepositReclaimed", () => reclaim(pdas));
+
+      assert.equal(reclaimed.refund.toString(), stakeAmount.toString());
+      assert.equal(reclaimed.bondForfeited.toNumber(), 0);
+      const whiteAfter = await provider.connection.getBalance(playerWhite.publicKey);
+      assert.equal(whiteAfter - whiteBefore, stakeAmount.toNumber());
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { cancelled: {} });
+    });
+
+    it("should forfeit the non-depositor's bond to the first depositor", async () => {
+      const pdas = await unmatchedGame(`match-window-bond-${Date.now()}`, bond);
+      await waitOutWindow();
+
+      const whiteBefore = await provider.connection.getBalance(playerWhite.publicKey);
+      const [reclaimed] = await collectEvents("unmatchedDepositReclaimed", () => reclaim(pdas));
+
+      assert.equal(reclaimed.bondForfeited.toString(), bond.toString());
+      const whiteAfter = await provider.connection.getBalance(playerWhite.publicKey);
+      assert.equal(whiteAfter - whiteBefore, stakeAmount.add(bond).toNumber());
+    });
+
+    it("should reject reclaiming while the opponent can still match", async () => {
+      const pdas = await unmatchedGame(`match-window-early-${Date.now()}`, new anchor.BN(0));
+      try {
+        await reclaim(pdas);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "DepositWindowOpen");
+      }
+    });
+
+    describe("without a match window", () => {
+      const timeLimit = new anchor.BN(2);
+
+      it("should refund the depositor once the time limit has passed since creation", async () => {
+        const pdas = await unmatchedGame(`deposit-deadline-${Date.now()}`, new anchor.BN(0), 0, timeLimit);
+        await new Promise((resolve) => setTimeout(resolve, 3000));
+
+        const whiteBefore = await provider.connection.getBalance(playerWhite.publicKey);
+        await reclaim(pdas);
+
+        const whiteAfter = await provider.connection.getBalance(playerWhite.publicKey);
+        assert.equal(whiteAfter - whiteBefore, stakeAmount.toNumber());
+        const game = await program.account.gameEscrow.fetch(pdas.escrow);
+        assert.deepEqual(game.gameState, { cancelled: {} });
+      });
+
+      it("should reject reclaiming before the deadline", async () => {
+        const pdas = await unmatchedGame(`deposit-deadline-early-${Date.now()}`, new anchor.BN(0), 0);
+        try {
+          await reclaim(pdas);
+          assert.fail("Should have thrown an error");
+        } catch (error) {
+          assert.include(error.toString(), "DepositWindowOpen");
+        }
+      });
+    });
+  });
+
+  describe("UCI export", () => {
+    it("should emit the moves in UCI, including a promotion as e7e8q", async () => {
+      const pdas = await startGame(`uci-${Date.now()}`);
+      await playMoves(pdas.escrow, [
+        ["e2", "e4", "P"],
+        ["f7", "f5", "p"],
+        ["e4", "f5", "P", { captured: "p" }],
+        ["e7", "e5", "p"],
+        ["f5", "e6", "P", { captured: "p", enPassant: true }],
+        ["g8", "f6", "n"],
+        ["e6", "e7", "P"],
+        ["e8", "f7", "k"],
+        ["e7", "e8", "P", { promotion: "Q", check: true }],
+      ]);
+
+      const [uci] = await collectEvents("uciMoves", () =>
+        program.methods.getUciMoves().accounts({ gameEscrow: pdas.escrow }).rpc()
+      );
+
+      assert.equal(uci.firstMoveNumber, 1);
+      assert.deepEqual(uci.moves, [
+        "e2e4", "f7f5", "e4f5", "e7e5", "f5e6", "g8f6", "e6e7", "e8f7", "e7e8q",
+      ]);
+    });
+  });
+
+  describe("spectator chat hashes", () => {
+    const messageHash = (text: string) => Array.from(createHash("sha256").update(text).digest());
+
+    const postChatHash = (escrow: PublicKey, sender: Keypair, text: string) =>
+      program.methods
+        .postChatHash(messageHash(text))
+        .accounts({ gameEscrow: escrow, sender: sender.publicKey })
+        .signers([sender])
+        .rpc();
+
+    it("should keep only the most recent chat hashes on the game", async () => {
+      const pdas = await startGame(`chat-${Date.now()}`, { ...defaultGameOptions(), spectatorChat: true });
+      const spectator = await fundedKeypair();
+
+      const posted = await collectEvents("chatHashPosted", async () => {
+        for (let i = 0; i < 10; i++) {
+          await postChatHash(pdas.escrow, spectator, `message ${i}`);
+        }
+      });
+
+      assert.lengthOf(posted, 10);
+      assert.equal(posted[9].sequence, 10);
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.chatHashCount, 10);
+      // Eight fit on the game: messages 2 through 9 remain, oldest first
+      assert.lengthOf(game.chatHashes, 8);
+      assert.deepEqual(game.chatHashes[0].messageHash, messageHash("message 2"));
+      assert.deepEqual(game.chatHashes[7].messageHash, messageHash("message 9"));
+      assert.ok(game.chatHashes[7].sender.equals(spectator.publicKey));
+    });
+
+    it("should reject chat hashes when spectator chat is off", async () => {
+      const pdas = await startGame(`chat-off-${Date.now()}`);
+      try {
+        await postChatHash(pdas.escrow, playerWhite, "hello");
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "ChatDisabled");
+      }
+    });
+  });
+
+  describe("rated pair cooldown", () => {
+    let first: Keypair;
+    let second: Keypair;
+    let pairHistoryPda: PublicKey;
+
+    const setCooldown = (ratedPairCooldownSeconds: number) =>
+      program.methods
+        .updateConfig({
+          feeCollector: null,
+          feeExempt: null,
+          referee: null,
+          defaultRating: null,
+          ratingFloorGap: null,
+          ratedPairCooldownSeconds,
+          minRatedInitialTime: null,
+        })
+        .accounts({ config: configPda, admin: provider.wallet.publicKey })
+        .rpc();
+
+    // A rated game created under the config, so it picks up the cooldown
+    const ratedGame = async (room: string) => {
+      const pdas = deriveGamePdas(room);
+      await program.methods
+        .initializeGame(room, stakeAmount, timeLimitSeconds, { ...defaultGameOptions(), isRated: true })
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: first.publicKey,
+          feeCollector: feeCollector.publicKey,
+          config: configPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([first])
+        .rpc();
+      await program.methods
+        .joinGame()
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: second.publicKey,
+          playerRating: ratingPdaOf(second.publicKey),
+          pairHistory: pairHistoryPda,
+        })
+        .signers([second])
+        .rpc();
+    };
+
+    before(async () => {
+      await setCooldown(3600);
+      first = await fundedKeypair();
+      second = await fundedKeypair();
+      await ensurePlayerRating(first);
+      await ensurePlayerRating(second);
+
+      const [a, b] = [first.publicKey, second.publicKey].sort((x, y) =>
+        Buffer.compare(x.toBuffer(), y.toBuffer())
+      );
+      [pairHistoryPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("pair"), a.toBuffer(), b.toBuffer()],
+        program.programId
+      );
+      await program.methods
+        .initializePairHistory(a, b)
+        .accounts({ pairHistory: pairHistoryPda, payer: first.publicKey })
+        .signers([first])
+        .rpc();
+    });
+
+    after(async () => {
+      await setCooldown(0);
+    });
+
+    it("should throttle a rapid rated rematch between the same pair", async () => {
+      await ratedGame(`pair-cooldown-1-${Date.now()}`);
+      const history = await program.account.pairHistory.fetch(pairHistoryPda);
+      assert.equal(history.ratedGames, 1);
+
+      try {
+        await ratedGame(`pair-cooldown-2-${Date.now()}`);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "RatedPairCooldown");
+      }
+    });
+
+    it("should require the pair history for rated games under a cooldown", async () => {
+      const room = `pair-cooldown-missing-${Date.now()}`;
+      const pdas = deriveGamePdas(room);
+      const stranger = await fundedKeypair();
+      await ensurePlayerRating(stranger);
+      await program.methods
+        .initializeGame(room, stakeAmount, timeLimitSeconds, { ...defaultGameOptions(), isRated: true })
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: stranger.publicKey,
+          feeCollector: feeCollector.publicKey,
+          config: configPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([stranger])
+        .rpc();
+      try {
+        await program.methods
+          .joinGame()
+          .accounts({
+            gameEscrow: pdas.escrow,
+            player: second.publicKey,
+            playerRating: ratingPdaOf(second.publicKey),
+            pairHistory: null,
+          })
+          .signers([second])
+          .rpc();
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "PairHistoryMissing");
+      }
+    });
+  });
+
+  describe("match series", () => {
+    it("should play a best-of-3 to completion and pay the series winner", async () => {
+      const seriesId = `series-${Date.now()}`;
+      const one = await fundedKeypair();
+      const two = await fundedKeypair();
+      const [seriesPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("series"), Buffer.from(seriesId)],
+        program.programId
+      );
+      const [seriesVault] = PublicKey.findProgramAddressSync(
+        [Buffer.from("series_vault"), seriesPda.toBuffer()],
+        program.programId
+      );
+
+      await program.methods
+        .createSeries(seriesId, two.publicKey, stakeAmount, 2, 0)
+        .accounts({ series: seriesPda, seriesVault, creator: one.publicKey })
+        .signers([one])
+        .rpc();
+      await program.methods
+        .joinSeries()
+        .accounts({ series: seriesPda, seriesVault, player: two.publicKey })
+        .signers([two])
+        .rpc();
+
+      // Colors alternate; the resigning side loses each game
+      const playAndReport = async (game: number, white: Keypair, black: Keypair, loser: Keypair) => {
+        const pdas = await startGame(`${seriesId}-g${game}`, defaultGameOptions(), white, black);
+        await program.methods
+          .declareResult(loser === white ? { black: {} } : { white: {} }, { resignation: {} })
+          .accounts({
+            gameEscrow: pdas.escrow,
+            player: loser.publicKey,
+            gameVault: pdas.vault,
+            playerWhite: white.publicKey,
+            playerBlack: black.publicKey,
+            feeCollector: feeCollector.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([loser])
+          .rpc();
+        await program.methods
+          .reportSeriesGame()
+          .accounts({
+            series: seriesPda,
+            seriesVault,
+            gameEscrow: pdas.escrow,
+            playerOne: one.publicKey,
+            playerTwo: two.publicKey,
+          })
+          .rpc();
+        return pdas;
+      };
+
+      const first = await playAndReport(1, one, two, two);
+      await playAndReport(2, two, one, one);
+      let series = await program.account.matchSeries.fetch(seriesPda);
+      assert.deepEqual(series.wins, [1, 1]);
+      assert.deepEqual(series.status, { inProgress: {} });
+
+      try {
+        await program.methods
+          .reportSeriesGame()
+          .accounts({
+            series: seriesPda,
+            seriesVault,
+            gameEscrow: first.escrow,
+            playerOne: one.publicKey,
+            playerTwo: two.publicKey,
+          })
+          .rpc();
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "SeriesGameAlreadyCounted");
+      }
+
+      const before = await provider.connection.getBalance(one.publicKey);
+      const events = await collectEvents("seriesFinished", () => playAndReport(3, two, one, two));
+      const after = await provider.connection.getBalance(one.publicKey);
+
+      series = await program.account.matchSeries.fetch(seriesPda);
+      assert.deepEqual(series.status, { finished: {} });
+      assert.ok(series.winner.equals(one.publicKey));
+      assert.deepEqual(series.wins, [2, 1]);
+      assert.equal(events.length, 1);
+      assert.isFalse(events[0].forfeit);
+      assert.equal(events[0].payout.toString(), stakeAmount.muln(2).toString());
+      // Game 3's own winnings are paid to one too; the series pot comes on top
+      assert.isAbove(after - before, stakeAmount.muln(2).toNumber());
+      assert.equal(await provider.connection.getBalance(seriesVault), 0);
+    });
+
+    const openSeries = async (label: string) => {
+      const seriesId = `series-${label}-${Date.now()}`;
+      const one = await fundedKeypair();
+      const two = await fundedKeypair();
+      const [seriesPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("series"), Buffer.from(seriesId)],
+        program.programId
+      );
+      const [seriesVault] = PublicKey.findProgramAddressSync(
+        [Buffer.from("series_vault"), seriesPda.toBuffer()],
+        program.programId
+      );
+      await program.methods
+        .createSeries(seriesId, two.publicKey, stakeAmount, 2, 0)
+        .accounts({ series: seriesPda, seriesVault, creator: one.publicKey })
+        .signers([one])
+        .rpc();
+      return { seriesPda, seriesVault, one, two };
+    };
+
+    it("should let the creator cancel a series nobody joined", async () => {
+      const { seriesPda, seriesVault, one, two } = await openSeries("cancel");
+
+      try {
+        await program.methods
+          .cancelSeries()
+          .accounts({ series: seriesPda, seriesVault, creator: two.publicKey })
+          .signers([two])
+          .rpc();
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "UnauthorizedPlayer");
+      }
+
+      const [cancelled] = await collectEvents("seriesCancelled", () =>
+        program.methods
+          .cancelSeries()
+          .accounts({ series: seriesPda, seriesVault, creator: one.publicKey })
+          .signers([one])
+          .rpc()
+      );
+      assert.equal(cancelled.refunds[0].toString(), stakeAmount.toString());
+      const series = await program.account.matchSeries.fetch(seriesPda);
+      assert.deepEqual(series.status, { cancelled: {} });
+      assert.equal(await provider.connection.getBalance(seriesVault), 0);
+
+      try {
+        await program.methods
+          .joinSeries()
+          .accounts({ series: seriesPda, seriesVault, player: two.publicKey })
+          .signers([two])
+          .rpc();
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "SeriesNotInProgress");
+      }
+    });
+
+    it("should not expire a series that has just started", async () => {
+      const { seriesPda, seriesVault, one, two } = await openSeries("expire");
+      await program.methods
+        .joinSeries()
+        .accounts({ series: seriesPda, seriesVault, player: two.publicKey })
+        .signers([two])
+        .rpc();
+
+      try {
+        await program.methods
+          .expireSeries()
+          .accounts({
+            series: seriesPda,
+            seriesVault,
+            playerOne: one.publicKey,
+            playerTwo: two.publicKey,
+          })
+          .rpc();
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "SeriesNotExpired");
+      }
+    });
+  });
+
+  describe("move interval floor", () => {
+    const floorOptions = (strict: boolean) => ({
+      ...defaultGameOptions(),
+      minMoveIntervalMs: 300,
+      strictMoveInterval: strict,
+    });
+
+    it("should reject a sub-floor move under the strict setting", async () => {
+      const pdas = await startGame(`floor-strict-${Date.now()}`, floorOptions(true));
+      try {
+        await recordMove(pdas.escrow, playerWhite, "e2", "e4", "P", { timeSpent: 100 });
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "MoveTooSoon");
+      }
+
+      await recordMove(pdas.escrow, playerWhite, "e2", "e4", "P", { timeSpent: 300 });
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.moveCount, 1);
+      assert.equal(game.antiCheatFlags & 2, 0);
+    });
+
+    it("should only flag a sub-floor move under the lenient setting", async () => {
+      const pdas = await startGame(`floor-lenient-${Date.now()}`, floorOptions(false));
+      await recordMove(pdas.escrow, playerWhite, "e2", "e4", "P", { timeSpent: 100 });
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.moveCount, 1);
+      assert.equal(game.antiCheatFlags & 2, 2);
+    });
+  });
+
+  describe("draw split", () => {
+    it("should pay a 60/40 draw split to each side", async () => {
+      const pdas = await startGame(`draw-split-${Date.now()}`, {
+        ...defaultGameOptions(),
+        drawSplitWhiteBps: 6000,
+        drawSplitBlackBps: 4000,
+      });
+      await offerDraw(pdas.escrow, playerBlack);
+      const whiteBefore = await provider.connection.getBalance(playerWhite.publicKey);
+      const blackBefore = await provider.connection.getBalance(playerBlack.publicKey);
+      const feeBefore = await provider.connection.getBalance(feeCollector.publicKey);
+
+      await program.methods
+        .declareResult({ draw: {} }, { agreement: {} })
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerWhite.publicKey,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+
+      const fee = (await provider.connection.getBalance(feeCollector.publicKey)) - feeBefore;
+      const remaining = stakeAmount.toNumber() * 2 - fee;
+      const blackAfter = await provider.connection.getBalance(playerBlack.publicKey);
+      const whiteAfter = await provider.connection.getBalance(playerWhite.publicKey);
+      // White's share rounds down and Black takes the rest, leaving the vault empty
+      const whiteShare = Math.floor((remaining * 6000) / 10000);
+      assert.equal(blackAfter - blackBefore, remaining - whiteShare);
+      // White signed the declaration, so allow for its transaction fee
+      assert.approximately(whiteAfter - whiteBefore, whiteShare, 10000);
+      assert.equal(await provider.connection.getBalance(pdas.vault), 0);
+    });
+
+    it("should reject shares that don't add up to 10000", async () => {
+      const room = `draw-split-bad-${Date.now()}`;
+      try {
+        await program.methods
+          .initializeGame(room, stakeAmount, timeLimitSeconds, {
+            ...defaultGameOptions(),
+            drawSplitWhiteBps: 6000,
+            drawSplitBlackBps: 5000,
+          })
+          .accounts({
+            gameEscrow: deriveGamePdas(room).escrow,
+            player: playerWhite.publicKey,
+            feeCollector: feeCollector.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([playerWhite])
+          .rpc();
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "InvalidDrawSplit");
+      }
+    });
+  });
+
+  describe("auto-resignation", () => {
+    const setAutoResign = (escrow: PublicKey, player: Keypair, belowSeconds: number) =>
+      program.methods
+        .setAutoResign(belowSeconds)
+        .accounts({ gameEscrow: escrow, player: player.publicKey })
+        .signers([player])
+        .rpc();
+
+    const poke = async (pdas: { escrow: PublicKey; vault: PublicKey }) => {
+      const claimant = await timeoutClaimant(pdas.escrow);
+      return program.methods
+        .handleTimeout()
+        .accounts({
+          gameEscrow: pdas.escrow,
+          claimant: claimant.publicKey,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([claimant])
+        .rpc();
+    };
+
+    it("should resign the player to move once their clock drops below their threshold", async () => {
+      const pdas = await startGame(`auto-resign-${Date.now()}`);
+      await setAutoResign(pdas.escrow, playerWhite, timeLimitSeconds.toNumber() - 1);
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+
+      const [finished] = await collectEvents("gameFinished", () => poke(pdas));
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { finished: {} });
+      assert.deepEqual(game.winner, { black: {} });
+      assert.deepEqual(finished.reason, { resignation: {} });
+    });
+
+    it("should ignore the waiting player's threshold", async () => {
+      const pdas = await startGame(`auto-resign-waiting-${Date.now()}`);
+      await setAutoResign(pdas.escrow, playerBlack, timeLimitSeconds.toNumber() - 1);
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+
+      try {
+        await poke(pdas);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "TimeNotExceeded");
+      }
+    });
+  });
+
+  describe("late registration", () => {
+    const tournamentId = "late-arena";
+    let creator: Keypair;
+    let tournamentPda: PublicKey;
+    let tournamentVaultPda: PublicKey;
+    let standingsPda: PublicKey;
+
+    const join = (player: Keypair, started: boolean) =>
+      program.methods
+        .joinTournament()
+        .accounts({
+          tournament: tournamentPda,
+          tournamentVault: tournamentVaultPda,
+          player: player.publicKey,
+          systemProgram: SystemProgram.programId,
+          standings: started ? standingsPda : null,
+        })
+        .signers([player])
+        .rpc();
+
+    before(async () => {
+      creator = await fundedKeypair();
+      [tournamentPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("tournament"), Buffer.from(tournamentId)],
+        program.programId
+      );
+      [tournamentVaultPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("tournament_vault"), tournamentPda.toBuffer()],
+        program.programId
+      );
+      [standingsPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("standings"), tournamentPda.toBuffer()],
+        program.programId
+      );
+
+      await program.methods
+        .createTournament(tournamentId, "Late Arena", new anchor.BN(1000), 8, {
+          initialTime: new anchor.BN(180),
+          increment: new anchor.BN(0),
+          delay: new anchor.BN(0),
+          timeControlType: { blitz: {} },
+        }, {
+          seedingMode: { rating: {} },
+          formWeightPct: 0,
+          payoutPcts: [100],
+          guaranteedPool: new anchor.BN(0),
+          lateRegistrationSeconds: 4,
+        })
+        .accounts({
+          tournament: tournamentPda,
+          tournamentVault: tournamentVaultPda,
+          creator: creator.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([creator])
+        .rpc();
+      for (let i = 0; i < 2; i++) await join(await fundedKeypair(), false);
+      await program.methods
+        .startTournament()
+        .accounts({
+          tournament: tournamentPda,
+          tournamentVault: tournamentVaultPda,
+          standings: standingsPda,
+          creator: creator.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([creator])
+        .rpc();
+    });
+
+    it("should refuse a player who can't cover the entry fee", async () => {
+      const before = await provider.connection.getBalance(tournamentVaultPda);
+      try {
+        await join(Keypair.generate(), true);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(String(error.logs ?? error), "insufficient lamports");
+      }
+
+      assert.equal(await provider.connection.getBalance(tournamentVaultPda), before);
+      const tournament = await program.account.tournament.fetch(tournamentPda);
+      assert.equal(tournament.currentParticipants, 2);
+    });
+
+    it("should admit a late joiner within the window on zero points", async () => {
+      const latecomer = await fundedKeypair();
+      await join(latecomer, true);
+
+      const tournament = await program.account.tournament.fetch(tournamentPda);
+      assert.equal(tournament.currentParticipants, 3);
+      assert.equal(tournament.prizePool.toNumber(), 3000);
+      const standings = await program.account.tournamentStandings.fetch(standingsPda);
+      const entry = standings.entries.find((e) => e.player.equals(latecomer.publicKey));
+      assert.ok(entry);
+      assert.equal(entry.points, 0);
+    });
+
+    it("should refuse to cancel once the tournament has started", async () => {
+      const tournament = await program.account.tournament.fetch(tournamentPda);
+      try {
+        await program.methods
+          .cancelTournament()
+          .accounts({
+            tournament: tournamentPda,
+            tournamentVault: tournamentVaultPda,
+            creator: creator.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .remainingAccounts(
+            tournament.participants.map((pubkey) => ({ pubkey, isSigner: false, isWritable: true }))
+          )
+          .signers([creator])
+          .rpc();
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "TournamentAlreadyStarted");
+      }
+    });
+
+    it("should reject a join once the window has passed", async () => {
+      await new Promise((resolve) => setTimeout(resolve, 5000));
+      try {
+        await join(await fundedKeypair(), true);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "TournamentAlreadyStarted");
+      }
+    });
+  });
+
+  describe("tournament addresses", () => {
+    it("should give each tournament id its own account", async () => {
+      const creator = await fundedKeypair();
+      const ids = [`open-a-${Date.now()}`, `open-b-${Date.now()}`];
+      const pdas = ids.map(
+        (id) =>
+          PublicKey.findProgramAddressSync(
+            [Buffer.from("tournament"), Buffer.from(id)],
+            program.programId
+          )[0]
+      );
+
+      for (const [i, id] of ids.entries()) {
+        await program.methods
+          .createTournament(id, `Open ${i}`, new anchor.BN(1000), 4, {
+            initialTime: new anchor.BN(600),
+            increment: new anchor.BN(0),
+            delay: new anchor.BN(0),
+            timeControlType: { rapid: {} },
+          }, {
+            seedingMode: { rating: {} },
+            formWeightPct: 0,
+            payoutPcts: [100],
+            guaranteedPool: new anchor.BN(0),
+            lateRegistrationSeconds: 0,
+          })
+          .accounts({
+            tournament: pdas[i],
+            tournamentVault: PublicKey.findProgramAddressSync(
+              [Buffer.from("tournament_vault"), pdas[i].toBuffer()],
+              program.programId
+            )[0],
+            creator: creator.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([creator])
+          .rpc();
+      }
+
+      assert.notEqual(pdas[0].toBase58(), pdas[1].toBase58());
+      const tournaments = await Promise.all(pdas.map((pda) => program.account.tournament.fetch(pda)));
+      assert.deepEqual(tournaments.map((t) => t.tournamentId), ids);
+    });
+  });
+
+  describe("move application", () => {
+    type Expected = { halfmove: number; enPassant: string | null; squares?: Record<string, string> };
+
+    // Plays the plies one by one, checking the stored position after each
+    const playAndCheck = async (room: string, plies: [string, string, string, MoveExtras?, Expected?][]) => {
+      const pdas = await startGame(room);
+      for (const [i, [from, to, piece, extras, expected]] of plies.entries()) {
+        await recordMove(pdas.escrow, i % 2 === 0 ? playerWhite : playerBlack, from, to, piece, extras);
+        if (!expected) continue;
+        const game = await program.account.gameEscrow.fetch(pdas.escrow);
+        assert.equal(game.halfmoveClock, expected.halfmove, `halfmove clock after ${from}${to}`);
+        assert.equal(
+          game.enPassantSquare,
+          expected.enPassant === null ? null : squareIndex(expected.enPassant),
+          `en-passant square after ${from}${to}`
+        );
+        for (const [square, code] of Object.entries(expected.squares ?? {})) {
+          assert.equal(pieceAt(game.board, square), code, `${square} after ${from}${to}`);
+        }
+      }
+    };
+
+    it("should update the board, en-passant target and halfmove clock through castling and en passant", async () => {
+      await playAndCheck(`apply-castle-ep-${Date.now()}`, [
+        ["e2", "e4", "P", {}, { halfmove: 0, enPassant: "e3" }],
+        ["g8", "f6", "n", {}, { halfmove: 1, enPassant: null }],
+        ["e4", "e5", "P", {}, { halfmove: 0, enPassant: null }],
+        ["d7", "d5", "p", {}, { halfmove: 0, enPassant: "d6" }],
+        ["e5", "d6", "P", { captured: "p", enPassant: true }, {
+          halfmove: 0,
+          enPassant: null,
+          squares: { d6: "P", d5: "", e5: "" },
+        }],
+        ["e7", "d6", "p", { captured: "P" }, { halfmove: 0, enPassant: null }],
+        ["g1", "f3", "N", {}, { halfmove: 1, enPassant: null }],
+        ["b8", "c6", "n", {}, { halfmove: 2, enPassant: null }],
+        ["f1", "e2", "B", {}, { halfmove: 3, enPassant: null }],
+        ["a7", "a6", "p", {}, { halfmove: 0, enPassant: null }],
+        ["e1", "g1", "K", { castle: true }, {
+          halfmove: 1,
+          enPassant: null,
+          squares: { g1: "K", f1: "R", e1: "", h1: "" },
+        }],
+      ]);
+    });
+
+    it("should replace a promoted pawn and reset the halfmove clock", async () => {
+      await playAndCheck(`apply-promotion-${Date.now()}`, [
+        ["e2", "e4", "P"],
+        ["f7", "f5", "p"],
+        ["e4", "f5", "P", { captured: "p" }],
+        ["e7", "e5", "p"],
+        ["f5", "e6", "P", { captured: "p", enPassant: true }],
+        ["g8", "f6", "n", {}, { halfmove: 1, enPassant: null }],
+        ["e6", "e7", "P"],
+        ["e8", "f7", "k", {}, { halfmove: 1, enPassant: null }],
+        ["e7", "e8", "P", { promotion: "Q", check: true }, {
+          halfmove: 0,
+          enPassant: null,
+          squares: { e8: "Q", e7: "" },
+        }],
+      ]);
+    });
+  });
+
+  describe("head-to-head records", () => {
+    it("should track each side's wins and the draws across games between a pair", async () => {
+      const a = await fundedKeypair();
+      const b = await fundedKeypair();
+      const [first, second] = [a, b].sort((x, y) =>
+        Buffer.compare(x.publicKey.toBuffer(), y.publicKey.toBuffer())
+      );
+      const [headToHeadPda] = PublicKey.findProgramAddressSync(
+        [Buffer.from("h2h"), first.publicKey.toBuffer(), second.publicKey.toBuffer()],
+        program.programId
+      );
+      await program.methods
+        .initializeHeadToHead(first.publicKey, second.publicKey)
+        .accounts({ headToHead: headToHeadPda, payer: a.publicKey })
+        .signers([a])
+        .rpc();
+
+      const settle = async (game: number, white: Keypair, black: Keypair, winner: object, reason: object, declarer: Keypair) => {
+        const pdas = await startGame(`h2h-${game}-${Date.now()}`, defaultGameOptions(), white, black);
+        if ("agreement" in reason) {
+          await offerDraw(pdas.escrow, declarer === white ? black : white);
+        }
+        await program.methods
+          .declareResult(winner, reason)
+          .accounts({
+            gameEscrow: pdas.escrow,
+            player: declarer.publicKey,
+            gameVault: pdas.vault,
+            playerWhite: white.publicKey,
+            playerBlack: black.publicKey,
+            feeCollector: feeCollector.publicKey,
+            systemProgram: SystemProgram.programId,
+            headToHead: headToHeadPda,
+          })
+          .signers([declarer])
+          .rpc();
+      };
+
+      // a wins with White, then with Black, b wins once and one game is drawn
+      await settle(1, a, b, { white: {} }, { resignation: {} }, b);
+      await settle(2, b, a, { black: {} }, { resignation: {} }, b);
+      await settle(3, a, b, { black: {} }, { resignation: {} }, a);
+      await settle(4, b, a, { draw: {} }, { agreement: {} }, b);
+
+      const record = await program.account.headToHead.fetch(headToHeadPda);
+      const aIndex = record.players[0].equals(a.publicKey) ? 0 : 1;
+      assert.equal(record.wins[aIndex], 2);
+      assert.equal(record.wins[1 - aIndex], 1);
+      assert.equal(record.draws, 1);
+
+      const [emitted] = await collectEvents("headToHeadRecord", () =>
+        program.methods.getHeadToHead().accounts({ headToHead: headToHeadPda }).rpc()
+      );
+      assert.equal(emitted.games, 4);
+    });
+  });
+
+  describe("minimum rated time control", () => {
+    const setMinimum = (minRatedInitialTime: number) =>
+      program.methods
+        .updateConfig({
+          feeCollector: null,
+          feeExempt: null,
+          referee: null,
+          defaultRating: null,
+          ratingFloorGap: null,
+          ratedPairCooldownSeconds: null,
+          minRatedInitialTime,
+        })
+        .accounts({ config: configPda, admin: provider.wallet.publicKey })
+        .rpc();
+
+    const createGame = (room: string, isRated: boolean) =>
+      program.methods
+        .initializeGame(room, stakeAmount, timeLimitSeconds, { ...defaultGameOptions(), isRated })
+        .accounts({
+          gameEscrow: deriveGamePdas(room).escrow,
+          player: playerWhite.publicKey,
+          feeCollector: feeCollector.publicKey,
+          config: configPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+
+    before(async () => {
+      await setMinimum(timeLimitSeconds.toNumber() * 2);
+    });
+
+    after(async () => {
+      await setMinimum(0);
+    });
+
+    it("should reject a rated game faster than the minimum", async () => {
+      try {
+        await createGame(`rated-too-fast-${Date.now()}`, true);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "TimeControlTooFastForRated");
+      }
+    });
+
+    it("should still allow the same time control unrated", async () => {
+      const room = `unrated-fast-${Date.now()}`;
+      await createGame(room, false);
+      const game = await program.account.gameEscrow.fetch(deriveGamePdas(room).escrow);
+      assert.equal(game.timeControl.initialTime.toNumber(), timeLimitSeconds.toNumber());
+    });
+  });
+
+  describe("compact move events", () => {
+    const squareName = (index: number) => "abcdefgh"[index % 8] + String(Math.floor(index / 8) + 1);
+
+    const decode = (event: any) => {
+      const bits: number = event.bits;
+      const promotion = PIECE_CODES[(bits >> 4) & 0xf].trim();
+      return {
+        fromSquare: squareName(event.from),
+        toSquare: squareName(event.to),
+        piece: PIECE_CODES[bits & 0xf],
+        isCapture: (bits & (1 << 8)) !== 0,
+        isCheck: (bits & (1 << 9)) !== 0,
+        isCheckmate: (bits & (1 << 10)) !== 0,
+        isCastle: (bits & (1 << 11)) !== 0,
+        isEnPassant: (bits & (1 << 12)) !== 0,
+        promotionPiece: promotion === "" ? null : promotion,
+      };
+    };
+
+    it("should emit compact events that decode to the recorded moves", async () => {
+      const pdas = await startGame(`compact-${Date.now()}`, { ...defaultGameOptions(), compactMoveEvents: true });
+      let full: any[] = [];
+      const compact = await collectEvents("moveRecordedCompact", async () => {
+        full = await collectEvents("moveRecorded", () =>
+          playMoves(pdas.escrow, [
+            ["e2", "e4", "P"],
+            ["f7", "f5", "p"],
+            ["e4", "f5", "P", { captured: "p" }],
+            ["e7", "e5", "p"],
+            ["f5", "e6", "P", { captured: "p", enPassant: true }],
+            ["g8", "f6", "n"],
+            ["e6", "e7", "P"],
+            ["e8", "f7", "k"],
+            ["e7", "e8", "P", { promotion: "Q", check: true }],
+          ])
+        );
+      });
+
+      // The compact event replaces the string-heavy one
+      assert.equal(full.length, 0);
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(compact.length, game.moveHistory.length);
+      compact.forEach((event, i) => {
+        const record = game.moveHistory[i];
+        assert.equal(event.moveCount, record.moveNumber);
+        assert.deepEqual(decode(event), {
+          fromSquare: record.fromSquare,
+          toSquare: record.toSquare,
+          piece: record.piece,
+          isCapture: record.capturedPiece !== null,
+          isCheck: record.isCheck,
+          isCheckmate: record.isCheckmate,
+          isCastle: record.isCastle,
+          isEnPassant: record.isEnPassant,
+          promotionPiece: record.promotionPiece,
+        });
+      });
+    });
+  });
+
+  describe("move audits", () => {
+    // Mirrors `GameEscrow::is_audited_ply`
+    const isAuditedPly = (positionHash: number[], moveCount: number, ratePct: number) => {
+      const plyBytes = Buffer.alloc(4);
+      plyBytes.writeUInt32LE(moveCount);
+      const seed = createHash("sha256").update(Buffer.from(positionHash)).update(plyBytes).digest();
+      return seed.readUInt16LE(0) % 100 < ratePct;
+    };
+
+    it("should catch an illegal move on an audited ply", async () => {
+      const pdas = await startGame(`audit-all-${Date.now()}`, {
+        ...defaultGameOptions(),
+        optimisticValidation: true,
+        auditRatePct: 100,
+      });
+      await recordMove(pdas.escrow, playerWhite, "e2", "e4", "P");
+      await recordMove(pdas.escrow, playerBlack, "e7", "e5", "p");
+      const events = await collectEvents("moveAudited", () =>
+        recordMove(pdas.escrow, playerWhite, "d1", "h5", "N")
+      );
+      assert.equal(events.length, 1);
+      assert.equal(events[0].moveNumber, 3);
+      assert.isFalse(events[0].passed);
+
+      // Still recorded, so it can be challenged, but flagged
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.moveCount, 3);
+      assert.equal(game.antiCheatFlags & (1 << 2), 1 << 2);
+    });
+
+    it("should not audit when the rate is zero", async () => {
+      const pdas = await startGame(`audit-none-${Date.now()}`, {
+        ...defaultGameOptions(),
+        optimisticValidation: true,
+      });
+      const events = await collectEvents("moveAudited", () =>
+        playMoves(pdas.escrow, [
+          ["e2", "e4", "P"],
+          ["e7", "e5", "p"],
+          ["d1", "h5", "N"],
+        ])
+      );
+      assert.equal(events.length, 0);
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.antiCheatFlags & (1 << 2), 0);
+    });
+
+    it("should pick audited plies deterministically from the move chain", async () => {
+      const pdas = await startGame(`audit-half-${Date.now()}`, {
+        ...defaultGameOptions(),
+        optimisticValidation: true,
+        auditRatePct: 50,
+      });
+      const moves: [string, string, string][] = [
+        ["g1", "f3", "N"],
+        ["g8", "f6", "n"],
+        ["f3", "g1", "N"],
+        ["f6", "g8", "n"],
+        ["b1", "c3", "N"],
+        ["b8", "c6", "n"],
+      ];
+      const expected: number[] = [];
+      const audited: number[] = [];
+      for (const [i, [from, to, piece]] of moves.entries()) {
+        const before = await program.account.gameEscrow.fetch(pdas.escrow);
+        if (isAuditedPly(before.positionHash, before.moveCount, 50)) {
+          expected.push(i + 1);
+        }
+        const events = await collectEvents("moveAudited", () =>
+          recordMove(pdas.escrow, i % 2 === 0 ? playerWhite : playerBlack, from, to, piece)
+        );
+        events.forEach((event) => {
+          assert.isTrue(event.passed);
+          audited.push(event.moveNumber);
+        });
+      }
+      assert.deepEqual(audited, expected);
+    });
+
+    it("should reject an audit rate above 100", async () => {
+      try {
+        await startGame(`audit-bad-${Date.now()}`, { ...defaultGameOptions(), auditRatePct: 101 });
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "InvalidAuditRate");
+      }
+    });
+  });
+
+  describe("low time warnings", () => {
+    it("should warn each side once when their clock drops below the threshold", async () => {
+      // Every move costs at least a second, so the first one already crosses a full-clock threshold
+      const pdas = await startGame(`low-time-${Date.now()}`, {
+        ...defaultGameOptions(),
+        lowTimeWarningSeconds: timeLimitSeconds.toNumber(),
+      });
+      const events = await collectEvents("lowTimeWarning", () =>
+        playMoves(pdas.escrow, [
+          ["g1", "f3", "N"],
+          ["g8", "f6", "n"],
+          ["f3", "g1", "N"],
+          ["f6", "g8", "n"],
+        ])
+      );
+      assert.deepEqual(
+        events.map((event) => event.player.toBase58()),
+        [playerWhite.publicKey.toBase58(), playerBlack.publicKey.toBase58()]
+      );
+      events.forEach((event) => {
+        assert.equal(event.threshold, timeLimitSeconds.toNumber());
+        assert.isBelow(event.timeRemaining.toNumber(), timeLimitSeconds.toNumber());
+      });
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.lowTimeWarned, [true, true]);
+    });
+
+    it("should stay quiet without a threshold", async () => {
+      const pdas = await startGame(`low-time-off-${Date.now()}`, defaultGameOptions());
+      const events = await collectEvents("lowTimeWarning", () =>
+        playMoves(pdas.escrow, [
+          ["e2", "e4", "P"],
+          ["e7", "e5", "p"],
+        ])
+      );
+      assert.equal(events.length, 0);
+    });
+  });
+
+  describe("authorized resignations", () => {
+    let relayer: Keypair;
+
+    // Domain tag, then the escrow address
+    const resignationMessage = (escrow: PublicKey) => Buffer.concat([Buffer.from("resign"), escrow.toBuffer()]);
+
+    const resignFor = (pdas: { escrow: PublicKey; vault: PublicKey }, signer: Keypair, message: Buffer) =>
+      program.methods
+        .resignWithAuthorization()
+        .accounts({
+          gameEscrow: pdas.escrow,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          relayer: relayer.publicKey,
+          instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+          systemProgram: SystemProgram.programId,
+          config: null,
+        })
+        .preInstructions([
+          Ed25519Program.createInstructionWithPrivateKey({ privateKey: signer.secretKey, message }),
+        ])
+        .signers([relayer])
+        .rpc();
+
+    before(async () => {
+      relayer = await fundedKeypair(1);
+    });
+
+    it("should resign a player on their signed authorization", async () => {
+      const pdas = await startGame(`resign-auth-${Date.now()}`);
+      await recordMove(pdas.escrow, playerWhite, "e2", "e4", "P");
+      const whiteBefore = await provider.connection.getBalance(playerWhite.publicKey);
+
+      const [event] = await collectEvents("resignationAuthorized", () =>
+        resignFor(pdas, playerBlack, resignationMessage(pdas.escrow))
+      );
+      assert.equal(event.player.toBase58(), playerBlack.publicKey.toBase58());
+      assert.equal(event.relayer.toBase58(), relayer.publicKey.toBase58());
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { finished: {} });
+      assert.deepEqual(game.winner, { white: {} });
+      assert.isTrue(game.fundsDistributed);
+      const pot = 2 * stakeAmount.toNumber();
+      assert.equal(
+        (await provider.connection.getBalance(playerWhite.publicKey)) - whiteBefore,
+        pot - (pot * 2) / 100
+      );
+    });
+
+    it("should reject an authorization signed by someone else", async () => {
+      const pdas = await startGame(`resign-forged-${Date.now()}`);
+      try {
+        await resignFor(pdas, Keypair.generate(), resignationMessage(pdas.escrow));
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "InvalidResignationAuthorization");
+      }
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { inProgress: {} });
+    });
+
+    it("should reject an authorization for another game", async () => {
+      const pdas = await startGame(`resign-replay-${Date.now()}`);
+      const other = await startGame(`resign-other-${Date.now()}`);
+      try {
+        await resignFor(pdas, playerBlack, resignationMessage(other.escrow));
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "InvalidResignationAuthorization");
+      }
+    });
+  });
+
+  describe("result and timeout races", () => {
+    const shortClock = new anchor.BN(10);
+
+    const escrowAccounts = (pdas: { escrow: PublicKey; vault: PublicKey }) => ({
+      gameEscrow: pdas.escrow,
+      gameVault: pdas.vault,
+      playerWhite: playerWhite.publicKey,
+      playerBlack: playerBlack.publicKey,
+      feeCollector: feeCollector.publicKey,
+      systemProgram: SystemProgram.programId,
+    });
+
+    const claimTimeout = async (pdas: { escrow: PublicKey; vault: PublicKey }) => {
+      const claimant = await timeoutClaimant(pdas.escrow);
+      return program.methods
+        .handleTimeout()
+        .accounts({ ...escrowAccounts(pdas), claimant: claimant.publicKey })
+        .signers([claimant])
+        .rpc();
+    };
+
+    const declare = (pdas: { escrow: PublicKey; vault: PublicKey }, declarer: Keypair, winner: object, reason: object) =>
+      program.methods
+        .declareResult(winner, reason)
+        .accounts({ ...escrowAccounts(pdas), player: declarer.publicKey })
+        .signers([declarer])
+        .rpc();
+
+    // Waits until the cluster clock is past the point where the player to move flags
+    const waitForFlag = async (escrow: PublicKey) => {
+      const game = await program.account.gameEscrow.fetch(escrow);
+      const remaining = game.moveCount % 2 === 0 ? game.whiteTimeRemaining : game.blackTimeRemaining;
+      const deadline = game.lastMoveTime.toNumber() + remaining.toNumber();
+      for (;;) {
+        const blockTime = await provider.connection.getBlockTime(await provider.connection.getSlot());
+        if (blockTime !== null && blockTime > deadline) return;
+        await new Promise((resolve) => setTimeout(resolve, 500));
+      }
+    };
+
+    // Fool's mate, recorded without the checkmate flag so the game stays open; White is
+    // mated and then runs out of time
+    const mateThenFlag = async (room: string) => {
+      const pdas = await startGame(`${room}-${Date.now()}`, defaultGameOptions(), playerWhite, playerBlack, shortClock);
+      await playMoves(pdas.escrow, [
+        ["f2", "f3", "P"],
+        ["e7", "e5", "p"],
+        ["g2", "g4", "P"],
+        ["d8", "h4", "q", { check: true }],
+      ]);
+      await waitForFlag(pdas.escrow);
+      return pdas;
+    };
+
+    it("should score a timeout on a mated board as checkmate", async () => {
+      const pdas = await mateThenFlag("race-mate-flag");
+      const [event] = await collectEvents("gameFinished", () => claimTimeout(pdas));
+      assert.deepEqual(event.winner, { black: {} });
+      assert.deepEqual(event.reason, { checkmate: {} });
+    });
+
+    it("should reach the same outcome whichever claim lands first", async () => {
+      const pdas = await mateThenFlag("race-both");
+      const events = await collectEvents("gameFinished", async () => {
+        const outcomes = await Promise.allSettled([
+          declare(pdas, playerBlack, { black: {} }, { checkmate: {} }),
+          claimTimeout(pdas),
+        ]);
+        // The loser of the race finds the game already finished
+        assert.equal(outcomes.filter((outcome) => outcome.status === "fulfilled").length, 1);
+      });
+      assert.equal(events.length, 1);
+      assert.deepEqual(events[0].winner, { black: {} });
+      assert.deepEqual(events[0].reason, { checkmate: {} });
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.winner, { black: {} });
+      assert.isTrue(game.fundsDistributed);
+    });
+
+    it("should reject an unconfirmed result once the mover has flagged", async () => {
+      const pdas = await startGame(`race-unconfirmed-${Date.now()}`, defaultGameOptions(), playerWhite, playerBlack, shortClock);
+      await playMoves(pdas.escrow, [
+        ["e2", "e4", "P"],
+        ["e7", "e5", "p"],
+      ]);
+      await waitForFlag(pdas.escrow);
+      try {
+        await declare(pdas, playerWhite, { white: {} }, { checkmate: {} });
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "TimeoutTakesPrecedence");
+      }
+
+      const [event] = await collectEvents("gameFinished", () => claimTimeout(pdas));
+      assert.deepEqual(event.winner, { black: {} });
+      assert.deepEqual(event.reason, { timeout: {} });
+    });
+  });
+
+  describe("allowed stake mints", () => {
+    let allowedMint: PublicKey;
+    let otherMint: PublicKey;
+
+    const createTokenGame = (room: string, mint: PublicKey) =>
+      program.methods
+        .initializeGame(room, stakeAmount, timeLimitSeconds, {
+          ...defaultGameOptions(),
+          crossAssetStake: { mint, amount: new anchor.BN(500_000) },
+        })
+        .accounts({
+          gameEscrow: deriveGamePdas(room).escrow,
+          player: playerWhite.publicKey,
+          feeCollector: feeCollector.publicKey,
+          config: configPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+
+    before(async () => {
+      const payer = (provider.wallet as anchor.Wallet).payer;
+      allowedMint = await createMint(provider.connection, payer, payer.publicKey, null, 6);
+      otherMint = await createMint(provider.connection, payer, payer.publicKey, null, 6);
+      await program.methods
+        .addAllowedMint(allowedMint)
+        .accounts({ config: configPda, admin: provider.wallet.publicKey })
+        .rpc();
+    });
+
+    after(async () => {
+      await program.methods
+        .removeAllowedMint(allowedMint)
+        .accounts({ config: configPda, admin: provider.wallet.publicKey })
+        .rpc();
+    });
+
+    it("should accept a game staked in an allowed mint", async () => {
+      const room = `mint-allowed-${Date.now()}`;
+      await createTokenGame(room, allowedMint);
+      const game = await program.account.gameEscrow.fetch(deriveGamePdas(room).escrow);
+      assert.equal(game.tokenMint.toBase58(), allowedMint.toBase58());
+    });
+
+    it("should reject a game staked in any other mint", async () => {
+      try {
+        await createTokenGame(`mint-disallowed-${Date.now()}`, otherMint);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "MintNotAllowed");
+      }
+    });
+
+    it("should only let the admin manage the list", async () => {
+      try {
+        await program.methods
+          .addAllowedMint(otherMint)
+          .accounts({ config: configPda, admin: playerWhite.publicKey })
+          .signers([playerWhite])
+          .rpc();
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "UnauthorizedAdmin");
+      }
+    });
+  });
+
+  describe("piece movement rules", () => {
+    const expectIllegal = async (action: () => Promise<unknown>) => {
+      try {
+        await action();
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "IllegalMove");
+      }
+    };
+
+    it("should stop a rook at the first piece in its way", async () => {
+      const pdas = await startGame(`rules-rook-${Date.now()}`);
+      await expectIllegal(() => recordMove(pdas.escrow, playerWhite, "a1", "a3", "R"));
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.moveCount, 0);
+    });
+
+    it("should reject a rook moving diagonally", async () => {
+      const pdas = await startGame(`rules-rook-diagonal-${Date.now()}`);
+      await playMoves(pdas.escrow, [
+        ["a2", "a4", "P"],
+        ["a7", "a6", "p"],
+        ["a1", "a3", "R"],
+        ["a6", "a5", "p"],
+      ]);
+      await expectIllegal(() => recordMove(pdas.escrow, playerWhite, "a3", "b4", "R"));
+    });
+
+    it("should let a knight jump over pieces", async () => {
+      const pdas = await startGame(`rules-knight-${Date.now()}`);
+      await recordMove(pdas.escrow, playerWhite, "g1", "f3", "N");
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(pieceAt(game.board, "f3"), "N");
+      await expectIllegal(() => recordMove(pdas.escrow, playerBlack, "g8", "g6", "n"));
+    });
+
+    it("should only let a pawn move diagonally onto a piece it captures", async () => {
+      const pdas = await startGame(`rules-pawn-${Date.now()}`);
+      await playMoves(pdas.escrow, [
+        ["e2", "e4", "P"],
+        ["d7", "d5", "p"],
+      ]);
+      await expectIllegal(() => recordMove(pdas.escrow, playerWhite, "a2", "b3", "P"));
+      await recordMove(pdas.escrow, playerWhite, "e4", "d5", "P", { captured: "p" });
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(pieceAt(game.board, "d5"), "P");
+    });
+
+    it("should leave the game untouched when a bishop moves through a piece", async () => {
+      const pdas = await startGame(`rules-bishop-${Date.now()}`);
+      await recordMove(pdas.escrow, playerWhite, "g1", "f3", "N");
+      await recordMove(pdas.escrow, playerBlack, "g8", "f6", "n");
+      const before = await program.account.gameEscrow.fetch(pdas.escrow);
+
+      // e2 still blocks the f1 bishop's diagonal
+      await expectIllegal(() => recordMove(pdas.escrow, playerWhite, "f1", "c4", "B"));
+
+      const after = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(after.moveCount, before.moveCount);
+      assert.equal(after.moveHistory.length, before.moveHistory.length);
+      assert.deepEqual(after.board, before.board);
+      assert.equal(pieceAt(after.board, "f1"), "B");
+    });
+
+    it("should keep the king to adjacent squares", async () => {
+      const pdas = await startGame(`rules-king-${Date.now()}`);
+      await playMoves(pdas.escrow, [
+        ["e2", "e4", "P"],
+        ["e7", "e5", "p"],
+      ]);
+      await expectIllegal(() => recordMove(pdas.escrow, playerWhite, "e1", "e3", "K"));
+      await recordMove(pdas.escrow, playerWhite, "e1", "e2", "K");
+    });
+  });
+
+  describe("checkmate verification", () => {
+    const verify = async (fen: string) => {
+      const [event] = await collectEvents("checkmateVerified", () =>
+        program.methods.verifyCheckmate(fen).accounts({}).rpc()
+      );
+      assert.equal(event.fen, fen);
+      return event.isCheckmate;
+    };
+
+    it("should confirm a mated position", async () => {
+      // Fool's mate
+      assert.isTrue(await verify("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3"));
+    });
+
+    it("should not call a check that can be answered a mate", async () => {
+      // Bb5+ after 1.e4 d6, which Black can block
+      assert.isFalse(await verify("rnbqkbnr/ppp1pppp/3p4/1B6/4P3/8/PPPP1PPP/RNBQK1NR b KQkq - 1 2"));
+    });
+
+    it("should reject a malformed FEN", async () => {
+      try {
+        await verify("rnbqkbnr/pppppppp/8/8 w KQkq -");
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "InvalidFen");
+      }
+    });
+  });
+
+  describe("king safety", () => {
+    const expectExposed = async (action: () => Promise<unknown>) => {
+      try {
+        await action();
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "MoveExposesKing");
+      }
+    };
+
+    it("should only accept moves that answer a check", async () => {
+      const pdas = await startGame(`king-check-${Date.now()}`);
+      await playMoves(pdas.escrow, [
+        ["e2", "e4", "P"],
+        ["e7", "e5", "p"],
+        ["d2", "d4", "P"],
+        ["f8", "b4", "b", { check: true }],
+      ]);
+      await expectExposed(() => recordMove(pdas.escrow, playerWhite, "a2", "a3", "P"));
+      // Blocking on c3 resolves the check
+      await recordMove(pdas.escrow, playerWhite, "b1", "c3", "N");
+    });
+
+    it("should keep a pinned knight in place", async () => {
+      const pdas = await startGame(`king-pin-${Date.now()}`);
+      await playMoves(pdas.escrow, [
+        ["e2", "e4", "P"],
+        ["e7", "e5", "p"],
+        ["d2", "d4", "P"],
+        ["f8", "b4", "b", { check: true }],
+        ["b1", "c3", "N"],
+        ["a7", "a6", "p"],
+      ]);
+      // The b4 bishop pins the c3 knight to the king on e1
+      await expectExposed(() => recordMove(pdas.escrow, playerWhite, "c3", "d5", "N"));
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(pieceAt(game.board, "c3"), "N");
+      assert.equal(game.moveCount, 6);
+    });
+
+    it("should stop the king walking onto a rook's file", async () => {
+      const pdas = await startGame(`king-file-${Date.now()}`);
+      await playMoves(pdas.escrow, [
+        ["e2", "e4", "P"],
+        ["a7", "a5", "p"],
+        ["e1", "e2", "K"],
+        ["a8", "a6", "r"],
+        ["a2", "a3", "P"],
+        ["a6", "d6", "r"],
+      ]);
+      await expectExposed(() => recordMove(pdas.escrow, playerWhite, "e2", "d3", "K"));
+      await recordMove(pdas.escrow, playerWhite, "e2", "e3", "K");
+    });
+  });
+
+  describe("free games", () => {
+    const createGame = (room: string, stake: anchor.BN, freeGame: boolean) =>
+      program.methods
+        .initializeGame(room, stake, timeLimitSeconds, { ...defaultGameOptions(), freeGame })
+        .accounts({
+          gameEscrow: deriveGamePdas(room).escrow,
+          player: playerWhite.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+
+    it("should start on join and settle without moving funds", async () => {
+      const room = `free-${Date.now()}`;
+      const pdas = deriveGamePdas(room);
+      await createGame(room, new anchor.BN(0), true);
+
+      const [started] = await collectEvents("gameStarted", () =>
+        program.methods
+          .joinGame()
+          .accounts({ gameEscrow: pdas.escrow, player: playerBlack.publicKey })
+          .signers([playerBlack])
+          .rpc()
+      );
+      assert.equal(started.roomId, room);
+      let game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { inProgress: {} });
+
+      await recordMove(pdas.escrow, playerWhite, "e2", "e4", "P");
+      const blackBefore = await provider.connection.getBalance(playerBlack.publicKey);
+      const feeBefore = await provider.connection.getBalance(feeCollector.publicKey);
+      await program.methods
+        .declareResult({ black: {} }, { resignation: {} })
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerWhite.publicKey,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+
+      game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.winner, { black: {} });
+      assert.equal(await provider.connection.getBalance(pdas.vault), 0);
+      assert.equal(await provider.connection.getBalance(playerBlack.publicKey), blackBefore);
+      assert.equal(await provider.connection.getBalance(feeCollector.publicKey), feeBefore);
+    });
+
+    it("should still require a stake from other games", async () => {
+      try {
+        await createGame(`free-unflagged-${Date.now()}`, new anchor.BN(0), false);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "InvalidStakeAmount");
+      }
+    });
+
+    it("should reject a free game with a stake", async () => {
+      try {
+        await createGame(`free-staked-${Date.now()}`, stakeAmount, true);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "InvalidStakeAmount");
+      }
+    });
+  });
+
+  describe("fullmove number", () => {
+    const exportFen = async (escrow: PublicKey) => {
+      const [event] = await collectEvents("positionFen", () =>
+        program.methods.getFen().accounts({ gameEscrow: escrow }).rpc()
+      );
+      return event.fen as string;
+    };
+
+    it("should only advance after Black's move", async () => {
+      const pdas = await startGame(`fullmove-${Date.now()}`);
+      const numbers: number[] = [];
+      for (const [i, [from, to, piece]] of [
+        ["e2", "e4", "P"],
+        ["e7", "e5", "p"],
+        ["g1", "f3", "N"],
+        ["b8", "c6", "n"],
+      ].entries()) {
+        await recordMove(pdas.escrow, i % 2 === 0 ? playerWhite : playerBlack, from, to, piece);
+        numbers.push((await program.account.gameEscrow.fetch(pdas.escrow)).fullmoveNumber);
+      }
+      assert.deepEqual(numbers, [1, 2, 2, 3]);
+    });
+
+    it("should round-trip through FEN", async () => {
+      const pdas = await startGame(`fullmove-fen-${Date.now()}`);
+      assert.equal(await exportFen(pdas.escrow), "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+
+      await playMoves(pdas.escrow, [
+        ["e2", "e4", "P"],
+        ["e7", "e5", "p"],
+        ["g1", "f3", "N"],
+      ]);
+      const fen = await exportFen(pdas.escrow);
+      assert.equal(fen, "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2");
+
+      // The exported record parses back to the same FEN
+      const [parsed] = await collectEvents("checkmateVerified", () =>
+        program.methods.verifyCheckmate(fen).accounts({}).rpc()
+      );
+      assert.equal(parsed.fen, fen);
+      assert.isFalse(parsed.isCheckmate);
+    });
+  });
+
+  describe("side to move", () => {
+    it("should flip the turn flag with every move", async () => {
+      const pdas = await startGame(`turn-${Date.now()}`);
+      let game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.isTrue(game.whiteToMove);
+      assert.isTrue(game.turnRecorded);
+
+      const turns: boolean[] = [];
+      for (const [i, [from, to, piece]] of [
+        ["e2", "e4", "P"],
+        ["e7", "e5", "p"],
+        ["g1", "f3", "N"],
+      ].entries()) {
+        await recordMove(pdas.escrow, i % 2 === 0 ? playerWhite : playerBlack, from, to, piece);
+        game = await program.account.gameEscrow.fetch(pdas.escrow);
+        turns.push(game.whiteToMove);
+      }
+      assert.deepEqual(turns, [false, true, false]);
+
+      // Black is on move, so White may not play again
+      try {
+        await recordMove(pdas.escrow, playerWhite, "d2", "d4", "P");
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "NotPlayerTurn");
+      }
+    });
+  });
+
+  describe("draw offers", () => {
+    const acceptDraw = (pdas: { escrow: PublicKey; vault: PublicKey }, player: Keypair) =>
+      program.methods
+        .acceptDraw()
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: player.publicKey,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([player])
+        .rpc();
+
+    it("should record a pending offer with its time", async () => {
+      const pdas = await startGame(`draw-offer-${Date.now()}`);
+      const [offer] = await collectEvents("drawOffered", () => offerDraw(pdas.escrow, playerWhite));
+
+      assert.ok(offer.offeredBy.equals(playerWhite.publicKey));
+      assert.isFalse(offer.automatic);
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.ok(game.drawOfferedBy.equals(playerWhite.publicKey));
+      assert.isAbove(game.drawOfferedAt.toNumber(), 0);
+    });
+
+    it("should finish as a draw when the opponent accepts", async () => {
+      const pdas = await startGame(`draw-accept-${Date.now()}`);
+      await offerDraw(pdas.escrow, playerWhite);
+      const [finished] = await collectEvents("gameFinished", () => acceptDraw(pdas, playerBlack));
+
+      assert.deepEqual(finished.winner, { draw: {} });
+      assert.deepEqual(finished.reason, { agreement: {} });
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { finished: {} });
+      assert.isTrue(game.fundsDistributed);
+      assert.isNull(game.drawOfferedBy);
+    });
+
+    it("should reject accepting your own offer", async () => {
+      const pdas = await startGame(`draw-own-${Date.now()}`);
+      await offerDraw(pdas.escrow, playerWhite);
+      try {
+        await acceptDraw(pdas, playerWhite);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "CannotAcceptOwnDrawOffer");
+      }
+    });
+
+    it("should let the offerer revoke the offer", async () => {
+      const pdas = await startGame(`draw-revoke-${Date.now()}`);
+      await offerDraw(pdas.escrow, playerBlack);
+      await program.methods
+        .revokeDrawOffer()
+        .accounts({ gameEscrow: pdas.escrow, player: playerBlack.publicKey })
+        .signers([playerBlack])
+        .rpc();
+
+      try {
+        await acceptDraw(pdas, playerWhite);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "NoDrawOffer");
+      }
+    });
+
+    it("should reject a draw by agreement declared without the opponent's offer", async () => {
+      const pdas = await startGame(`draw-unoffered-${Date.now()}`);
+      const declareDraw = (player: Keypair) =>
+        program.methods
+          .declareResult({ draw: {} }, { agreement: {} })
+          .accounts({
+            gameEscrow: pdas.escrow,
+            player: player.publicKey,
+            gameVault: pdas.vault,
+            playerWhite: playerWhite.publicKey,
+            playerBlack: playerBlack.publicKey,
+            feeCollector: feeCollector.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([player])
+          .rpc();
+
+      // Neither with no offer on the table nor on the strength of the declarer's own
+      await offerDraw(pdas.escrow, playerWhite);
+      try {
+        await declareDraw(playerWhite);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "NoDrawOffer");
+      }
+
+      await declareDraw(playerBlack);
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.winner, { draw: {} });
+      assert.isNull(game.drawOfferedBy);
+    });
+
+    it("should lapse once the opponent plays on", async () => {
+      const pdas = await startGame(`draw-lapse-${Date.now()}`);
+      await offerDraw(pdas.escrow, playerBlack);
+      await recordMove(pdas.escrow, playerWhite, "e2", "e4", "P");
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.isNull(game.drawOfferedBy);
+    });
+  });
+
+  describe("settlement by agreement", () => {
+    const settleByAgreement = (
+      pdas: { escrow: PublicKey; vault: PublicKey },
+      winner: object,
+      reason: object,
+      black: Keypair = playerBlack
+    ) =>
+      program.methods
+        .settleByAgreement(winner, reason)
+        .accounts({
+          gameEscrow: pdas.escrow,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: black.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite, black])
+        .rpc();
+
+    it("should settle at once on a result both players sign", async () => {
+      const pdas = await startGame(`agree-settle-${Date.now()}`);
+      const [finished] = await collectEvents("gameFinished", () =>
+        settleByAgreement(pdas, { black: {} }, { resignation: {} })
+      );
+
+      assert.deepEqual(finished.winner, { black: {} });
+      assert.deepEqual(finished.reason, { resignation: {} });
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { finished: {} });
+      assert.isTrue(game.fundsDistributed);
+      assert.equal(await provider.connection.getBalance(pdas.vault), 0);
+    });
+
+    it("should reject a signer who isn't a player", async () => {
+      const pdas = await startGame(`agree-outsider-${Date.now()}`);
+      const outsider = await fundedKeypair(1);
+      try {
+        await settleByAgreement(pdas, { white: {} }, { resignation: {} }, outsider);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "ConstraintAddress");
+      }
+    });
+
+    it("should reject a reason that doesn't fit the winner", async () => {
+      const pdas = await startGame(`agree-mismatch-${Date.now()}`);
+      try {
+        await settleByAgreement(pdas, { white: {} }, { stalemate: {} });
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "InvalidWinnerDeclaration");
+      }
+    });
+  });
+
+  describe("dead vault sweeps", () => {
+    const setWindow = (seconds: number) =>
+      program.methods
+        .setDeadVaultWindow(seconds)
+        .accounts({ config: configPda, admin: provider.wallet.publicKey })
+        .rpc();
+
+    const sweep = async (pdas: { escrow: PublicKey; vault: PublicKey }) =>
+      program.methods
+        .sweepDeadVault()
+        .accounts({
+          gameEscrow: pdas.escrow,
+          gameVault: pdas.vault,
+          config: configPda,
+          admin: provider.wallet.publicKey,
+          treasury: (await program.account.programConfig.fetch(configPda)).feeCollector,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+    // An aborted game whose vault has picked up a stray transfer after its refunds
+    const deadGame = async (room: string) => {
+      const pdas = await startGame(room);
+      await program.methods
+        .mutualAbort()
+        .accounts({
+          gameEscrow: pdas.escrow,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite, playerBlack])
+        .rpc();
+      await provider.sendAndConfirm(
+        new Transaction().add(
+          SystemProgram.transfer({
+            fromPubkey: provider.wallet.publicKey,
+            toPubkey: pdas.vault,
+            lamports: LAMPORTS_PER_SOL / 100,
+          })
+        )
+      );
+      return pdas;
+    };
+
+    before(() => setWindow(2));
+    after(() => setWindow(0));
+
+    it("should leave a recently finished game alone", async () => {
+      const pdas = await deadGame(`sweep-recent-${Date.now()}`);
+      try {
+        await sweep(pdas);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "DeadVaultWindowNotElapsed");
+      }
+      assert.equal(await provider.connection.getBalance(pdas.vault), LAMPORTS_PER_SOL / 100);
+    });
+
+    it("should sweep the residue once the window has passed", async () => {
+      const pdas = await deadGame(`sweep-dead-${Date.now()}`);
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+
+      const [swept] = await collectEvents("deadVaultSwept", () => sweep(pdas));
+      assert.equal(swept.amount.toNumber(), LAMPORTS_PER_SOL / 100);
+      assert.equal(await provider.connection.getBalance(pdas.vault), 0);
+    });
+
+    it("should never touch a game still in progress", async () => {
+      const pdas = await startGame(`sweep-live-${Date.now()}`);
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+      try {
+        await sweep(pdas);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "GameNotSettled");
+      }
+    });
+  });
+
+  describe("resign", () => {
+    it("should pay the full pot less the fee to Black when White resigns", async () => {
+      const pdas = await startGame(`resign-${Date.now()}`);
+      const blackBefore = await provider.connection.getBalance(playerBlack.publicKey);
+      const feeBefore = await provider.connection.getBalance(feeCollector.publicKey);
+
+      const [finished] = await collectEvents("gameFinished", () =>
+        program.methods
+          .resign()
+          .accounts({
+            gameEscrow: pdas.escrow,
+            player: playerWhite.publicKey,
+            gameVault: pdas.vault,
+            playerWhite: playerWhite.publicKey,
+            playerBlack: playerBlack.publicKey,
+            feeCollector: feeCollector.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([playerWhite])
+          .rpc()
+      );
+
+      assert.deepEqual(finished.winner, { black: {} });
+      assert.deepEqual(finished.reason, { resignation: {} });
+      const totalStake = stakeAmount.toNumber() * 2;
+      const feeAmount = Math.floor(totalStake * 0.01);
+      assert.equal(
+        (await provider.connection.getBalance(playerBlack.publicKey)) - blackBefore,
+        totalStake - feeAmount
+      );
+      assert.equal(
+        (await provider.connection.getBalance(feeCollector.publicKey)) - feeBefore,
+        feeAmount
+      );
+      assert.equal(await provider.connection.getBalance(pdas.vault), 0);
+    });
+
+    it("should pay out a vault holding both stakes plus a rent reserve in full", async () => {
+      const pdas = await startGame(`resign-rent-${Date.now()}`);
+      const rentReserve = await provider.connection.getMinimumBalanceForRentExemption(0);
+      await provider.sendAndConfirm(
+        new Transaction().add(
+          SystemProgram.transfer({
+            fromPubkey: provider.wallet.publicKey,
+            toPubkey: pdas.vault,
+            lamports: rentReserve,
+          })
+        )
+      );
+      const vaultBefore = await provider.connection.getBalance(pdas.vault);
+      assert.equal(vaultBefore, stakeAmount.toNumber() * 2 + rentReserve);
+      const blackBefore = await provider.connection.getBalance(playerBlack.publicKey);
+      const feeBefore = await provider.connection.getBalance(feeCollector.publicKey);
+
+      await program.methods
+        .resign()
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerWhite.publicKey,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+
+      // Every lamport, reserve included, goes to the winner and the fee collector
+      const blackGain = (await provider.connection.getBalance(playerBlack.publicKey)) - blackBefore;
+      const feeGain = (await provider.connection.getBalance(feeCollector.publicKey)) - feeBefore;
+      assert.equal(blackGain + feeGain, vaultBefore);
+      assert.isNull(await provider.connection.getAccountInfo(pdas.vault));
+    });
+  });
+
+  describe("timing anomalies", () => {
+    const TIMING_ANOMALY_FLAG = 1 << 3;
+
+    const shuffleWithBlackTimes = async (room: string, blackTimeSpent: number) => {
+      const pdas = await startGame(room);
+      for (const [player, from, to, piece] of knightShuffle(6)) {
+        const timeSpent = player === playerBlack ? blackTimeSpent : 5000;
+        await recordMove(pdas.escrow, player, from, to, piece, { timeSpent });
+      }
+      return program.account.gameEscrow.fetch(pdas.escrow);
+    };
+
+    it("should flag a burst of instant answers in complex positions", async () => {
+      const game = await shuffleWithBlackTimes(`timing-burst-${Date.now()}`, 0);
+
+      assert.equal(game.antiCheatFlags & TIMING_ANOMALY_FLAG, TIMING_ANOMALY_FLAG);
+      assert.equal(game.fastMoveStreak[1], 3);
+      assert.equal(game.thinkTimeAvgMs[1], 0);
+      assert.equal(game.fastMoveStreak[0], 0);
+    });
+
+    it("should leave a normally paced game alone", async () => {
+      const game = await shuffleWithBlackTimes(`timing-normal-${Date.now()}`, 4000);
+
+      assert.equal(game.antiCheatFlags, 0);
+      assert.equal(game.fastMoveStreak[1], 0);
+      assert.equal(game.thinkTimeAvgMs[1], 4000);
+    });
+  });
+
+  describe("fee basis points", () => {
+    // Fee collected when White resigns a game created with `feeBps`
+    const feeAt = async (feeBps: number) => {
+      const pdas = await startGame(`fee-bps-${feeBps}-${Date.now()}`, {
+        ...defaultGameOptions(),
+        feeBps,
+      });
+      const before = await provider.connection.getBalance(feeCollector.publicKey);
+      await program.methods
+        .resign()
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerWhite.publicKey,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+      return (await provider.connection.getBalance(feeCollector.publicKey)) - before;
+    };
+
+    const pot = () => stakeAmount.toNumber() * 2;
+
+    it("should take nothing at 0 bps", async () => {
+      assert.equal(await feeAt(0), 0);
+    });
+
+    it("should take 2.5% at 250 bps", async () => {
+      assert.equal(await feeAt(250), Math.floor((pot() * 250) / 10000));
+    });
+
+    it("should take 10% at the maximum", async () => {
+      assert.equal(await feeAt(1000), Math.floor((pot() * 1000) / 10000));
+    });
+
+    it("should reject a fee above the maximum", async () => {
+      try {
+        await startGame(`fee-bps-over-${Date.now()}`, { ...defaultGameOptions(), feeBps: 1001 });
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "InvalidFeeBps");
+      }
+    });
+  });
+
+  describe("rematches", () => {
+    const rematchCall = (method: "proposeRematch" | "withdrawRematch", escrow: PublicKey, player: Keypair) =>
+      program.methods[method]()
+        .accounts({ gameEscrow: escrow, player: player.publicKey })
+        .signers([player])
+        .rpc();
+
+    const acceptRematch = (escrow: PublicKey, room: string, player: Keypair) =>
+      program.methods
+        .acceptRematch(room)
+        .accounts({
+          gameEscrow: escrow,
+          rematchEscrow: deriveGamePdas(room).escrow,
+          player: player.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([player])
+        .rpc();
+
+    const finishedGame = async (room: string) => {
+      const pdas = await startGame(room);
+      await program.methods
+        .resign()
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerWhite.publicKey,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+      return pdas;
+    };
+
+    it("should create a colour-swapped game on the same stake awaiting deposits", async () => {
+      const pdas = await finishedGame(`rematch-${Date.now()}`);
+      const rematchRoom = `rematch-next-${Date.now()}`;
+      await rematchCall("proposeRematch", pdas.escrow, playerWhite);
+      const [accepted] = await collectEvents("rematchAccepted", () =>
+        acceptRematch(pdas.escrow, rematchRoom, playerBlack)
+      );
+
+      assert.equal(accepted.rematchRoomId, rematchRoom);
+      const rematch = await program.account.gameEscrow.fetch(deriveGamePdas(rematchRoom).escrow);
+      assert.ok(rematch.playerWhite.equals(playerBlack.publicKey));
+      assert.ok(rematch.playerBlack.equals(playerWhite.publicKey));
+      assert.equal(rematch.stakeAmount.toString(), stakeAmount.toString());
+      assert.deepEqual(rematch.gameState, { waitingForDeposits: {} });
+      assert.equal(rematch.moveCount, 0);
+      assert.isFalse(rematch.whiteDeposited);
+      assert.isFalse(rematch.blackDeposited);
+
+      const previous = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.isNull(previous.rematchProposedBy);
+    });
+
+    it("should reject accepting your own proposal", async () => {
+      const pdas = await finishedGame(`rematch-own-${Date.now()}`);
+      await rematchCall("proposeRematch", pdas.escrow, playerWhite);
+      try {
+        await acceptRematch(pdas.escrow, `rematch-own-next-${Date.now()}`, playerWhite);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "CannotAcceptOwnRematch");
+      }
+    });
+
+    it("should fail once the proposal is withdrawn", async () => {
+      const pdas = await finishedGame(`rematch-withdrawn-${Date.now()}`);
+      await rematchCall("proposeRematch", pdas.escrow, playerWhite);
+      await rematchCall("withdrawRematch", pdas.escrow, playerWhite);
+      try {
+        await acceptRematch(pdas.escrow, `rematch-withdrawn-next-${Date.now()}`, playerBlack);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "NoRematchProposed");
+      }
+    });
+  });
+
+  describe("elo updates", () => {
+    // Fresh players rated 1400 (White) and 1800 (Black); new ratings use K = 32
+    const unevenRatedGame = async (label: string) => {
+      const white = await fundedKeypair();
+      const black = await fundedKeypair();
+      for (const [player, rating] of [[white, 1400], [black, 1800]] as [Keypair, number][]) {
+        await ensurePlayerRating(player);
+        await program.methods
+          .recalcRatingFromGames(rating, [])
+          .accounts({
+            config: configPda,
+            admin: provider.wallet.publicKey,
+            playerRating: ratingPdaOf(player.publicKey),
+          })
+          .rpc();
+      }
+      const pdas = await startGame(
+        `elo-${label}-${Date.now()}`,
+        { ...defaultGameOptions(), isRated: true },
+        white,
+        black
+      );
+      return { pdas, white, black };
+    };
+
+    const finish = (
+      game: Awaited<ReturnType<typeof unevenRatedGame>>,
+      signer: Keypair,
+      call: (methods: typeof program.methods) => any
+    ) =>
+      call(program.methods)
+        .accounts({
+          gameEscrow: game.pdas.escrow,
+          player: signer.publicKey,
+          gameVault: game.pdas.vault,
+          playerWhite: game.white.publicKey,
+          playerBlack: game.black.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+          whiteRating: ratingPdaOf(game.white.publicKey),
+          blackRating: ratingPdaOf(game.black.publicKey),
+        })
+        .signers([signer])
+        .rpc();
+
+    const ratingsAfter = async (game: Awaited<ReturnType<typeof unevenRatedGame>>) => {
+      const white = await program.account.playerRating.fetch(ratingPdaOf(game.white.publicKey));
+      const black = await program.account.playerRating.fetch(ratingPdaOf(game.black.publicKey));
+      assert.equal(white.gamesPlayed, 1);
+      assert.equal(black.gamesPlayed, 1);
+      return [white.rating, black.rating];
+    };
+
+    it("should move ratings a long way on an upset", async () => {
+      const game = await unevenRatedGame("upset");
+      await finish(game, game.black, (methods) => methods.resign());
+      assert.deepEqual(await ratingsAfter(game), [1429, 1771]);
+    });
+
+    it("should barely move ratings on an expected win", async () => {
+      const game = await unevenRatedGame("expected");
+      await finish(game, game.white, (methods) => methods.resign());
+      assert.deepEqual(await ratingsAfter(game), [1397, 1803]);
+    });
+
+    it("should favour the lower-rated player on a draw", async () => {
+      const game = await unevenRatedGame("draw");
+      await offerDraw(game.pdas.escrow, game.black);
+      await finish(game, game.white, (methods) => methods.declareResult({ draw: {} }, { agreement: {} }));
+      assert.deepEqual(await ratingsAfter(game), [1413, 1787]);
+    });
+  });
+
+  describe("promotion consistency", () => {
+    // White's e-pawn marches to e7 while Black's king steps off e8
+    const pawnOnSeventh = async (room: string) => {
+      const pdas = await startGame(room);
+      await playMoves(pdas.escrow, [
+        ["e2", "e4", "P"],
+        ["f7", "f5", "p"],
+        ["e4", "f5", "P", { captured: "p" }],
+        ["e7", "e5", "p"],
+        ["f5", "e6", "P", { captured: "p", enPassant: true }],
+        ["g8", "f6", "n"],
+        ["e6", "e7", "P"],
+        ["e8", "f7", "k"],
+      ]);
+      return pdas;
+    };
+
+    it("should accept a queen promotion on the back rank", async () => {
+      const pdas = await pawnOnSeventh(`promote-queen-${Date.now()}`);
+      await recordMove(pdas.escrow, playerWhite, "e7", "e8", "P", { promotion: "Q", check: true });
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(pieceAt(game.board, "e8"), "Q");
+    });
+
+    it("should reject promoting to a king", async () => {
+      const pdas = await pawnOnSeventh(`promote-king-${Date.now()}`);
+      try {
+        await recordMove(pdas.escrow, playerWhite, "e7", "e8", "P", { promotion: "K" });
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "InvalidPromotion");
+      }
+    });
+
+    it("should reject a pawn reaching the back rank without promoting", async () => {
+      const pdas = await pawnOnSeventh(`promote-missing-${Date.now()}`);
+      try {
+        await recordMove(pdas.escrow, playerWhite, "e7", "e8", "P");
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "InvalidPromotion");
+      }
+    });
+  });
+
+  describe("position replay", () => {
+    const replay = async (escrow: PublicKey) => {
+      const [event] = await collectEvents("positionReplayed", () =>
+        program.methods.replayPosition().accounts({ gameEscrow: escrow }).rpc()
+      );
+      return event;
+    };
+
+    it("should rebuild the board from the move history", async () => {
+      const pdas = await startGame(`replay-${Date.now()}`);
+      await recordMove(pdas.escrow, playerWhite, "e2", "e4", "P");
+      await recordMove(pdas.escrow, playerBlack, "e7", "e5", "p");
+      await recordMove(pdas.escrow, playerWhite, "g1", "f3", "N");
+
+      const event = await replay(pdas.escrow);
+
+      assert.equal(event.moveCount, 3);
+      assert.isTrue(event.matchesStored);
+      assert.equal(pieceAt(event.board, "e4"), "P");
+      assert.equal(pieceAt(event.board, "e5"), "p");
+      assert.equal(pieceAt(event.board, "f3"), "N");
+      for (const square of ["e2", "e7", "g1"]) assert.equal(pieceAt(event.board, square), "");
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(Array.from(event.board), Array.from(game.board));
+    });
+
+    it("should refuse to replay once the window has evicted a move", async () => {
+      const pdas = await startGame(`replay-evicted-${Date.now()}`, {
+        ...defaultGameOptions(),
+        moveHistoryCapacity: 4,
+      });
+      for (const [player, from, to, piece] of knightShuffle(6)) {
+        await recordMove(pdas.escrow, player, from, to, piece);
+      }
+
+      try {
+        await replay(pdas.escrow);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "MoveHistoryIncomplete");
+      }
+    });
+  });
+
+  describe("position hash commitment", () => {
+    const e4WithHash = (escrow: PublicKey, hash: number[]) =>
+      program.methods
+        .recordMove(moveSubmission("e2", "e4", "P", hash), new anchor.BN(1000), 0)
+        .accounts({ gameEscrow: escrow, player: playerWhite.publicKey })
+        .signers([playerWhite])
+        .rpc();
+
+    const stateAfterE4 = async (escrow: PublicKey) =>
+      playOnBoard(boardState(await program.account.gameEscrow.fetch(escrow)), "e2", "e4", "P");
+
+    it("should accept a hash of the resulting position", async () => {
+      const pdas = await startGame(`position-hash-${Date.now()}`);
+      const state = await stateAfterE4(pdas.escrow);
+      assert.equal(state.enPassant, squareIndex("e3"));
+
+      await e4WithHash(pdas.escrow, positionDigest(state));
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.moveCount, 1);
+      assert.deepEqual(Array.from(game.board), state.board);
+    });
+
+    it("should reject a tampered or missing hash", async () => {
+      const pdas = await startGame(`position-hash-tampered-${Date.now()}`);
+      const state = await stateAfterE4(pdas.escrow);
+      for (const hash of [
+        // Claims e4 was played without leaving an en-passant target
+        positionDigest({ ...state, enPassant: null }),
+        // Claims White has lost the right to castle
+        positionDigest({ ...state, castlingRights: state.castlingRights & ~3 }),
+        Array(32).fill(0),
+      ]) {
+        try {
+          await e4WithHash(pdas.escrow, hash);
+          assert.fail("Should have thrown an error");
+        } catch (error) {
+          assert.include(error.toString(), "PositionHashMismatch");
+        }
+      }
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.moveCount, 0);
+    });
+  });
+
+  describe("fischer increment", () => {
+    const incrementGame = (room: string, timeLimit: anchor.BN) =>
+      startGame(`${room}-${Date.now()}`, { ...defaultGameOptions(), incrementSeconds: 5 }, playerWhite, playerBlack, timeLimit);
+
+    it("should credit the increment after every move", async () => {
+      const pdas = await incrementGame("fischer-credit", timeLimitSeconds);
+      const limit = timeLimitSeconds.toNumber();
+      let previous = await program.account.gameEscrow.fetch(pdas.escrow);
+
+      for (const [player, from, to, piece] of knightShuffle(4)) {
+        await recordMove(pdas.escrow, player, from, to, piece);
+        const game = await program.account.gameEscrow.fetch(pdas.escrow);
+        const elapsed = game.lastMoveTime.toNumber() - previous.lastMoveTime.toNumber();
+        const isWhite = player === playerWhite;
+        const before = (isWhite ? previous.whiteTimeRemaining : previous.blackTimeRemaining).toNumber();
+        const after = (isWhite ? game.whiteTimeRemaining : game.blackTimeRemaining).toNumber();
+        assert.equal(after, before - elapsed + 5);
+        previous = game;
+      }
+      assert.isAbove(previous.whiteTimeRemaining.toNumber(), limit - 4);
+    });
+
+    it("should award the game to the opponent of the player who flags", async () => {
+      const pdas = await incrementGame("fischer-flag", new anchor.BN(6));
+      await recordMove(pdas.escrow, playerWhite, "e2", "e4", "P");
+
+      // Black never answers; White's clock holds the increment while Black's runs out
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      const deadline = game.lastMoveTime.toNumber() + game.blackTimeRemaining.toNumber();
+      for (;;) {
+        const blockTime = await provider.connection.getBlockTime(await provider.connection.getSlot());
+        if (blockTime !== null && blockTime > deadline) break;
+        await new Promise((resolve) => setTimeout(resolve, 500));
+      }
+      const claimant = await timeoutClaimant(pdas.escrow);
+      const [finished] = await collectEvents("gameFinished", () =>
+        program.methods
+          .handleTimeout()
+          .accounts({
+            gameEscrow: pdas.escrow,
+            claimant: claimant.publicKey,
+            gameVault: pdas.vault,
+            playerWhite: playerWhite.publicKey,
+            playerBlack: playerBlack.publicKey,
+            feeCollector: feeCollector.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([claimant])
+          .rpc()
+      );
+
+      assert.deepEqual(finished.winner, { white: {} });
+      assert.deepEqual(finished.reason, { timeout: {} });
+      assert.isAbove(game.whiteTimeRemaining.toNumber(), 0);
+    });
+  });
+
+  describe("anti-cheat escalation", () => {
+    const BELOW_MOVE_FLOOR_FLAG = 1 << 1;
+    const floorOptions = (antiCheatReviewThreshold: number) => ({
+      ...defaultGameOptions(),
+      minMoveIntervalMs: 300,
+      antiCheatReviewThreshold,
+    });
+
+    const resign = (pdas: { escrow: PublicKey; vault: PublicKey }) =>
+      program.methods
+        .resign()
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerWhite.publicKey,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+
+    const clearReview = (escrow: PublicKey) =>
+      program.methods
+        .clearAntiCheatReview()
+        .accounts({ gameEscrow: escrow, config: configPda, moderator: provider.wallet.publicKey })
+        .rpc();
+
+    it("should announce each newly raised flag once", async () => {
+      const pdas = await startGame(`flag-event-${Date.now()}`, floorOptions(0));
+      const first = await collectEvents("antiCheatFlagged", () =>
+        recordMove(pdas.escrow, playerWhite, "g1", "f3", "N", { timeSpent: 100 })
+      );
+      assert.lengthOf(first, 1);
+      assert.equal(first[0].flagBits, BELOW_MOVE_FLOOR_FLAG);
+      assert.ok(first[0].player.equals(playerWhite.publicKey));
+      assert.equal(first[0].moveCount, 1);
+
+      // The same flag again raises nothing new
+      const second = await collectEvents("antiCheatFlagged", () =>
+        recordMove(pdas.escrow, playerBlack, "g8", "f6", "n", { timeSpent: 100 })
+      );
+      assert.lengthOf(second, 0);
+    });
+
+    it("should hold the pot for review once the threshold is reached", async () => {
+      const pdas = await startGame(`flag-review-${Date.now()}`, floorOptions(1));
+      const [required] = await collectEvents("antiCheatReviewRequired", () =>
+        recordMove(pdas.escrow, playerWhite, "g1", "f3", "N", { timeSpent: 100 })
+      );
+      assert.equal(required.antiCheatFlags, BELOW_MOVE_FLOOR_FLAG);
+
+      try {
+        await resign(pdas);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "AntiCheatReviewPending");
+      }
+
+      await program.methods
+        .addModerator(provider.wallet.publicKey)
+        .accounts({ config: configPda, admin: provider.wallet.publicKey })
+        .rpc();
+      try {
+        await clearReview(pdas.escrow);
+      } finally {
+        await program.methods
+          .removeModerator(provider.wallet.publicKey)
+          .accounts({ config: configPda, admin: provider.wallet.publicKey })
+          .rpc();
+      }
+
+      await resign(pdas);
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.isFalse(game.reviewRequired);
+      assert.isTrue(game.fundsDistributed);
+      assert.equal(game.antiCheatFlags & BELOW_MOVE_FLOOR_FLAG, BELOW_MOVE_FLOOR_FLAG);
+    });
+
+    it("should only let a moderator clear a review", async () => {
+      const pdas = await startGame(`flag-review-unlisted-${Date.now()}`, floorOptions(1));
+      await recordMove(pdas.escrow, playerWhite, "g1", "f3", "N", { timeSpent: 100 });
+      try {
+        await clearReview(pdas.escrow);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "ModeratorNotListed");
+      }
+    });
+  });
+
+  describe("side bets", () => {
+    const sideBetPdas = (escrow: PublicKey, bettor: PublicKey) => ({
+      sideBet: PublicKey.findProgramAddressSync(
+        [Buffer.from("sidebet"), escrow.toBuffer(), bettor.toBuffer()],
+        program.programId
+      )[0],
+      sideBetVault: PublicKey.findProgramAddressSync(
+        [Buffer.from("sidebet_vault"), escrow.toBuffer()],
+        program.programId
+      )[0],
+    });
+
+    const placeBet = (escrow: PublicKey, bettor: Keypair, predictedWinner: object, amount: anchor.BN) =>
+      program.methods
+        .placeSideBet(predictedWinner as any, amount)
+        .accounts({
+          gameEscrow: escrow,
+          ...sideBetPdas(escrow, bettor.publicKey),
+          bettor: bettor.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([bettor])
+        .rpc();
+
+    const settleBet = (escrow: PublicKey, bettor: PublicKey) =>
+      program.methods
+        .settleSideBet()
+        .accounts({
+          gameEscrow: escrow,
+          ...sideBetPdas(escrow, bettor),
+          bettor,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+    const resign = (pdas: { escrow: PublicKey; vault: PublicKey }) =>
+      program.methods
+        .resign()
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerWhite.publicKey,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+
+    it("should pay winning bets their share of the losing side", async () => {
+      const pdas = await startGame(`side-bets-${Date.now()}`);
+      const [onBlack, alsoOnBlack, onWhite] = await Promise.all([
+        fundedKeypair(),
+        fundedKeypair(),
+        fundedKeypair(),
+      ]);
+      await placeBet(pdas.escrow, onBlack, { black: {} }, new anchor.BN(LAMPORTS_PER_SOL));
+      await placeBet(pdas.escrow, alsoOnBlack, { black: {} }, new anchor.BN(3 * LAMPORTS_PER_SOL));
+      await placeBet(pdas.escrow, onWhite, { white: {} }, new anchor.BN(2 * LAMPORTS_PER_SOL));
+
+      let game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(
+        game.sideBetTotals.map((total: anchor.BN) => total.toNumber()),
+        [2 * LAMPORTS_PER_SOL, 4 * LAMPORTS_PER_SOL, 0]
+      );
+      assert.equal(game.openSideBets, 3);
+
+      // White resigns, so the 2 SOL backing White is split 1:3 between the Black bets
+      await resign(pdas);
+
+      const [settled] = await collectEvents("sideBetSettled", () =>
+        settleBet(pdas.escrow, onBlack.publicKey)
+      );
+      assert.equal(settled.payout.toNumber(), 1.5 * LAMPORTS_PER_SOL);
+
+      const [lost] = await collectEvents("sideBetSettled", () =>
+        settleBet(pdas.escrow, onWhite.publicKey)
+      );
+      assert.equal(lost.payout.toNumber(), 0);
+      const lostBet = await program.account.sideBet.fetchNullable(
+        sideBetPdas(pdas.escrow, onWhite.publicKey).sideBet
+      );
+      assert.isNull(lostBet);
+
+      const before = await provider.connection.getBalance(alsoOnBlack.publicKey);
+      await settleBet(pdas.escrow, alsoOnBlack.publicKey);
+      const after = await provider.connection.getBalance(alsoOnBlack.publicKey);
+      // The last bet settled also collects the vault's rent reserve and the bet account's rent
+      assert.isAtLeast(after - before, 4.5 * LAMPORTS_PER_SOL);
+
+      const vault = sideBetPdas(pdas.escrow, onBlack.publicKey).sideBetVault;
+      assert.equal(await provider.connection.getBalance(vault), 0);
+      game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.openSideBets, 0);
+    });
+
+    it("should refund every bet when nobody backed the result", async () => {
+      const pdas = await startGame(`side-bets-unbacked-${Date.now()}`);
+      const bettor = await fundedKeypair();
+      await placeBet(pdas.escrow, bettor, { white: {} }, new anchor.BN(LAMPORTS_PER_SOL));
+      await resign(pdas);
+
+      const [settled] = await collectEvents("sideBetSettled", () =>
+        settleBet(pdas.escrow, bettor.publicKey)
+      );
+      assert.equal(settled.payout.toNumber(), LAMPORTS_PER_SOL);
+    });
+
+    it("should close betting once the first move is played", async () => {
+      const pdas = await startGame(`side-bets-closed-${Date.now()}`);
+      await recordMove(pdas.escrow, playerWhite, "e2", "e4", "P");
+      const bettor = await fundedKeypair();
+      try {
+        await placeBet(pdas.escrow, bettor, { draw: {} }, new anchor.BN(LAMPORTS_PER_SOL));
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "SideBetsClosed");
+      }
+    });
+
+    it("should not let the players bet on their own game", async () => {
+      const pdas = await startGame(`side-bets-player-${Date.now()}`);
+      try {
+        await placeBet(pdas.escrow, playerBlack, { white: {} }, new anchor.BN(LAMPORTS_PER_SOL));
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "PlayersCannotSideBet");
+      }
+    });
+
+    it("should hold bets until the pot is paid, so a challenge can still overturn the mate", async () => {
+      const pdas = await startGame(`side-bets-challenge-${Date.now()}`, {
+        ...defaultGameOptions(),
+        optimisticValidation: true,
+      });
+      const bettor = await fundedKeypair();
+      await placeBet(pdas.escrow, bettor, { white: {} }, new anchor.BN(LAMPORTS_PER_SOL));
+
+      // Scholar's mate, with the queen jumping straight from d1 to f7
+      await playMoves(pdas.escrow, [
+        ["e2", "e4", "P"],
+        ["e7", "e5", "p"],
+        ["f1", "c4", "B"],
+        ["b8", "c6", "n"],
+        ["d1", "f7", "Q", { captured: "p", check: true, checkmate: true }],
+      ]);
+      let game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { finished: {} });
+      assert.isFalse(game.fundsDistributed);
+      try {
+        await settleBet(pdas.escrow, bettor.publicKey);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "GameNotSettled");
+      }
+
+      await program.methods
+        .challengeLastMove()
+        .accounts({ gameEscrow: pdas.escrow, player: playerBlack.publicKey })
+        .signers([playerBlack])
+        .rpc();
+      game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { inProgress: {} });
+      assert.equal(game.openSideBets, 1);
+    });
+
+    it("should not settle bets before the game ends", async () => {
+      const pdas = await startGame(`side-bets-early-${Date.now()}`);
+      const bettor = await fundedKeypair();
+      await placeBet(pdas.escrow, bettor, { white: {} }, new anchor.BN(LAMPORTS_PER_SOL));
+      try {
+        await settleBet(pdas.escrow, bettor.publicKey);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "GameNotFinished");
+      }
+    });
+  });
+
+  describe("stalemate claims", () => {
+    const declare = (pdas: { escrow: PublicKey; vault: PublicKey }, declarer: Keypair, winner: object, reason: object) =>
+      program.methods
+        .declareResult(winner, reason)
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: declarer.publicKey,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([declarer])
+        .rpc();
+
+    it("should accept a draw claim on a real stalemate", async () => {
+      const pdas = await startGame(`stalemate-loyd-${Date.now()}`);
+      // Sam Loyd's ten-move stalemate: Black to move and nothing is legal
+      await playMoves(pdas.escrow, [
+        ["e2", "e3", "P"],
+        ["a7", "a5", "p"],
+        ["d1", "h5", "Q"],
+        ["a8", "a6", "r"],
+        ["h5", "a5", "Q", { captured: "p" }],
+        ["h7", "h5", "p"],
+        ["h2", "h4", "P"],
+        ["a6", "h6", "r"],
+        ["a5", "c7", "Q", { captured: "p" }],
+        ["f7", "f6", "p"],
+        ["c7", "d7", "Q", { captured: "p", check: true }],
+        ["e8", "f7", "k"],
+        ["d7", "b7", "Q", { captured: "p" }],
+        ["d8", "d3", "q"],
+        ["b7", "b8", "Q", { captured: "n" }],
+        ["d3", "h7", "q"],
+        ["b8", "c8", "Q", { captured: "b" }],
+        ["f7", "g6", "k"],
+        ["c8", "e6", "Q"],
+      ]);
+
+      const [event] = await collectEvents("gameFinished", () =>
+        declare(pdas, playerBlack, { draw: {} }, { stalemate: {} })
+      );
+      assert.deepEqual(event.winner, { draw: {} });
+      assert.deepEqual(event.reason, { stalemate: {} });
+    });
+
+    it("should rule a claimed stalemate that is mate as checkmate", async () => {
+      const pdas = await startGame(`stalemate-mated-${Date.now()}`);
+      // Fool's mate, recorded without the checkmate flag so the game stays open
+      await playMoves(pdas.escrow, [
+        ["f2", "f3", "P"],
+        ["e7", "e5", "p"],
+        ["g2", "g4", "P"],
+        ["d8", "h4", "q", { check: true }],
+      ]);
+
+      const [event] = await collectEvents("gameFinished", () =>
+        declare(pdas, playerWhite, { draw: {} }, { stalemate: {} })
+      );
+      assert.deepEqual(event.winner, { black: {} });
+      assert.deepEqual(event.reason, { checkmate: {} });
+    });
+
+    it("should reject a stalemate claim while moves remain", async () => {
+      const pdas = await startGame(`stalemate-bogus-${Date.now()}`);
+      await playMoves(pdas.escrow, [
+        ["e2", "e4", "P"],
+        ["e7", "e5", "p"],
+      ]);
+      try {
+        await declare(pdas, playerWhite, { draw: {} }, { stalemate: {} });
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "NotStalemate");
+      }
+    });
+
+    it("should reject a stalemate claim in an atomic game", async () => {
+      const pdas = await startGame(`stalemate-atomic-${Date.now()}`, {
+        ...defaultGameOptions(),
+        variant: { atomic: {} },
+      });
+      await playMoves(pdas.escrow, [
+        ["e2", "e4", "P"],
+        ["e7", "e5", "p"],
+      ]);
+      try {
+        await declare(pdas, playerWhite, { draw: {} }, { stalemate: {} });
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "NotStalemate");
+      }
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { inProgress: {} });
+    });
+  });
+
+  describe("move delay", () => {
+    const delayOptions = () => ({ ...defaultGameOptions(), delaySeconds: 5 });
+
+    it("should not charge a move made inside the delay", async () => {
+      const { escrow } = await startGame(`delay-free-${Date.now()}`, delayOptions());
+      await recordMove(escrow, playerWhite, "e2", "e4", "P", { timeSpent: 1_000 });
+
+      const game = await program.account.gameEscrow.fetch(escrow);
+      assert.equal(game.timeControl.delay.toNumber(), 5);
+      assert.equal(game.whiteTimeRemaining.toNumber(), timeLimitSeconds.toNumber());
+    });
+
+    it("should flag a mover once the delay and their clock have both run out", async () => {
+      const shortClock = new anchor.BN(2);
+      const pdas = await startGame(
+        `delay-flag-${Date.now()}`,
+        delayOptions(),
+        playerWhite,
+        playerBlack,
+        shortClock
+      );
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      const deadline = game.lastMoveTime.toNumber() + 5 + shortClock.toNumber();
+      for (;;) {
+        const blockTime = await provider.connection.getBlockTime(await provider.connection.getSlot());
+        if (blockTime !== null && blockTime > deadline) break;
+        await new Promise((resolve) => setTimeout(resolve, 500));
+      }
+
+      try {
+        await recordMove(pdas.escrow, playerWhite, "e2", "e4", "P");
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "MoveTimeExceeded");
+      }
+
+      const claimant = await timeoutClaimant(pdas.escrow);
+      const [event] = await collectEvents("gameFinished", () =>
+        program.methods
+          .handleTimeout()
+          .accounts({
+            gameEscrow: pdas.escrow,
+            claimant: claimant.publicKey,
+            gameVault: pdas.vault,
+            playerWhite: playerWhite.publicKey,
+            playerBlack: playerBlack.publicKey,
+            feeCollector: feeCollector.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([claimant])
+          .rpc()
+      );
+      assert.deepEqual(event.winner, { black: {} });
+      assert.deepEqual(event.reason, { timeout: {} });
+    });
+  });
+
+  describe("fee collector enforcement", () => {
+    const setEnforced = (enforced: boolean) =>
+      program.methods
+        .setFeeCollectorEnforced(enforced)
+        .accounts({ config: configPda, admin: provider.wallet.publicKey })
+        .rpc();
+
+    const createGame = (room: string, collector: PublicKey) =>
+      program.methods
+        .initializeGame(room, stakeAmount, timeLimitSeconds, defaultGameOptions())
+        .accounts({
+          gameEscrow: deriveGamePdas(room).escrow,
+          player: playerWhite.publicKey,
+          feeCollector: collector,
+          config: configPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+
+    it("should only accept the config's collector while enforced", async () => {
+      const canonical = (await program.account.programConfig.fetch(configPda)).feeCollector;
+      await setEnforced(true);
+      try {
+        const room = `enforced-collector-${Date.now()}`;
+        await createGame(room, canonical);
+        const game = await program.account.gameEscrow.fetch(deriveGamePdas(room).escrow);
+        assert.ok(game.feeCollector.equals(canonical));
+
+        try {
+          await createGame(`arbitrary-collector-${Date.now()}`, Keypair.generate().publicKey);
+          assert.fail("Should have thrown an error");
+        } catch (error) {
+          assert.include(error.toString(), "FeeCollectorNotAllowed");
+        }
+      } finally {
+        await setEnforced(false);
+      }
+
+      // Lifting enforcement accepts any collector again
+      const room = `any-collector-${Date.now()}`;
+      const collector = Keypair.generate().publicKey;
+      await createGame(room, collector);
+      const game = await program.account.gameEscrow.fetch(deriveGamePdas(room).escrow);
+      assert.ok(game.feeCollector.equals(collector));
+    });
+
+    it("should only let the admin change enforcement", async () => {
+      const outsider = await fundedKeypair();
+      try {
+        await program.methods
+          .setFeeCollectorEnforced(true)
+          .accounts({ config: configPda, admin: outsider.publicKey })
+          .signers([outsider])
+          .rpc();
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "UnauthorizedAdmin");
+      }
+    });
+  });
+
+  describe("global pause", () => {
+    const setPaused = (paused: boolean) =>
+      program.methods
+        .setPaused(paused)
+        .accounts({ config: configPda, admin: provider.wallet.publicKey })
+        .rpc();
+
+    it("should block deposits and new games while paused but let running games finish", async () => {
+      const running = await startGame(`pause-running-${Date.now()}`);
+      const room = `pause-waiting-${Date.now()}`;
+      const waiting = deriveGamePdas(room);
+      await program.methods
+        .initializeGame(room, stakeAmount, timeLimitSeconds, defaultGameOptions())
+        .accounts({
+          gameEscrow: waiting.escrow,
+          player: playerWhite.publicKey,
+          feeCollector: feeCollector.publicKey,
+          config: configPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+      await program.methods
+        .joinGame()
+        .accounts({ gameEscrow: waiting.escrow, player: playerBlack.publicKey, playerRating: null, config: configPda })
+        .signers([playerBlack])
+        .rpc();
+
+      await setPaused(true);
+      try {
+        try {
+          await program.methods
+            .depositStake()
+            .accounts({
+              gameEscrow: waiting.escrow,
+              player: playerWhite.publicKey,
+              gameVault: waiting.vault,
+              systemProgram: SystemProgram.programId,
+              config: configPda,
+            })
+            .signers([playerWhite])
+            .rpc();
+          assert.fail("Should have thrown an error");
+        } catch (error) {
+          assert.include(error.toString(), "ProgramPaused");
+        }
+
+        try {
+          const blocked = `pause-new-${Date.now()}`;
+          await program.methods
+            .initializeGame(blocked, stakeAmount, timeLimitSeconds, defaultGameOptions())
+            .accounts({
+              gameEscrow: deriveGamePdas(blocked).escrow,
+              player: playerWhite.publicKey,
+              feeCollector: feeCollector.publicKey,
+              config: configPda,
+              systemProgram: SystemProgram.programId,
+            })
+            .signers([playerWhite])
+            .rpc();
+          assert.fail("Should have thrown an error");
+        } catch (error) {
+          assert.include(error.toString(), "ProgramPaused");
+        }
+
+        // A game already under way still settles and pays out
+        await program.methods
+          .declareResult({ black: {} }, { resignation: {} })
+          .accounts({
+            gameEscrow: running.escrow,
+            player: playerWhite.publicKey,
+            gameVault: running.vault,
+            playerWhite: playerWhite.publicKey,
+            playerBlack: playerBlack.publicKey,
+            feeCollector: feeCollector.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([playerWhite])
+          .rpc();
+        const game = await program.account.gameEscrow.fetch(running.escrow);
+        assert.deepEqual(game.gameState, { finished: {} });
+        assert.isTrue(game.fundsDistributed);
+      } finally {
+        await setPaused(false);
+      }
+    });
+
+    it("should only let the admin pause", async () => {
+      const outsider = await fundedKeypair();
+      try {
+        await program.methods
+          .setPaused(true)
+          .accounts({ config: configPda, admin: outsider.publicKey })
+          .signers([outsider])
+          .rpc();
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "UnauthorizedAdmin");
+      }
+    });
+  });
+
+  describe("token games", () => {
+    const tokenStake = 1_000_000;
+    let mint: PublicKey;
+    const tokenAccounts = new Map<string, PublicKey>();
+    const tokenAccountOf = (owner: Keypair) => tokenAccounts.get(owner.publicKey.toString());
+    const tokenBalance = async (address: PublicKey) =>
+      Number((await getAccount(provider.connection, address)).amount);
+
+    before(async () => {
+      const payer = (provider.wallet as anchor.Wallet).payer;
+      mint = await createMint(provider.connection, payer, payer.publicKey, null, 6);
+      for (const owner of [playerWhite, playerBlack, feeCollector]) {
+        const account = await getOrCreateAssociatedTokenAccount(
+          provider.connection,
+          payer,
+          mint,
+          owner.publicKey
+        );
+        tokenAccounts.set(owner.publicKey.toString(), account.address);
+      }
+      for (const player of [playerWhite, playerBlack]) {
+        await mintTo(provider.connection, payer, mint, tokenAccountOf(player), payer, 10 * tokenStake);
+      }
+    });
+
+    const depositTokens = (
+      pdas: { escrow: PublicKey; vault: PublicKey; vaultTokenAccount: PublicKey },
+      player: Keypair
+    ) =>
+      program.methods
+        .depositTokenStake()
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: player.publicKey,
+          gameVault: pdas.vault,
+          mint,
+          playerTokenAccount: tokenAccountOf(player),
+          vaultTokenAccount: pdas.vaultTokenAccount,
+        })
+        .signers([player])
+        .rpc();
+
+    // Both players join and stake `tokenStake` of the mint; no SOL is staked
+    const startTokenGame = async (room: string) => {
+      const pdas = deriveGamePdas(room);
+      const vaultTokenAccount = getAssociatedTokenAddressSync(mint, pdas.vault, true);
+      await program.methods
+        .initializeGame(room, new anchor.BN(tokenStake), timeLimitSeconds, {
+          ...defaultGameOptions(),
+          tokenStakeMint: mint,
+        })
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerWhite.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+      await program.methods
+        .joinGame()
+        .accounts({ gameEscrow: pdas.escrow, player: playerBlack.publicKey })
+        .signers([playerBlack])
+        .rpc();
+      const tokenPdas = { ...pdas, vaultTokenAccount };
+      for (const player of [playerWhite, playerBlack]) {
+        await depositTokens(tokenPdas, player);
+      }
+      return tokenPdas;
+    };
+
+    const settleTokenStake = (pdas: { escrow: PublicKey; vault: PublicKey; vaultTokenAccount: PublicKey }) =>
+      program.methods
+        .settleTokenStake()
+        .accounts({
+          gameEscrow: pdas.escrow,
+          gameVault: pdas.vault,
+          mint,
+          vaultTokenAccount: pdas.vaultTokenAccount,
+          whiteTokenAccount: tokenAccountOf(playerWhite),
+          blackTokenAccount: tokenAccountOf(playerBlack),
+          feeTokenAccount: tokenAccountOf(feeCollector),
+        })
+        .rpc();
+
+    it("should escrow both stakes in the token and pay the winner less the fee", async () => {
+      const pdas = await startTokenGame(`token-game-${Date.now()}`);
+      let game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { inProgress: {} });
+      assert.isTrue(game.tokenGame);
+      assert.equal(await tokenBalance(pdas.vaultTokenAccount), 2 * tokenStake);
+      assert.equal(await provider.connection.getBalance(pdas.vault), 0);
+
+      const blackBefore = await tokenBalance(tokenAccountOf(playerBlack));
+      const feeBefore = await tokenBalance(tokenAccountOf(feeCollector));
+      await program.methods
+        .declareResult({ black: {} }, { resignation: {} })
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerWhite.publicKey,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+      const [settled] = await collectEvents("tokenPotSettled", () => settleTokenStake(pdas));
+
+      // Same 2% pot rake as a SOL game
+      const fee = (2 * tokenStake * 2) / 100;
+      assert.equal(settled.feeAmount.toNumber(), fee);
+      assert.equal(settled.whiteAmount.toNumber(), 0);
+      assert.equal(settled.blackAmount.toNumber(), 2 * tokenStake - fee);
+      assert.equal((await tokenBalance(tokenAccountOf(playerBlack))) - blackBefore, 2 * tokenStake - fee);
+      assert.equal((await tokenBalance(tokenAccountOf(feeCollector))) - feeBefore, fee);
+      assert.equal(await tokenBalance(pdas.vaultTokenAccount), 0);
+
+      game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.isTrue(game.tokenFundsDistributed);
+    });
+
+    it("should return each token stake when the game is aborted", async () => {
+      const pdas = await startTokenGame(`token-abort-${Date.now()}`);
+      const whiteBefore = await tokenBalance(tokenAccountOf(playerWhite));
+      const blackBefore = await tokenBalance(tokenAccountOf(playerBlack));
+      await program.methods
+        .mutualAbort()
+        .accounts({
+          gameEscrow: pdas.escrow,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite, playerBlack])
+        .rpc();
+      await settleTokenStake(pdas);
+
+      assert.equal((await tokenBalance(tokenAccountOf(playerWhite))) - whiteBefore, tokenStake);
+      assert.equal((await tokenBalance(tokenAccountOf(playerBlack))) - blackBefore, tokenStake);
+    });
+
+    it("should refuse a SOL deposit in a token game", async () => {
+      const room = `token-sol-deposit-${Date.now()}`;
+      const pdas = deriveGamePdas(room);
+      await program.methods
+        .initializeGame(room, new anchor.BN(tokenStake), timeLimitSeconds, {
+          ...defaultGameOptions(),
+          tokenStakeMint: mint,
+        })
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerWhite.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+      await program.methods
+        .joinGame()
+        .accounts({ gameEscrow: pdas.escrow, player: playerBlack.publicKey })
+        .signers([playerBlack])
+        .rpc();
+      try {
+        await program.methods
+          .depositStake()
+          .accounts({
+            gameEscrow: pdas.escrow,
+            player: playerWhite.publicKey,
+            gameVault: pdas.vault,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([playerWhite])
+          .rpc();
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "StakeAssetMismatch");
+      }
+    });
+  });
+
+  describe("pull payouts", () => {
+    const declare = async (pdas, winner, reason, signer: Keypair) =>
+      program.methods
+        .declareResult(winner, reason)
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: signer.publicKey,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([signer])
+        .rpc();
+
+    const claim = async (pdas, player: Keypair) =>
+      program.methods
+        .claimPayout()
+        .accounts({
+          gameEscrow: pdas.escrow,
+          gameVault: pdas.vault,
+          player: player.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([player])
+        .rpc();
+
+    it("should record the winner's share and pay only the fee at settlement", async () => {
+      const pdas = await startGame(`pull-win-${Date.now()}`, {
+        ...defaultGameOptions(),
+        pullPayouts: true,
+      });
+      const pot = stakeAmount.toNumber() * 2;
+      const feeBefore = await provider.connection.getBalance(feeCollector.publicKey);
+      const whiteBefore = await provider.connection.getBalance(playerWhite.publicKey);
+
+      await declare(pdas, { white: {} }, { resignation: {} }, playerBlack);
+
+      const fee = (await provider.connection.getBalance(feeCollector.publicKey)) - feeBefore;
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.isTrue(game.fundsDistributed);
+      assert.equal(game.claimable[0].toNumber(), pot - fee);
+      assert.equal(game.claimable[1].toNumber(), 0);
+      assert.equal(await provider.connection.getBalance(playerWhite.publicKey), whiteBefore);
+      assert.equal(await provider.connection.getBalance(pdas.vault), pot - fee);
+    });
+
+    it("should let the winner claim exactly once", async () => {
+      const pdas = await startGame(`pull-claim-${Date.now()}`, {
+        ...defaultGameOptions(),
+        pullPayouts: true,
+      });
+      await declare(pdas, { white: {} }, { resignation: {} }, playerBlack);
+      const owed = (await program.account.gameEscrow.fetch(pdas.escrow)).claimable[0].toNumber();
+
+      const events = await collectEvents("payoutClaimed", () => claim(pdas, playerWhite));
+      assert.equal(events[0].amount.toNumber(), owed);
+      assert.equal(await provider.connection.getBalance(pdas.vault), 0);
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.claimable[0].toNumber(), 0);
+
+      try {
+        await claim(pdas, playerWhite);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "NothingToClaim");
+      }
+      try {
+        await claim(pdas, playerBlack);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "NothingToClaim");
+      }
+    });
+
+    it("should record both shares of a draw and let each player claim once", async () => {
+      const pdas = await startGame(`pull-draw-${Date.now()}`, {
+        ...defaultGameOptions(),
+        pullPayouts: true,
+      });
+      await offerDraw(pdas.escrow, playerBlack);
+      await declare(pdas, { draw: {} }, { agreement: {} }, playerWhite);
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      const [whiteShare, blackShare] = game.claimable.map((amount) => amount.toNumber());
+      assert.isAbove(whiteShare, 0);
+      assert.equal(whiteShare, blackShare);
+
+      for (const [player, share] of [[playerWhite, whiteShare], [playerBlack, blackShare]] as const) {
+        const before = await provider.connection.getBalance(player.publicKey);
+        await claim(pdas, player);
+        const after = await provider.connection.getBalance(player.publicKey);
+        // The claimant also pays the transaction fee
+        assert.approximately(after - before, share, 10_000);
+        try {
+          await claim(pdas, player);
+          assert.fail("Should have thrown an error");
+        } catch (error) {
+          assert.include(error.toString(), "NothingToClaim");
+        }
+      }
+    });
+
+    it("should pay a share too small to leave in the vault at settlement", async () => {
+      const pdas = await startGame(`pull-small-share-${Date.now()}`, {
+        ...defaultGameOptions(),
+        pullPayouts: true,
+        payoutPolicy: { consolationSplit: { loserBps: 1 } },
+      });
+      const blackBefore = await provider.connection.getBalance(playerBlack.publicKey);
+      await declare(pdas, { white: {} }, { resignation: {} }, playerBlack);
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      const consolation = (await provider.connection.getBalance(playerBlack.publicKey)) - blackBefore;
+      assert.isAbove(consolation, 0);
+      assert.isBelow(consolation, await provider.connection.getMinimumBalanceForRentExemption(0));
+      assert.equal(game.claimable[1].toNumber(), 0);
+
+      // Anything sent to the vault since goes out with the last claim
+      await provider.sendAndConfirm(
+        new Transaction().add(
+          SystemProgram.transfer({
+            fromPubkey: provider.wallet.publicKey,
+            toPubkey: pdas.vault,
+            lamports: 1,
+          })
+        )
+      );
+      const [claimed] = await collectEvents("payoutClaimed", () => claim(pdas, playerWhite));
+      assert.equal(claimed.amount.toNumber(), game.claimable[0].toNumber() + 1);
+      assert.equal(await provider.connection.getBalance(pdas.vault), 0);
+    });
+
+    it("should keep a game with unclaimed payouts open", async () => {
+      const pdas = await startGame(`pull-close-${Date.now()}`, {
+        ...defaultGameOptions(),
+        pullPayouts: true,
+      });
+      await declare(pdas, { black: {} }, { resignation: {} }, playerWhite);
+
+      try {
+        await program.methods
+          .closeGame()
+          .accounts({
+            gameEscrow: pdas.escrow,
+            rentBeneficiary: playerWhite.publicKey,
+          })
+          .rpc();
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "GameNotSettled");
+      }
+    });
+  });
+
+  describe("withdraw_stake", () => {
+    // Creates a room and deposits White's stake before anyone joins
+    const openWithDeposit = async (label: string) => {
+      const room = `withdraw-${label}-${Date.now()}`;
+      const game = deriveGamePdas(room);
+      await program.methods
+        .initializeGame(room, stakeAmount, timeLimitSeconds, defaultGameOptions())
+        .accounts({
+          gameEscrow: game.escrow,
+          player: playerWhite.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+      await program.methods
+        .depositStake()
+        .accounts({
+          gameEscrow: game.escrow,
+          player: playerWhite.publicKey,
+          gameVault: game.vault,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+      return game;
+    };
+
+    const withdraw = (pdas: { escrow: PublicKey; vault: PublicKey }, player: Keypair = playerWhite) =>
+      program.methods
+        .withdrawStake()
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: player.publicKey,
+          gameVault: pdas.vault,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([player])
+        .rpc();
+
+    it("should refund White and leave the room open", async () => {
+      const pdas = await openWithDeposit("ok");
+      assert.equal(await provider.connection.getBalance(pdas.vault), stakeAmount.toNumber());
+
+      const [refunded] = await collectEvents("depositRefunded", () => withdraw(pdas));
+      assert.equal(refunded.amount.toNumber(), stakeAmount.toNumber());
+      assert.ok(refunded.player.equals(playerWhite.publicKey));
+      assert.equal(await provider.connection.getBalance(pdas.vault), 0);
+
+      let game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { waitingForPlayers: {} });
+      assert.isFalse(game.whiteDeposited);
+      assert.equal(game.totalDeposited.toNumber(), 0);
+
+      // A second withdrawal has nothing to return
+      try {
+        await withdraw(pdas);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "NoStakeToWithdraw");
+      }
+
+      // Someone can still join the same room
+      await program.methods
+        .joinGame()
+        .accounts({ gameEscrow: pdas.escrow, player: playerBlack.publicKey, playerRating: null })
+        .signers([playerBlack])
+        .rpc();
+      game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.ok(game.playerBlack.equals(playerBlack.publicKey));
+    });
+
+    it("should reject a withdrawal once Black has joined", async () => {
+      const pdas = await openWithDeposit("joined");
+      await program.methods
+        .joinGame()
+        .accounts({ gameEscrow: pdas.escrow, player: playerBlack.publicKey, playerRating: null })
+        .signers([playerBlack])
+        .rpc();
+
+      try {
+        await withdraw(pdas);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "GameAlreadyHasOpponent");
+      }
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.isTrue(game.whiteDeposited);
+    });
+
+    it("should reject anyone but White", async () => {
+      const pdas = await openWithDeposit("stranger");
+      try {
+        await withdraw(pdas, playerBlack);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "UnauthorizedPlayer");
+      }
+    });
+  });
+
+  describe("vault surplus", () => {
+    it("should split a draw from the deposits and sweep an airdrop to the fee collector", async () => {
+      const pdas = await startGame(`vault-surplus-${Date.now()}`);
+      const pot = stakeAmount.toNumber() * 2;
+      const fee = (pot * 2) / 100;
+      const extra = 1_000_000;
+      await provider.sendAndConfirm(
+        new Transaction().add(
+          SystemProgram.transfer({
+            fromPubkey: provider.wallet.publicKey,
+            toPubkey: pdas.vault,
+            lamports: extra,
+          })
+        )
+      );
+
+      await offerDraw(pdas.escrow, playerWhite);
+      const whiteBefore = await provider.connection.getBalance(playerWhite.publicKey);
+      const blackBefore = await provider.connection.getBalance(playerBlack.publicKey);
+      const feeBefore = await provider.connection.getBalance(feeCollector.publicKey);
+      const [swept] = await collectEvents("vaultSurplusSwept", () =>
+        program.methods
+          .declareResult({ draw: {} }, { agreement: {} })
+          .accounts({
+            gameEscrow: pdas.escrow,
+            player: playerBlack.publicKey,
+            gameVault: pdas.vault,
+            playerWhite: playerWhite.publicKey,
+            playerBlack: playerBlack.publicKey,
+            feeCollector: feeCollector.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([playerBlack])
+          .rpc()
+      );
+
+      assert.equal(swept.amount.toNumber(), extra);
+      // White didn't sign, so White's balance moves by the payout alone
+      assert.equal(
+        (await provider.connection.getBalance(playerWhite.publicKey)) - whiteBefore,
+        (pot - fee) / 2
+      );
+      assert.isAbove(
+        (await provider.connection.getBalance(playerBlack.publicKey)) - blackBefore,
+        (pot - fee) / 2 - 10_000
+      );
+      assert.equal(
+        (await provider.connection.getBalance(feeCollector.publicKey)) - feeBefore,
+        fee + extra
+      );
+      assert.equal(await provider.connection.getBalance(pdas.vault), 0);
+    });
+
+    it("should sweep a donation to the fee collector when a game is cancelled", async () => {
+      const room = `vault-surplus-cancel-${Date.now()}`;
+      const pdas = deriveGamePdas(room);
+      await program.methods
+        .initializeGame(room, stakeAmount, timeLimitSeconds, defaultGameOptions())
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerWhite.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+      await program.methods
+        .joinGame()
+        .accounts({ gameEscrow: pdas.escrow, player: playerBlack.publicKey, playerRating: null })
+        .signers([playerBlack])
+        .rpc();
+      await program.methods
+        .depositStake()
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerBlack.publicKey,
+          gameVault: pdas.vault,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerBlack])
+        .rpc();
+      // A single lamport would leave the vault rent-paying once Black's stake is refunded
+      await provider.sendAndConfirm(
+        new Transaction().add(
+          SystemProgram.transfer({
+            fromPubkey: provider.wallet.publicKey,
+            toPubkey: pdas.vault,
+            lamports: 1,
+          })
+        )
+      );
+
+      const feeBefore = await provider.connection.getBalance(feeCollector.publicKey);
+      const [swept] = await collectEvents("vaultSurplusSwept", () =>
+        program.methods
+          .cancelGame()
+          .accounts({
+            gameEscrow: pdas.escrow,
+            player: playerWhite.publicKey,
+            gameVault: pdas.vault,
+            playerWhite: playerWhite.publicKey,
+            playerBlack: playerBlack.publicKey,
+            feeCollector: feeCollector.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([playerWhite])
+          .rpc()
+      );
+
+      assert.equal(swept.amount.toNumber(), 1);
+      assert.equal((await provider.connection.getBalance(feeCollector.publicKey)) - feeBefore, 1);
+      assert.equal(await provider.connection.getBalance(pdas.vault), 0);
+    });
+  });
+
+  describe("expected move count", () => {
+    it("should accept moves submitted against the current count", async () => {
+      const pdas = await startGame(`move-count-${Date.now()}`);
+      await recordMove(pdas.escrow, playerWhite, "e2", "e4", "P", { moveCount: 0 });
+      await recordMove(pdas.escrow, playerBlack, "e7", "e5", "p", { moveCount: 1 });
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.moveCount, 2);
+    });
+
+    it("should reject a stale or premature move count", async () => {
+      const pdas = await startGame(`move-count-stale-${Date.now()}`);
+      await recordMove(pdas.escrow, playerWhite, "e2", "e4", "P", { moveCount: 0 });
+
+      // Black's reply built before White's move landed, and one built a move ahead
+      for (const moveCount of [0, 2]) {
+        try {
+          await recordMove(pdas.escrow, playerBlack, "e7", "e5", "p", { moveCount });
+          assert.fail("Should have thrown an error");
+        } catch (error) {
+          assert.include(error.toString(), "StaleMove");
+        }
+      }
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.moveCount, 1);
+    });
+  });
+
+  describe("timeout grace", () => {
+    const claim = (pdas: { escrow: PublicKey; vault: PublicKey }, claimant: Keypair) =>
+      program.methods
+        .handleTimeout()
+        .accounts({
+          gameEscrow: pdas.escrow,
+          claimant: claimant.publicKey,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([claimant])
+        .rpc();
+
+    // Waits until the cluster clock is more than `extra` seconds past White's flag
+    const waitPastFlag = async (escrow: PublicKey, extra: number) => {
+      const game = await program.account.gameEscrow.fetch(escrow);
+      const deadline = game.lastMoveTime.toNumber() + game.whiteTimeRemaining.toNumber() + extra;
+      for (;;) {
+        const blockTime = await provider.connection.getBlockTime(await provider.connection.getSlot());
+        if (blockTime !== null && blockTime > deadline) return;
+        await new Promise((resolve) => setTimeout(resolve, 500));
+      }
+    };
+
+    const graceGame = (label: string, timeoutGraceSeconds: number | null) =>
+      startGame(
+        `timeout-grace-${label}-${Date.now()}`,
+        { ...defaultGameOptions(), timeoutGraceSeconds },
+        playerWhite,
+        playerBlack,
+        new anchor.BN(1)
+      );
+
+    it("should refuse a claim inside the grace and accept one past it", async () => {
+      const pdas = await graceGame("window", 5);
+
+      await waitPastFlag(pdas.escrow, 0);
+      try {
+        await claim(pdas, playerBlack);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "TimeNotExceeded");
+      }
+
+      await waitPastFlag(pdas.escrow, 5);
+      const [finished] = await collectEvents("gameFinished", () => claim(pdas, playerBlack));
+      assert.deepEqual(finished.winner, { black: {} });
+      assert.deepEqual(finished.reason, { timeout: {} });
+    });
+
+    it("should default to a short grace", async () => {
+      const pdas = await graceGame("default", null);
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.timeoutGraceSeconds, 2);
+    });
+
+    it("should leave the claim to the opponent", async () => {
+      const pdas = await graceGame("opponent", 0);
+      await waitPastFlag(pdas.escrow, 0);
+
+      try {
+        await claim(pdas, Keypair.generate());
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "TimeoutClaimNotAllowed");
+      }
+      await claim(pdas, playerBlack);
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { finished: {} });
+    });
+  });
+
+  describe("stake bounds", () => {
+    const setBounds = (min: anchor.BN, max: anchor.BN) =>
+      program.methods
+        .setStakeBounds(min, max)
+        .accounts({ config: configPda, admin: provider.wallet.publicKey })
+        .rpc();
+
+    const createGame = (room: string, stake: anchor.BN, config: PublicKey = configPda) =>
+      program.methods
+        .initializeGame(room, stake, timeLimitSeconds, defaultGameOptions())
+        .accounts({
+          gameEscrow: deriveGamePdas(room).escrow,
+          player: playerWhite.publicKey,
+          feeCollector: feeCollector.publicKey,
+          config,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerWhite])
+        .rpc();
+
+    const min = stakeAmount.muln(2);
+    const max = stakeAmount.muln(10);
+
+    it("should reject stakes outside the configured range and accept those inside", async () => {
+      await setBounds(min, max);
+      try {
+        for (const [label, stake, error] of [
+          ["below", stakeAmount, "StakeBelowConfigMinimum"],
+          ["above", stakeAmount.muln(11), "StakeAboveConfigMaximum"],
+        ] as const) {
+          try {
+            await createGame(`stake-${label}-${Date.now()}`, stake);
+            assert.fail("Should have thrown an error");
+          } catch (err) {
+            assert.include(err.toString(), error);
+          }
+        }
+
+        const room = `stake-in-range-${Date.now()}`;
+        await createGame(room, stakeAmount.muln(3));
+        const game = await program.account.gameEscrow.fetch(deriveGamePdas(room).escrow);
+        assert.equal(game.stakeAmount.toNumber(), stakeAmount.muln(3).toNumber());
+
+        // The bounds can't be dodged by passing some other account for the config
+        try {
+          await createGame(`stake-no-config-${Date.now()}`, stakeAmount, Keypair.generate().publicKey);
+          assert.fail("Should have thrown an error");
+        } catch (err) {
+          assert.include(err.toString(), "ConstraintSeeds");
+        }
+      } finally {
+        await setBounds(new anchor.BN(0), new anchor.BN(0));
+      }
+    });
+
+    it("should reject a minimum above the maximum", async () => {
+      try {
+        await setBounds(max, min);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "InvalidStakeBounds");
+      }
+    });
+  });
+
+  describe("total time used", () => {
+    it("should total each side's reported think time into GameFinished", async () => {
+      const pdas = await startGame(`total-time-${Date.now()}`);
+      await playMoves(pdas.escrow, [
+        ["e2", "e4", "P", { timeSpent: 1200 }],
+        ["e7", "e5", "p", { timeSpent: 3400 }],
+        ["g1", "f3", "N", { timeSpent: 2500 }],
+        ["b8", "c6", "n", { timeSpent: 600 }],
+        ["f1", "c4", "B", { timeSpent: 4100 }],
+      ]);
+
+      const [finished] = await collectEvents("gameFinished", () =>
+        program.methods
+          .resign()
+          .accounts({
+            gameEscrow: pdas.escrow,
+            player: playerBlack.publicKey,
+            gameVault: pdas.vault,
+            playerWhite: playerWhite.publicKey,
+            playerBlack: playerBlack.publicKey,
+            feeCollector: feeCollector.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([playerBlack])
+          .rpc()
+      );
+
+      assert.equal(finished.whiteTotalTimeMs.toNumber(), 1200 + 2500 + 4100);
+      assert.equal(finished.blackTotalTimeMs.toNumber(), 3400 + 600);
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.whiteTiming.totalMs.toNumber(), 1200 + 2500 + 4100);
+      assert.equal(game.blackTiming.totalMs.toNumber(), 3400 + 600);
+    });
+  });
+
+  describe("disputes", () => {
+    const declareWhiteWin = (pdas) =>
+      program.methods
+        .declareResult({ white: {} }, { resignation: {} })
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: playerBlack.publicKey,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([playerBlack])
+        .rpc();
+
+    const dispute = (pdas, player: Keypair) =>
+      program.methods
+        .disputeResult()
+        .accounts({ gameEscrow: pdas.escrow, player: player.publicKey })
+        .signers([player])
+        .rpc();
+
+    const resolve = (pdas, winner, arbiter: PublicKey, signers: Keypair[] = []) =>
+      program.methods
+        .resolveDispute(winner)
+        .accounts({
+          gameEscrow: pdas.escrow,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          config: configPda,
+          arbiter,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers(signers)
+        .rpc();
+
+    const disputedGame = async (label: string) => {
+      const pdas = await startGame(`dispute-${label}-${Date.now()}`, {
+        ...defaultGameOptions(),
+        disputeWindowSeconds: 60,
+      });
+      await declareWhiteWin(pdas);
+      return pdas;
+    };
+
+    it("should hold the pot while a dispute is raised", async () => {
+      const pdas = await disputedGame("hold");
+      const pot = stakeAmount.toNumber() * 2;
+      assert.equal(await provider.connection.getBalance(pdas.vault), pot);
+
+      const events = await collectEvents("resultDisputed", () => dispute(pdas, playerWhite));
+      assert.ok(events[0].disputedBy.equals(playerWhite.publicKey));
+      assert.deepEqual(events[0].declaredWinner, { white: {} });
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { disputed: {} });
+      assert.isFalse(game.fundsDistributed);
+
+      try {
+        await settleGame(pdas);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "GameNotFinished");
+      }
+      assert.equal(await provider.connection.getBalance(pdas.vault), pot);
+    });
+
+    it("should not pay out while the dispute window is open", async () => {
+      const pdas = await disputedGame("window");
+      try {
+        await settleGame(pdas);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "DisputeWindowOpen");
+      }
+    });
+
+    it("should let a moderator resolve the dispute and release the pot", async () => {
+      const pdas = await disputedGame("resolve");
+      await dispute(pdas, playerWhite);
+      await program.methods
+        .addModerator(provider.wallet.publicKey)
+        .accounts({ config: configPda, admin: provider.wallet.publicKey })
+        .rpc();
+      try {
+        const blackBefore = await provider.connection.getBalance(playerBlack.publicKey);
+        const events = await collectEvents("disputeResolved", () =>
+          resolve(pdas, { black: {} }, provider.wallet.publicKey)
+        );
+        assert.deepEqual(events[0].winner, { black: {} });
+
+        const game = await program.account.gameEscrow.fetch(pdas.escrow);
+        assert.deepEqual(game.gameState, { finished: {} });
+        assert.deepEqual(game.winner, { black: {} });
+        assert.isNull(game.disputedBy);
+        assert.isTrue(game.fundsDistributed);
+        assert.isAbove(
+          await provider.connection.getBalance(playerBlack.publicKey),
+          blackBefore + stakeAmount.toNumber()
+        );
+        assert.equal(await provider.connection.getBalance(pdas.vault), 0);
+      } finally {
+        await program.methods
+          .removeModerator(provider.wallet.publicKey)
+          .accounts({ config: configPda, admin: provider.wallet.publicKey })
+          .rpc();
+      }
+    });
+
+    it("should reject disputes from outsiders and rulings from non-moderators", async () => {
+      const pdas = await disputedGame("auth");
+      try {
+        await dispute(pdas, unauthorizedPlayer);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "UnauthorizedPlayer");
+      }
+
+      await dispute(pdas, playerBlack);
+      try {
+        await resolve(pdas, { white: {} }, unauthorizedPlayer.publicKey, [unauthorizedPlayer]);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "ModeratorNotListed");
+      }
+    });
+  });
+
+  describe("single-elimination brackets", () => {
+    const pdaOf = (seeds: Buffer[]) => PublicKey.findProgramAddressSync(seeds, program.programId)[0];
+
+    // Creates, fills and starts a tournament of `size` players, then pairs round one
+    const bracketTournament = async (size: number) => {
+      const creator = await fundedKeypair();
+      const players: Keypair[] = [];
+      for (let i = 0; i < size; i++) players.push(await fundedKeypair());
+      const id = `bracket-${size}-${Date.now()}`;
+      const tournament = pdaOf([Buffer.from("tournament"), Buffer.from(id)]);
+      const tournamentVault = pdaOf([Buffer.from("tournament_vault"), tournament.toBuffer()]);
+
+      await program.methods
+        .createTournament(id, `Bracket ${size}`, new anchor.BN(1000), size, {
+          initialTime: new anchor.BN(600),
+          increment: new anchor.BN(0),
+          delay: new anchor.BN(0),
+          timeControlType: { rapid: {} },
+        }, {
+          seedingMode: { rating: {} },
+          formWeightPct: 0,
+          payoutPcts: [100],
+          guaranteedPool: new anchor.BN(0),
+          lateRegistrationSeconds: 0,
+        })
+        .accounts({ tournament, tournamentVault, creator: creator.publicKey, systemProgram: SystemProgram.programId })
+        .signers([creator])
+        .rpc();
+      for (const player of players) {
+        await program.methods
+          .joinTournament()
+          .accounts({
+            tournament,
+            tournamentVault,
+            player: player.publicKey,
+            systemProgram: SystemProgram.programId,
+            standings: null,
+          })
+          .signers([player])
+          .rpc();
+      }
+      await program.methods
+        .startTournament()
+        .accounts({
+          tournament,
+          tournamentVault,
+          standings: pdaOf([Buffer.from("standings"), tournament.toBuffer()]),
+          creator: creator.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([creator])
+        .rpc();
+
+      // Nobody has a rating or stats account, so the field seeds in pubkey order
+      const [event] = await collectEvents("bracketRoundPaired", () =>
+        program.methods
+          .generateBrackets()
+          .accounts({ tournament, creator: creator.publicKey })
+          .remainingAccounts(
+            players.flatMap((player) => ["rating", "stats"].map((seed) => ({
+              pubkey: pdaOf([Buffer.from(seed), player.publicKey.toBuffer()]),
+              isWritable: false,
+              isSigner: false,
+            })))
+          )
+          .signers([creator])
+          .rpc()
+      );
+      return { creator, tournament, event };
+    };
+
+    const advance = (t: { creator: Keypair; tournament: PublicKey }, winners: PublicKey[]) =>
+      program.methods
+        .advanceBracket(winners)
+        .accounts({ tournament: t.tournament, creator: t.creator.publicKey })
+        .signers([t.creator])
+        .rpc();
+
+    const keys = (list: PublicKey[]) => list.map((key) => key.toString());
+
+    it("should pair an 8-player field into 4 first-round games", async () => {
+      const { tournament, event } = await bracketTournament(8);
+      const account = await program.account.tournament.fetch(tournament);
+      const seeds = keys(account.seeds);
+
+      assert.equal(account.bracketRound, 1);
+      assert.isNull(account.bracketBye);
+      assert.lengthOf(account.bracketPairings, 4);
+      assert.lengthOf(account.brackets, 4);
+      assert.equal(new Set(account.brackets).size, 4);
+      assert.deepEqual(event.roomIds, account.brackets);
+      // Top seed meets bottom seed, second meets seventh, and so on
+      account.bracketPairings.forEach((pairing, i) => {
+        assert.equal(pairing.white.toString(), seeds[i]);
+        assert.equal(pairing.black.toString(), seeds[7 - i]);
+      });
+      assert.sameMembers(
+        account.bracketPairings.flatMap((p) => keys([p.white, p.black])),
+        seeds
+      );
+    });
+
+    it("should give one player a bye in a 5-player field", async () => {
+      const { tournament, event } = await bracketTournament(5);
+      const account = await program.account.tournament.fetch(tournament);
+
+      assert.lengthOf(account.bracketPairings, 2);
+      assert.lengthOf(account.brackets, 2);
+      assert.equal(account.bracketBye.toString(), account.seeds[0].toString());
+      assert.equal(event.bye.toString(), account.seeds[0].toString());
+    });
+
+    it("should advance the winners round by round to a champion", async () => {
+      const t = await bracketTournament(8);
+      let account = await program.account.tournament.fetch(t.tournament);
+      const firstRound = account.bracketPairings.map((p) => p.white);
+
+      const [event] = await collectEvents("bracketRoundPaired", () => advance(t, firstRound));
+      assert.equal(event.round, 2);
+      account = await program.account.tournament.fetch(t.tournament);
+      assert.deepEqual(
+        account.bracketPairings.map((p) => keys([p.white, p.black])),
+        [keys([firstRound[0], firstRound[3]]), keys([firstRound[1], firstRound[2]])]
+      );
+      assert.deepEqual(event.roomIds, account.brackets);
+
+      await advance(t, account.bracketPairings.map((p) => p.black));
+      account = await program.account.tournament.fetch(t.tournament);
+      assert.lengthOf(account.bracketPairings, 1);
+
+      const champion = account.bracketPairings[0].white;
+      const [decided] = await collectEvents("bracketDecided", () => advance(t, [champion]));
+      assert.equal(decided.champion.toString(), champion.toString());
+      assert.equal(decided.rounds, 3);
+      account = await program.account.tournament.fetch(t.tournament);
+      assert.equal(account.bracketChampion.toString(), champion.toString());
+      assert.lengthOf(account.brackets, 0);
+    });
+
+    it("should reject winners who weren't in the pairing", async () => {
+      const t = await bracketTournament(4);
+      const account = await program.account.tournament.fetch(t.tournament);
+      const [first, second] = account.bracketPairings;
+      try {
+        await advance(t, [first.white, first.black]);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "BracketWinnersMismatch");
+      }
+      try {
+        await advance(t, [second.white]);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "BracketWinnersMismatch");
+      }
+    });
+  });
+
+  describe("creator color", () => {
+    const create = async (room: string, creatorColor: object, creator: Keypair = playerBlack) => {
+      const pdas = deriveGamePdas(room);
+      await program.methods
+        .initializeGame(room, stakeAmount, timeLimitSeconds, { ...defaultGameOptions(), creatorColor })
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: creator.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([creator])
+        .rpc();
+      return pdas;
+    };
+
+    const join = (pdas, player: Keypair) =>
+      program.methods
+        .joinGame()
+        .accounts({ gameEscrow: pdas.escrow, player: player.publicKey, playerRating: null })
+        .signers([player])
+        .rpc();
+
+    it("should seat a creator who chooses Black as Black", async () => {
+      const pdas = await create(`color-black-${Date.now()}`, { black: {} });
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.ok(game.playerBlack.equals(playerBlack.publicKey));
+      assert.ok(game.playerWhite.equals(PublicKey.default));
+      assert.deepEqual(game.gameState, { waitingForPlayers: {} });
+    });
+
+    it("should give the joiner the color the creator left open", async () => {
+      const pdas = await create(`color-join-${Date.now()}`, { black: {} });
+      const [event] = await collectEvents("playerJoined", () => join(pdas, playerWhite));
+      assert.ok(event.playerWhite.equals(playerWhite.publicKey));
+      assert.ok(event.playerBlack.equals(playerBlack.publicKey));
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.ok(game.playerWhite.equals(playerWhite.publicKey));
+      assert.ok(game.playerBlack.equals(playerBlack.publicKey));
+      assert.deepEqual(game.gameState, { waitingForDeposits: {} });
+
+      try {
+        await join(pdas, unauthorizedPlayer);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "GameAlreadyHasOpponent");
+      }
+
+      // The joiner moves first
+      for (const player of [playerBlack, playerWhite]) {
+        await program.methods
+          .depositStake()
+          .accounts({
+            gameEscrow: pdas.escrow,
+            player: player.publicKey,
+            gameVault: pdas.vault,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([player])
+          .rpc();
+      }
+      await recordMove(pdas.escrow, playerWhite, "e2", "e4", "P");
+      assert.equal((await program.account.gameEscrow.fetch(pdas.escrow)).moveCount, 1);
+    });
+
+    it("should not let a Black creator join their own game", async () => {
+      const pdas = await create(`color-self-${Date.now()}`, { black: {} });
+      try {
+        await join(pdas, playerBlack);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "CannotPlayAgainstSelf");
+      }
+    });
+
+    it("should seat a random-color creator on one side and the joiner on the other", async () => {
+      const pdas = await create(`color-random-${Date.now()}`, { random: {} });
+      await join(pdas, playerWhite);
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.sameMembers(
+        [game.playerWhite.toString(), game.playerBlack.toString()],
+        [playerWhite.publicKey.toString(), playerBlack.publicKey.toString()]
+      );
+    });
+  });
+
+  describe("square parsing", () => {
+    const expectRejected = async (escrow: PublicKey, from: string, to: string, expected: string) => {
+      try {
+        await recordMove(escrow, playerWhite, from, to, "P");
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), expected);
+      }
+    };
+
+    it("should reject multi-byte squares as malformed", async () => {
+      const pdas = await startGame(`square-utf8-${Date.now()}`);
+      // "é" is two bytes but one character, the length that used to slip through
+      await expectRejected(pdas.escrow, "é", "e4", "InvalidSquareFormat");
+      await expectRejected(pdas.escrow, "e2", "é", "InvalidSquareFormat");
+      await expectRejected(pdas.escrow, "à2", "e4", "InvalidSquareFormat");
+    });
+
+    it("should reject empty and out-of-range squares without failing the program", async () => {
+      const pdas = await startGame(`square-empty-${Date.now()}`);
+      await expectRejected(pdas.escrow, "", "e4", "InvalidMoveFormat");
+      await expectRejected(pdas.escrow, "e2", "", "InvalidMoveFormat");
+      await expectRejected(pdas.escrow, "E2", "e4", "ImpossibleMove");
+
+      // The game is untouched and still takes a well-formed move
+      await recordMove(pdas.escrow, playerWhite, "e2", "e4", "P");
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.moveCount, 1);
+    });
+  });
+
+  describe("insufficient material", () => {
+    const claim = (escrow: PublicKey, player: Keypair) =>
+      program.methods
+        .claimInsufficientMaterial()
+        .accounts({ gameEscrow: escrow, player: player.publicKey })
+        .signers([player])
+        .rpc();
+
+    const BACK_RANK: Record<string, string> = { a: "R", b: "N", c: "B", d: "Q", f: "B", g: "N", h: "R" };
+    const pieceOn = (square: string) =>
+      square[1] === "2" ? "P" : square[1] === "7" ? "p" : square[1] === "1" ? BACK_RANK[square[0]] : BACK_RANK[square[0]].toLowerCase();
+
+    // Without on-chain validation the queens can sweep the board: White's takes every
+    // black piece, Black's every white one but `keep`, then they trade and `keep` takes
+    // the last queen, leaving the two kings and `keep`
+    const sweepBoardExcept = async (keep: string) => {
+      const pdas = await startGame(`material-${keep}-${Date.now()}`, {
+        ...defaultGameOptions(),
+        optimisticValidation: true,
+      });
+      const blackTargets = [..."abcdefgh"].map((file) => `${file}7`).concat(["a8", "b8", "c8", "f8", "g8", "h8"]);
+      const whiteTargets = [..."abcdefgh"].map((file) => `${file}2`)
+        .concat(["a1", "b1", "c1", "f1", "g1", "h1"])
+        .filter((square) => square !== keep);
+
+      const plies: [string, string, string, MoveExtras?][] = [];
+      let whiteQueen = "d1";
+      let blackQueen = "d8";
+      blackTargets.forEach((target, i) => {
+        plies.push([whiteQueen, target, "Q", { captured: pieceOn(target) }]);
+        whiteQueen = target;
+        if (i < whiteTargets.length) {
+          plies.push([blackQueen, whiteTargets[i], "q", { captured: pieceOn(whiteTargets[i]) }]);
+          blackQueen = whiteTargets[i];
+        }
+      });
+      plies.push([blackQueen, whiteQueen, "q", { captured: "Q" }]);
+      plies.push([keep, whiteQueen, pieceOn(keep), { captured: "q" }]);
+      await playMoves(pdas.escrow, plies);
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.equal(game.board.filter((code) => code !== 0).length, 3);
+      return pdas;
+    };
+
+    it("should draw king and knight against king", async () => {
+      const pdas = await sweepBoardExcept("g1");
+      const [event] = await collectEvents("gameFinished", () => claim(pdas.escrow, playerBlack));
+      assert.deepEqual(event.reason, { insufficientMaterial: {} });
+      assert.deepEqual(event.winner, { draw: {} });
+
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { finished: {} });
+      assert.deepEqual(game.winner, { draw: {} });
+    });
+
+    it("should reject the claim while a rook remains", async () => {
+      const pdas = await sweepBoardExcept("h1");
+      try {
+        await claim(pdas.escrow, playerBlack);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "MatingMaterialRemains");
+      }
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.gameState, { inProgress: {} });
+    });
+
+    it("should reject the claim from the starting position", async () => {
+      const pdas = await startGame(`material-start-${Date.now()}`);
+      try {
+        await claim(pdas.escrow, playerWhite);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "MatingMaterialRemains");
+      }
+    });
+  });
+
+  describe("payout policy", () => {
+    // Settles a pull-payout game for `winner`, so each share stays readable in `claimable`
+    const settleUnder = async (payoutPolicy: object, winner: object, rakeMode: object = { potRake: {} }) => {
+      const pdas = await startGame(`payout-${Object.keys(payoutPolicy)[0]}-${Date.now()}`, {
+        ...defaultGameOptions(),
+        payoutPolicy,
+        rakeMode,
+        pullPayouts: true,
+      });
+      const feeBefore = await provider.connection.getBalance(feeCollector.publicKey);
+      const loser = "white" in winner ? playerBlack : playerWhite;
+      await program.methods
+        .declareResult(winner, { resignation: {} })
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: loser.publicKey,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([loser])
+        .rpc();
+      const fee = (await provider.connection.getBalance(feeCollector.publicKey)) - feeBefore;
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      return {
+        fee,
+        feeBps: game.feeBps,
+        white: game.claimable[0].toNumber(),
+        black: game.claimable[1].toNumber(),
+        vault: await provider.connection.getBalance(pdas.vault),
+      };
+    };
+
+    const pot = () => stakeAmount.toNumber() * 2;
+
+    it("should give the winner the whole pot by default", async () => {
+      const { fee, white, black, vault } = await settleUnder({ winnerTakeAll: {} }, { white: {} });
+      assert.equal(white, pot() - fee);
+      assert.equal(black, 0);
+      assert.equal(vault, white);
+    });
+
+    it("should hand the loser a 10% consolation, spending exactly the pot", async () => {
+      const { fee, white, black, vault } = await settleUnder(
+        { consolationSplit: { loserBps: 1000 } },
+        { black: {} }
+      );
+      const remaining = pot() - fee;
+      assert.equal(white, Math.floor((remaining * 1000) / 10_000));
+      assert.equal(black, remaining - white);
+      assert.equal(fee + white + black, pot());
+      assert.equal(vault, white + black);
+    });
+
+    it("should not count the consolation as winnings under WinningsRake", async () => {
+      const { fee, feeBps, white, black } = await settleUnder(
+        { consolationSplit: { loserBps: 1000 } },
+        { white: {} },
+        { winningsRake: {} }
+      );
+      // Black gets 10% of the whole pot back, and White's gain over their stake excludes it
+      const consolation = Math.floor((pot() * 1000) / 10_000);
+      const winnings = pot() - stakeAmount.toNumber() - consolation;
+      assert.equal(black, consolation);
+      assert.equal(fee, Math.floor((winnings * feeBps) / 10_000));
+      assert.equal(white, pot() - fee - consolation);
+    });
+
+    it("should reject a consolation outside 1 to 5000 basis points", async () => {
+      for (const loserBps of [0, 5001]) {
+        const room = `payout-bad-${loserBps}-${Date.now()}`;
+        try {
+          await program.methods
+            .initializeGame(room, stakeAmount, timeLimitSeconds, {
+              ...defaultGameOptions(),
+              payoutPolicy: { consolationSplit: { loserBps } },
+            })
+            .accounts({
+              gameEscrow: deriveGamePdas(room).escrow,
+              player: playerWhite.publicKey,
+              feeCollector: feeCollector.publicKey,
+              systemProgram: SystemProgram.programId,
+            })
+            .signers([playerWhite])
+            .rpc();
+          assert.fail("Should have thrown an error");
+        } catch (error) {
+          assert.include(error.toString(), "InvalidPayoutPolicy");
+        }
+      }
+    });
+  });
+
+  describe("repeat finalization", () => {
+    const declare = (pdas, winner: object, reason: object, player: Keypair) =>
+      program.methods
+        .declareResult(winner, reason)
+        .accounts({
+          gameEscrow: pdas.escrow,
+          player: player.publicKey,
+          gameVault: pdas.vault,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+          feeCollector: feeCollector.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([player])
+        .rpc();
+
+    const balances = (pdas) =>
+      Promise.all(
+        [pdas.vault, feeCollector.publicKey, playerWhite.publicKey, playerBlack.publicKey].map((key) =>
+          provider.connection.getBalance(key)
+        )
+      );
+
+    it("should move no funds when a finished game is declared again", async () => {
+      const pdas = await startGame(`repeat-declare-${Date.now()}`);
+      await declare(pdas, { white: {} }, { resignation: {} }, playerBlack);
+      const before = await balances(pdas);
+      const gameBefore = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.isTrue(gameBefore.fundsDistributed);
+
+      for (const [winner, player] of [
+        [{ white: {} }, playerBlack],
+        [{ black: {} }, playerWhite],
+      ] as [object, Keypair][]) {
+        try {
+          await declare(pdas, winner, { resignation: {} }, player);
+          assert.fail("Should have thrown an error");
+        } catch (error) {
+          assert.include(error.toString(), "GameNotInProgress");
+        }
+      }
+      try {
+        await program.methods
+          .handleTimeout()
+          .accounts({
+            gameEscrow: pdas.escrow,
+            claimant: playerWhite.publicKey,
+            gameVault: pdas.vault,
+            playerWhite: playerWhite.publicKey,
+            playerBlack: playerBlack.publicKey,
+            feeCollector: feeCollector.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([playerWhite])
+          .rpc();
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "GameNotInProgress");
+      }
+
+      assert.deepEqual(await balances(pdas), before);
+      const game = await program.account.gameEscrow.fetch(pdas.escrow);
+      assert.deepEqual(game.winner, { white: {} });
+      assert.isTrue(game.fundsDistributed);
+    });
+
+    it("should not pay a settled game out again through settle_game", async () => {
+      const pdas = await startGame(`repeat-settle-${Date.now()}`);
+      await declare(pdas, { white: {} }, { resignation: {} }, playerBlack);
+      const before = await balances(pdas);
+      try {
+        await settleGame(pdas);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "FundsAlreadyDistributed");
+      }
+      assert.deepEqual(await balances(pdas), before);
+    });
+  });
+
+  describe("batched moves", () => {
+    // Builds the batch from the game's current position, each move's hash following on
+    // from the position the one before it left
+    const batchOf = async (escrow: PublicKey, moves: [string, string, string][]) => {
+      let state = boardState(await program.account.gameEscrow.fetch(escrow));
+      return moves.map(([from, to, piece]) => {
+        state = playOnBoard(state, from, to, piece);
+        return {
+          submission: moveSubmission(from, to, piece, positionDigest(state)),
+          timeSpent: new anchor.BN(1000),
+        };
+      });
+    };
+
+    const recordMovesBatch = async (escrow: PublicKey, moves: [string, string, string][]) =>
+      program.methods
+        .recordMovesBatch(await batchOf(escrow, moves), 0)
+        .accounts({
+          gameEscrow: escrow,
+          playerWhite: playerWhite.publicKey,
+          playerBlack: playerBlack.publicKey,
+        })
+        .signers([playerWhite, playerBlack])
+        .rpc();
+
+    it("should apply a batch of moves in order", async () => {
+      const { escrow } = await startGame(`batch-${Date.now()}`);
+      const events = await collectEvents("moveRecorded", () =>
+        recordMovesBatch(escrow, [
+          ["e2", "e4", "P"],
+          ["e7", "e5", "p"],
+          ["g1", "f3", "N"],
+        ])
+      );
+      assert.deepEqual(
+        events.map((event) => event.moveCount),
+        [1, 2, 3]
+      );
+      assert.deepEqual(
+        events.map((event) => event.player.toBase58()),
+        [playerWhite, playerBlack, playerWhite].map((player) => player.publicKey.toBase58())
+      );
+
+      const game = await program.account.gameEscrow.fetch(escrow);
+      assert.equal(game.moveCount, 3);
+      assert.equal(game.moveHistory.length, 3);
+      assert.equal(pieceAt(game.board, "e4"), "P");
+      assert.equal(pieceAt(game.board, "e5"), "p");
+      assert.equal(pieceAt(game.board, "f3"), "N");
+      assert.equal(pieceAt(game.board, "g1"), "");
+    });
+
+    it("should reject the whole batch when one move is illegal", async () => {
+      const { escrow } = await startGame(`batch-illegal-${Date.now()}`);
+      try {
+        // The queen is blocked by her own d-pawn
+        await recordMovesBatch(escrow, [
+          ["e2", "e4", "P"],
+          ["d8", "d6", "q"],
+          ["g1", "f3", "N"],
+        ]);
+        assert.fail("Should have thrown an error");
+      } catch (error) {
+        assert.include(error.toString(), "IllegalMove");
+      }
+
+      const game = await program.account.gameEscrow.fetch(escrow);
+      assert.equal(game.moveCount, 0);
+      assert.equal(game.moveHistory.length, 0);
+      assert.equal(pieceAt(game.board, "e2"), "P");
+      assert.equal(pieceAt(game.board, "e4"), "");
+    });
+  });
+});